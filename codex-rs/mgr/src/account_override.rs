@@ -0,0 +1,67 @@
+//! The `x-codex-mgr-account` header, which pins a request to one pool member when the pool sets
+//! `allow_account_override`.
+
+use axum::http::HeaderMap;
+
+use crate::routing;
+
+/// Why a request's account pin was refused; either way the request fails with `400`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OverrideRejected {
+    /// The pool does not set `allow_account_override`.
+    NotAllowed(String),
+    /// The named account is not a member of the pool.
+    NotAMember(String),
+}
+
+/// The account `headers` pin the request to, if any. `pinnable` is the pool's members, or `None`
+/// when the pool does not allow overrides.
+pub(crate) fn pinned_account(
+    headers: &HeaderMap,
+    pinnable: Option<&[String]>,
+) -> Result<Option<String>, OverrideRejected> {
+    let Some(account) = routing::extract_account_override(headers) else {
+        return Ok(None);
+    };
+    match pinnable {
+        None => Err(OverrideRejected::NotAllowed(account)),
+        Some(labels) if !labels.contains(&account) => Err(OverrideRejected::NotAMember(account)),
+        Some(_) => Ok(Some(account)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn pins_only_members_of_pools_that_allow_it() {
+        let labels = vec!["work".to_string(), "home".to_string()];
+        let mut headers = HeaderMap::new();
+        assert_eq!(pinned_account(&headers, None), Ok(None));
+
+        headers.insert(
+            routing::ACCOUNT_OVERRIDE_HEADER,
+            HeaderValue::from_static("work"),
+        );
+        assert_eq!(
+            pinned_account(&headers, None),
+            Err(OverrideRejected::NotAllowed("work".to_string()))
+        );
+        assert_eq!(
+            pinned_account(&headers, Some(labels.as_slice())),
+            Ok(Some("work".to_string()))
+        );
+
+        headers.insert(
+            routing::ACCOUNT_OVERRIDE_HEADER,
+            HeaderValue::from_static("other"),
+        );
+        assert_eq!(
+            pinned_account(&headers, Some(labels.as_slice())),
+            Err(OverrideRejected::NotAMember("other".to_string()))
+        );
+    }
+}
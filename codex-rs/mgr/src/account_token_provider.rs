@@ -2,13 +2,17 @@ use anyhow::Context;
 use base64::Engine;
 use codex_login::AuthCredentialsStoreMode;
 use codex_login::AuthManager;
+use rand::Rng;
 use rand::TryRngCore;
 use serde::Deserialize;
 use serde::Serialize;
 use std::path::Path;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
-use crate::time::now_ms;
+use crate::observability::GatewayMetrics;
+use crate::redis_conn;
+use crate::time::Clock;
 
 const TOKEN_CACHE_KEY_PREFIX: &str = "gw:acct_token:";
 const TOKEN_REFRESH_LOCK_KEY_PREFIX: &str = "gw:lock:acct_token_refresh:";
@@ -16,6 +20,29 @@ const TOKEN_REFRESH_LOCK_KEY_PREFIX: &str = "gw:lock:acct_token_refresh:";
 const REFRESH_LOCK_TTL_MS: i64 = 15_000;
 const LOCK_WAIT_POLL_MS: i64 = 200;
 
+/// Pacing of `serve`'s startup prewarm (`gateway.token_prewarm_stagger_ms` and
+/// `gateway.token_prewarm_jitter_ms`). Any refresh it triggers still goes through the per-account
+/// Redis lock, so a fleet prewarming together refreshes each account once.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PrewarmSchedule {
+    pub(crate) stagger_ms: i64,
+    pub(crate) jitter_ms: i64,
+}
+
+impl PrewarmSchedule {
+    /// Wait before prewarming account number `index`: the stagger (skipped for the first
+    /// account) plus a random share of the jitter.
+    pub(crate) fn delay(self, index: usize) -> Duration {
+        let base = if index == 0 { 0 } else { self.stagger_ms };
+        let jitter = if self.jitter_ms > 0 {
+            rand::rng().random_range(0..=self.jitter_ms)
+        } else {
+            0
+        };
+        Duration::from_millis(u64::try_from(base.saturating_add(jitter)).unwrap_or(0))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct AuthMaterial {
     pub(crate) authorization: String,
@@ -24,22 +51,34 @@ pub(crate) struct AuthMaterial {
 }
 
 pub(crate) async fn get(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     accounts_root: &Path,
     account_id: &str,
     token_safety_window_seconds: i64,
+    max_token_cache_seconds: i64,
+    metrics: &GatewayMetrics,
+    clock: &dyn Clock,
 ) -> anyhow::Result<AuthMaterial> {
-    let start_ms = now_ms();
+    let start_ms = clock.now_ms();
     if token_safety_window_seconds < 0 {
         anyhow::bail!("token_safety_window_seconds must be >= 0");
     }
-    let safety_ms = token_safety_window_seconds.saturating_mul(1000);
 
     if let Some(material) = get_cached(conn, account_id).await?
-        && material.expires_at_ms.saturating_sub(start_ms) > safety_ms
+        && outside_safety_window(
+            material.expires_at_ms,
+            start_ms,
+            token_safety_window_seconds,
+        )
     {
+        metrics
+            .token_cache_hits_total
+            .fetch_add(1, Ordering::Relaxed);
         return Ok(material);
     }
+    metrics
+        .token_cache_misses_total
+        .fetch_add(1, Ordering::Relaxed);
 
     let lock_key = format!("{TOKEN_REFRESH_LOCK_KEY_PREFIX}{account_id}");
     let lock_value = random_value()?;
@@ -53,12 +92,31 @@ pub(crate) async fn get(
         .await?;
 
     if acquired.is_some() {
-        let material =
-            load_from_auth(accounts_root, account_id, token_safety_window_seconds).await?;
-        put_cached(conn, account_id, &material, token_safety_window_seconds).await?;
+        metrics
+            .token_refresh_lock_acquired_total
+            .fetch_add(1, Ordering::Relaxed);
+        let material = load_from_auth(
+            accounts_root,
+            account_id,
+            token_safety_window_seconds,
+            clock,
+        )
+        .await?;
+        put_cached(
+            conn,
+            account_id,
+            &material,
+            token_safety_window_seconds,
+            max_token_cache_seconds,
+            clock,
+        )
+        .await?;
         return Ok(material);
     }
 
+    metrics
+        .token_refresh_wait_total
+        .fetch_add(1, Ordering::Relaxed);
     let deadline_ms = start_ms.saturating_add(REFRESH_LOCK_TTL_MS);
     loop {
         tokio::time::sleep(Duration::from_millis(
@@ -67,23 +125,51 @@ pub(crate) async fn get(
         .await;
 
         if let Some(material) = get_cached(conn, account_id).await?
-            && material.expires_at_ms.saturating_sub(now_ms()) > safety_ms
+            && outside_safety_window(
+                material.expires_at_ms,
+                clock.now_ms(),
+                token_safety_window_seconds,
+            )
         {
             return Ok(material);
         }
 
-        if now_ms() >= deadline_ms {
+        if clock.now_ms() >= deadline_ms {
             break;
         }
     }
 
-    let material = load_from_auth(accounts_root, account_id, token_safety_window_seconds).await?;
-    put_cached(conn, account_id, &material, token_safety_window_seconds).await?;
+    let material = load_from_auth(
+        accounts_root,
+        account_id,
+        token_safety_window_seconds,
+        clock,
+    )
+    .await?;
+    put_cached(
+        conn,
+        account_id,
+        &material,
+        token_safety_window_seconds,
+        max_token_cache_seconds,
+        clock,
+    )
+    .await?;
     Ok(material)
 }
 
+/// True while a token expiring at `expires_at_ms` still has more than the safety window left.
+fn outside_safety_window(
+    expires_at_ms: i64,
+    now_ms: i64,
+    token_safety_window_seconds: i64,
+) -> bool {
+    let safety_ms = token_safety_window_seconds.saturating_mul(1000);
+    expires_at_ms.saturating_sub(now_ms) > safety_ms
+}
+
 pub(crate) async fn invalidate_cached(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     account_id: &str,
 ) -> anyhow::Result<bool> {
     let key = format!("{TOKEN_CACHE_KEY_PREFIX}{account_id}");
@@ -92,7 +178,7 @@ pub(crate) async fn invalidate_cached(
 }
 
 async fn get_cached(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     account_id: &str,
 ) -> anyhow::Result<Option<AuthMaterial>> {
     let key = format!("{TOKEN_CACHE_KEY_PREFIX}{account_id}");
@@ -106,20 +192,26 @@ async fn get_cached(
 }
 
 async fn put_cached(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     account_id: &str,
     material: &AuthMaterial,
     token_safety_window_seconds: i64,
+    max_token_cache_seconds: i64,
+    clock: &dyn Clock,
 ) -> anyhow::Result<()> {
     let key = format!("{TOKEN_CACHE_KEY_PREFIX}{account_id}");
-    let now_ms = now_ms();
-    let ttl_seconds =
-        (material.expires_at_ms.saturating_sub(now_ms) / 1000) - token_safety_window_seconds;
-    if ttl_seconds <= 0 {
+    let now_ms = clock.now_ms();
+    let Some(ttl_seconds) = cache_ttl_seconds(
+        material.expires_at_ms,
+        now_ms,
+        token_safety_window_seconds,
+        max_token_cache_seconds,
+    ) else {
+        let expires_in_seconds = material.expires_at_ms.saturating_sub(now_ms) / 1000;
         anyhow::bail!(
-            "refusing to cache expired/near-expiry access token for account {account_id:?}"
+            "refusing to cache access token for account {account_id:?}: it expires in {expires_in_seconds}s, inside gateway.token_safety_window_seconds ({token_safety_window_seconds}s) even after a refresh; lower the window or check the host clock"
         );
-    }
+    };
     let value = serde_json::to_string(material).context("serializing AuthMaterial")?;
     let _: () = redis::cmd("SET")
         .arg(&key)
@@ -131,10 +223,52 @@ async fn put_cached(
     Ok(())
 }
 
-async fn load_from_auth(
+/// Returns the Redis TTL for a cached token, or `None` when the token is already inside the
+/// safety window. The TTL is clamped to `1..=max_token_cache_seconds` so sub-second remainders
+/// still cache briefly and bogus far-future `exp` claims cannot pin a token indefinitely.
+fn cache_ttl_seconds(
+    expires_at_ms: i64,
+    now_ms: i64,
+    token_safety_window_seconds: i64,
+    max_token_cache_seconds: i64,
+) -> Option<i64> {
+    let safety_ms = token_safety_window_seconds.max(0).saturating_mul(1000);
+    let usable_ms = expires_at_ms
+        .saturating_sub(now_ms)
+        .saturating_sub(safety_ms);
+    if usable_ms <= 0 {
+        return None;
+    }
+
+    let max_seconds = max_token_cache_seconds.max(1);
+    let ttl_seconds = usable_ms / 1000;
+    if ttl_seconds > max_seconds {
+        tracing::warn!(
+            expires_at_ms,
+            ttl_seconds,
+            max_token_cache_seconds = max_seconds,
+            "clamping account token cache ttl to max_token_cache_seconds"
+        );
+        return Some(max_seconds);
+    }
+    if ttl_seconds < 1 {
+        tracing::debug!(
+            expires_at_ms,
+            usable_ms,
+            "rounding sub-second account token cache ttl up to 1s"
+        );
+        return Some(1);
+    }
+    Some(ttl_seconds)
+}
+
+/// Reads the account's access token from `auth.json`, refreshing it first when it expires
+/// within the safety window.
+pub(crate) async fn load_from_auth(
     accounts_root: &Path,
     account_id: &str,
     token_safety_window_seconds: i64,
+    clock: &dyn Clock,
 ) -> anyhow::Result<AuthMaterial> {
     let account_home = accounts_root.join(account_id);
     let auth_manager = AuthManager::new(
@@ -152,9 +286,7 @@ async fn load_from_auth(
     let mut expires_at_ms = jwt_exp_ms(&token_data.access_token)
         .with_context(|| format!("parsing access token exp for account {account_id:?}"))?;
 
-    let safety_ms = token_safety_window_seconds.saturating_mul(1000);
-    let now_ms = now_ms();
-    if expires_at_ms.saturating_sub(now_ms) <= safety_ms {
+    if !outside_safety_window(expires_at_ms, clock.now_ms(), token_safety_window_seconds) {
         auth_manager
             .refresh_token()
             .await
@@ -178,7 +310,7 @@ async fn load_from_auth(
     })
 }
 
-fn jwt_exp_ms(jwt: &str) -> anyhow::Result<i64> {
+pub(crate) fn jwt_exp_ms(jwt: &str) -> anyhow::Result<i64> {
     #[derive(Deserialize)]
     struct Claims {
         exp: i64,
@@ -203,3 +335,92 @@ fn random_value() -> anyhow::Result<String> {
         .context("generating random bytes")?;
     Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MockClock;
+    use pretty_assertions::assert_eq;
+
+    const NOW_MS: i64 = 1_700_000_000_000;
+
+    #[test]
+    fn cache_ttl_refuses_tokens_exactly_at_safety_window() {
+        let expires_at_ms = NOW_MS + 120_000;
+        assert_eq!(cache_ttl_seconds(expires_at_ms, NOW_MS, 120, 3600), None);
+        assert_eq!(cache_ttl_seconds(NOW_MS - 1, NOW_MS, 0, 3600), None);
+    }
+
+    #[test]
+    fn cache_ttl_rounds_near_expiry_tokens_up_to_one_second() {
+        let expires_at_ms = NOW_MS + 120_000 + 400;
+        assert_eq!(cache_ttl_seconds(expires_at_ms, NOW_MS, 120, 3600), Some(1));
+    }
+
+    #[test]
+    fn cache_ttl_clamps_far_future_tokens() {
+        assert_eq!(cache_ttl_seconds(i64::MAX, NOW_MS, 120, 3600), Some(3600));
+        assert_eq!(
+            cache_ttl_seconds(NOW_MS + 600_000, NOW_MS, 120, 3600),
+            Some(480)
+        );
+    }
+
+    #[test]
+    fn safety_window_boundary_follows_the_clock() {
+        let clock = MockClock::new(NOW_MS);
+        let expires_at_ms = NOW_MS + 150_000;
+        assert!(outside_safety_window(expires_at_ms, clock.now_ms(), 120));
+
+        clock.advance_ms(29_999);
+        assert!(outside_safety_window(expires_at_ms, clock.now_ms(), 120));
+
+        clock.advance_ms(1);
+        assert!(!outside_safety_window(expires_at_ms, clock.now_ms(), 120));
+    }
+
+    #[test]
+    fn prewarm_delay_staggers_after_the_first_account_within_jitter() {
+        let fixed = PrewarmSchedule {
+            stagger_ms: 250,
+            jitter_ms: 0,
+        };
+        assert_eq!(fixed.delay(0), Duration::ZERO);
+        assert_eq!(fixed.delay(3), Duration::from_millis(250));
+
+        let jittered = PrewarmSchedule {
+            stagger_ms: 250,
+            jitter_ms: 100,
+        };
+        for _ in 0..32 {
+            assert!(jittered.delay(0) <= Duration::from_millis(100));
+            let delay = jittered.delay(1);
+            assert!(delay >= Duration::from_millis(250) && delay <= Duration::from_millis(350));
+        }
+    }
+
+    #[tokio::test]
+    async fn load_from_auth_skips_refresh_outside_safety_window() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let home = temp.path().join("a");
+        std::fs::create_dir_all(&home).expect("create home");
+        // Payload is {"exp":1700000300}, i.e. 300s after NOW_MS.
+        let access_token = "e30.eyJleHAiOjE3MDAwMDAzMDB9.sig";
+        let auth = serde_json::json!({
+            "OPENAI_API_KEY": null,
+            "tokens": {
+                "id_token": "e30.e30.sig",
+                "access_token": access_token,
+                "refresh_token": "rt",
+            },
+        });
+        std::fs::write(home.join("auth.json"), auth.to_string()).expect("write auth.json");
+
+        let clock = MockClock::new(NOW_MS);
+        let material = load_from_auth(temp.path(), "a", 120, &clock)
+            .await
+            .expect("load without refresh");
+        assert_eq!(material.authorization, format!("Bearer {access_token}"));
+        assert_eq!(material.expires_at_ms, NOW_MS + 300_000);
+    }
+}
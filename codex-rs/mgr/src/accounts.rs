@@ -1,22 +1,66 @@
 use anyhow::Context;
+use codex_login::AuthCredentialsStoreMode;
 use codex_login::AuthDotJson;
+use codex_login::AuthManager;
+use serde::Deserialize;
 use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
+use std::time::Duration;
 
 use crate::account_token_provider;
+use crate::aliases;
 use crate::config;
+use crate::exit_code::ErrorCategory;
 use crate::label::validate_label;
+use crate::layout::ProjectTrust;
+use crate::layout::create_account_home;
 use crate::layout::ensure_shared_config;
 use crate::layout::ensure_shared_layout;
+use crate::layout::purge_account_home;
+use crate::layout::restrict_account_permissions;
+use crate::layout::write_auth_file;
 use crate::redis_conn;
+use crate::state::AuthIndexEntry;
+use crate::state::CachedUsage;
+use crate::state::UsageSnapshot;
+use crate::state::WindowSnapshot;
 use crate::state::load_state;
-use crate::state::save_state;
+use crate::state::update_state;
 use crate::time::now_ms;
 use crate::upstream;
 use crate::usage;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ListSortKey {
+    /// Weekly remaining percent, most exhausted first.
+    Weekly,
+    /// Five-hour remaining percent, most exhausted first.
+    #[value(name = "5h")]
+    FiveHour,
+    /// Usage snapshot age, freshest first.
+    Age,
+    /// Account label.
+    Label,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ListOptions {
+    pub(crate) json: bool,
+    /// Emit one JSON object per account as soon as it is resolved, unsorted.
+    pub(crate) ndjson: bool,
+    pub(crate) sort: ListSortKey,
+    pub(crate) reverse: bool,
+    /// Only list accounts carrying this tag.
+    pub(crate) tag: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct AccountsListRow {
     label: String,
@@ -26,6 +70,32 @@ struct AccountsListRow {
     weekly_remaining_percent: Option<f64>,
     snapshot_age_seconds: Option<i64>,
     status: String,
+    reserved: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct WhoamiOut {
+    label: String,
+    email: Option<String>,
+    chatgpt_account_id: Option<String>,
+    chatgpt_user_id: Option<String>,
+    plan_type: Option<String>,
+    access_token_expires_at_ms: Option<i64>,
+    access_token_expires_in_seconds: Option<i64>,
+    last_refresh: Option<String>,
+}
+
+pub(crate) struct LoginOptions {
+    /// Pass `--device-auth` to the upstream `codex login`.
+    pub(crate) device_auth: bool,
+    /// Replace an existing account home with the same label.
+    pub(crate) force: bool,
+    /// How `ensure_shared_config` registers the current directory.
+    pub(crate) trust: ProjectTrust,
 }
 
 pub(crate) async fn login(
@@ -33,11 +103,17 @@ pub(crate) async fn login(
     shared_root: &Path,
     accounts_root: &Path,
     state_root: &Path,
+    config_path: &Path,
     label: String,
-    device_auth: bool,
-    force: bool,
+    options: LoginOptions,
 ) -> anyhow::Result<()> {
+    let LoginOptions {
+        device_auth,
+        force,
+        trust,
+    } = options;
     validate_label(&label)?;
+    aliases::ensure_not_alias(state_root, &label)?;
     let account_home = accounts_root.join(&label);
     if account_home.exists() && !force {
         anyhow::bail!("label {label} already exists");
@@ -58,13 +134,14 @@ pub(crate) async fn login(
         std::fs::remove_dir_all(&account_home)
             .with_context(|| format!("removing account home {account_home:?}"))?;
 
-        if let Ok(mut state) = load_state(state_root) {
+        let _ = update_state(state_root, |state| {
             state.usage_cache.remove(&label);
-            let _ = save_state(state_root, &state);
-        }
+            state.auth_index.remove(&label);
+            Ok(())
+        });
     }
-    std::fs::create_dir_all(&account_home).context("create account home")?;
-    ensure_shared_config(shared_root).context("ensure shared config")?;
+    create_account_home(&account_home)?;
+    ensure_shared_config(shared_root, trust).context("ensure shared config")?;
     ensure_shared_layout(&account_home, shared_root).context("ensure shared layout")?;
 
     let codex = upstream::resolve_codex_binary(codex_path);
@@ -90,11 +167,15 @@ pub(crate) async fn login(
         .as_ref()
         .is_some_and(|t| !t.refresh_token.trim().is_empty());
     if !refresh_ok {
-        anyhow::bail!("login completed but auth.json is missing refresh_token for label {label}");
+        return Err(ErrorCategory::Auth.wrap(anyhow::anyhow!(
+            "login completed but auth.json is missing refresh_token for label {label}"
+        )));
     }
+    // Upstream writes auth.json with its own umask; tighten it before anything reads it.
+    restrict_account_permissions(&account_home)?;
 
-    if let Ok(cfg) = config::load(state_root) {
-        match redis_conn::connect(&cfg.gateway.redis_url).await {
+    if let Ok(cfg) = config::load(config_path) {
+        match redis_conn::connect(&cfg.gateway).await {
             Ok(mut conn) => {
                 if let Err(err) = account_token_provider::invalidate_cached(&mut conn, &label).await
                 {
@@ -122,65 +203,101 @@ pub(crate) async fn login(
     Ok(())
 }
 
-pub(crate) async fn list(
+/// Imports the `auth.json` of an existing `CODEX_HOME` as a new account. Only auth is copied;
+/// the new home links shared state into `shared_root` like any other account.
+pub(crate) fn add(
+    shared_root: &Path,
     accounts_root: &Path,
     state_root: &Path,
-    json: bool,
+    label: String,
+    from: &Path,
+    trust: ProjectTrust,
 ) -> anyhow::Result<()> {
-    let now_ms = now_ms();
-    let state = load_state(state_root).unwrap_or_default();
+    validate_label(&label)?;
+    aliases::ensure_not_alias(state_root, &label)?;
+    let account_home = accounts_root.join(&label);
+    if account_home.exists() {
+        anyhow::bail!("label {label} already exists");
+    }
 
-    let mut rows = Vec::new();
-    for label in list_labels(accounts_root)? {
-        let account_home = accounts_root.join(&label);
-        let auth_path = account_home.join("auth.json");
+    let source_auth = from.join("auth.json");
+    let auth_contents = std::fs::read_to_string(&source_auth)
+        .with_context(|| format!("reading {source_auth:?}"))?;
+    let parsed: AuthDotJson =
+        serde_json::from_str(&auth_contents).with_context(|| format!("parsing {source_auth:?}"))?;
+    let refresh_ok = parsed
+        .tokens
+        .as_ref()
+        .is_some_and(|t| !t.refresh_token.trim().is_empty());
+    if !refresh_ok {
+        return Err(ErrorCategory::Auth.wrap(anyhow::anyhow!(
+            "{source_auth:?} is missing refresh_token; log in with `codex login` there first"
+        )));
+    }
+    if let (Ok(from), Ok(accounts_root)) = (from.canonicalize(), accounts_root.canonicalize())
+        && from.parent() == Some(accounts_root.as_path())
+    {
+        anyhow::bail!("{from:?} is already a codex-mgr account home");
+    }
 
-        let (email, workspace_id, auth_present) = match read_auth_dot_json(&auth_path) {
-            Ok(Some(auth)) => {
-                let info = auth
-                    .tokens
-                    .as_ref()
-                    .map(|t| (&t.id_token.email, &t.id_token.chatgpt_account_id));
-                let (email, workspace_id) = match info {
-                    Some((email, workspace_id)) => (email.clone(), workspace_id.clone()),
-                    None => (None, None),
-                };
-                (email, workspace_id, true)
-            }
-            Ok(None) => (None, None, false),
-            Err(_) => (None, None, true),
-        };
+    create_account_home(&account_home)?;
+    if let Err(err) = import_auth(shared_root, &account_home, &auth_contents, trust) {
+        let _ = std::fs::remove_dir_all(&account_home);
+        return Err(err);
+    }
 
-        let cached = state.usage_cache.get(&label);
-        let snapshot_age_seconds = cached.map(|c| (now_ms - c.captured_at_ms) / 1000);
+    println!("Imported {label} from {from:?}");
+    println!(
+        "note: {from:?} and {label} now share a refresh token; once either one refreshes, the other may need to log in again"
+    );
+    Ok(())
+}
 
-        let five_hour_remaining_percent =
-            cached.and_then(|c| c.snapshot.five_hour.as_ref().map(|w| w.remaining_percent));
-        let weekly_remaining_percent =
-            cached.and_then(|c| c.snapshot.weekly.as_ref().map(|w| w.remaining_percent));
+fn import_auth(
+    shared_root: &Path,
+    account_home: &Path,
+    auth_contents: &str,
+    trust: ProjectTrust,
+) -> anyhow::Result<()> {
+    write_auth_file(account_home, auth_contents)?;
+    ensure_shared_config(shared_root, trust).context("ensure shared config")?;
+    ensure_shared_layout(account_home, shared_root).context("ensure shared layout")
+}
 
-        let status = if !auth_present {
-            "auth_missing".to_string()
-        } else if cached.is_none() {
-            "usage_unknown".to_string()
-        } else if snapshot_age_seconds.is_some_and(|age| age > usage::USAGE_CACHE_TTL_SECONDS) {
-            "stale".to_string()
-        } else {
-            "ok".to_string()
-        };
+/// Five-hour and weekly remaining percent as selection scores them, so raw snapshots
+/// (`rate_limit_windows = "raw"`) show their primary and secondary windows.
+fn remaining_percents(snapshot: &UsageSnapshot) -> (Option<f64>, Option<f64>) {
+    let Some(score) = usage::usage_score(snapshot) else {
+        return (None, None);
+    };
+    (
+        score.five_present.then_some(score.five_remaining),
+        score.weekly_present.then_some(score.weekly_remaining),
+    )
+}
 
-        rows.push(AccountsListRow {
-            label,
-            email,
-            workspace_id,
-            five_hour_remaining_percent,
-            weekly_remaining_percent,
-            snapshot_age_seconds,
-            status,
+pub(crate) async fn list(
+    accounts_root: &Path,
+    state_root: &Path,
+    config_path: &Path,
+    options: ListOptions,
+) -> anyhow::Result<()> {
+    let tag = options.tag.as_deref();
+    if options.ndjson {
+        let mut stdout = std::io::stdout().lock();
+        return for_each_row(accounts_root, state_root, config_path, tag, |row| {
+            write_ndjson_row(&mut stdout, &row)
         });
     }
 
-    if json {
+    let mut rows = Vec::new();
+    for_each_row(accounts_root, state_root, config_path, tag, |row| {
+        rows.push(row);
+        Ok(())
+    })?;
+    sort_rows(&mut rows, options.sort, options.reverse);
+
+    if options.json {
         let out = serde_json::to_string_pretty(&rows)?;
         println!("{out}");
         return Ok(());
@@ -188,21 +305,26 @@ pub(crate) async fn list(
 
     let mut label_w = "label".len();
     let mut email_w = "email".len();
+    let mut tags_w = "tags".len();
     for row in &rows {
         label_w = label_w.max(row.label.len());
         email_w = email_w.max(row.email.as_deref().unwrap_or("unknown").len());
+        tags_w = tags_w.max(row.tags.join(",").len());
     }
 
     println!(
-        "{:<12} {:<label_w$} {:<email_w$} {:>8} {:>8} {:>6}",
+        "{:<12} {:<label_w$} {:<email_w$} {:>8} {:>8} {:>6} {:>8} {:<tags_w$} aliases",
         "status",
         "label",
         "email",
         "weekly",
         "5h",
         "age",
+        "reserved",
+        "tags",
         label_w = label_w,
-        email_w = email_w
+        email_w = email_w,
+        tags_w = tags_w
     );
 
     for row in rows {
@@ -220,31 +342,290 @@ pub(crate) async fn list(
             .map(|s| s.to_string())
             .unwrap_or_else(|| "-".to_string());
 
+        let reserved = if row.reserved { "yes" } else { "-" };
+        let tags = if row.tags.is_empty() {
+            "-".to_string()
+        } else {
+            row.tags.join(",")
+        };
+        let aliases = if row.aliases.is_empty() {
+            "-".to_string()
+        } else {
+            row.aliases.join(",")
+        };
+
         println!(
-            "{:<12} {:<label_w$} {:<email_w$} {:>8} {:>8} {:>6}",
+            "{:<12} {:<label_w$} {:<email_w$} {:>8} {:>8} {:>6} {:>8} {tags:<tags_w$} {aliases}",
             row.status,
             row.label,
             email,
             weekly,
             five,
             age,
+            reserved,
             label_w = label_w,
-            email_w = email_w
+            email_w = email_w,
+            tags_w = tags_w
         );
     }
 
     Ok(())
 }
 
+/// Builds the `list` row for each account in label order and hands it to `emit` before
+/// resolving the next one, so `--ndjson` output goes out as rows are produced.
+fn for_each_row(
+    accounts_root: &Path,
+    state_root: &Path,
+    config_path: &Path,
+    tag: Option<&str>,
+    mut emit: impl FnMut(AccountsListRow) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let now_ms = now_ms();
+    let cache_ttl_seconds = config::usage_cache_ttl_ms(config_path)? / 1000;
+    let mut state = load_state(state_root).unwrap_or_default();
+    let mut auth_index_changed = false;
+    let mut aliases_by_label = aliases::by_label(&state.aliases);
+
+    for label in list_labels(accounts_root)? {
+        let tags: Vec<String> = state
+            .tags
+            .get(&label)
+            .map(|tags| tags.iter().cloned().collect())
+            .unwrap_or_default();
+        if let Some(tag) = tag
+            && !tags.iter().any(|t| t == tag)
+        {
+            continue;
+        }
+        let account_home = accounts_root.join(&label);
+        let auth_path = account_home.join("auth.json");
+
+        let (email, workspace_id, auth_present) =
+            match indexed_identity(&mut state.auth_index, &label, &auth_path) {
+                Ok(Some((entry, changed))) => {
+                    auth_index_changed |= changed;
+                    (entry.email, entry.chatgpt_account_id, true)
+                }
+                Ok(None) => {
+                    auth_index_changed |= state.auth_index.remove(&label).is_some();
+                    (None, None, false)
+                }
+                Err(_) => (None, None, true),
+            };
+
+        let cached = state.usage_cache.get(&label);
+        let snapshot_age_seconds = cached.map(|c| (now_ms - c.captured_at_ms) / 1000);
+
+        let (five_hour_remaining_percent, weekly_remaining_percent) = cached
+            .map(|c| remaining_percents(&c.snapshot))
+            .unwrap_or_default();
+
+        let status = if !auth_present {
+            "auth_missing".to_string()
+        } else if cached.is_none() {
+            "usage_unknown".to_string()
+        } else if snapshot_age_seconds.is_some_and(|age| age > cache_ttl_seconds) {
+            "stale".to_string()
+        } else {
+            "ok".to_string()
+        };
+
+        let reserved = state.reserved.contains(&label);
+        let row = AccountsListRow {
+            label,
+            email,
+            workspace_id,
+            five_hour_remaining_percent,
+            weekly_remaining_percent,
+            snapshot_age_seconds,
+            status,
+            reserved,
+            tags,
+            aliases: aliases_by_label.remove(&label).unwrap_or_default(),
+        };
+        emit(row)?;
+    }
+
+    // The index is a cache, so this run's view replaces it wholesale; everything else in the
+    // file is re-read under the lock and kept as is.
+    if auth_index_changed
+        && let Err(err) = update_state(state_root, |fresh| {
+            fresh.auth_index = state.auth_index;
+            Ok(())
+        })
+    {
+        tracing::warn!(error = %err, "failed to persist auth index");
+    }
+    Ok(())
+}
+
+fn write_ndjson_row(out: &mut impl Write, row: &AccountsListRow) -> anyhow::Result<()> {
+    writeln!(out, "{}", serde_json::to_string(row)?)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Redraws the `list` table every `interval_seconds` until Ctrl-C, refreshing usage first.
+///
+/// Usage fetches honor the cache TTL, so short intervals only re-fetch expired snapshots. When
+/// stdout is not a terminal the table is printed once instead.
+pub(crate) async fn watch(
+    shared_root: &Path,
+    accounts_root: &Path,
+    state_root: &Path,
+    config_path: &Path,
+    options: ListOptions,
+    interval_seconds: u64,
+) -> anyhow::Result<()> {
+    if !std::io::stdout().is_terminal() {
+        tracing::warn!("stdout is not a terminal; printing accounts once instead of watching");
+        return list(accounts_root, state_root, config_path, options.clone()).await;
+    }
+
+    let interval = std::time::Duration::from_secs(interval_seconds.max(1));
+    loop {
+        if let Err(err) = usage::scan_and_update_usage(
+            shared_root,
+            accounts_root,
+            state_root,
+            config_path,
+            usage::ScanOptions::default(),
+        )
+        .await
+        {
+            tracing::warn!(error = %err, "failed to refresh usage");
+        }
+
+        // Clear the screen and home the cursor before redrawing.
+        print!("\x1b[2J\x1b[H");
+        list(accounts_root, state_root, config_path, options.clone()).await?;
+        println!();
+        println!("refreshing every {}s; Ctrl-C to exit", interval.as_secs());
+        std::io::stdout().flush()?;
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            () = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+/// Sorts rows by `key`; rows missing the sort value go last and ties keep label order.
+fn sort_rows(rows: &mut [AccountsListRow], key: ListSortKey, reverse: bool) {
+    fn missing_last<T>(a: Option<T>, b: Option<T>, cmp: impl Fn(T, T) -> Ordering) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => cmp(a, b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    rows.sort_by(|a, b| {
+        let ordering = match key {
+            ListSortKey::Weekly => missing_last(
+                a.weekly_remaining_percent,
+                b.weekly_remaining_percent,
+                |a, b| a.total_cmp(&b),
+            ),
+            ListSortKey::FiveHour => missing_last(
+                a.five_hour_remaining_percent,
+                b.five_hour_remaining_percent,
+                |a, b| a.total_cmp(&b),
+            ),
+            ListSortKey::Age => {
+                missing_last(a.snapshot_age_seconds, b.snapshot_age_seconds, |a, b| {
+                    a.cmp(&b)
+                })
+            }
+            ListSortKey::Label => Ordering::Equal,
+        };
+        ordering.then_with(|| a.label.cmp(&b.label))
+    });
+    if reverse {
+        rows.reverse();
+    }
+}
+
+pub(crate) async fn whoami(accounts_root: &Path, label: String, json: bool) -> anyhow::Result<()> {
+    validate_label(&label)?;
+    let auth_path = accounts_root.join(&label).join("auth.json");
+    let auth = read_auth_dot_json(&auth_path)
+        .with_context(|| format!("reading {auth_path:?}"))?
+        .with_context(|| format!("label {label} does not exist or is not logged in"))
+        .map_err(|err| ErrorCategory::Auth.wrap(err))?;
+    let tokens = auth
+        .tokens
+        .as_ref()
+        .with_context(|| format!("auth.json for label {label} has no ChatGPT tokens"))
+        .map_err(|err| ErrorCategory::Auth.wrap(err))?;
+
+    let access_token_expires_at_ms = account_token_provider::jwt_exp_ms(&tokens.access_token).ok();
+    let out = WhoamiOut {
+        label,
+        email: tokens.id_token.email.clone(),
+        chatgpt_account_id: tokens.id_token.chatgpt_account_id.clone(),
+        chatgpt_user_id: tokens.id_token.chatgpt_user_id.clone(),
+        plan_type: tokens.id_token.get_chatgpt_plan_type(),
+        access_token_expires_at_ms,
+        access_token_expires_in_seconds: access_token_expires_at_ms
+            .map(|expires_at_ms| expires_at_ms.saturating_sub(now_ms()) / 1000),
+        last_refresh: auth.last_refresh.map(|t| t.to_rfc3339()),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    let expires = match out.access_token_expires_in_seconds {
+        Some(seconds) if seconds <= 0 => "expired".to_string(),
+        Some(seconds) => format!("in {seconds}s"),
+        None => "unknown".to_string(),
+    };
+    println!("label: {}", out.label);
+    println!("email: {}", out.email.as_deref().unwrap_or("unknown"));
+    println!(
+        "chatgpt_account_id: {}",
+        out.chatgpt_account_id.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "chatgpt_user_id: {}",
+        out.chatgpt_user_id.as_deref().unwrap_or("unknown")
+    );
+    println!("plan: {}", out.plan_type.as_deref().unwrap_or("unknown"));
+    println!("access_token_expires: {expires}");
+    println!(
+        "last_refresh: {}",
+        out.last_refresh.as_deref().unwrap_or("unknown")
+    );
+    Ok(())
+}
+
+/// What `accounts del` removes besides the account's cached state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DeleteScope {
+    /// Only `auth.json`; the rest of the account home stays.
+    Credentials,
+    /// The whole account home (`--purge`).
+    Home,
+}
+
+/// Deletes `label`'s credentials and state. The account home itself is only removed with
+/// [`DeleteScope::Home`].
 pub(crate) async fn del(
+    shared_root: &Path,
     accounts_root: &Path,
     state_root: &Path,
+    config_path: &Path,
     label: String,
+    scope: DeleteScope,
 ) -> anyhow::Result<()> {
     validate_label(&label)?;
 
     // Safety check: ensure account is not in any pool
-    if let Ok(root) = config::load_value_optional(state_root)
+    if let Ok(root) = config::load_value_optional(config_path)
         && let Ok(pools) = config::extract_pools(&root)
     {
         let mut in_pools = Vec::new();
@@ -277,19 +658,334 @@ pub(crate) async fn del(
         anyhow::bail!("refusing to delete non-directory account home {account_home:?}");
     }
 
-    let auth_path = account_home.join("auth.json");
-    let _ = std::fs::remove_file(&auth_path);
-    std::fs::remove_dir_all(&account_home)
-        .with_context(|| format!("removing account home {account_home:?}"))?;
+    match scope {
+        DeleteScope::Home => purge_account_home(&account_home, shared_root)?,
+        DeleteScope::Credentials => {
+            let auth_path = account_home.join("auth.json");
+            match std::fs::remove_file(&auth_path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).with_context(|| format!("removing {auth_path:?}")),
+            }
+        }
+    }
 
-    if let Ok(mut state) = load_state(state_root) {
+    let _ = update_state(state_root, |state| {
         state.usage_cache.remove(&label);
-        let _ = save_state(state_root, &state);
+        state.auth_index.remove(&label);
+        state.reserved.remove(&label);
+        state.tags.remove(&label);
+        state.aliases.retain(|_, target| *target != label);
+        Ok(())
+    });
+
+    Ok(())
+}
+
+/// Marks `label` as reserved (or releases it). Reserved accounts are skipped by `run --auto`
+/// and by the gateway's default pool, but still serve `--label` and pools that list them.
+pub(crate) fn set_reserved(
+    accounts_root: &Path,
+    state_root: &Path,
+    label: String,
+    reserved: bool,
+) -> anyhow::Result<()> {
+    validate_label(&label)?;
+    if !accounts_root.join(&label).is_dir() {
+        anyhow::bail!("label {label} does not exist");
+    }
+
+    update_state(state_root, |state| {
+        if reserved {
+            state.reserved.insert(label.clone());
+        } else {
+            state.reserved.remove(&label);
+        }
+        Ok(())
+    })?;
+    if reserved {
+        println!("{label:?} is reserved; it is only used when selected explicitly");
+    } else {
+        println!("{label:?} is available for automatic selection");
+    }
+    Ok(())
+}
+
+/// Replaces `label`'s tags; an empty list clears them.
+pub(crate) fn set_tags(
+    accounts_root: &Path,
+    state_root: &Path,
+    label: String,
+    tags: Vec<String>,
+) -> anyhow::Result<()> {
+    validate_label(&label)?;
+    if !accounts_root.join(&label).is_dir() {
+        anyhow::bail!("label {label} does not exist");
+    }
+    for tag in &tags {
+        validate_tag(tag)?;
     }
 
+    let tags: BTreeSet<String> = tags.into_iter().collect();
+    let summary = tags.iter().cloned().collect::<Vec<_>>().join(", ");
+    update_state(state_root, |state| {
+        if tags.is_empty() {
+            state.tags.remove(&label);
+        } else {
+            state.tags.insert(label.clone(), tags);
+        }
+        Ok(())
+    })?;
+    if summary.is_empty() {
+        println!("{label:?} has no tags");
+    } else {
+        println!("{label:?} tagged {summary}");
+    }
+    Ok(())
+}
+
+/// Remaining percentages to record for one account, as `accounts set-usage` flags or one entry of
+/// its `--from-file` JSON object (`{"<label>": {"five_hour": 80, "weekly": 40}}`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct UsageSeed {
+    pub(crate) five_hour: Option<f64>,
+    pub(crate) weekly: Option<f64>,
+}
+
+pub(crate) enum UsageSeedSource {
+    Label { label: String, seed: UsageSeed },
+    File(PathBuf),
+}
+
+/// Writes synthetic usage into the usage cache, captured now, so selection can be exercised
+/// without reaching the backend. The entries are treated like any other cached fetch and are
+/// replaced by the next real fetch once they go stale.
+pub(crate) fn set_usage(
+    accounts_root: &Path,
+    state_root: &Path,
+    source: UsageSeedSource,
+) -> anyhow::Result<()> {
+    let seeds: BTreeMap<String, UsageSeed> = match source {
+        UsageSeedSource::Label { label, seed } => BTreeMap::from([(label, seed)]),
+        UsageSeedSource::File(path) => {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("reading usage seed file {path:?}"))?;
+            serde_json::from_str(&text)
+                .with_context(|| format!("parsing usage seed file {path:?}"))?
+        }
+    };
+    if seeds.is_empty() {
+        anyhow::bail!("no usage entries to write");
+    }
+
+    let captured_at_ms = now_ms();
+    let mut entries = Vec::with_capacity(seeds.len());
+    for (label, seed) in seeds {
+        validate_label(&label)?;
+        if !accounts_root.join(&label).is_dir() {
+            anyhow::bail!("label {label} does not exist");
+        }
+        let snapshot = seeded_snapshot(&label, seed)?;
+        entries.push((
+            label,
+            CachedUsage {
+                captured_at_ms,
+                snapshot,
+            },
+        ));
+    }
+
+    for (label, cached) in &entries {
+        println!(
+            "{label:?} usage set (5h {}, weekly {})",
+            format_seed_percent(cached.snapshot.five_hour.as_ref()),
+            format_seed_percent(cached.snapshot.weekly.as_ref())
+        );
+    }
+    update_state(state_root, |state| {
+        state.usage_cache.extend(entries);
+        Ok(())
+    })
+}
+
+fn seeded_snapshot(label: &str, seed: UsageSeed) -> anyhow::Result<UsageSnapshot> {
+    if seed.five_hour.is_none() && seed.weekly.is_none() {
+        anyhow::bail!("{label}: set at least one of the five-hour or weekly remaining percent");
+    }
+    let window = |remaining: Option<f64>, name: &str, window_minutes: i64| {
+        remaining
+            .map(|remaining| {
+                if !(0.0..=100.0).contains(&remaining) {
+                    anyhow::bail!(
+                        "{label}: {name} remaining percent must be 0-100 (got {remaining})"
+                    );
+                }
+                Ok(WindowSnapshot {
+                    used_percent: 100.0 - remaining,
+                    remaining_percent: remaining,
+                    window_minutes: Some(window_minutes),
+                    resets_at: None,
+                })
+            })
+            .transpose()
+    };
+    Ok(UsageSnapshot {
+        five_hour: window(seed.five_hour, "five-hour", 300)?,
+        weekly: window(seed.weekly, "weekly", 10_080)?,
+        primary: None,
+        secondary: None,
+    })
+}
+
+fn format_seed_percent(window: Option<&WindowSnapshot>) -> String {
+    window.map_or_else(
+        || "-".to_string(),
+        |window| format!("{}% left", window.remaining_percent),
+    )
+}
+
+fn validate_tag(tag: &str) -> anyhow::Result<()> {
+    if !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Ok(());
+    }
+    anyhow::bail!("invalid tag {tag:?}; use only ASCII letters/numbers plus '-', '_' or '.'");
+}
+
+/// Existing account labels carrying `tag`, sorted.
+pub(crate) fn labels_with_tag(
+    accounts_root: &Path,
+    state_root: &Path,
+    tag: &str,
+) -> anyhow::Result<Vec<String>> {
+    let tags = load_state(state_root)?.tags;
+    Ok(list_labels(accounts_root)?
+        .into_iter()
+        .filter(|label| tags.get(label).is_some_and(|t| t.contains(tag)))
+        .collect())
+}
+
+/// Which accounts `accounts refresh` should touch.
+pub(crate) enum RefreshTarget {
+    Labels(Vec<String>),
+    All,
+    Tag(String),
+}
+
+/// Default `accounts refresh --min-remaining`: bulk refreshes leave tokens valid this long alone.
+pub(crate) const DEFAULT_REFRESH_MIN_REMAINING_SECS: u64 = 60 * 60;
+
+pub(crate) struct RefreshOptions {
+    /// For `All`/`Tag` targets, skip accounts whose access token outlives this. Tokens with an
+    /// unreadable expiry are always refreshed.
+    pub(crate) min_remaining: Duration,
+    /// Refresh every selected account regardless of `min_remaining`.
+    pub(crate) force: bool,
+}
+
+/// Refreshes each selected account's access token, reporting every failure before returning
+/// an error so one dead account does not hide the rest.
+pub(crate) async fn refresh(
+    accounts_root: &Path,
+    state_root: &Path,
+    target: RefreshTarget,
+    options: RefreshOptions,
+) -> anyhow::Result<()> {
+    // Explicitly named labels are always refreshed; only bulk targets skip still-valid tokens.
+    let skip_fresh = !options.force && !matches!(target, RefreshTarget::Labels(_));
+    let min_remaining_ms = i64::try_from(options.min_remaining.as_millis()).unwrap_or(i64::MAX);
+    let labels = match target {
+        RefreshTarget::Labels(labels) => {
+            for label in &labels {
+                validate_label(label)?;
+                if !accounts_root.join(label).is_dir() {
+                    anyhow::bail!("label {label} does not exist");
+                }
+            }
+            labels
+        }
+        RefreshTarget::All => list_labels(accounts_root)?,
+        RefreshTarget::Tag(tag) => {
+            let labels = labels_with_tag(accounts_root, state_root, &tag)?;
+            if labels.is_empty() {
+                anyhow::bail!("no accounts tagged {tag:?}");
+            }
+            labels
+        }
+    };
+
+    let mut failed = Vec::new();
+    let mut refreshed = 0usize;
+    let mut skipped = 0usize;
+    for label in &labels {
+        let account_home = accounts_root.join(label);
+        if skip_fresh {
+            let expires_at_ms = access_token_expires_at_ms(&account_home.join("auth.json"));
+            if let Some(remaining_ms) = skip_refresh_for(expires_at_ms, now_ms(), min_remaining_ms)
+            {
+                println!(
+                    "{label}: skipped (access token valid for {}m)",
+                    remaining_ms / 60_000
+                );
+                skipped += 1;
+                continue;
+            }
+        }
+        let auth_manager = AuthManager::new(account_home, false, AuthCredentialsStoreMode::File);
+        match auth_manager.refresh_token().await {
+            Ok(()) => {
+                println!("{label}: refreshed");
+                refreshed += 1;
+            }
+            Err(err) => {
+                println!("{label}: FAIL ({err})");
+                failed.push(label.clone());
+            }
+        }
+    }
+    println!(
+        "refreshed {refreshed}, skipped {skipped}, failed {}",
+        failed.len()
+    );
+
+    if !failed.is_empty() {
+        return Err(ErrorCategory::Auth.wrap(anyhow::anyhow!(
+            "token refresh failed for: {}",
+            failed.join(", ")
+        )));
+    }
     Ok(())
 }
 
+/// Expiry of the access token in `auth_path`, or `None` when it cannot be read.
+fn access_token_expires_at_ms(auth_path: &Path) -> Option<i64> {
+    let tokens = read_auth_dot_json(auth_path).ok()??.tokens?;
+    account_token_provider::jwt_exp_ms(&tokens.access_token).ok()
+}
+
+/// Returns the remaining token lifetime when it exceeds `min_remaining_ms`, meaning the refresh
+/// can be skipped. An unknown expiry never skips.
+fn skip_refresh_for(expires_at_ms: Option<i64>, now_ms: i64, min_remaining_ms: i64) -> Option<i64> {
+    let remaining_ms = expires_at_ms?.saturating_sub(now_ms);
+    (remaining_ms > min_remaining_ms).then_some(remaining_ms)
+}
+
+/// Labels eligible for automatic selection: every account except the reserved ones.
+pub(crate) fn list_unreserved_labels(
+    accounts_root: &Path,
+    state_root: &Path,
+) -> anyhow::Result<Vec<String>> {
+    let reserved = load_state(state_root)?.reserved;
+    Ok(list_labels(accounts_root)?
+        .into_iter()
+        .filter(|label| !reserved.contains(label))
+        .collect())
+}
+
 pub(crate) fn list_labels(accounts_root: &Path) -> anyhow::Result<Vec<String>> {
     let mut labels = Vec::new();
     for entry in std::fs::read_dir(accounts_root).context("read accounts_root")? {
@@ -305,6 +1001,51 @@ pub(crate) fn list_labels(accounts_root: &Path) -> anyhow::Result<Vec<String>> {
     Ok(labels)
 }
 
+/// Returns the identity for `label` from `auth_index`, re-parsing `auth.json` only when its
+/// mtime or size changed. The flag is true when the index was updated. `None` means the file is
+/// missing.
+fn indexed_identity(
+    auth_index: &mut BTreeMap<String, AuthIndexEntry>,
+    label: &str,
+    auth_path: &Path,
+) -> anyhow::Result<Option<(AuthIndexEntry, bool)>> {
+    let metadata = match std::fs::metadata(auth_path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let auth_mtime_ms = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|since_epoch| i64::try_from(since_epoch.as_millis()).ok())
+        .unwrap_or(0);
+    let auth_len = metadata.len();
+
+    if let Some(entry) = auth_index.get(label)
+        && entry.auth_mtime_ms == auth_mtime_ms
+        && entry.auth_len == auth_len
+    {
+        return Ok(Some((entry.clone(), false)));
+    }
+
+    let Some(auth) = read_auth_dot_json(auth_path)? else {
+        return Ok(None);
+    };
+    let (email, chatgpt_account_id) = auth
+        .tokens
+        .map(|t| (t.id_token.email, t.id_token.chatgpt_account_id))
+        .unwrap_or_default();
+    let entry = AuthIndexEntry {
+        auth_mtime_ms,
+        auth_len,
+        email,
+        chatgpt_account_id,
+    };
+    auth_index.insert(label.to_string(), entry.clone());
+    Ok(Some((entry, true)))
+}
+
 fn read_auth_dot_json(path: &Path) -> anyhow::Result<Option<AuthDotJson>> {
     let contents = match std::fs::read_to_string(path) {
         Ok(s) => s,
@@ -320,9 +1061,107 @@ mod tests {
     use pretty_assertions::assert_eq;
     use std::collections::BTreeMap;
 
+    #[test]
+    fn remaining_percents_fall_back_to_raw_windows() {
+        let window = |remaining_percent| {
+            Some(WindowSnapshot {
+                used_percent: 100.0 - remaining_percent,
+                remaining_percent,
+                window_minutes: None,
+                resets_at: None,
+            })
+        };
+        let raw = UsageSnapshot {
+            five_hour: None,
+            weekly: None,
+            primary: window(70.0),
+            secondary: window(40.0),
+        };
+        assert_eq!(remaining_percents(&raw), (Some(70.0), Some(40.0)));
+
+        let bucketed = UsageSnapshot {
+            five_hour: window(90.0),
+            weekly: None,
+            primary: None,
+            secondary: None,
+        };
+        assert_eq!(remaining_percents(&bucketed), (Some(90.0), None));
+    }
+
+    #[test]
+    fn skip_refresh_only_when_token_outlives_threshold() {
+        let hour_ms = 60 * 60 * 1000;
+        assert_eq!(
+            skip_refresh_for(Some(3 * hour_ms), 0, hour_ms),
+            Some(3 * hour_ms)
+        );
+        assert_eq!(skip_refresh_for(Some(hour_ms), 0, hour_ms), None);
+        assert_eq!(skip_refresh_for(Some(10), 20, hour_ms), None);
+        assert_eq!(skip_refresh_for(None, 0, 0), None);
+    }
+
+    #[test]
+    fn set_usage_seeds_the_usage_cache_from_a_file() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        let state_root = temp.path().join("state");
+        std::fs::create_dir_all(accounts_root.join("work")).expect("create work");
+        std::fs::create_dir_all(accounts_root.join("personal")).expect("create personal");
+        std::fs::create_dir_all(&state_root).expect("create state root");
+
+        let seed_file = temp.path().join("usage.json");
+        std::fs::write(
+            &seed_file,
+            r#"{"work": {"five_hour": 80, "weekly": 40}, "personal": {"weekly": 150}}"#,
+        )
+        .expect("write seed file");
+        assert!(
+            set_usage(
+                &accounts_root,
+                &state_root,
+                UsageSeedSource::File(seed_file.clone())
+            )
+            .is_err()
+        );
+        assert_eq!(
+            load_state(&state_root)
+                .expect("load state")
+                .usage_cache
+                .len(),
+            0
+        );
+
+        std::fs::write(
+            &seed_file,
+            r#"{"work": {"five_hour": 80, "weekly": 40}, "personal": {"weekly": 10}}"#,
+        )
+        .expect("write seed file");
+        set_usage(
+            &accounts_root,
+            &state_root,
+            UsageSeedSource::File(seed_file),
+        )
+        .expect("seed usage");
+
+        let state = load_state(&state_root).expect("load state");
+        let work = &state.usage_cache["work"].snapshot;
+        assert_eq!(
+            work.five_hour
+                .as_ref()
+                .map(|w| (w.remaining_percent, w.used_percent)),
+            Some((80.0, 20.0))
+        );
+        assert_eq!(
+            work.weekly.as_ref().map(|w| w.remaining_percent),
+            Some(40.0)
+        );
+        assert_eq!(state.usage_cache["personal"].snapshot.five_hour, None);
+    }
+
     #[tokio::test]
     async fn del_removes_account_home_and_usage_cache() {
         let temp = tempfile::tempdir().expect("create temp dir");
+        let shared_root = temp.path().join("shared");
         let accounts_root = temp.path().join("accounts");
         let state_root = temp.path().join("state");
         std::fs::create_dir_all(&accounts_root).expect("create accounts root");
@@ -342,18 +1181,307 @@ mod tests {
                 snapshot: crate::state::UsageSnapshot {
                     five_hour: None,
                     weekly: None,
+                    primary: None,
+                    secondary: None,
                 },
             },
         );
-        crate::state::save_state(&state_root, &crate::state::ManagerState { usage_cache })
-            .expect("save state");
+        crate::state::save_state(
+            &state_root,
+            &crate::state::ManagerState {
+                usage_cache,
+                ..crate::state::ManagerState::default()
+            },
+        )
+        .expect("save state");
 
-        del(&accounts_root, &state_root, label.clone())
-            .await
-            .expect("delete account");
+        del(
+            &shared_root,
+            &accounts_root,
+            &state_root,
+            &config::default_path(&state_root),
+            label.clone(),
+            DeleteScope::Credentials,
+        )
+        .await
+        .expect("delete account");
+        assert_eq!(account_home.join("auth.json").exists(), false);
+        assert_eq!(account_home.join("extra.txt").exists(), true);
 
+        del(
+            &shared_root,
+            &accounts_root,
+            &state_root,
+            &config::default_path(&state_root),
+            label.clone(),
+            DeleteScope::Home,
+        )
+        .await
+        .expect("purge account");
         assert_eq!(account_home.exists(), false);
         let state = crate::state::load_state(&state_root).expect("load state");
         assert_eq!(state, crate::state::ManagerState::default());
     }
+
+    fn write_auth(home: &Path, refresh_token: &str) {
+        std::fs::create_dir_all(home).expect("create home");
+        let auth = serde_json::json!({
+            "OPENAI_API_KEY": null,
+            "tokens": {
+                "id_token": "e30.e30.sig",
+                "access_token": "e30.e30.sig",
+                "refresh_token": refresh_token,
+            },
+        });
+        std::fs::write(home.join("auth.json"), auth.to_string()).expect("write auth.json");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn add_imports_only_auth_from_existing_codex_home() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let shared_root = temp.path().join("shared");
+        let accounts_root = temp.path().join("accounts");
+        let state_root = temp.path().join("state");
+        let codex_home = temp.path().join("codex-home");
+        std::fs::create_dir_all(&accounts_root).expect("create accounts root");
+        std::fs::create_dir_all(&state_root).expect("create state root");
+        write_auth(&codex_home, "refresh");
+        std::fs::write(codex_home.join("history.jsonl"), "old\n").expect("write history");
+
+        add(
+            &shared_root,
+            &accounts_root,
+            &state_root,
+            "imported".to_string(),
+            &codex_home,
+            ProjectTrust::default(),
+        )
+        .expect("add");
+
+        let account_home = accounts_root.join("imported");
+        assert_eq!(
+            std::fs::read_to_string(account_home.join("auth.json")).expect("read auth"),
+            std::fs::read_to_string(codex_home.join("auth.json")).expect("read source auth")
+        );
+        assert_eq!(
+            std::fs::read_link(account_home.join("history.jsonl")).expect("history link"),
+            shared_root.join("history.jsonl")
+        );
+        assert_eq!(shared_root.join("history.jsonl").exists(), false);
+        assert!(
+            add(
+                &shared_root,
+                &accounts_root,
+                &state_root,
+                "imported".to_string(),
+                &codex_home,
+                ProjectTrust::default(),
+            )
+            .is_err()
+        );
+
+        aliases::set(
+            &accounts_root,
+            &state_root,
+            "proj-x".to_string(),
+            Some("imported".to_string()),
+        )
+        .expect("alias");
+        assert!(
+            add(
+                &shared_root,
+                &accounts_root,
+                &state_root,
+                "proj-x".to_string(),
+                &codex_home,
+                ProjectTrust::default(),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn add_rejects_auth_without_refresh_token() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        let codex_home = temp.path().join("codex-home");
+        std::fs::create_dir_all(&accounts_root).expect("create accounts root");
+        write_auth(&codex_home, " ");
+
+        let err = add(
+            &temp.path().join("shared"),
+            &accounts_root,
+            &temp.path().join("state"),
+            "imported".to_string(),
+            &codex_home,
+            ProjectTrust::default(),
+        )
+        .expect_err("missing refresh token");
+
+        assert_eq!(crate::exit_code::for_error(&err), 4);
+        assert_eq!(accounts_root.join("imported").exists(), false);
+    }
+
+    #[test]
+    fn indexed_identity_reuses_entry_until_auth_json_changes() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let auth_path = temp.path().join("auth.json");
+        let mut auth_index = BTreeMap::new();
+
+        assert_eq!(
+            indexed_identity(&mut auth_index, "demo", &auth_path).expect("missing auth"),
+            None
+        );
+
+        std::fs::write(&auth_path, "{}").expect("write auth.json");
+        let (entry, changed) = indexed_identity(&mut auth_index, "demo", &auth_path)
+            .expect("read auth")
+            .expect("auth present");
+        assert_eq!(changed, true);
+        assert_eq!(entry.auth_len, 2);
+        assert_eq!(auth_index.get("demo"), Some(&entry));
+
+        let (_, changed) = indexed_identity(&mut auth_index, "demo", &auth_path)
+            .expect("read auth")
+            .expect("auth present");
+        assert_eq!(changed, false);
+
+        std::fs::write(&auth_path, r#"{"OPENAI_API_KEY":null}"#).expect("rewrite auth.json");
+        let (entry, changed) = indexed_identity(&mut auth_index, "demo", &auth_path)
+            .expect("read auth")
+            .expect("auth present");
+        assert_eq!(changed, true);
+        assert_eq!(entry.auth_len, 23);
+    }
+
+    fn row(label: &str, weekly: Option<f64>) -> AccountsListRow {
+        AccountsListRow {
+            label: label.to_string(),
+            email: None,
+            workspace_id: None,
+            five_hour_remaining_percent: None,
+            weekly_remaining_percent: weekly,
+            snapshot_age_seconds: None,
+            status: "ok".to_string(),
+            reserved: false,
+            tags: Vec::new(),
+            aliases: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn sort_rows_puts_most_exhausted_first_and_unknown_last() {
+        let mut rows = vec![
+            row("a", Some(80.0)),
+            row("b", None),
+            row("c", Some(5.0)),
+            row("d", Some(80.0)),
+        ];
+
+        sort_rows(&mut rows, ListSortKey::Weekly, false);
+        let labels: Vec<&str> = rows.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, vec!["c", "a", "d", "b"]);
+
+        sort_rows(&mut rows, ListSortKey::Label, true);
+        let labels: Vec<&str> = rows.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, vec!["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn reserved_labels_are_left_out_of_automatic_selection() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        let state_root = temp.path().join("state");
+        std::fs::create_dir_all(accounts_root.join("a")).expect("create a");
+        std::fs::create_dir_all(accounts_root.join("b")).expect("create b");
+        std::fs::create_dir_all(&state_root).expect("create state root");
+
+        set_reserved(&accounts_root, &state_root, "b".to_string(), true).expect("reserve");
+        assert_eq!(
+            list_unreserved_labels(&accounts_root, &state_root).expect("labels"),
+            vec!["a".to_string()]
+        );
+
+        set_reserved(&accounts_root, &state_root, "b".to_string(), false).expect("release");
+        assert_eq!(
+            list_unreserved_labels(&accounts_root, &state_root).expect("labels"),
+            vec!["a".to_string(), "b".to_string()]
+        );
+
+        assert!(set_reserved(&accounts_root, &state_root, "missing".to_string(), true).is_err());
+    }
+
+    #[test]
+    fn tags_select_accounts_and_are_replaced_wholesale() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        let state_root = temp.path().join("state");
+        for label in ["a", "b", "c"] {
+            std::fs::create_dir_all(accounts_root.join(label)).expect("create account");
+        }
+        std::fs::create_dir_all(&state_root).expect("create state root");
+        let tag = |label: &str, tags: &[&str]| {
+            set_tags(
+                &accounts_root,
+                &state_root,
+                label.to_string(),
+                tags.iter().map(ToString::to_string).collect(),
+            )
+        };
+
+        tag("a", &["team-a", "prod"]).expect("tag a");
+        tag("c", &["team-a"]).expect("tag c");
+        assert_eq!(
+            labels_with_tag(&accounts_root, &state_root, "team-a").expect("labels"),
+            vec!["a".to_string(), "c".to_string()]
+        );
+
+        tag("c", &[]).expect("clear c");
+        assert_eq!(
+            labels_with_tag(&accounts_root, &state_root, "team-a").expect("labels"),
+            vec!["a".to_string()]
+        );
+        assert!(tag("b", &["bad tag"]).is_err());
+        assert!(tag("missing", &["x"]).is_err());
+    }
+
+    #[test]
+    fn rows_are_handed_out_one_at_a_time_in_label_order() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        let state_root = temp.path().join("state");
+        for label in ["c", "a", "b"] {
+            std::fs::create_dir_all(accounts_root.join(label)).expect("create account");
+        }
+        std::fs::create_dir_all(&state_root).expect("create state root");
+        let config_path = temp.path().join("config.toml");
+
+        let mut seen = Vec::new();
+        let result = for_each_row(&accounts_root, &state_root, &config_path, None, |row| {
+            seen.push(row.label.clone());
+            if row.label == "b" {
+                anyhow::bail!("consumer went away");
+            }
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(seen, vec!["a".to_string(), "b".to_string()]);
+
+        let mut ndjson = Vec::new();
+        for_each_row(&accounts_root, &state_root, &config_path, None, |row| {
+            write_ndjson_row(&mut ndjson, &row)
+        })
+        .expect("rows");
+        let labels: Vec<String> = String::from_utf8(ndjson)
+            .expect("utf-8")
+            .lines()
+            .map(|line| {
+                let row: serde_json::Value =
+                    serde_json::from_str(line).expect("one object per line");
+                row["label"].as_str().expect("label").to_string()
+            })
+            .collect();
+        assert_eq!(labels, vec!["a", "b", "c"]);
+    }
 }
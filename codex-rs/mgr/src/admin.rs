@@ -0,0 +1,388 @@
+//! `/admin/*` endpoints, authenticated with `gateway.admin_token` rather than a gateway session.
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::FromRef;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::Request;
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::http::header::HeaderValue;
+use axum::response::Response;
+use axum::routing::post;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use crate::account_token_provider;
+use crate::capture;
+use crate::label::validate_label;
+use crate::proxy;
+use crate::serve::ServeState;
+use crate::serve::constant_time_eq;
+use crate::serve::parse_bearer_token;
+
+/// Largest JSON body accepted by the `/admin/*` endpoints.
+const ADMIN_BODY_LIMIT_BYTES: usize = 64 * 1024;
+
+/// The part of [`ServeState`] the admin endpoints need, so they can be served without Redis.
+#[derive(Clone)]
+pub(crate) struct AdminState {
+    pub(crate) token: Option<String>,
+    pub(crate) capture: Arc<capture::RequestCapture>,
+}
+
+impl FromRef<Arc<ServeState>> for AdminState {
+    fn from_ref(state: &Arc<ServeState>) -> Self {
+        Self {
+            token: state.admin_token.clone(),
+            capture: Arc::clone(&state.capture),
+        }
+    }
+}
+
+/// The `/admin/*` endpoints, to be nested at `/admin`. Any other path under it is a 404 rather
+/// than a request proxied upstream.
+pub(crate) fn router() -> Router<Arc<ServeState>> {
+    routes_without_redis().route("/invalidate-token", post(invalidate_token_handler))
+}
+
+/// Every admin route but `/invalidate-token`, which needs Redis, so tests can serve these with an
+/// [`AdminState`] alone.
+fn routes_without_redis<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    AdminState: FromRef<S>,
+{
+    Router::new()
+        .route("/capture", post(capture_handler))
+        .fallback(unknown_endpoint)
+}
+
+async fn unknown_endpoint() -> Response {
+    proxy::json_error_response(StatusCode::NOT_FOUND, "unknown admin endpoint")
+}
+
+/// `POST /admin/invalidate-token?account=<label>` drops the account's cached access token from
+/// Redis so the next request reloads `auth.json`, e.g. after re-running `login` for it.
+async fn invalidate_token_handler(
+    State(admin): State<AdminState>,
+    State(state): State<Arc<ServeState>>,
+    request: Request<Body>,
+) -> Response {
+    let account = match invalidate_token_account(&admin, &request) {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+
+    let mut conn = state.redis.clone();
+    let invalidated = match account_token_provider::invalidate_cached(&mut conn, &account).await {
+        Ok(invalidated) => invalidated,
+        Err(err) => {
+            tracing::error!(error = %err, %account, "redis error invalidating cached token");
+            state
+                .metrics
+                .redis_errors_total
+                .fetch_add(1, Ordering::Relaxed);
+            return proxy::json_error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "failed to invalidate cached token",
+            );
+        }
+    };
+    tracing::info!(event = %"admin_invalidate_token", %account, invalidated);
+
+    #[derive(serde::Serialize)]
+    struct InvalidateTokenBody<'a> {
+        account: &'a str,
+        invalidated: bool,
+    }
+    json_response(&InvalidateTokenBody {
+        account: &account,
+        invalidated,
+    })
+}
+
+/// Authorizes an `/admin/invalidate-token` request and returns the account it names; the error
+/// is the response to send instead.
+fn invalidate_token_account(
+    admin: &AdminState,
+    request: &Request<Body>,
+) -> Result<String, Response> {
+    authorize_admin(admin, request.headers())?;
+    request
+        .uri()
+        .query()
+        .and_then(account_param)
+        .filter(|account| validate_label(account).is_ok())
+        .ok_or_else(|| {
+            proxy::json_error_response(
+                StatusCode::BAD_REQUEST,
+                "missing or invalid `account` query parameter",
+            )
+        })
+}
+
+/// The percent-decoded `account` parameter of a `/admin/invalidate-token` query string.
+fn account_param(query: &str) -> Option<String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .find_map(|(key, value)| (key == "account").then(|| value.into_owned()))
+}
+
+/// `POST /admin/capture` with `{"count": N, "pool"?, "account"?, "path_prefix"?}` records the next
+/// `N` matching upstream attempts, request and response, to `captures.jsonl` under the state
+/// root. Credentials are redacted and capture disarms itself after the last one; `count: 0`
+/// disarms it early. WebSocket sessions are not captured.
+async fn capture_handler(State(admin): State<AdminState>, request: Request<Body>) -> Response {
+    if let Err(response) = authorize_admin(&admin, request.headers()) {
+        return response;
+    }
+    let command = match axum::body::to_bytes(request.into_body(), ADMIN_BODY_LIMIT_BYTES)
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|body| {
+            serde_json::from_slice::<capture::CaptureCommand>(&body).map_err(|err| err.to_string())
+        }) {
+        Ok(command) => command,
+        Err(err) => {
+            return proxy::json_error_response(
+                StatusCode::BAD_REQUEST,
+                format!("invalid capture request: {err}"),
+            );
+        }
+    };
+
+    let count = admin.capture.arm(command.count, command.filter.clone());
+    tracing::info!(
+        event = %"admin_capture",
+        count,
+        pool = command.filter.pool.as_deref().unwrap_or("*"),
+        account = command.filter.account.as_deref().unwrap_or("*"),
+        path_prefix = command.filter.path_prefix.as_deref().unwrap_or("*"),
+    );
+
+    #[derive(serde::Serialize)]
+    struct CaptureBody<'a> {
+        count: u32,
+        #[serde(flatten)]
+        filter: &'a capture::CaptureFilter,
+        file: String,
+    }
+    json_response(&CaptureBody {
+        count,
+        filter: &command.filter,
+        file: admin.capture.path().display().to_string(),
+    })
+}
+
+/// Checks the admin bearer token; the error is the response to send instead.
+fn authorize_admin(admin: &AdminState, headers: &HeaderMap) -> Result<(), Response> {
+    let Some(admin_token) = admin.token.as_deref() else {
+        return Err(proxy::json_error_response(
+            StatusCode::NOT_FOUND,
+            "admin endpoints are disabled",
+        ));
+    };
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_bearer_token);
+    if !presented.is_some_and(|token| constant_time_eq(token.as_bytes(), admin_token.as_bytes())) {
+        tracing::warn!("admin request with missing or invalid admin token");
+        return Err(proxy::json_error_response(
+            StatusCode::UNAUTHORIZED,
+            "invalid admin token",
+        ));
+    }
+    Ok(())
+}
+
+fn json_response<T: serde::Serialize>(body: &T) -> Response {
+    match serde_json::to_vec(body) {
+        Ok(body) => {
+            let mut out = Response::new(Body::from(body));
+            let _ = out.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            out
+        }
+        Err(err) => proxy::json_error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to serialize response: {err}"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const ADMIN_TOKEN: &str = "admin-secret";
+
+    /// Serves the admin routes that need no Redis under `/admin` on a loopback port and returns
+    /// the `/admin/capture` URL.
+    async fn spawn_capture_endpoint(capture: Arc<capture::RequestCapture>) -> String {
+        let router = Router::new()
+            .nest("/admin", routes_without_redis())
+            .with_state(AdminState {
+                token: Some(ADMIN_TOKEN.to_string()),
+                capture,
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind admin");
+        let addr = listener.local_addr().expect("admin addr");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        format!("http://{addr}/admin/capture")
+    }
+
+    async fn post_capture(url: &str, token: Option<&str>, body: &str) -> StatusCode {
+        let mut request = reqwest::Client::new().post(url).body(body.to_string());
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.expect("admin request").status()
+    }
+
+    #[tokio::test]
+    async fn capture_requires_the_admin_token() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let url = spawn_capture_endpoint(Arc::new(capture::RequestCapture::new(dir.path()))).await;
+
+        assert_eq!(
+            post_capture(&url, None, r#"{"count":1}"#).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            post_capture(&url, Some("wrong"), r#"{"count":1}"#).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            post_capture(&url, Some(ADMIN_TOKEN), r#"{"count":1}"#).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_admin_paths_are_not_found() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let url = spawn_capture_endpoint(Arc::new(capture::RequestCapture::new(dir.path()))).await;
+        let unknown = url.replace("/admin/capture", "/admin/responses");
+
+        assert_eq!(
+            post_capture(&unknown, Some(ADMIN_TOKEN), "{}").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn invalidate_token_requires_the_admin_token_and_a_valid_account() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let admin = AdminState {
+            token: Some(ADMIN_TOKEN.to_string()),
+            capture: Arc::new(capture::RequestCapture::new(dir.path())),
+        };
+        let request = |uri: &str, token: Option<&str>| {
+            let mut request = Request::post(uri);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            request.body(Body::empty()).expect("request")
+        };
+        let status = |request: Request<Body>| {
+            invalidate_token_account(&admin, &request).map_err(|response| response.status())
+        };
+
+        let uri = "/admin/invalidate-token?account=team-a";
+        assert_eq!(status(request(uri, None)), Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(
+            status(request(uri, Some("wrong"))),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(request("/admin/invalidate-token", Some(ADMIN_TOKEN))),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            status(request(
+                "/admin/invalidate-token?account=..",
+                Some(ADMIN_TOKEN)
+            )),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            status(request(uri, Some(ADMIN_TOKEN))),
+            Ok("team-a".to_string())
+        );
+    }
+
+    #[test]
+    fn account_param_is_percent_decoded() {
+        assert_eq!(
+            account_param("x=1&account=team%2Da"),
+            Some("team-a".to_string())
+        );
+        assert_eq!(account_param("account=a+b"), Some("a b".to_string()));
+        assert_eq!(account_param("accounts=a"), None);
+    }
+
+    #[tokio::test]
+    async fn capture_rejects_unknown_fields() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let url = spawn_capture_endpoint(Arc::new(capture::RequestCapture::new(dir.path()))).await;
+
+        assert_eq!(
+            post_capture(&url, Some(ADMIN_TOKEN), r#"{"count":1,"bogus":1}"#).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn armed_capture_writes_requests_without_their_credentials() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let capture = Arc::new(capture::RequestCapture::new(dir.path()));
+        let url = spawn_capture_endpoint(Arc::clone(&capture)).await;
+        assert_eq!(
+            post_capture(&url, Some(ADMIN_TOKEN), r#"{"count":1}"#).await,
+            StatusCode::OK
+        );
+
+        let (parts, ()) = Request::post("/responses")
+            .header(header::AUTHORIZATION, "Bearer gw_session_secret")
+            .header(header::COOKIE, "session=cookie_secret")
+            .body(())
+            .expect("request")
+            .into_parts();
+        let record = capture
+            .begin(capture::CaptureAttempt {
+                pool_id: "team",
+                account: "a",
+                request_id: Some("req-1"),
+                parts: &parts,
+                body: &proxy::ForwardBody::Empty,
+            })
+            .expect("capture armed");
+        capture.write(&record);
+
+        let written = std::fs::read_to_string(capture.path()).expect("read captures");
+        assert_eq!(written.lines().count(), 1);
+        assert!(written.contains("\"request_id\":\"req-1\""));
+        assert!(!written.contains("gw_session_secret"));
+        assert!(!written.contains("cookie_secret"));
+        assert!(
+            capture
+                .begin(capture::CaptureAttempt {
+                    pool_id: "team",
+                    account: "a",
+                    request_id: None,
+                    parts: &parts,
+                    body: &proxy::ForwardBody::Empty,
+                })
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::label::validate_label;
+use crate::state::load_state;
+use crate::state::update_state;
+
+/// Points `alias` at the account `label`; no label removes the alias. An alias cannot share a
+/// name with an account home, so it never shadows one.
+pub(crate) fn set(
+    accounts_root: &Path,
+    state_root: &Path,
+    alias: String,
+    label: Option<String>,
+) -> anyhow::Result<()> {
+    validate_label(&alias)?;
+    let Some(label) = label else {
+        update_state(state_root, |state| {
+            if state.aliases.remove(&alias).is_none() {
+                anyhow::bail!("{alias:?} is not an alias");
+            }
+            Ok(())
+        })?;
+        println!("removed alias {alias:?}");
+        return Ok(());
+    };
+    validate_label(&label)?;
+    if accounts_root.join(&alias).exists() {
+        anyhow::bail!("{alias:?} is already an account label");
+    }
+    if !accounts_root.join(&label).is_dir() {
+        anyhow::bail!("label {label} does not exist");
+    }
+
+    update_state(state_root, |state| {
+        state.aliases.insert(alias.clone(), label.clone());
+        Ok(())
+    })?;
+    println!("{alias:?} is an alias for {label:?}");
+    Ok(())
+}
+
+/// The account label `label` names: its target when it is an alias set with `accounts alias`,
+/// otherwise `label` itself. Callers resolve before touching the account home.
+pub(crate) fn resolve(state_root: &Path, label: String) -> String {
+    resolve_in(&load(state_root), label)
+}
+
+/// [`resolve`] for a list of labels.
+pub(crate) fn resolve_all(state_root: &Path, labels: Vec<String>) -> Vec<String> {
+    let aliases = load(state_root);
+    labels
+        .into_iter()
+        .map(|label| resolve_in(&aliases, label))
+        .collect()
+}
+
+/// Refuses a new account label that is already taken by an alias, which would otherwise be
+/// unreachable because every lookup resolves the alias first.
+pub(crate) fn ensure_not_alias(state_root: &Path, label: &str) -> anyhow::Result<()> {
+    if let Some(target) = load(state_root).get(label) {
+        anyhow::bail!(
+            "{label:?} is an alias for {target:?}; remove it with `accounts alias {label}` first"
+        );
+    }
+    Ok(())
+}
+
+/// Account labels mapped to the aliases that point at them.
+pub(crate) fn by_label(aliases: &BTreeMap<String, String>) -> BTreeMap<String, Vec<String>> {
+    let mut by_label: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (alias, label) in aliases {
+        by_label
+            .entry(label.clone())
+            .or_default()
+            .push(alias.clone());
+    }
+    by_label
+}
+
+/// Aliases are a convenience, so an unreadable `state.json` leaves labels as given rather than
+/// failing the command that named them.
+fn load(state_root: &Path) -> BTreeMap<String, String> {
+    match load_state(state_root) {
+        Ok(state) => state.aliases,
+        Err(err) => {
+            tracing::warn!(error = %err, "state.json is unreadable; ignoring account aliases");
+            BTreeMap::new()
+        }
+    }
+}
+
+fn resolve_in(aliases: &BTreeMap<String, String>, label: String) -> String {
+    match aliases.get(&label) {
+        Some(target) => {
+            tracing::debug!(alias = %label, %target, "resolved account alias");
+            target.clone()
+        }
+        None => label,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn aliases_resolve_to_their_account_and_cannot_shadow_one() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        let state_root = temp.path().join("state");
+        for label in ["a", "b"] {
+            std::fs::create_dir_all(accounts_root.join(label)).expect("create account");
+        }
+        std::fs::create_dir_all(&state_root).expect("create state root");
+
+        set(
+            &accounts_root,
+            &state_root,
+            "proj-x".to_string(),
+            Some("a".to_string()),
+        )
+        .expect("alias");
+        assert_eq!(
+            resolve_all(
+                &state_root,
+                vec!["proj-x".to_string(), "b".to_string(), "c".to_string()]
+            ),
+            vec!["a".to_string(), "b".to_string(), "c".to_string()]
+        );
+        assert!(ensure_not_alias(&state_root, "proj-x").is_err());
+        assert!(ensure_not_alias(&state_root, "c").is_ok());
+
+        assert!(
+            set(
+                &accounts_root,
+                &state_root,
+                "b".to_string(),
+                Some("a".to_string())
+            )
+            .is_err()
+        );
+        assert!(
+            set(
+                &accounts_root,
+                &state_root,
+                "y".to_string(),
+                Some("missing".to_string())
+            )
+            .is_err()
+        );
+
+        set(&accounts_root, &state_root, "proj-x".to_string(), None).expect("remove");
+        assert_eq!(resolve(&state_root, "proj-x".to_string()), "proj-x");
+        assert!(set(&accounts_root, &state_root, "proj-x".to_string(), None).is_err());
+    }
+
+    #[test]
+    fn unreadable_state_leaves_labels_unresolved() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        std::fs::write(temp.path().join("state.json"), "{not json").expect("write state");
+
+        assert_eq!(resolve(temp.path(), "a".to_string()), "a");
+        assert!(ensure_not_alias(temp.path(), "a").is_ok());
+    }
+}
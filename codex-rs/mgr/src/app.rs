@@ -1,16 +1,33 @@
 use anyhow::Context;
+use clap::ArgMatches;
 use clap::Args;
+use clap::CommandFactory;
+use clap::FromArgMatches;
 use clap::Parser;
 use clap::Subcommand;
+use clap_complete::Shell;
+use std::ffi::OsStr;
 use std::ffi::OsString;
+use std::path::Path;
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 use crate::accounts;
+use crate::aliases;
+use crate::audit;
+use crate::config;
+use crate::doctor;
+use crate::exit_code;
 use crate::gateway;
+use crate::layout;
+use crate::migrate;
 use crate::observability;
 use crate::pools;
+use crate::pools_validate;
 use crate::run_cmd;
 use crate::serve;
+use crate::state_show;
+use crate::usage;
 
 const DEFAULT_STATE_DIRNAME: &str = ".codex-mgr";
 
@@ -34,6 +51,21 @@ struct Cli {
     #[arg(long, global = true, env = "CODEX_MGR_STATE_ROOT")]
     state_root: Option<PathBuf>,
 
+    /// Gateway/pool config file to use instead of `<state_root>/config.toml` (e.g. a read-only
+    /// mount). Runtime state still lives under the state root.
+    #[arg(
+        long = "config",
+        global = true,
+        env = "CODEX_MGR_CONFIG",
+        value_name = "PATH"
+    )]
+    config_path: Option<PathBuf>,
+
+    /// On failure, print `{"error": {"kind": ..., "message": ...}}` to stderr instead of prose.
+    /// Implied by any subcommand's `--json`.
+    #[arg(long, global = true, env = JSON_ERRORS_ENV)]
+    json_errors: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -43,9 +75,32 @@ enum Commands {
     Login(LoginArgs),
     Accounts(AccountsArgs),
     Pools(PoolsArgs),
+    /// Check how pool routing spreads conversations across accounts.
+    Routing(RoutingArgs),
     Gateway(GatewayArgs),
     Run(RunArgs),
+    /// Select an account like `run --auto` and print its home (or label) to stdout, for
+    /// `CODEX_HOME=$(codex-mgr pick) codex ...`.
+    Pick(PickArgs),
     Serve(ServeArgs),
+    /// Check that every account home still links its shared entries into shared_root and is not
+    /// readable by other users.
+    Doctor(DoctorArgs),
+    /// Move legacy ~/.codex-shared and ~/.codex-accounts into the current layout.
+    Migrate(MigrateArgs),
+    /// Inspect the log of pool and gateway-session changes.
+    Audit(AuditArgs),
+    /// Inspect what state.json records about each account.
+    State(StateArgs),
+    /// Print a shell completion script to stdout.
+    #[command(hide = true)]
+    Completions(CompletionsArgs),
+}
+
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+    #[arg(value_enum)]
+    shell: Shell,
 }
 
 #[derive(Args, Debug)]
@@ -61,6 +116,9 @@ struct LoginArgs {
     /// Re-login with an existing label by removing the current account home first.
     #[arg(long)]
     force: bool,
+
+    #[command(flatten)]
+    trust: TrustArgs,
 }
 
 #[derive(Args, Debug)]
@@ -71,8 +129,22 @@ struct AccountsArgs {
 
 #[derive(Subcommand, Debug)]
 enum AccountsCommands {
+    /// Import an existing CODEX_HOME's auth.json as a new account.
+    Add(AccountsAddArgs),
     List(AccountsListArgs),
+    Whoami(AccountsWhoamiArgs),
     Del(AccountsDelArgs),
+    /// Keep an account out of automatic selection (`run --auto`, the gateway's default pool).
+    SetReserved(AccountsSetReservedArgs),
+    /// Record synthetic usage in the usage cache, e.g. to test selection without the backend.
+    SetUsage(AccountsSetUsageArgs),
+    /// Replace an account's tags (no tags clears them).
+    Tag(AccountsTagArgs),
+    /// Give an account another name accepted by `run --label` and `pools set --labels`
+    /// (no label removes the alias).
+    Alias(AccountsAliasArgs),
+    /// Refresh access tokens for the given accounts.
+    Refresh(AccountsRefreshArgs),
 }
 
 #[derive(Args, Debug)]
@@ -85,10 +157,43 @@ struct PoolsArgs {
 enum PoolsCommands {
     Set(PoolsSetArgs),
     List(PoolsListArgs),
+    Show(PoolsShowArgs),
     Del(PoolsDelArgs),
     AddMember(PoolsAddMemberArgs),
     RemoveMember(PoolsRemoveMemberArgs),
     Validate(PoolsValidateArgs),
+    /// Replace a pool's policy_key (random by default) and report how routing shifts.
+    RotatePolicy(PoolsRotatePolicyArgs),
+}
+
+#[derive(Args, Debug)]
+struct RoutingArgs {
+    #[command(subcommand)]
+    command: RoutingCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum RoutingCommands {
+    /// Measure the hash-ring first-choice distribution and compare it with an even split.
+    Bench(RoutingBenchArgs),
+}
+
+#[derive(Args, Debug)]
+struct RoutingBenchArgs {
+    #[arg(long)]
+    pool: String,
+
+    /// Number of synthetic conversation keys to hash.
+    #[arg(long, default_value_t = pools::DEFAULT_BENCH_SAMPLES)]
+    samples: i64,
+
+    /// Fail when any account's share deviates from uniform by more than this percent.
+    #[arg(long, default_value_t = pools::DEFAULT_BENCH_MAX_DEVIATION_PERCENT)]
+    max_deviation_percent: f64,
+
+    /// Output JSON.
+    #[arg(long)]
+    json: bool,
 }
 
 #[derive(Args, Debug)]
@@ -99,9 +204,38 @@ struct PoolsSetArgs {
     #[arg(long, value_delimiter = ',', num_args = 1..)]
     labels: Vec<String>,
 
+    /// Add every account tagged with this (combined with --labels if both are given).
+    #[arg(long)]
+    from_tag: Option<String>,
+
     /// Optional selection policy key for this pool.
     #[arg(long)]
     policy_key: Option<String>,
+
+    /// Note used by `gateway issue` when `--note` is omitted (pass "" to clear).
+    #[arg(long)]
+    default_note: Option<String>,
+
+    /// Enable or disable sticky conversation routing for this pool (default: true).
+    #[arg(long)]
+    sticky: Option<bool>,
+
+    /// Let clients pin a request to one member via `x-codex-mgr-account` (default: false).
+    #[arg(long)]
+    allow_account_override: Option<bool>,
+
+    /// Route each request by this header's value (e.g. `x-tenant-id`) so a tenant always lands
+    /// on the same member (pass "" to clear).
+    #[arg(long, value_name = "HEADER")]
+    tenant_header: Option<String>,
+
+    /// Print the resulting pool block and member checks without writing config.toml.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print a before/after diff of the pool block.
+    #[arg(long)]
+    diff: bool,
 }
 
 #[derive(Args, Debug)]
@@ -111,11 +245,45 @@ struct PoolsListArgs {
     json: bool,
 }
 
+#[derive(Args, Debug)]
+struct PoolsShowArgs {
+    pool_id: String,
+
+    /// Number of synthetic conversation keys to hash when sampling the routing distribution.
+    #[arg(long, default_value_t = pools::DEFAULT_DISTRIBUTION_SAMPLES)]
+    samples: i64,
+
+    /// Output JSON.
+    #[arg(long)]
+    json: bool,
+}
+
 #[derive(Args, Debug)]
 struct PoolsDelArgs {
     pool_id: String,
 }
 
+#[derive(Args, Debug)]
+struct PoolsRotatePolicyArgs {
+    pool_id: String,
+
+    /// New policy_key; a random one is generated when omitted.
+    #[arg(long, value_name = "KEY")]
+    new_key: Option<String>,
+
+    /// Number of synthetic conversation keys to hash when estimating the shift.
+    #[arg(long, default_value_t = pools::DEFAULT_DISTRIBUTION_SAMPLES)]
+    samples: i64,
+
+    /// Report the expected shift without writing config.toml.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Output JSON.
+    #[arg(long)]
+    json: bool,
+}
+
 #[derive(Args, Debug)]
 struct PoolsAddMemberArgs {
     pool_id: String,
@@ -132,6 +300,10 @@ struct PoolsRemoveMemberArgs {
 struct PoolsValidateArgs {
     /// Optional pool ID to validate specific pool. If unset, validates all.
     pool_id: Option<String>,
+
+    /// Pool ID (or alias) to validate; same as the positional argument.
+    #[arg(long = "pool", value_name = "POOL_ID", conflicts_with = "pool_id")]
+    pool: Option<String>,
 }
 
 #[derive(Args, Debug)]
@@ -144,26 +316,46 @@ struct GatewayArgs {
 enum GatewayCommands {
     Issue(GatewayIssueArgs),
     List(GatewayListArgs),
+    Inspect(GatewayInspectArgs),
     Revoke(GatewayRevokeArgs),
+    /// Inspect or override which account a conversation is pinned to.
+    Sticky(GatewayStickyArgs),
 }
 
 #[derive(Args, Debug)]
 struct GatewayIssueArgs {
-    /// Pool id (configured via `codex-mgr pools set`).
+    /// Pool id (configured via `codex-mgr pools set`) or an alias from `[pool_aliases]`
+    /// (default: `gateway.default_pool`).
     #[arg(long)]
-    pool: String,
+    pool: Option<String>,
 
     /// TTL for this gateway token session (default: 31536000).
     #[arg(long)]
     ttl_seconds: Option<i64>,
 
-    /// Optional human note to store alongside the session.
+    /// Optional human note to store alongside the session (defaults to the pool's default_note).
     #[arg(long)]
     note: Option<String>,
 
+    /// Restrict the token to this path prefix (repeatable; default: unrestricted).
+    #[arg(long = "allow-path", value_name = "PREFIX")]
+    allow_paths: Vec<String>,
+
     /// Output JSON.
     #[arg(long)]
     json: bool,
+
+    /// Also print a ready-to-paste curl command that calls the gateway with the new token.
+    #[arg(long, conflicts_with = "json")]
+    example: bool,
+
+    /// Check that at least one pool member has valid (or refreshable) auth before issuing.
+    #[arg(long)]
+    verify: bool,
+
+    /// Issue a non-renewable token whose TTL is capped at one day, whatever --ttl-seconds says.
+    #[arg(long)]
+    preview: bool,
 }
 
 #[derive(Args, Debug)]
@@ -173,28 +365,276 @@ struct GatewayListArgs {
     json: bool,
 }
 
+#[derive(Args, Debug)]
+struct GatewayInspectArgs {
+    token: String,
+
+    /// Output JSON.
+    #[arg(long)]
+    json: bool,
+}
+
 #[derive(Args, Debug)]
 struct GatewayRevokeArgs {
     token: String,
 }
 
+#[derive(Args, Debug)]
+struct GatewayStickyArgs {
+    #[command(subcommand)]
+    command: GatewayStickyCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum GatewayStickyCommands {
+    /// Show the account a conversation is currently bound to.
+    Get(GatewayStickyTarget),
+    /// Bind a conversation to an account, replacing any existing binding.
+    Set(GatewayStickySetArgs),
+    /// Release a conversation so its next request re-selects an account.
+    Clear(GatewayStickyTarget),
+}
+
+#[derive(Args, Debug)]
+struct GatewayStickyTarget {
+    /// Pool id or an alias from `[pool_aliases]`.
+    #[arg(long)]
+    pool: String,
+
+    /// Value of the `conversation_id` (or `session_id`) request header.
+    #[arg(long)]
+    conversation_id: String,
+
+    /// Output JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct GatewayStickySetArgs {
+    #[command(flatten)]
+    target: GatewayStickyTarget,
+
+    /// Account label to bind the conversation to; must be a pool member.
+    #[arg(long)]
+    label: String,
+
+    /// Binding lifetime (default: gateway.sticky_ttl_seconds).
+    #[arg(long)]
+    ttl_seconds: Option<i64>,
+}
+
+#[derive(Args, Debug)]
+struct AccountsAddArgs {
+    /// Local label for the imported account (unique).
+    label: String,
+
+    /// Existing Codex home (e.g. ~/.codex) whose auth.json is copied.
+    #[arg(long, value_name = "CODEX_HOME")]
+    from: PathBuf,
+
+    #[command(flatten)]
+    trust: TrustArgs,
+}
+
 #[derive(Args, Debug)]
 struct AccountsListArgs {
     /// Output JSON.
     #[arg(long)]
     json: bool,
+
+    /// Output newline-delimited JSON, one object per account as it is resolved.
+    #[arg(long, conflicts_with_all = ["json", "sort", "reverse"])]
+    ndjson: bool,
+
+    /// Column to order rows by.
+    #[arg(long, value_enum, default_value = "label")]
+    sort: accounts::ListSortKey,
+
+    /// Reverse the sort order.
+    #[arg(long)]
+    reverse: bool,
+
+    /// Redraw the table periodically, refreshing usage (Ctrl-C to exit).
+    #[arg(long, conflicts_with_all = ["json", "ndjson"])]
+    watch: bool,
+
+    /// Seconds between redraws in `--watch` mode.
+    #[arg(long, requires = "watch", default_value_t = 30)]
+    interval: u64,
+
+    /// Only list accounts with this tag.
+    #[arg(long)]
+    tag: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct AccountsWhoamiArgs {
+    label: String,
+
+    /// Output JSON.
+    #[arg(long)]
+    json: bool,
 }
 
 #[derive(Args, Debug)]
 struct AccountsDelArgs {
     label: String,
+
+    /// Remove the whole account home, not just `auth.json`. Links into the shared root are
+    /// unlinked, never followed.
+    #[arg(long)]
+    purge: bool,
+}
+
+#[derive(Args, Debug)]
+struct AccountsSetReservedArgs {
+    label: String,
+
+    /// `true` to reserve the account, `false` to release it.
+    #[arg(action = clap::ArgAction::Set)]
+    reserved: bool,
+}
+
+#[derive(Args, Debug)]
+struct AccountsSetUsageArgs {
+    /// Account to record usage for; pass at least one of --5h / --weekly.
+    #[arg(long, required_unless_present = "from_file")]
+    label: Option<String>,
+
+    /// Five-hour window remaining percent (0-100).
+    #[arg(long = "5h", value_name = "PERCENT", requires = "label")]
+    five_hour: Option<f64>,
+
+    /// Weekly window remaining percent (0-100).
+    #[arg(long, value_name = "PERCENT", requires = "label")]
+    weekly: Option<f64>,
+
+    /// JSON object of label to `{"five_hour": <percent>, "weekly": <percent>}` remaining.
+    #[arg(long, value_name = "PATH", conflicts_with = "label")]
+    from_file: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct AccountsTagArgs {
+    label: String,
+
+    tags: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct AccountsAliasArgs {
+    alias: String,
+
+    label: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false, args = ["labels", "all", "tag"])]
+struct AccountsRefreshArgs {
+    labels: Vec<String>,
+
+    /// Refresh every account.
+    #[arg(long)]
+    all: bool,
+
+    /// Refresh every account with this tag.
+    #[arg(long)]
+    tag: Option<String>,
+
+    /// With --all or --tag, skip accounts whose access token is valid for more than this many
+    /// seconds.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = accounts::DEFAULT_REFRESH_MIN_REMAINING_SECS,
+        conflicts_with = "labels"
+    )]
+    min_remaining: u64,
+
+    /// With --all or --tag, refresh every account even if its access token is still valid.
+    #[arg(long, conflicts_with = "labels")]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+struct DoctorArgs {
+    /// Output JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct MigrateArgs {
+    /// Print what would be moved and relinked without changing anything.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+struct AuditArgs {
+    #[command(subcommand)]
+    command: AuditCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditCommands {
+    /// Print the most recent audit entries as JSON lines, oldest first.
+    Tail(AuditTailArgs),
+}
+
+#[derive(Args, Debug)]
+struct StateArgs {
+    #[command(subcommand)]
+    command: StateCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum StateCommands {
+    /// Print every label with its reservation, tags, cached usage (age, freshness) and the
+    /// score selection ranks it by.
+    Show(StateShowArgs),
+}
+
+#[derive(Args, Debug)]
+struct StateShowArgs {
+    /// Print JSON, including the raw cached usage snapshots.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct AuditTailArgs {
+    /// Number of entries to print.
+    #[arg(short = 'n', long = "lines", default_value_t = audit::DEFAULT_TAIL_LINES)]
+    lines: usize,
 }
 
 #[derive(Args, Debug)]
 struct ServeArgs {
-    /// Enable debug logging of headers.
+    /// Enable debug logging of headers and this crate's DEBUG-level events such as the routing
+    /// decision trail. `RUST_LOG=codex_mgr::routing=debug` enables the trail on its own.
     #[arg(long)]
     debug: bool,
+
+    /// Log the timing of each request phase (session lookup, routing, token provision, upstream
+    /// forward) as its span closes.
+    #[arg(long, conflicts_with = "print_effective_config")]
+    trace_spans: bool,
+
+    /// Print the resolved config (defaults applied, credentials and secrets redacted) as TOML and
+    /// exit.
+    #[arg(long)]
+    print_effective_config: bool,
+
+    /// With `--print-effective-config`, print JSON instead of TOML.
+    #[arg(long, requires = "print_effective_config")]
+    json: bool,
+
+    /// Write the bound listen address (e.g. the port picked for `gateway.listen = "127.0.0.1:0"`)
+    /// to this file once the gateway is listening.
+    #[arg(long, value_name = "PATH", conflicts_with = "print_effective_config")]
+    addr_file: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -203,10 +643,54 @@ struct RunArgs {
     #[arg(long, conflicts_with = "label")]
     auto: bool,
 
-    /// Use a specific account label.
-    #[arg(long)]
+    /// Use a specific account label or alias (see `accounts alias`).
+    #[arg(long, conflicts_with_all = ["pool", "no_usage_fetch"])]
     label: Option<String>,
 
+    #[command(flatten)]
+    select: SelectArgs,
+
+    #[command(flatten)]
+    trust: TrustArgs,
+
+    /// Arguments passed through to the upstream `codex` binary after `--`.
+    #[arg(trailing_var_arg = true)]
+    args: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct PickArgs {
+    #[command(flatten)]
+    select: SelectArgs,
+
+    /// What to print for the selected account.
+    #[arg(long, value_enum, default_value_t = run_cmd::PickOutput::Home)]
+    print: run_cmd::PickOutput,
+
+    #[command(flatten)]
+    trust: TrustArgs,
+}
+
+/// Shared-config trust flag for every command that sets up an account home.
+#[derive(Args, Debug)]
+struct TrustArgs {
+    /// How to register the current directory in the shared config's `[projects]`.
+    #[arg(
+        long,
+        value_enum,
+        env = "CODEX_MGR_TRUST_LEVEL",
+        default_value_t = layout::ProjectTrust::Trusted
+    )]
+    trust_level: layout::ProjectTrust,
+}
+
+/// Auto-selection flags shared by `run` and `pick`.
+#[derive(Args, Debug)]
+struct SelectArgs {
+    /// Only auto-select among the labels of this pool (see `codex-mgr pools`).
+    #[arg(long, value_name = "POOL_ID")]
+    pool: Option<String>,
+
     /// Force a token refresh before fetching usage.
     #[arg(long)]
     refresh: bool,
@@ -215,14 +699,179 @@ struct RunArgs {
     #[arg(long)]
     no_cache: bool,
 
-    /// Arguments passed through to the upstream `codex` binary after `--`.
-    #[arg(trailing_var_arg = true)]
-    args: Vec<OsString>,
+    /// Never fetch usage: rank by cached snapshots (however old), or fall back to
+    /// `[selection] no_fetch_fallback` ("first" or "round_robin") when nothing is cached.
+    #[arg(
+        long,
+        conflicts_with_all = ["refresh", "no_cache", "allow_stale", "select_timeout"]
+    )]
+    no_usage_fetch: bool,
+
+    /// Maximum concurrent usage fetches during auto selection (clamped to 1..=32).
+    #[arg(
+        long,
+        env = "CODEX_MGR_USAGE_CONCURRENCY",
+        default_value_t = usage::DEFAULT_USAGE_FETCH_CONCURRENCY
+    )]
+    concurrency: i64,
+
+    /// If no account can be scored from fresh or fetched usage (e.g. the backend is down),
+    /// select from expired cached snapshots instead of failing.
+    #[arg(long)]
+    allow_stale: bool,
+
+    /// Give auto selection at most this many seconds, then pick from the accounts scored so far
+    /// (cached or already fetched).
+    #[arg(long, value_name = "SECONDS")]
+    select_timeout: Option<u64>,
+
+    /// When auto selection finds no usable account, print why each one was skipped as JSON to
+    /// stderr.
+    #[arg(long)]
+    json: bool,
+}
+
+impl SelectArgs {
+    fn into_options(self) -> run_cmd::SelectOptions {
+        run_cmd::SelectOptions {
+            pool: self.pool,
+            refresh: self.refresh,
+            no_cache: self.no_cache,
+            no_usage_fetch: self.no_usage_fetch,
+            concurrency: self.concurrency,
+            allow_stale: self.allow_stale,
+            select_timeout: self.select_timeout.map(std::time::Duration::from_secs),
+            json: self.json,
+        }
+    }
+}
+
+/// Runs the command line and returns the process exit code, printing any failure to stderr.
+///
+/// | code | meaning |
+/// |------|---------|
+/// | 1 | any other failure |
+/// | 2 | no account available (none logged in, or usage unavailable for all) |
+/// | 3 | missing or invalid manager config |
+/// | 4 | account credentials missing or incomplete |
+/// | 64 | the command line could not be parsed |
+///
+/// With `--json-errors` or a subcommand's `--json`, failures print as
+/// `{"error": {"kind": ..., "message": ...}}`; `kind` is one of `no_accounts`, `config`, `auth`,
+/// `usage` (matching the codes above) or `error`. When the command line itself does not parse,
+/// only `--json-errors` and `CODEX_MGR_JSON_ERRORS` can be seen.
+pub async fn main() -> ExitCode {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let unparsed_format =
+        || unparsed_error_format(&args, std::env::var_os(JSON_ERRORS_ENV).as_deref());
+    let matches = match Cli::command().try_get_matches_from(&args) {
+        Ok(matches) => matches,
+        Err(err) => return report_error(&usage_error(err), unparsed_format()),
+    };
+    let cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(err) => return report_error(&usage_error(err), unparsed_format()),
+    };
+    let format = if cli.json_errors || json_output_requested(&matches) {
+        ErrorFormat::Json
+    } else {
+        ErrorFormat::Prose
+    };
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => report_error(&err, format),
+    }
+}
+
+const JSON_ERRORS_ENV: &str = "CODEX_MGR_JSON_ERRORS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    /// anyhow's report, or clap's own message for a parse error.
+    Prose,
+    /// The `{"error": ...}` envelope.
+    Json,
+}
+
+/// Prints `err` to stderr in `format` and returns its exit code.
+fn report_error(err: &anyhow::Error, format: ErrorFormat) -> ExitCode {
+    match format {
+        ErrorFormat::Json => eprintln!("{}", exit_code::json_envelope(err)),
+        // clap's message already carries its own `error:` prefix and usage hint.
+        ErrorFormat::Prose
+            if exit_code::category_of(err) == Some(exit_code::ErrorCategory::Usage) =>
+        {
+            eprint!("{err}");
+        }
+        ErrorFormat::Prose => eprintln!("Error: {err:?}"),
+    }
+    ExitCode::from(exit_code::for_error(err))
 }
 
-pub async fn run() -> anyhow::Result<()> {
-    observability::init_tracing();
-    let cli = Cli::parse();
+/// The error format for a command line clap rejected: `--json-errors` anywhere before a `--`,
+/// or `CODEX_MGR_JSON_ERRORS` set to anything clap would read as true.
+fn unparsed_error_format(args: &[OsString], env: Option<&OsStr>) -> ErrorFormat {
+    let flag = args
+        .iter()
+        .skip(1)
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == "--json-errors");
+    let env = env.and_then(OsStr::to_str).is_some_and(|value| {
+        !matches!(
+            value.to_ascii_lowercase().as_str(),
+            "" | "0" | "n" | "no" | "f" | "false" | "off"
+        )
+    });
+    if flag || env {
+        ErrorFormat::Json
+    } else {
+        ErrorFormat::Prose
+    }
+}
+
+/// Help and version requests exit here, successfully; real parse errors come back categorized
+/// so they exit with the usage code instead of clap's 2.
+fn usage_error(err: clap::Error) -> anyhow::Error {
+    if !err.use_stderr() {
+        err.exit();
+    }
+    exit_code::ErrorCategory::Usage.wrap(anyhow::anyhow!(err.render().to_string()))
+}
+
+/// Whether the innermost subcommand was given `--json`.
+fn json_output_requested(matches: &ArgMatches) -> bool {
+    let mut current = matches;
+    while let Some((_, sub)) = current.subcommand() {
+        current = sub;
+    }
+    matches!(current.try_get_one::<bool>("json"), Ok(Some(true)))
+}
+
+/// `--config` when given, otherwise `config.toml` under the state root.
+fn config_path(cli: &Cli, state_root: &Path) -> PathBuf {
+    cli.config_path
+        .clone()
+        .unwrap_or_else(|| config::default_path(state_root))
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    observability::init_tracing(match &cli.command {
+        Commands::Serve(args) => observability::TracingOptions {
+            span_timings: args.trace_spans,
+            debug: args.debug,
+        },
+        _ => observability::TracingOptions::default(),
+    });
+    // Needs no state, so answer before resolving (and creating) any roots.
+    if let Commands::Completions(args) = &cli.command {
+        clap_complete::generate(
+            args.shell,
+            &mut Cli::command(),
+            "codex-mgr",
+            &mut std::io::stdout(),
+        );
+        return Ok(());
+    }
 
     let home = dirs::home_dir().context("failed to resolve home directory")?;
     let state_root = cli
@@ -230,6 +879,8 @@ pub async fn run() -> anyhow::Result<()> {
         .clone()
         .unwrap_or_else(|| home.join(DEFAULT_STATE_DIRNAME));
 
+    let config_path = config_path(&cli, &state_root);
+
     let shared_root = cli
         .shared_root
         .clone()
@@ -244,9 +895,7 @@ pub async fn run() -> anyhow::Result<()> {
         let legacy_shared = home.join(".codex-shared");
         if legacy_shared.exists() && !shared_root.exists() {
             tracing::warn!(
-                "Legacy shared directory found at {:?}, but new location {:?} does not exist. Please move it: `mv {:?} {:?}`",
-                legacy_shared,
-                shared_root,
+                "Legacy shared directory found at {:?}, but new location {:?} does not exist. Run `codex-mgr migrate` to move it and relink accounts.",
                 legacy_shared,
                 shared_root
             );
@@ -257,9 +906,7 @@ pub async fn run() -> anyhow::Result<()> {
         let legacy_accounts = home.join(".codex-accounts");
         if legacy_accounts.exists() && !accounts_root.exists() {
             tracing::warn!(
-                "Legacy accounts directory found at {:?}, but new location {:?} does not exist. Please move it: `mv {:?} {:?}`",
-                legacy_accounts,
-                accounts_root,
+                "Legacy accounts directory found at {:?}, but new location {:?} does not exist. Run `codex-mgr migrate` to move it and relink accounts.",
                 legacy_accounts,
                 accounts_root
             );
@@ -277,56 +924,233 @@ pub async fn run() -> anyhow::Result<()> {
                 &shared_root,
                 &accounts_root,
                 &state_root,
+                &config_path,
                 args.label,
-                args.device_auth,
-                args.force,
+                accounts::LoginOptions {
+                    device_auth: args.device_auth,
+                    force: args.force,
+                    trust: args.trust.trust_level,
+                },
             )
             .await
         }
         Commands::Accounts(args) => match args.command {
+            AccountsCommands::Add(add) => accounts::add(
+                &shared_root,
+                &accounts_root,
+                &state_root,
+                add.label,
+                &add.from,
+                add.trust.trust_level,
+            ),
             AccountsCommands::List(list) => {
-                accounts::list(&accounts_root, &state_root, list.json).await
+                let options = accounts::ListOptions {
+                    json: list.json,
+                    ndjson: list.ndjson,
+                    sort: list.sort,
+                    reverse: list.reverse,
+                    tag: list.tag,
+                };
+                if list.watch {
+                    accounts::watch(
+                        &shared_root,
+                        &accounts_root,
+                        &state_root,
+                        &config_path,
+                        options,
+                        list.interval,
+                    )
+                    .await
+                } else {
+                    accounts::list(&accounts_root, &state_root, &config_path, options).await
+                }
+            }
+            AccountsCommands::Whoami(whoami) => {
+                accounts::whoami(&accounts_root, whoami.label, whoami.json).await
             }
             AccountsCommands::Del(del) => {
-                accounts::del(&accounts_root, &state_root, del.label).await
+                accounts::del(
+                    &shared_root,
+                    &accounts_root,
+                    &state_root,
+                    &config_path,
+                    del.label,
+                    if del.purge {
+                        accounts::DeleteScope::Home
+                    } else {
+                        accounts::DeleteScope::Credentials
+                    },
+                )
+                .await
+            }
+            AccountsCommands::SetReserved(set) => {
+                accounts::set_reserved(&accounts_root, &state_root, set.label, set.reserved)
+            }
+            AccountsCommands::SetUsage(set) => {
+                let source = match (set.from_file, set.label) {
+                    (Some(path), _) => accounts::UsageSeedSource::File(path),
+                    (None, Some(label)) => accounts::UsageSeedSource::Label {
+                        label,
+                        seed: accounts::UsageSeed {
+                            five_hour: set.five_hour,
+                            weekly: set.weekly,
+                        },
+                    },
+                    (None, None) => anyhow::bail!("pass --label or --from-file"),
+                };
+                accounts::set_usage(&accounts_root, &state_root, source)
+            }
+            AccountsCommands::Tag(tag) => {
+                accounts::set_tags(&accounts_root, &state_root, tag.label, tag.tags)
+            }
+            AccountsCommands::Alias(alias) => {
+                aliases::set(&accounts_root, &state_root, alias.alias, alias.label)
+            }
+            AccountsCommands::Refresh(refresh) => {
+                let target = if refresh.all {
+                    accounts::RefreshTarget::All
+                } else if let Some(tag) = refresh.tag {
+                    accounts::RefreshTarget::Tag(tag)
+                } else {
+                    accounts::RefreshTarget::Labels(refresh.labels)
+                };
+                accounts::refresh(
+                    &accounts_root,
+                    &state_root,
+                    target,
+                    accounts::RefreshOptions {
+                        min_remaining: std::time::Duration::from_secs(refresh.min_remaining),
+                        force: refresh.force,
+                    },
+                )
+                .await
             }
         },
         Commands::Pools(args) => match args.command {
             PoolsCommands::Set(set) => {
                 pools::set(
                     &state_root,
+                    &config_path,
                     &accounts_root,
-                    set.pool_id,
-                    set.labels,
-                    set.policy_key,
+                    pools::SetOptions {
+                        pool_id: set.pool_id,
+                        labels: set.labels,
+                        policy_key: set.policy_key,
+                        default_note: set.default_note,
+                        sticky: set.sticky,
+                        allow_account_override: set.allow_account_override,
+                        tenant_header: set.tenant_header,
+                        from_tag: set.from_tag,
+                        dry_run: set.dry_run,
+                        diff: set.diff,
+                    },
+                )
+                .await
+            }
+            PoolsCommands::List(list) => pools::list(&config_path, list.json).await,
+            PoolsCommands::Show(show) => {
+                pools::show(&config_path, show.pool_id, show.samples, show.json).await
+            }
+            PoolsCommands::Del(del) => pools::del(&state_root, &config_path, del.pool_id).await,
+            PoolsCommands::RotatePolicy(rotate) => {
+                pools::rotate_policy(
+                    &state_root,
+                    &config_path,
+                    pools::RotatePolicyOptions {
+                        pool_id: rotate.pool_id,
+                        new_key: rotate.new_key,
+                        samples: rotate.samples,
+                        dry_run: rotate.dry_run,
+                        json: rotate.json,
+                    },
                 )
                 .await
             }
-            PoolsCommands::List(list) => pools::list(&state_root, list.json).await,
-            PoolsCommands::Del(del) => pools::del(&state_root, del.pool_id).await,
             PoolsCommands::AddMember(add) => {
-                pools::add_member(&state_root, &accounts_root, add.pool_id, add.label).await
+                pools::add_member(
+                    &state_root,
+                    &config_path,
+                    &accounts_root,
+                    add.pool_id,
+                    add.label,
+                )
+                .await
             }
             PoolsCommands::RemoveMember(remove) => {
-                pools::remove_member(&state_root, remove.pool_id, remove.label).await
+                pools::remove_member(&state_root, &config_path, remove.pool_id, remove.label).await
             }
             PoolsCommands::Validate(validate) => {
-                pools::validate(&state_root, &accounts_root, validate.pool_id).await
+                pools_validate::validate(
+                    &config_path,
+                    &accounts_root,
+                    validate.pool.or(validate.pool_id),
+                )
+                .await
+            }
+        },
+        Commands::Routing(args) => match args.command {
+            RoutingCommands::Bench(bench) => {
+                pools::bench(
+                    &config_path,
+                    bench.pool,
+                    bench.samples,
+                    bench.max_deviation_percent,
+                    bench.json,
+                )
+                .await
             }
         },
         Commands::Gateway(args) => match args.command {
             GatewayCommands::Issue(issue) => {
                 gateway::issue(
                     &state_root,
-                    issue.pool,
-                    issue.ttl_seconds,
-                    issue.note,
-                    issue.json,
+                    &config_path,
+                    &accounts_root,
+                    gateway::IssueOptions {
+                        pool_id: issue.pool,
+                        ttl_seconds: issue.ttl_seconds,
+                        note: issue.note,
+                        allowed_paths: issue.allow_paths,
+                        json: issue.json,
+                        example: issue.example,
+                        verify: issue.verify,
+                        preview: issue.preview,
+                    },
+                )
+                .await
+            }
+            GatewayCommands::List(list) => gateway::list(&config_path, list.json).await,
+            GatewayCommands::Inspect(inspect) => {
+                gateway::inspect(&config_path, inspect.token, inspect.json).await
+            }
+            GatewayCommands::Revoke(revoke) => {
+                gateway::revoke(&state_root, &config_path, revoke.token).await
+            }
+            GatewayCommands::Sticky(sticky) => {
+                let (target, action) = match sticky.command {
+                    GatewayStickyCommands::Get(target) => (target, gateway::StickyAction::Get),
+                    GatewayStickyCommands::Set(set) => (
+                        set.target,
+                        gateway::StickyAction::Set {
+                            label: set.label,
+                            ttl_seconds: set.ttl_seconds,
+                        },
+                    ),
+                    GatewayStickyCommands::Clear(target) => (target, gateway::StickyAction::Clear),
+                };
+                gateway::sticky(
+                    &state_root,
+                    &config_path,
+                    &accounts_root,
+                    gateway::StickyOptions {
+                        pool_id: target.pool,
+                        conversation_id: target.conversation_id,
+                        action,
+                        json: target.json,
+                    },
                 )
                 .await
             }
-            GatewayCommands::List(list) => gateway::list(&state_root, list.json).await,
-            GatewayCommands::Revoke(revoke) => gateway::revoke(&state_root, revoke.token).await,
         },
         Commands::Run(args) => {
             run_cmd::run(
@@ -334,18 +1158,112 @@ pub async fn run() -> anyhow::Result<()> {
                 &shared_root,
                 &accounts_root,
                 &state_root,
+                &config_path,
                 run_cmd::RunOptions {
                     auto: args.auto,
                     label: args.label,
-                    refresh: args.refresh,
-                    no_cache: args.no_cache,
+                    select: args.select.into_options(),
+                    trust: args.trust.trust_level,
                     upstream_args: args.args,
                 },
             )
             .await
         }
+        Commands::Pick(args) => {
+            run_cmd::pick(
+                &shared_root,
+                &accounts_root,
+                &state_root,
+                &config_path,
+                args.select.into_options(),
+                args.print,
+                args.trust.trust_level,
+            )
+            .await
+        }
         Commands::Serve(args) => {
-            serve::run(&state_root, &shared_root, &accounts_root, args.debug).await
+            if args.print_effective_config {
+                return serve::print_effective_config(&config_path, args.json);
+            }
+            serve::run(
+                &state_root,
+                &config_path,
+                &shared_root,
+                &accounts_root,
+                serve::ServeOptions {
+                    debug: args.debug,
+                    addr_file: args.addr_file,
+                },
+            )
+            .await
+        }
+        Commands::Doctor(args) => doctor::run(&shared_root, &accounts_root, args.json).await,
+        Commands::Migrate(args) => migrate::run(migrate::MigrateOptions {
+            home,
+            shared_root,
+            accounts_root,
+            dry_run: args.dry_run,
+        }),
+        Commands::Audit(args) => match args.command {
+            AuditCommands::Tail(tail) => audit::tail(&state_root, tail.lines),
+        },
+        Commands::State(args) => match args.command {
+            StateCommands::Show(show) => {
+                state_show::show(&accounts_root, &state_root, &config_path, show.json)
+            }
+        },
+        // Answered before the roots were set up.
+        Commands::Completions(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn config_flag_replaces_the_state_root_config() {
+        let state_root = Path::new("/state");
+        let cli = Cli::try_parse_from(["codex-mgr", "pools", "list"]).expect("parse");
+        assert_eq!(
+            config_path(&cli, state_root),
+            state_root.join("config.toml")
+        );
+
+        let cli = Cli::try_parse_from(["codex-mgr", "pools", "list", "--config", "/ro/gw.toml"])
+            .expect("parse");
+        assert_eq!(config_path(&cli, state_root), PathBuf::from("/ro/gw.toml"));
+    }
+
+    #[test]
+    fn unparsed_command_lines_honor_json_errors_flag_and_env() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            unparsed_error_format(&args(&["codex-mgr", "bogus", "--json-errors"]), None),
+            ErrorFormat::Json
+        );
+        assert_eq!(
+            unparsed_error_format(&args(&["codex-mgr", "bogus"]), None),
+            ErrorFormat::Prose
+        );
+        assert_eq!(
+            unparsed_error_format(&args(&["codex-mgr", "run", "--", "--json-errors"]), None),
+            ErrorFormat::Prose
+        );
+        for (value, format) in [
+            ("1", ErrorFormat::Json),
+            ("true", ErrorFormat::Json),
+            ("false", ErrorFormat::Prose),
+            ("0", ErrorFormat::Prose),
+            ("", ErrorFormat::Prose),
+        ] {
+            assert_eq!(
+                unparsed_error_format(&args(&["codex-mgr", "bogus"]), Some(OsStr::new(value))),
+                format,
+                "{value:?}"
+            );
         }
     }
 }
@@ -0,0 +1,154 @@
+use anyhow::Context;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::time::now_ms;
+
+const AUDIT_FILENAME: &str = "audit.jsonl";
+
+/// Characters of a gateway token kept in the log: the `gw_` marker plus enough of the random
+/// part to tell sessions apart without recording anything usable as a credential.
+const TOKEN_PREFIX_CHARS: usize = 11;
+
+pub(crate) const DEFAULT_TAIL_LINES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AuditEntry {
+    pub(crate) ts_ms: i64,
+    pub(crate) command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pool_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) labels: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) token_prefix: Option<String>,
+}
+
+impl AuditEntry {
+    pub(crate) fn new(command: &str) -> Self {
+        Self {
+            ts_ms: now_ms(),
+            command: command.to_string(),
+            pool_id: None,
+            labels: Vec::new(),
+            token_prefix: None,
+        }
+    }
+
+    pub(crate) fn pool(mut self, pool_id: &str) -> Self {
+        self.pool_id = Some(pool_id.to_string());
+        self
+    }
+
+    pub(crate) fn labels(mut self, labels: &[String]) -> Self {
+        self.labels = labels.to_vec();
+        self
+    }
+
+    pub(crate) fn token(mut self, token: &str) -> Self {
+        self.token_prefix = Some(token_prefix(token));
+        self
+    }
+}
+
+pub(crate) fn audit_path(state_root: &Path) -> PathBuf {
+    state_root.join(AUDIT_FILENAME)
+}
+
+fn token_prefix(token: &str) -> String {
+    token.chars().take(TOKEN_PREFIX_CHARS).collect()
+}
+
+/// Appends one entry to `audit.jsonl`. Called after the change it describes has been applied,
+/// so a failure here is reported as a warning rather than failing a command that already took
+/// effect.
+pub(crate) fn record(state_root: &Path, entry: AuditEntry) {
+    if let Err(err) = append(state_root, &entry) {
+        tracing::warn!(
+            "failed to append {:?} to audit log {:?}: {err:#}",
+            entry.command,
+            audit_path(state_root)
+        );
+    }
+}
+
+fn append(state_root: &Path, entry: &AuditEntry) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(entry).context("serializing audit entry")?;
+    line.push(b'\n');
+
+    let path = audit_path(state_root);
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("opening {path:?}"))?;
+    // A single write keeps concurrent appenders from interleaving within a line.
+    file.write_all(&line)
+        .with_context(|| format!("writing {path:?}"))?;
+    Ok(())
+}
+
+fn read_tail(state_root: &Path, lines: usize) -> anyhow::Result<Vec<String>> {
+    let path = audit_path(state_root);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("reading {path:?}")),
+    };
+    let all: Vec<&str> = contents.lines().filter(|l| !l.trim().is_empty()).collect();
+    let start = all.len().saturating_sub(lines);
+    Ok(all[start..].iter().map(ToString::to_string).collect())
+}
+
+pub(crate) fn tail(state_root: &Path, lines: usize) -> anyhow::Result<()> {
+    for line in read_tail(state_root, lines)? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn record_appends_lines_and_tail_returns_the_last_n() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        assert_eq!(
+            read_tail(dir.path(), 5).expect("tail"),
+            Vec::<String>::new()
+        );
+
+        record(
+            dir.path(),
+            AuditEntry::new("pools set")
+                .pool("team")
+                .labels(&["a".to_string(), "b".to_string()]),
+        );
+        record(dir.path(), AuditEntry::new("pools del").pool("team"));
+        record(
+            dir.path(),
+            AuditEntry::new("gateway revoke").token("gw_abcdefghijklmnopqrstuvwxyz"),
+        );
+
+        let lines = read_tail(dir.path(), 2).expect("tail");
+        assert_eq!(lines.len(), 2);
+        let first: AuditEntry = serde_json::from_str(&lines[0]).expect("parse");
+        assert_eq!(first.command, "pools del");
+        assert_eq!(first.pool_id.as_deref(), Some("team"));
+        let last: AuditEntry = serde_json::from_str(&lines[1]).expect("parse");
+        assert_eq!(last.token_prefix.as_deref(), Some("gw_abcdefgh"));
+        assert!(!lines[1].contains("ijklmnop"));
+
+        assert_eq!(read_tail(dir.path(), 10).expect("tail").len(), 3);
+    }
+}
@@ -0,0 +1,391 @@
+use anyhow::Context;
+use axum::body::Body;
+use axum::http::HeaderMap;
+use axum::http::request::Parts;
+use axum::response::Response;
+use base64::Engine;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use crate::proxy::ForwardBody;
+use crate::time::now_ms;
+
+const CAPTURE_FILENAME: &str = "captures.jsonl";
+
+/// Upper bound on `count` for one `POST /admin/capture`, so a typo cannot leave capture running
+/// indefinitely.
+pub(crate) const MAX_CAPTURE_COUNT: u32 = 100;
+
+/// Bodies are kept up to this many bytes in the capture file; the client still receives all of it.
+const MAX_CAPTURED_BODY_BYTES: usize = 1024 * 1024;
+
+/// Credentials never written to a capture. The client's `Authorization` is its gateway session
+/// token; the account token the gateway sends upstream is added after the capture is taken.
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+const REDACTED: &str = "[redacted]";
+
+/// Which proxied exchanges `POST /admin/capture` records; unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CaptureFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pool: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) account: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) path_prefix: Option<String>,
+}
+
+impl CaptureFilter {
+    fn matches(&self, pool_id: &str, account: &str, path: &str) -> bool {
+        self.pool.as_deref().is_none_or(|pool| pool == pool_id)
+            && self.account.as_deref().is_none_or(|label| label == account)
+            && self
+                .path_prefix
+                .as_deref()
+                .is_none_or(|prefix| path.starts_with(prefix))
+    }
+}
+
+/// Body of `POST /admin/capture`: `{"count": 5, "pool": "team", "path_prefix": "/responses"}`.
+/// `count: 0` disarms a capture in progress.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CaptureCommand {
+    pub(crate) count: u32,
+    #[serde(flatten)]
+    pub(crate) filter: CaptureFilter,
+}
+
+struct Armed {
+    remaining: u32,
+    filter: CaptureFilter,
+}
+
+/// The gateway's capture switch. Each upstream attempt that matches the filter takes one of the
+/// armed slots, and capture disarms itself once the last one is taken.
+pub(crate) struct RequestCapture {
+    path: PathBuf,
+    armed: Mutex<Option<Armed>>,
+}
+
+impl RequestCapture {
+    pub(crate) fn new(state_root: &Path) -> Self {
+        Self {
+            path: state_root.join(CAPTURE_FILENAME),
+            armed: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replaces any capture in progress; `count` is clamped to `MAX_CAPTURE_COUNT`.
+    pub(crate) fn arm(&self, count: u32, filter: CaptureFilter) -> u32 {
+        let count = count.min(MAX_CAPTURE_COUNT);
+        let mut armed = self.armed.lock().unwrap_or_else(PoisonError::into_inner);
+        *armed = (count > 0).then_some(Armed {
+            remaining: count,
+            filter,
+        });
+        count
+    }
+
+    fn claim(&self, pool_id: &str, account: &str, path: &str) -> bool {
+        let mut armed = self.armed.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(current) = armed.as_mut() else {
+            return false;
+        };
+        if !current.filter.matches(pool_id, account, path) {
+            return false;
+        }
+        current.remaining -= 1;
+        if current.remaining == 0 {
+            *armed = None;
+            tracing::info!(event = %"capture_complete", "request capture disarmed");
+        }
+        true
+    }
+
+    /// Takes a capture slot for one upstream attempt, returning the request half of its record.
+    pub(crate) fn begin(&self, attempt: CaptureAttempt<'_>) -> Option<CaptureRecord> {
+        let CaptureAttempt {
+            pool_id,
+            account,
+            request_id,
+            parts,
+            body,
+        } = attempt;
+        if !self.claim(pool_id, account, parts.uri.path()) {
+            return None;
+        }
+        Some(CaptureRecord {
+            ts_ms: now_ms(),
+            request_id: request_id.map(str::to_string),
+            pool_id: pool_id.to_string(),
+            account: account.to_string(),
+            method: parts.method.to_string(),
+            uri: parts
+                .uri
+                .path_and_query()
+                .map_or_else(|| parts.uri.path().to_string(), ToString::to_string),
+            request_headers: redacted_headers(&parts.headers),
+            request_body: match body {
+                ForwardBody::Empty => Some(CapturedBody::new(&[], 0)),
+                ForwardBody::Buffered(bytes) => Some(CapturedBody::new(bytes, bytes.len())),
+                ForwardBody::Streaming(_) => None,
+            },
+            response_status: None,
+            response_headers: BTreeMap::new(),
+            response_body: None,
+            error: None,
+        })
+    }
+
+    /// Appends `record` to `captures.jsonl`; failures are logged, never surfaced to the client.
+    pub(crate) fn write(&self, record: &CaptureRecord) {
+        if let Err(err) = self.append(record) {
+            tracing::warn!(path = ?self.path, "failed to write request capture: {err:#}");
+        }
+    }
+
+    fn append(&self, record: &CaptureRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record).context("serializing capture record")?;
+        line.push(b'\n');
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&self.path)
+            .with_context(|| format!("opening {:?}", self.path))?;
+        file.write_all(&line)
+            .with_context(|| format!("appending to {:?}", self.path))
+    }
+
+    /// Relays `response` unchanged while copying its body into `record`, which is written once
+    /// the body finishes or the client goes away.
+    pub(crate) fn relay(
+        self: &Arc<Self>,
+        mut record: CaptureRecord,
+        response: Response,
+    ) -> Response {
+        record.response_head(&response);
+        let (parts, body) = response.into_parts();
+        let mut tee = ResponseTee {
+            capture: Arc::clone(self),
+            record: Some(record),
+            body: Vec::new(),
+            total: 0,
+        };
+        let stream = body.into_data_stream().map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                tee.push(bytes);
+            }
+            chunk
+        });
+        Response::from_parts(parts, Body::from_stream(stream))
+    }
+}
+
+pub(crate) struct CaptureAttempt<'a> {
+    pub(crate) pool_id: &'a str,
+    pub(crate) account: &'a str,
+    pub(crate) request_id: Option<&'a str>,
+    pub(crate) parts: &'a Parts,
+    pub(crate) body: &'a ForwardBody,
+}
+
+/// One line of `captures.jsonl`.
+#[derive(Debug, Serialize)]
+pub(crate) struct CaptureRecord {
+    ts_ms: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    pool_id: String,
+    account: String,
+    method: String,
+    uri: String,
+    request_headers: BTreeMap<String, String>,
+    /// `None` for streamed uploads, which are relayed without being buffered.
+    request_body: Option<CapturedBody>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_status: Option<u16>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    response_headers: BTreeMap<String, String>,
+    /// `None` when the attempt failed or its response was discarded for a retry.
+    response_body: Option<CapturedBody>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl CaptureRecord {
+    /// Status and headers of a response that is not relayed, e.g. a 429 retried elsewhere.
+    pub(crate) fn response_head(&mut self, response: &Response) {
+        self.response_status = Some(response.status().as_u16());
+        self.response_headers = redacted_headers(response.headers());
+    }
+
+    pub(crate) fn failed(&mut self, status: axum::http::StatusCode, detail: &str) {
+        self.response_status = Some(status.as_u16());
+        self.error = Some(detail.to_string());
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct CapturedBody {
+    bytes: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base64: Option<String>,
+}
+
+impl CapturedBody {
+    /// `kept` is the captured prefix of a body that was `total` bytes long. UTF-8 is stored as
+    /// text and anything else as base64.
+    fn new(kept: &[u8], total: usize) -> Self {
+        let kept = &kept[..kept.len().min(MAX_CAPTURED_BODY_BYTES)];
+        let (text, base64) = match std::str::from_utf8(kept) {
+            Ok(text) => (Some(text.to_string()), None),
+            Err(_) => (
+                None,
+                Some(base64::engine::general_purpose::STANDARD.encode(kept)),
+            ),
+        };
+        Self {
+            bytes: total,
+            truncated: kept.len() < total,
+            text,
+            base64,
+        }
+    }
+}
+
+fn redacted_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    for (name, value) in headers {
+        let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+            REDACTED.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        out.entry(name.as_str().to_string())
+            .and_modify(|existing: &mut String| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    out
+}
+
+/// Copies relayed chunks into the record and writes it when dropped, i.e. when the body has
+/// been fully relayed or the client disconnected part way through.
+struct ResponseTee {
+    capture: Arc<RequestCapture>,
+    record: Option<CaptureRecord>,
+    body: Vec<u8>,
+    total: usize,
+}
+
+impl ResponseTee {
+    fn push(&mut self, chunk: &[u8]) {
+        self.total += chunk.len();
+        let room = MAX_CAPTURED_BODY_BYTES.saturating_sub(self.body.len());
+        self.body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+}
+
+impl Drop for ResponseTee {
+    fn drop(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            record.response_body = Some(CapturedBody::new(&self.body, self.total));
+            self.capture.write(&record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use axum::http::Request;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn capture_disarms_after_count_and_honours_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let capture = RequestCapture::new(dir.path());
+        let (mut parts, ()) = Request::post("/responses?x=1")
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts.headers.insert(
+            axum::http::header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer gw_secret"),
+        );
+        let body = ForwardBody::Buffered(bytes::Bytes::from_static(b"{\"input\":1}"));
+        let attempt = |account| CaptureAttempt {
+            pool_id: "team",
+            account,
+            request_id: None,
+            parts: &parts,
+            body: &body,
+        };
+
+        assert!(capture.begin(attempt("a")).is_none());
+
+        capture.arm(
+            2,
+            CaptureFilter {
+                account: Some("b".to_string()),
+                ..CaptureFilter::default()
+            },
+        );
+        assert!(capture.begin(attempt("a")).is_none());
+        let record = capture.begin(attempt("b")).unwrap();
+        assert_eq!(record.uri, "/responses?x=1");
+        assert_eq!(
+            record
+                .request_headers
+                .get("authorization")
+                .map(String::as_str),
+            Some(REDACTED)
+        );
+        assert_eq!(
+            record.request_body,
+            Some(CapturedBody::new(b"{\"input\":1}", 11))
+        );
+        assert!(capture.begin(attempt("b")).is_some());
+        assert!(capture.begin(attempt("b")).is_none());
+    }
+
+    #[test]
+    fn captured_body_truncates_and_falls_back_to_base64() {
+        let body = CapturedBody::new(&[0xff, 0x00], 10);
+        assert!(body.truncated);
+        assert_eq!(body.text, None);
+        assert_eq!(body.base64.as_deref(), Some("/wA="));
+    }
+}
@@ -1,44 +1,203 @@
 use anyhow::Context;
 use serde::Deserialize;
+use serde::Serialize;
 use std::collections::BTreeMap;
 use std::path::Path;
 use std::path::PathBuf;
 use toml::Value;
 
+use crate::exit_code::ErrorCategory;
+
 const DEFAULT_LISTEN: &str = "127.0.0.1:8787";
 const DEFAULT_UPSTREAM_BASE_URL: &str = "https://chatgpt.com/backend-api/codex";
+const DEFAULT_ALLOWED_UPSTREAM_HOSTS: &[&str] = &["chatgpt.com"];
 const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1:6379";
+const DEFAULT_REDIS_COMMAND_TIMEOUT_MS: i64 = 2000;
+const DEFAULT_REDIS_RETRY_MAX: i64 = 2;
+const MAX_REDIS_RETRY_MAX: i64 = 10;
 const DEFAULT_STICKY_TTL_SECONDS: i64 = 7200;
-const DEFAULT_TOKEN_SAFETY_WINDOW_SECONDS: i64 = 120;
+pub(crate) const DEFAULT_TOKEN_SAFETY_WINDOW_SECONDS: i64 = 120;
+pub(crate) const DEFAULT_MIN_TOKEN_SAFETY_WINDOW_SECONDS: i64 = 30;
+const DEFAULT_MAX_TOKEN_CACHE_SECONDS: i64 = 86_400;
+const DEFAULT_USAGE_REFRESH_INTERVAL_SECONDS: i64 = 60;
+const DEFAULT_USAGE_FETCH_TIMEOUT_SECONDS: i64 = 20;
+pub(crate) const DEFAULT_USAGE_CACHE_TTL_SECONDS: i64 = 900;
+const DEFAULT_GATEWAY_USAGE_CACHE_TTL_SECONDS: i64 = 300;
+const DEFAULT_OTLP_INTERVAL_SECONDS: i64 = 60;
+const DEFAULT_MAX_LABELS_PER_POOL: i64 = 64;
+const DEFAULT_STRIP_RESPONSE_HEADERS: &[&str] = &["set-cookie"];
 
-pub(crate) fn config_path(state_root: &Path) -> PathBuf {
+/// The config file used when `--config` is not given. The readers and writers below take the
+/// resolved path, so `--config` can point elsewhere while state files stay under `state_root`.
+pub(crate) fn default_path(state_root: &Path) -> PathBuf {
     state_root.join("config.toml")
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct ManagerConfig {
     pub(crate) gateway: GatewayConfig,
     pub(crate) pools: BTreeMap<String, PoolConfig>,
+    /// Friendly names for pool ids, from `[pool_aliases]`.
+    pub(crate) pool_aliases: BTreeMap<String, String>,
+}
+
+/// Redis topology behind `gateway.redis_url` / `gateway.redis_nodes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RedisMode {
+    #[default]
+    Single,
+    Cluster,
+    Sentinel,
 }
 
-#[derive(Debug, Clone)]
+/// How a request carrying both `conversation_id` and `session_id` with different values is
+/// routed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConversationIdConflict {
+    /// Reject the request with `400`.
+    Strict,
+    /// Route on `conversation_id`.
+    #[default]
+    PreferConversation,
+    /// Route on `session_id`.
+    PreferSession,
+}
+
+impl GatewayConfig {
+    pub(crate) fn redis_node_urls(&self) -> Vec<String> {
+        if self.redis_nodes.is_empty() {
+            vec![self.redis_url.clone()]
+        } else {
+            self.redis_nodes.clone()
+        }
+    }
+}
+
+/// Pool id that works without a `[pools.default]` entry, routing across every unreserved
+/// account. Once `[pools.default]` is configured it is used like any other pool.
+pub(crate) const DEFAULT_POOL_ID: &str = "default";
+
+impl ManagerConfig {
+    /// Whether `pool_id` (already alias-resolved) is the implicit all-accounts pool: `default`
+    /// with no `[pools.default]` entry.
+    pub(crate) fn is_implicit_default_pool(&self, pool_id: &str) -> bool {
+        pool_id == DEFAULT_POOL_ID && !self.pools.contains_key(pool_id)
+    }
+
+    /// Maps `name` through `[pool_aliases]`, returning it unchanged when it is not an alias.
+    pub(crate) fn resolve_pool_alias(&self, name: &str) -> String {
+        self.pool_aliases
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct GatewayConfig {
     pub(crate) listen: String,
     pub(crate) upstream_base_url: String,
     pub(crate) redis_url: String,
+    pub(crate) redis_mode: RedisMode,
+    /// Cluster seed nodes or sentinel addresses; defaults to `redis_url` alone.
+    pub(crate) redis_nodes: Vec<String>,
+    /// Master name the sentinels monitor; required when `redis_mode = "sentinel"`.
+    pub(crate) redis_sentinel_master: Option<String>,
+    /// Upper bound on connecting to Redis and on each Redis command.
+    pub(crate) redis_command_timeout_ms: i64,
+    /// Extra attempts for idempotent Redis reads (session and sticky lookups) after a transient
+    /// error such as a dropped connection; 0 disables retries.
+    pub(crate) redis_retry_max: i64,
     pub(crate) sticky_ttl_seconds: i64,
     pub(crate) token_safety_window_seconds: i64,
+    /// Floor for `token_safety_window_seconds`; smaller configured windows are raised to it.
+    pub(crate) min_token_safety_window_seconds: i64,
+    pub(crate) max_token_cache_seconds: i64,
+    /// When set, `serve` loads every pool member's token into the cache at startup, one account
+    /// every this many milliseconds; unset leaves tokens to load on first use.
+    pub(crate) token_prewarm_stagger_ms: Option<i64>,
+    /// Up to this many extra milliseconds, chosen at random, added to each prewarm gap and to the
+    /// first one, so gateways restarted together do not refresh in lockstep.
+    pub(crate) token_prewarm_jitter_ms: i64,
+    /// Per-pool budget for `gateway issue`, as a token bucket: up to this many at once, refilled at
+    /// this many per minute.
+    pub(crate) max_issues_per_minute: Option<i64>,
+    /// Cap on concurrently handled non-health requests; excess requests get `503`.
+    pub(crate) max_concurrent_requests: Option<i64>,
+    /// Total budget for a non-streaming request, upstream body download included; exceeding it
+    /// returns `504`. Requests accepting `text/event-stream` are exempt.
+    pub(crate) non_streaming_deadline_ms: Option<i64>,
+    /// Longest gap between chunks of a relayed SSE stream before the gateway ends it.
+    pub(crate) sse_idle_timeout_ms: Option<i64>,
+    /// Path under `upstream_base_url` that `/readyz` probes; unset skips the upstream check.
+    pub(crate) upstream_health_path: Option<String>,
+    /// How often `serve` refreshes the usage scores used for routing; `0` disables the refresher.
+    pub(crate) usage_refresh_interval_seconds: i64,
+    /// Per-account timeout for a single usage fetch.
+    pub(crate) usage_fetch_timeout_seconds: i64,
+    /// Longest a cached usage snapshot stays usable for routing before the refresher refetches it,
+    /// whether read from `state.json` or from another replica's `gw:usage:` key in Redis;
+    /// independent of the CLI's `[usage] cache_ttl_seconds`.
+    pub(crate) usage_cache_ttl_seconds: i64,
+    /// Alternate upstream that receives `canary_percent` of HTTP traffic.
+    pub(crate) canary_upstream_base_url: Option<String>,
+    /// Share of conversations (0-100) routed to `canary_upstream_base_url`.
+    pub(crate) canary_percent: i64,
+    /// Hosts (and their subdomains) that upstream URLs may point at, since account tokens are
+    /// sent to them.
+    pub(crate) allowed_upstream_hosts: Vec<String>,
+    /// Explicit opt-out of `allowed_upstream_hosts`, e.g. for a local mock upstream.
+    pub(crate) allow_any_upstream_host: bool,
+    /// Account that also receives a copy of `shadow_percent` of HTTP requests, for comparing
+    /// its status and latency against the account that served the client.
+    pub(crate) shadow_account: Option<String>,
+    /// Share of requests (0-100) mirrored to `shadow_account`.
+    pub(crate) shadow_percent: i64,
+    /// Bearer token for `/admin/*` endpoints; the endpoints are disabled when unset.
+    pub(crate) admin_token: Option<String>,
+    /// Rewrite `Accept-Encoding` to `identity` on `text/event-stream` requests (default: true).
+    pub(crate) sse_identity_encoding: bool,
+    /// Which id wins when `conversation_id` and `session_id` headers disagree.
+    pub(crate) conversation_id_conflict: ConversationIdConflict,
+    /// Extra request headers dropped before forwarding upstream.
+    pub(crate) strip_request_headers: Vec<String>,
+    /// Upstream response headers dropped before reaching clients; replaces the default list.
+    pub(crate) strip_response_headers: Vec<String>,
+    /// API version/beta headers (e.g. `OpenAI-Beta`) set on every upstream request, replacing
+    /// any value the client sent.
+    pub(crate) upstream_api_headers: BTreeMap<String, String>,
+    /// OTLP/HTTP metrics endpoint (e.g. `http://collector:4318/v1/metrics`). Requires the `otlp`
+    /// cargo feature; `/metrics` keeps serving Prometheus text either way.
+    pub(crate) otlp_endpoint: Option<String>,
+    /// How often metrics are pushed to `otlp_endpoint`.
+    pub(crate) otlp_interval_seconds: i64,
+    /// Pool (or alias) `gateway issue` uses when `--pool` is omitted.
+    pub(crate) default_pool: Option<String>,
+    /// Most labels a pool may list; larger pools are refused on load and by the `pools` writers.
+    pub(crate) max_labels_per_pool: i64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct PoolConfig {
     pub(crate) labels: Vec<String>,
     pub(crate) policy_key: Option<String>,
+    pub(crate) default_note: Option<String>,
+    pub(crate) sticky: bool,
+    /// Honor `x-codex-mgr-account` on requests for this pool (default: false).
+    pub(crate) allow_account_override: bool,
+    /// Lowercased request header whose value alone picks the account, so each tenant always
+    /// lands on the same member regardless of conversation.
+    pub(crate) tenant_header: Option<String>,
+}
+
+pub(crate) fn load(path: &Path) -> anyhow::Result<ManagerConfig> {
+    load_config(path).map_err(|err| ErrorCategory::Config.wrap(err))
 }
 
-pub(crate) fn load(state_root: &Path) -> anyhow::Result<ManagerConfig> {
-    let path = config_path(state_root);
-    let text = std::fs::read_to_string(&path).with_context(|| {
+fn load_config(path: &Path) -> anyhow::Result<ManagerConfig> {
+    let text = std::fs::read_to_string(path).with_context(|| {
         format!(
             "reading config file {path:?} (create it; see docs/multi_account_gateway.md for an example)"
         )
@@ -49,6 +208,8 @@ pub(crate) fn load(state_root: &Path) -> anyhow::Result<ManagerConfig> {
         gateway: Option<RawGatewayConfig>,
         #[serde(default)]
         pools: BTreeMap<String, RawPoolConfig>,
+        #[serde(default)]
+        pool_aliases: BTreeMap<String, String>,
     }
 
     #[derive(Deserialize)]
@@ -56,14 +217,51 @@ pub(crate) fn load(state_root: &Path) -> anyhow::Result<ManagerConfig> {
         listen: Option<String>,
         upstream_base_url: Option<String>,
         redis_url: Option<String>,
+        redis_mode: Option<RedisMode>,
+        redis_nodes: Option<Vec<String>>,
+        redis_sentinel_master: Option<String>,
+        redis_command_timeout_ms: Option<i64>,
+        redis_retry_max: Option<i64>,
         sticky_ttl_seconds: Option<i64>,
         token_safety_window_seconds: Option<i64>,
+        min_token_safety_window_seconds: Option<i64>,
+        max_token_cache_seconds: Option<i64>,
+        token_prewarm_stagger_ms: Option<i64>,
+        token_prewarm_jitter_ms: Option<i64>,
+        max_issues_per_minute: Option<i64>,
+        max_concurrent_requests: Option<i64>,
+        non_streaming_deadline_ms: Option<i64>,
+        sse_idle_timeout_ms: Option<i64>,
+        upstream_health_path: Option<String>,
+        usage_refresh_interval_seconds: Option<i64>,
+        usage_fetch_timeout_seconds: Option<i64>,
+        usage_cache_ttl_seconds: Option<i64>,
+        canary_upstream_base_url: Option<String>,
+        canary_percent: Option<i64>,
+        allowed_upstream_hosts: Option<Vec<String>>,
+        allow_any_upstream_host: Option<bool>,
+        shadow_account: Option<String>,
+        shadow_percent: Option<i64>,
+        admin_token: Option<String>,
+        sse_identity_encoding: Option<bool>,
+        conversation_id_conflict: Option<ConversationIdConflict>,
+        strip_request_headers: Option<Vec<String>>,
+        strip_response_headers: Option<Vec<String>>,
+        upstream_api_headers: Option<BTreeMap<String, String>>,
+        otlp_endpoint: Option<String>,
+        otlp_interval_seconds: Option<i64>,
+        default_pool: Option<String>,
+        max_labels_per_pool: Option<i64>,
     }
 
     #[derive(Deserialize)]
     struct RawPoolConfig {
         labels: Vec<String>,
         policy_key: Option<String>,
+        default_note: Option<String>,
+        sticky: Option<bool>,
+        allow_account_override: Option<bool>,
+        tenant_header: Option<String>,
     }
 
     let raw: RawConfig =
@@ -72,7 +270,7 @@ pub(crate) fn load(state_root: &Path) -> anyhow::Result<ManagerConfig> {
         .gateway
         .context("missing [gateway] config section in config.toml")?;
 
-    let gateway = GatewayConfig {
+    let mut gateway = GatewayConfig {
         listen: gw.listen.unwrap_or_else(|| DEFAULT_LISTEN.to_string()),
         upstream_base_url: gw
             .upstream_base_url
@@ -82,32 +280,212 @@ pub(crate) fn load(state_root: &Path) -> anyhow::Result<ManagerConfig> {
             .redis_url
             .filter(|v| !v.trim().is_empty())
             .unwrap_or_else(|| DEFAULT_REDIS_URL.to_string()),
+        redis_mode: gw.redis_mode.unwrap_or_default(),
+        redis_nodes: gw
+            .redis_nodes
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|v| !v.trim().is_empty())
+            .collect(),
+        redis_sentinel_master: gw.redis_sentinel_master.filter(|v| !v.trim().is_empty()),
+        redis_command_timeout_ms: gw
+            .redis_command_timeout_ms
+            .unwrap_or(DEFAULT_REDIS_COMMAND_TIMEOUT_MS),
+        redis_retry_max: gw.redis_retry_max.unwrap_or(DEFAULT_REDIS_RETRY_MAX),
         sticky_ttl_seconds: gw.sticky_ttl_seconds.unwrap_or(DEFAULT_STICKY_TTL_SECONDS),
         token_safety_window_seconds: gw
             .token_safety_window_seconds
             .unwrap_or(DEFAULT_TOKEN_SAFETY_WINDOW_SECONDS),
+        min_token_safety_window_seconds: gw
+            .min_token_safety_window_seconds
+            .unwrap_or(DEFAULT_MIN_TOKEN_SAFETY_WINDOW_SECONDS),
+        max_token_cache_seconds: gw
+            .max_token_cache_seconds
+            .unwrap_or(DEFAULT_MAX_TOKEN_CACHE_SECONDS),
+        token_prewarm_stagger_ms: gw.token_prewarm_stagger_ms,
+        token_prewarm_jitter_ms: gw.token_prewarm_jitter_ms.unwrap_or(0),
+        max_issues_per_minute: gw.max_issues_per_minute,
+        max_concurrent_requests: gw.max_concurrent_requests,
+        non_streaming_deadline_ms: gw.non_streaming_deadline_ms,
+        sse_idle_timeout_ms: gw.sse_idle_timeout_ms,
+        upstream_health_path: gw.upstream_health_path,
+        usage_refresh_interval_seconds: gw
+            .usage_refresh_interval_seconds
+            .unwrap_or(DEFAULT_USAGE_REFRESH_INTERVAL_SECONDS),
+        usage_fetch_timeout_seconds: gw
+            .usage_fetch_timeout_seconds
+            .unwrap_or(DEFAULT_USAGE_FETCH_TIMEOUT_SECONDS),
+        usage_cache_ttl_seconds: gw
+            .usage_cache_ttl_seconds
+            .unwrap_or(DEFAULT_GATEWAY_USAGE_CACHE_TTL_SECONDS),
+        canary_upstream_base_url: gw.canary_upstream_base_url.filter(|v| !v.trim().is_empty()),
+        canary_percent: gw.canary_percent.unwrap_or(0),
+        allowed_upstream_hosts: gw
+            .allowed_upstream_hosts
+            .map(|hosts| {
+                hosts
+                    .iter()
+                    .map(|host| host.trim().to_ascii_lowercase())
+                    .collect()
+            })
+            .unwrap_or_else(|| {
+                DEFAULT_ALLOWED_UPSTREAM_HOSTS
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect()
+            }),
+        allow_any_upstream_host: gw.allow_any_upstream_host.unwrap_or(false),
+        shadow_account: gw.shadow_account.filter(|v| !v.trim().is_empty()),
+        shadow_percent: gw.shadow_percent.unwrap_or(0),
+        admin_token: gw.admin_token.filter(|v| !v.trim().is_empty()),
+        sse_identity_encoding: gw.sse_identity_encoding.unwrap_or(true),
+        conversation_id_conflict: gw.conversation_id_conflict.unwrap_or_default(),
+        strip_request_headers: gw.strip_request_headers.unwrap_or_default(),
+        strip_response_headers: gw.strip_response_headers.unwrap_or_else(|| {
+            DEFAULT_STRIP_RESPONSE_HEADERS
+                .iter()
+                .map(|name| (*name).to_string())
+                .collect()
+        }),
+        upstream_api_headers: gw.upstream_api_headers.unwrap_or_default(),
+        otlp_endpoint: gw.otlp_endpoint.filter(|v| !v.trim().is_empty()),
+        otlp_interval_seconds: gw
+            .otlp_interval_seconds
+            .unwrap_or(DEFAULT_OTLP_INTERVAL_SECONDS),
+        default_pool: gw.default_pool.filter(|v| !v.trim().is_empty()),
+        max_labels_per_pool: gw
+            .max_labels_per_pool
+            .unwrap_or(DEFAULT_MAX_LABELS_PER_POOL),
     };
+    if gateway.redis_mode == RedisMode::Sentinel && gateway.redis_sentinel_master.is_none() {
+        anyhow::bail!(
+            "gateway.redis_sentinel_master is required when gateway.redis_mode = \"sentinel\""
+        );
+    }
+    if gateway.redis_command_timeout_ms <= 0 {
+        anyhow::bail!("gateway.redis_command_timeout_ms must be > 0");
+    }
+    if !(0..=MAX_REDIS_RETRY_MAX).contains(&gateway.redis_retry_max) {
+        anyhow::bail!("gateway.redis_retry_max must be between 0 and {MAX_REDIS_RETRY_MAX}");
+    }
+    if gateway.token_safety_window_seconds < 0 {
+        anyhow::bail!("gateway.token_safety_window_seconds must be >= 0");
+    }
+    if gateway.min_token_safety_window_seconds < 0 {
+        anyhow::bail!("gateway.min_token_safety_window_seconds must be >= 0");
+    }
+    if gateway.token_safety_window_seconds < gateway.min_token_safety_window_seconds {
+        // Tokens used right up to expiry fail mid-request under clock jitter.
+        tracing::warn!(
+            configured = gateway.token_safety_window_seconds,
+            floor = gateway.min_token_safety_window_seconds,
+            "gateway.token_safety_window_seconds is below gateway.min_token_safety_window_seconds; using the floor"
+        );
+        gateway.token_safety_window_seconds = gateway.min_token_safety_window_seconds;
+    }
+    if gateway.max_token_cache_seconds <= 0 {
+        anyhow::bail!("gateway.max_token_cache_seconds must be > 0");
+    }
+    if gateway.token_prewarm_stagger_ms.is_some_and(|ms| ms < 0) {
+        anyhow::bail!("gateway.token_prewarm_stagger_ms must be >= 0");
+    }
+    if gateway.token_prewarm_jitter_ms < 0 {
+        anyhow::bail!("gateway.token_prewarm_jitter_ms must be >= 0");
+    }
+    if gateway.max_issues_per_minute.is_some_and(|max| max <= 0) {
+        anyhow::bail!("gateway.max_issues_per_minute must be > 0 when set");
+    }
+    if gateway.max_concurrent_requests.is_some_and(|max| max <= 0) {
+        anyhow::bail!("gateway.max_concurrent_requests must be > 0 when set");
+    }
+    if gateway.non_streaming_deadline_ms.is_some_and(|ms| ms <= 0) {
+        anyhow::bail!("gateway.non_streaming_deadline_ms must be > 0 when set");
+    }
+    if gateway.sse_idle_timeout_ms.is_some_and(|ms| ms <= 0) {
+        anyhow::bail!("gateway.sse_idle_timeout_ms must be > 0 when set");
+    }
+    if gateway
+        .upstream_health_path
+        .as_deref()
+        .is_some_and(|path| !path.starts_with('/'))
+    {
+        anyhow::bail!("gateway.upstream_health_path must start with '/'");
+    }
+    if gateway.usage_refresh_interval_seconds < 0 {
+        anyhow::bail!("gateway.usage_refresh_interval_seconds must be >= 0");
+    }
+    if gateway.otlp_interval_seconds <= 0 {
+        anyhow::bail!("gateway.otlp_interval_seconds must be > 0");
+    }
+    if gateway.usage_fetch_timeout_seconds <= 0 {
+        anyhow::bail!("gateway.usage_fetch_timeout_seconds must be > 0");
+    }
+    if gateway.usage_cache_ttl_seconds <= 0 {
+        anyhow::bail!("gateway.usage_cache_ttl_seconds must be > 0");
+    }
+    if !(0..=100).contains(&gateway.canary_percent) {
+        anyhow::bail!("gateway.canary_percent must be between 0 and 100");
+    }
+    if gateway.canary_percent > 0 && gateway.canary_upstream_base_url.is_none() {
+        anyhow::bail!("gateway.canary_percent requires gateway.canary_upstream_base_url");
+    }
+    if gateway.allowed_upstream_hosts.iter().any(String::is_empty) {
+        anyhow::bail!("gateway.allowed_upstream_hosts must not contain empty hosts");
+    }
+    let upstream_hosts = gateway.upstream_hosts();
+    upstream_hosts.check(&gateway.upstream_base_url)?;
+    if let Some(canary) = &gateway.canary_upstream_base_url {
+        upstream_hosts.check(canary)?;
+    }
+    if !(0..=100).contains(&gateway.shadow_percent) {
+        anyhow::bail!("gateway.shadow_percent must be between 0 and 100");
+    }
+    if gateway.shadow_percent > 0 && gateway.shadow_account.is_none() {
+        anyhow::bail!("gateway.shadow_percent requires gateway.shadow_account");
+    }
+    if let Some(label) = &gateway.shadow_account {
+        crate::label::validate_label(label)
+            .with_context(|| format!("invalid gateway.shadow_account {label:?}"))?;
+    }
+    if gateway.max_labels_per_pool <= 0 {
+        anyhow::bail!("gateway.max_labels_per_pool must be > 0");
+    }
 
     let pools = raw
         .pools
         .into_iter()
         .map(|(k, v)| {
-            (
+            if i64::try_from(v.labels.len()).unwrap_or(i64::MAX) > gateway.max_labels_per_pool {
+                anyhow::bail!(
+                    "pool {k:?} lists {} labels, more than gateway.max_labels_per_pool ({})",
+                    v.labels.len(),
+                    gateway.max_labels_per_pool
+                );
+            }
+            let tenant_header = tenant_header(&k, v.tenant_header.as_deref())?;
+            Ok((
                 k,
                 PoolConfig {
                     labels: v.labels,
                     policy_key: v.policy_key,
+                    default_note: v.default_note.filter(|note| !note.trim().is_empty()),
+                    sticky: v.sticky.unwrap_or(true),
+                    allow_account_override: v.allow_account_override.unwrap_or(false),
+                    tenant_header,
                 },
-            )
+            ))
         })
-        .collect();
+        .collect::<anyhow::Result<_>>()?;
 
-    Ok(ManagerConfig { gateway, pools })
+    Ok(ManagerConfig {
+        gateway,
+        pools,
+        pool_aliases: raw.pool_aliases,
+    })
 }
 
-pub(crate) fn load_value_for_update(state_root: &Path) -> anyhow::Result<Value> {
-    let path = config_path(state_root);
-    match std::fs::read_to_string(&path) {
+pub(crate) fn load_value_for_update(path: &Path) -> anyhow::Result<Value> {
+    match std::fs::read_to_string(path) {
         Ok(text) => toml::from_str(&text).with_context(|| format!("parsing config file {path:?}")),
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
             Ok(Value::Table(toml::Table::new()))
@@ -116,9 +494,71 @@ pub(crate) fn load_value_for_update(state_root: &Path) -> anyhow::Result<Value>
     }
 }
 
-pub(crate) fn load_value_optional(state_root: &Path) -> anyhow::Result<Value> {
-    let path = config_path(state_root);
-    match std::fs::read_to_string(&path) {
+/// Where the gateway may send account tokens: `gateway.allowed_upstream_hosts`, unless
+/// `gateway.allow_any_upstream_host` lifts the restriction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum UpstreamHosts {
+    Any,
+    /// Lowercased hosts; each also admits its subdomains.
+    Only(Vec<String>),
+}
+
+impl GatewayConfig {
+    pub(crate) fn upstream_hosts(&self) -> UpstreamHosts {
+        if self.allow_any_upstream_host {
+            UpstreamHosts::Any
+        } else {
+            UpstreamHosts::Only(self.allowed_upstream_hosts.clone())
+        }
+    }
+}
+
+impl UpstreamHosts {
+    pub(crate) fn check(&self, url: &str) -> anyhow::Result<()> {
+        let Self::Only(hosts) = self else {
+            return Ok(());
+        };
+        let parsed = reqwest::Url::parse(url.trim())
+            .with_context(|| format!("parsing upstream url {url:?}"))?;
+        let host = parsed
+            .host_str()
+            .with_context(|| format!("upstream url {url:?} has no host"))?
+            .to_ascii_lowercase();
+        let allowed = hosts.iter().any(|allowed| {
+            host == *allowed
+                || host
+                    .strip_suffix(allowed.as_str())
+                    .is_some_and(|rest| rest.ends_with('.'))
+        });
+        if !allowed {
+            anyhow::bail!(
+                "upstream host {host:?} (from {url:?}) is not in gateway.allowed_upstream_hosts [{}]; add it there or set gateway.allow_any_upstream_host = true",
+                hosts.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// `gateway.max_labels_per_pool` from a raw config document (default: 64), for `pools set` and
+/// `pools add-member`, which edit the document rather than a loaded [`GatewayConfig`].
+pub(crate) fn max_labels_per_pool(root: &Value) -> anyhow::Result<usize> {
+    let value = root
+        .get("gateway")
+        .and_then(|gateway| gateway.get("max_labels_per_pool"));
+    let max = match value {
+        None => DEFAULT_MAX_LABELS_PER_POOL,
+        Some(Value::Integer(max)) => *max,
+        Some(_) => anyhow::bail!("gateway.max_labels_per_pool must be an integer"),
+    };
+    usize::try_from(max)
+        .ok()
+        .filter(|max| *max > 0)
+        .context("gateway.max_labels_per_pool must be > 0")
+}
+
+pub(crate) fn load_value_optional(path: &Path) -> anyhow::Result<Value> {
+    match std::fs::read_to_string(path) {
         Ok(text) => toml::from_str(&text).with_context(|| format!("parsing config file {path:?}")),
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
             Ok(Value::Table(toml::Table::new()))
@@ -127,23 +567,275 @@ pub(crate) fn load_value_optional(state_root: &Path) -> anyhow::Result<Value> {
     }
 }
 
-pub(crate) fn write_value(state_root: &Path, root: &Value) -> anyhow::Result<()> {
-    let path = config_path(state_root);
+/// `[selection]` settings for `run --auto`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SelectionConfig {
+    pub(crate) strategy: Option<String>,
+    /// Accounts within this percent of the best headroom are picked at random, weighted by
+    /// headroom, instead of always taking the single best.
+    pub(crate) spread_percent: Option<f64>,
+    pub(crate) priority: SelectionPriority,
+    /// How `run --no-usage-fetch` picks when no account has a usable cached score.
+    pub(crate) no_fetch_fallback: NoFetchFallback,
+}
+
+/// `[selection] no_fetch_fallback`: the pick when `run --no-usage-fetch` has nothing cached to
+/// rank by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum NoFetchFallback {
+    /// The first unreserved account in label order.
+    #[default]
+    First,
+    /// The unreserved account after the previous fallback pick, wrapping around.
+    RoundRobin,
+}
+
+impl NoFetchFallback {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "first" => Ok(Self::First),
+            "round_robin" => Ok(Self::RoundRobin),
+            other => anyhow::bail!(
+                "selection.no_fetch_fallback must be \"first\" or \"round_robin\" (got {other:?})"
+            ),
+        }
+    }
+}
+
+/// Which usage window `[selection] priority` ranks accounts by first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SelectionPriority {
+    /// Weekly headroom, then five-hour headroom.
+    #[default]
+    Weekly,
+    /// Five-hour headroom, then weekly; suits short bursty workloads.
+    FiveHour,
+    /// The smaller of the two remaining percentages, i.e. whichever window binds first.
+    Balanced,
+}
+
+impl SelectionPriority {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "weekly" => Ok(Self::Weekly),
+            "five_hour" => Ok(Self::FiveHour),
+            "balanced" => Ok(Self::Balanced),
+            other => anyhow::bail!(
+                "selection.priority must be \"weekly\", \"five_hour\" or \"balanced\" (got {other:?})"
+            ),
+        }
+    }
+}
+
+/// Returns `[selection]` from config.toml. Unlike [`load`], this does not require a `[gateway]`
+/// section so CLI-only setups can use it.
+pub(crate) fn selection_config(path: &Path) -> anyhow::Result<SelectionConfig> {
+    let root = load_value_optional(path)?;
+    let Some(selection) = root.get("selection") else {
+        return Ok(SelectionConfig::default());
+    };
+    let spread_percent = match selection.get("spread_percent") {
+        None => None,
+        Some(Value::Float(value)) => Some(*value),
+        Some(Value::Integer(value)) => Some(*value as f64),
+        Some(_) => anyhow::bail!("selection.spread_percent must be a number"),
+    };
+    if spread_percent.is_some_and(|value| !(0.0..=100.0).contains(&value)) {
+        anyhow::bail!("selection.spread_percent must be between 0 and 100");
+    }
+    let priority = match selection.get("priority") {
+        None => SelectionPriority::default(),
+        Some(Value::String(value)) => SelectionPriority::parse(value)?,
+        Some(_) => anyhow::bail!("selection.priority must be a string"),
+    };
+    let no_fetch_fallback = match selection.get("no_fetch_fallback") {
+        None => NoFetchFallback::default(),
+        Some(Value::String(value)) => NoFetchFallback::parse(value)?,
+        Some(_) => anyhow::bail!("selection.no_fetch_fallback must be a string"),
+    };
+    Ok(SelectionConfig {
+        strategy: selection
+            .get("strategy")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        spread_percent,
+        priority,
+        no_fetch_fallback,
+    })
+}
+
+/// How the `primary` and `secondary` windows of a rate-limit response map onto the five-hour
+/// and weekly buckets, from `[usage] rate_limit_windows`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum RateLimitWindows {
+    /// Bucket each window by its `window_minutes`.
+    #[default]
+    Auto,
+    /// Primary is the five-hour window and secondary the weekly one, whatever their durations.
+    PrimaryFiveSecondaryWeekly,
+    /// Keep both windows as reported, without bucketing.
+    Raw,
+}
+
+impl RateLimitWindows {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "primary_five_secondary_weekly" => Ok(Self::PrimaryFiveSecondaryWeekly),
+            "raw" => Ok(Self::Raw),
+            other => anyhow::bail!(
+                "usage.rate_limit_windows must be \"auto\", \"primary_five_secondary_weekly\" or \"raw\" (got {other:?})"
+            ),
+        }
+    }
+}
+
+/// Returns `[usage] rate_limit_windows`. Like [`selection_config`], this does not require a
+/// `[gateway]` section.
+pub(crate) fn rate_limit_windows(path: &Path) -> anyhow::Result<RateLimitWindows> {
+    let root = load_value_optional(path)?;
+    match root
+        .get("usage")
+        .and_then(|usage| usage.get("rate_limit_windows"))
+    {
+        None => Ok(RateLimitWindows::default()),
+        Some(Value::String(value)) => {
+            RateLimitWindows::parse(value).map_err(|err| ErrorCategory::Config.wrap(err))
+        }
+        Some(_) => Err(ErrorCategory::Config
+            .wrap(anyhow::anyhow!("usage.rate_limit_windows must be a string"))),
+    }
+}
+
+/// How long a cached usage snapshot stays usable by CLI selection (`run`, `pick`, `usage`),
+/// in milliseconds, from `[usage] cache_ttl_seconds`. Readable without a `[gateway]` section.
+pub(crate) fn usage_cache_ttl_ms(path: &Path) -> anyhow::Result<i64> {
+    let root = load_value_optional(path)?;
+    match root
+        .get("usage")
+        .and_then(|usage| usage.get("cache_ttl_seconds"))
+    {
+        None => Ok(DEFAULT_USAGE_CACHE_TTL_SECONDS * 1000),
+        Some(Value::Integer(seconds)) if *seconds > 0 => Ok(seconds.saturating_mul(1000)),
+        Some(_) => Err(ErrorCategory::Config.wrap(anyhow::anyhow!(
+            "usage.cache_ttl_seconds must be an integer > 0"
+        ))),
+    }
+}
+
+/// Writes `root` to config.toml. Edits are applied to the existing file through `toml_edit`, so
+/// comments, key order and formatting an operator added by hand survive; only entries whose
+/// value changed are rewritten. A missing or unparsable file is rendered from scratch.
+pub(crate) fn write_value(path: &Path, root: &Value) -> anyhow::Result<()> {
     let Some(parent) = path.parent() else {
         anyhow::bail!("invalid config path {path:?}");
     };
     std::fs::create_dir_all(parent).with_context(|| format!("creating parent dir {parent:?}"))?;
 
-    let tmp = path.with_file_name("config.toml.tmp");
-    let mut out = toml::to_string_pretty(root).context("rendering config.toml")?;
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    let existing = std::fs::read_to_string(path).ok();
+    let mut out = match existing
+        .as_deref()
+        .and_then(|text| render_preserving(text, root))
+    {
+        Some(out) => out,
+        None => toml::to_string_pretty(root).context("rendering config.toml")?,
+    };
     if !out.ends_with('\n') {
         out.push('\n');
     }
     std::fs::write(&tmp, out.as_bytes()).with_context(|| format!("writing temp {tmp:?}"))?;
-    std::fs::rename(&tmp, &path).with_context(|| format!("replacing config {path:?}"))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("replacing config {path:?}"))?;
     Ok(())
 }
 
+/// `root` rendered as an edit of `existing`; `None` when `existing` is not a valid document.
+fn render_preserving(existing: &str, root: &Value) -> Option<String> {
+    let old: toml::Table = toml::from_str(existing).ok()?;
+    let mut doc: toml_edit::DocumentMut = existing.parse().ok()?;
+    let new = root.as_table()?;
+    sync_table(doc.as_table_mut(), &old, new);
+    Some(doc.to_string())
+}
+
+/// Makes `doc` hold `new`, touching only the keys whose value differs from `old` (what `doc`
+/// held when it was parsed). Rewritten values keep their surrounding comments and whitespace.
+fn sync_table(doc: &mut toml_edit::Table, old: &toml::Table, new: &toml::Table) {
+    let stale: Vec<String> = doc
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in stale {
+        doc.remove(&key);
+    }
+    for (key, new_value) in new {
+        let old_value = old.get(key);
+        if old_value == Some(new_value) && doc.contains_key(key) {
+            continue;
+        }
+        let synced = match doc.get_mut(key) {
+            Some(toml_edit::Item::Table(table)) => match (old_value, new_value) {
+                (Some(Value::Table(old)), Value::Table(new)) => {
+                    sync_table(table, old, new);
+                    true
+                }
+                _ => false,
+            },
+            Some(toml_edit::Item::Value(existing)) if !new_value.is_table() => {
+                let decor = existing.decor().clone();
+                *existing = edit_value(new_value);
+                *existing.decor_mut() = decor;
+                true
+            }
+            _ => false,
+        };
+        if !synced {
+            doc.insert(key, edit_item(new_value));
+        }
+    }
+}
+
+fn edit_item(value: &Value) -> toml_edit::Item {
+    match value {
+        Value::Table(table) => {
+            let mut out = toml_edit::Table::new();
+            // Parents holding only sub-tables (`[pools]` above `[pools.team]`) get no header.
+            out.set_implicit(true);
+            for (key, value) in table {
+                out.insert(key, edit_item(value));
+            }
+            toml_edit::Item::Table(out)
+        }
+        other => toml_edit::Item::Value(edit_value(other)),
+    }
+}
+
+fn edit_value(value: &Value) -> toml_edit::Value {
+    match value {
+        Value::String(value) => value.as_str().into(),
+        Value::Integer(value) => (*value).into(),
+        Value::Float(value) => (*value).into(),
+        Value::Boolean(value) => (*value).into(),
+        Value::Datetime(value) => value
+            .to_string()
+            .parse::<toml_edit::Datetime>()
+            .map_or_else(|_| value.to_string().into(), Into::into),
+        Value::Array(values) => values
+            .iter()
+            .map(edit_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| (key.as_str(), edit_value(value)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    }
+}
+
 pub(crate) fn ensure_gateway_defaults(root: &mut Value) -> anyhow::Result<()> {
     let table = root.as_table_mut().context("config root is not a table")?;
     let gateway_value = table
@@ -168,15 +860,27 @@ pub(crate) fn ensure_gateway_defaults(root: &mut Value) -> anyhow::Result<()> {
     gateway
         .entry("token_safety_window_seconds")
         .or_insert_with(|| Value::Integer(DEFAULT_TOKEN_SAFETY_WINDOW_SECONDS));
+    gateway
+        .entry("max_token_cache_seconds")
+        .or_insert_with(|| Value::Integer(DEFAULT_MAX_TOKEN_CACHE_SECONDS));
 
     Ok(())
 }
 
+/// Field updates applied by `set_pool`. `None` leaves the existing value untouched.
+pub(crate) struct PoolUpdate<'a> {
+    pub(crate) labels: &'a [String],
+    pub(crate) policy_key: Option<&'a str>,
+    pub(crate) default_note: Option<&'a str>,
+    pub(crate) sticky: Option<bool>,
+    pub(crate) allow_account_override: Option<bool>,
+    pub(crate) tenant_header: Option<&'a str>,
+}
+
 pub(crate) fn set_pool(
     root: &mut Value,
     pool_id: &str,
-    labels: &[String],
-    policy_key: Option<&str>,
+    update: PoolUpdate<'_>,
 ) -> anyhow::Result<()> {
     let table = root.as_table_mut().context("config root is not a table")?;
     let pools_value = table
@@ -186,31 +890,49 @@ pub(crate) fn set_pool(
         .as_table_mut()
         .context("[pools] is not a table")?;
 
-    let existing_policy_key = pools
+    // Start from the existing table so keys this command does not manage survive updates.
+    let mut pool = pools
         .get(pool_id)
         .and_then(Value::as_table)
-        .and_then(|t| t.get("policy_key"))
-        .and_then(Value::as_str)
-        .map(str::to_string);
-
-    let policy_key = match policy_key {
-        Some(value) if !value.trim().is_empty() => Some(value.to_string()),
-        Some(_) => None,
-        None => existing_policy_key,
-    };
-
-    let mut pool = toml::Table::new();
+        .cloned()
+        .unwrap_or_default();
     pool.insert(
         "labels".to_string(),
-        Value::Array(labels.iter().cloned().map(Value::String).collect()),
+        Value::Array(update.labels.iter().cloned().map(Value::String).collect()),
     );
-    if let Some(policy_key) = policy_key {
-        pool.insert("policy_key".to_string(), Value::String(policy_key));
+    merge_optional_string(&mut pool, "policy_key", update.policy_key);
+    merge_optional_string(&mut pool, "default_note", update.default_note);
+    if let Some(sticky) = update.sticky {
+        pool.insert("sticky".to_string(), Value::Boolean(sticky));
     }
+    if let Some(allow) = update.allow_account_override {
+        pool.insert("allow_account_override".to_string(), Value::Boolean(allow));
+    }
+    // Store the normalized name; an empty update still clears the key.
+    let tenant = tenant_header(pool_id, update.tenant_header)?;
+    merge_optional_string(
+        &mut pool,
+        "tenant_header",
+        tenant.as_deref().or(update.tenant_header),
+    );
     pools.insert(pool_id.to_string(), Value::Table(pool));
     Ok(())
 }
 
+/// Applies an optional string pool field for `set_pool`: `None` keeps the existing value, an
+/// empty string clears it, and anything else replaces it.
+fn merge_optional_string(pool: &mut toml::Table, key: &str, update: Option<&str>) {
+    match update {
+        Some(value) if !value.trim().is_empty() => {
+            pool.insert(key.to_string(), Value::String(value.to_string()));
+        }
+        Some(_) => {
+            pool.remove(key);
+        }
+        None => {}
+    }
+}
+
 pub(crate) fn remove_pool(root: &mut Value, pool_id: &str) -> anyhow::Result<bool> {
     let Some(table) = root.as_table_mut() else {
         return Ok(false);
@@ -255,8 +977,346 @@ pub(crate) fn extract_pools(root: &Value) -> anyhow::Result<BTreeMap<String, Poo
             .get("policy_key")
             .and_then(Value::as_str)
             .map(str::to_string);
-        out.insert(pool_id.to_string(), PoolConfig { labels, policy_key });
+        let default_note = pool
+            .get("default_note")
+            .and_then(Value::as_str)
+            .filter(|note| !note.trim().is_empty())
+            .map(str::to_string);
+        let sticky = pool.get("sticky").and_then(Value::as_bool).unwrap_or(true);
+        let allow_account_override = pool
+            .get("allow_account_override")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let tenant_header =
+            tenant_header(pool_id, pool.get("tenant_header").and_then(Value::as_str))?;
+        out.insert(
+            pool_id.to_string(),
+            PoolConfig {
+                labels,
+                policy_key,
+                default_note,
+                sticky,
+                allow_account_override,
+                tenant_header,
+            },
+        );
     }
 
     Ok(out)
 }
+
+/// Normalizes `[pools.<pool_id>].tenant_header`: blank means unset, anything else must be a
+/// valid header name and is lowercased.
+fn tenant_header(pool_id: &str, header: Option<&str>) -> anyhow::Result<Option<String>> {
+    let Some(header) = header.map(str::trim).filter(|h| !h.is_empty()) else {
+        return Ok(None);
+    };
+    let name = axum::http::HeaderName::from_bytes(header.as_bytes()).with_context(|| {
+        format!("[pools.{pool_id}].tenant_header {header:?} is not a valid header name")
+    })?;
+    Ok(Some(name.as_str().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn update<'a>(
+        labels: &'a [String],
+        policy_key: Option<&'a str>,
+        default_note: Option<&'a str>,
+    ) -> PoolUpdate<'a> {
+        PoolUpdate {
+            labels,
+            policy_key,
+            default_note,
+            sticky: None,
+            allow_account_override: None,
+            tenant_header: None,
+        }
+    }
+
+    #[test]
+    fn set_pool_keeps_default_note_unless_overridden() {
+        let mut root = Value::Table(toml::Table::new());
+        let labels = vec!["a".to_string()];
+
+        set_pool(
+            &mut root,
+            "team",
+            update(&labels, None, Some("team tokens")),
+        )
+        .expect("set pool");
+        set_pool(&mut root, "team", update(&labels, Some("v2"), None)).expect("update pool");
+        let pools = extract_pools(&root).expect("extract pools");
+        assert_eq!(pools["team"].default_note.as_deref(), Some("team tokens"));
+        assert_eq!(pools["team"].policy_key.as_deref(), Some("v2"));
+
+        set_pool(&mut root, "team", update(&labels, None, Some(""))).expect("clear note");
+        let pools = extract_pools(&root).expect("extract pools");
+        assert_eq!(pools["team"].default_note, None);
+        assert_eq!(pools["team"].policy_key.as_deref(), Some("v2"));
+    }
+
+    #[test]
+    fn set_pool_preserves_sticky_and_defaults_to_true() {
+        let mut root = Value::Table(toml::Table::new());
+        let labels = vec!["a".to_string()];
+
+        set_pool(&mut root, "team", update(&labels, None, None)).expect("set pool");
+        assert_eq!(extract_pools(&root).expect("extract")["team"].sticky, true);
+
+        set_pool(
+            &mut root,
+            "team",
+            PoolUpdate {
+                sticky: Some(false),
+                ..update(&labels, None, None)
+            },
+        )
+        .expect("disable sticky");
+        set_pool(&mut root, "team", update(&labels, None, None)).expect("update labels");
+        assert_eq!(extract_pools(&root).expect("extract")["team"].sticky, false);
+    }
+
+    #[test]
+    fn set_pool_normalizes_and_clears_tenant_header() {
+        let mut root = Value::Table(toml::Table::new());
+        let labels = vec!["a".to_string()];
+        let with_tenant = |header| PoolUpdate {
+            tenant_header: Some(header),
+            ..update(&labels, None, None)
+        };
+
+        set_pool(&mut root, "team", with_tenant("X-Tenant-Id")).expect("set tenant header");
+        assert_eq!(
+            extract_pools(&root).expect("extract")["team"]
+                .tenant_header
+                .as_deref(),
+            Some("x-tenant-id")
+        );
+        assert!(set_pool(&mut root, "team", with_tenant("bad header")).is_err());
+
+        set_pool(&mut root, "team", with_tenant("")).expect("clear tenant header");
+        assert_eq!(
+            extract_pools(&root).expect("extract")["team"].tenant_header,
+            None
+        );
+    }
+
+    #[test]
+    fn load_resolves_pool_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        std::fs::write(
+            &path,
+            r#"[gateway]
+
+[pools.team-a]
+labels = ["work"]
+
+[pool_aliases]
+team = "team-a"
+"#,
+        )
+        .unwrap();
+
+        let cfg = load(&path).unwrap();
+
+        assert_eq!(cfg.resolve_pool_alias("team"), "team-a");
+        assert_eq!(cfg.resolve_pool_alias("team-a"), "team-a");
+        assert_eq!(cfg.resolve_pool_alias("default"), "default");
+        assert!(cfg.is_implicit_default_pool("default"));
+        assert!(!cfg.is_implicit_default_pool("team-a"));
+
+        std::fs::write(&path, "[gateway]\n\n[pools.default]\nlabels = [\"work\"]\n").unwrap();
+        assert!(!load(&path).unwrap().is_implicit_default_pool("default"));
+    }
+
+    #[test]
+    fn load_reads_redis_topology() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        std::fs::write(&path, "[gateway]\n").unwrap();
+        let cfg = load(&path).unwrap();
+        assert_eq!(cfg.gateway.redis_mode, RedisMode::Single);
+        assert_eq!(
+            cfg.gateway.redis_node_urls(),
+            vec![DEFAULT_REDIS_URL.to_string()]
+        );
+
+        std::fs::write(
+            &path,
+            "[gateway]\nredis_mode = \"cluster\"\nredis_nodes = [\"redis://a:7000\", \"redis://b:7000\"]\n",
+        )
+        .unwrap();
+        let cfg = load(&path).unwrap();
+        assert_eq!(cfg.gateway.redis_mode, RedisMode::Cluster);
+        assert_eq!(
+            cfg.gateway.redis_node_urls(),
+            vec!["redis://a:7000".to_string(), "redis://b:7000".to_string()]
+        );
+
+        std::fs::write(&path, "[gateway]\nredis_mode = \"sentinel\"\n").unwrap();
+        assert!(load(&path).is_err());
+    }
+
+    #[test]
+    fn cli_and_gateway_usage_cache_ttls_are_independent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        std::fs::write(&path, "[gateway]\n").unwrap();
+        assert_eq!(
+            usage_cache_ttl_ms(&path).unwrap(),
+            DEFAULT_USAGE_CACHE_TTL_SECONDS * 1000
+        );
+        assert_eq!(
+            load(&path).unwrap().gateway.usage_cache_ttl_seconds,
+            DEFAULT_GATEWAY_USAGE_CACHE_TTL_SECONDS
+        );
+
+        std::fs::write(
+            &path,
+            "[usage]\ncache_ttl_seconds = 1800\n\n[gateway]\nusage_cache_ttl_seconds = 120\n",
+        )
+        .unwrap();
+        assert_eq!(usage_cache_ttl_ms(&path).unwrap(), 1_800_000);
+        assert_eq!(load(&path).unwrap().gateway.usage_cache_ttl_seconds, 120);
+
+        std::fs::write(&path, "[usage]\ncache_ttl_seconds = 0\n").unwrap();
+        assert!(usage_cache_ttl_ms(&path).is_err());
+    }
+
+    #[test]
+    fn write_value_keeps_comments_and_untouched_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        let original = "\
+# Managed by hand and by codex-mgr.
+[gateway]
+listen = \"127.0.0.1:8787\" # keep local
+
+# The team pool.
+[pools.team]
+labels = [\"a\"] # members
+sticky = true
+
+[pools.old]
+labels = [\"z\"]
+";
+        std::fs::write(&path, original).unwrap();
+
+        let mut root = load_value_for_update(&path).unwrap();
+        let labels = vec!["a".to_string(), "b".to_string()];
+        set_pool(&mut root, "team", update(&labels, Some("v1"), None)).unwrap();
+        assert!(remove_pool(&mut root, "old").unwrap());
+        write_value(&path, &root).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            "\
+# Managed by hand and by codex-mgr.
+[gateway]
+listen = \"127.0.0.1:8787\" # keep local
+
+# The team pool.
+[pools.team]
+labels = [\"a\", \"b\"] # members
+sticky = true
+policy_key = \"v1\"
+"
+        );
+        assert_eq!(load_value_for_update(&path).unwrap(), root);
+    }
+
+    #[test]
+    fn load_enforces_upstream_host_allowlist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        let write = |gateway: &str| {
+            std::fs::write(&path, format!("[gateway]\n{gateway}")).unwrap();
+        };
+
+        write("upstream_base_url = \"https://api.chatgpt.com/backend-api/codex\"\n");
+        assert!(load(&path).is_ok());
+
+        write("upstream_base_url = \"https://chatgpt.com.evil.example/codex\"\n");
+        let err = load(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("allowed_upstream_hosts"));
+
+        write(
+            "canary_upstream_base_url = \"http://127.0.0.1:9000\"\ncanary_percent = 5\nallowed_upstream_hosts = [\"chatgpt.com\", \"127.0.0.1\"]\n",
+        );
+        assert!(load(&path).is_ok());
+
+        write("upstream_base_url = \"http://localhost:9000\"\nallow_any_upstream_host = true\n");
+        assert_eq!(
+            load(&path).unwrap().gateway.upstream_hosts(),
+            UpstreamHosts::Any
+        );
+    }
+
+    #[test]
+    fn load_enforces_max_labels_per_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        let write = |gateway: &str| {
+            std::fs::write(
+                &path,
+                format!("[gateway]\n{gateway}\n[pools.team]\nlabels = [\"a\", \"b\", \"c\"]\n"),
+            )
+            .unwrap();
+        };
+
+        write("");
+        assert_eq!(
+            load(&path).unwrap().gateway.max_labels_per_pool,
+            DEFAULT_MAX_LABELS_PER_POOL
+        );
+        write("max_labels_per_pool = 3\n");
+        assert_eq!(load(&path).unwrap().gateway.max_labels_per_pool, 3);
+
+        write("max_labels_per_pool = 2\n");
+        let err = load(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("more than gateway.max_labels_per_pool (2)"));
+        write("max_labels_per_pool = 0\n");
+        assert!(load(&path).is_err());
+    }
+
+    #[test]
+    fn load_bounds_redis_retry_max() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        for (retry_max, ok) in [(0, true), (MAX_REDIS_RETRY_MAX, true), (-1, false)] {
+            std::fs::write(&path, format!("[gateway]\nredis_retry_max = {retry_max}\n")).unwrap();
+            assert_eq!(load(&path).is_ok(), ok, "redis_retry_max = {retry_max}");
+        }
+        std::fs::write(
+            &path,
+            format!("[gateway]\nredis_retry_max = {}\n", MAX_REDIS_RETRY_MAX + 1),
+        )
+        .unwrap();
+        let err = load(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("redis_retry_max"));
+    }
+
+    #[test]
+    fn load_raises_token_safety_window_to_floor() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_path(dir.path());
+        std::fs::write(&path, "[gateway]\ntoken_safety_window_seconds = 0\n").unwrap();
+        assert_eq!(
+            load(&path).unwrap().gateway.token_safety_window_seconds,
+            DEFAULT_MIN_TOKEN_SAFETY_WINDOW_SECONDS
+        );
+
+        std::fs::write(
+            &path,
+            "[gateway]\ntoken_safety_window_seconds = 0\nmin_token_safety_window_seconds = 0\n",
+        )
+        .unwrap();
+        assert_eq!(load(&path).unwrap().gateway.token_safety_window_seconds, 0);
+    }
+}
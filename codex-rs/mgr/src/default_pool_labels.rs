@@ -24,7 +24,8 @@ impl DefaultPoolLabels {
         self.labels.read().await.clone()
     }
 
-    pub(crate) fn spawn_refresh_task(&self, accounts_root: PathBuf) {
+    /// Periodically re-lists account homes, leaving out labels reserved in `state.json`.
+    pub(crate) fn spawn_refresh_task(&self, accounts_root: PathBuf, state_root: PathBuf) {
         let labels = Arc::clone(&self.labels);
 
         tokio::spawn(async move {
@@ -32,9 +33,11 @@ impl DefaultPoolLabels {
                 tokio::time::sleep(REFRESH_INTERVAL).await;
 
                 let accounts_root = accounts_root.clone();
-                let refreshed =
-                    tokio::task::spawn_blocking(move || accounts::list_labels(&accounts_root))
-                        .await;
+                let state_root = state_root.clone();
+                let refreshed = tokio::task::spawn_blocking(move || {
+                    accounts::list_unreserved_labels(&accounts_root, &state_root)
+                })
+                .await;
 
                 match refreshed {
                     Ok(Ok(next_labels)) => {
@@ -0,0 +1,100 @@
+use serde::Serialize;
+use std::path::Path;
+
+use crate::accounts;
+use crate::layout::LoosePermission;
+use crate::layout::SharedLayoutDrift;
+use crate::layout::inspect_account_permissions;
+use crate::layout::inspect_shared_layout;
+
+#[derive(Debug, Clone, Serialize)]
+struct DoctorRow {
+    label: String,
+    drift: Vec<SharedLayoutDrift>,
+    loose_permissions: Vec<LoosePermission>,
+    error: Option<String>,
+}
+
+pub(crate) async fn run(
+    shared_root: &Path,
+    accounts_root: &Path,
+    json: bool,
+) -> anyhow::Result<()> {
+    let rows: Vec<DoctorRow> = accounts::list_labels(accounts_root)?
+        .into_iter()
+        .map(|label| {
+            let account_home = accounts_root.join(&label);
+            let inspected = inspect_shared_layout(&account_home, shared_root)
+                .and_then(|drift| Ok((drift, inspect_account_permissions(&account_home)?)));
+            match inspected {
+                Ok((drift, loose_permissions)) => DoctorRow {
+                    label,
+                    drift,
+                    loose_permissions,
+                    error: None,
+                },
+                Err(err) => DoctorRow {
+                    label,
+                    drift: Vec::new(),
+                    loose_permissions: Vec::new(),
+                    error: Some(format!("{err:#}")),
+                },
+            }
+        })
+        .collect();
+    let unhealthy = rows
+        .iter()
+        .filter(|row| !row.drift.is_empty() || row.error.is_some())
+        .count();
+    let loose = rows
+        .iter()
+        .filter(|row| !row.loose_permissions.is_empty())
+        .count();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else if rows.is_empty() {
+        println!("no accounts found");
+    } else {
+        for row in &rows {
+            if let Some(error) = &row.error {
+                println!("{}: error: {error}", row.label);
+                continue;
+            }
+            if row.drift.is_empty() && row.loose_permissions.is_empty() {
+                println!("{}: ok", row.label);
+                continue;
+            }
+            if !row.drift.is_empty() {
+                println!("{}: drifted", row.label);
+                for drift in &row.drift {
+                    println!(
+                        "  - {} is a {} ({})",
+                        drift.entry, drift.found, drift.detail
+                    );
+                }
+            }
+            if !row.loose_permissions.is_empty() {
+                println!("{}: loose permissions", row.label);
+                for loose in &row.loose_permissions {
+                    println!(
+                        "  - {} has mode {} (expected {})",
+                        loose.entry, loose.mode, loose.expected
+                    );
+                }
+            }
+        }
+    }
+
+    if unhealthy > 0 {
+        anyhow::bail!(
+            "shared layout drift detected in {unhealthy} account(s); the next `codex-mgr run` on a drifted account moves file contents back into shared_root (last writer wins)"
+        );
+    }
+    if loose > 0 {
+        anyhow::bail!(
+            "{loose} account home(s) are readable by group or other users; they hold refresh tokens, so run `chmod -R go-rwx` on them"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Process exit codes for failures that wrapping scripts may want to tell apart.
+//!
+//! Uncategorized errors exit with 1. Categories are attached with [`ErrorCategory::wrap`] without
+//! changing the error message, and survive any `.context(...)` added further up the stack.
+//! Command-line parse errors are remapped to [`ErrorCategory::Usage`], since clap's own exit code
+//! for them (2) is taken by [`ErrorCategory::NoAccounts`].
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorCategory {
+    /// No account could be selected (none logged in, or usage unavailable for all of them).
+    NoAccounts,
+    /// The manager config is missing or invalid.
+    Config,
+    /// An account's credentials are missing, incomplete, or rejected.
+    Auth,
+    /// The command line could not be parsed.
+    Usage,
+}
+
+impl ErrorCategory {
+    pub(crate) fn exit_code(self) -> u8 {
+        match self {
+            Self::NoAccounts => 2,
+            Self::Config => 3,
+            Self::Auth => 4,
+            // `EX_USAGE` from sysexits.h.
+            Self::Usage => 64,
+        }
+    }
+
+    /// Stable `error.kind` for the `--json-errors` envelope.
+    pub(crate) fn kind(self) -> &'static str {
+        match self {
+            Self::NoAccounts => "no_accounts",
+            Self::Config => "config",
+            Self::Auth => "auth",
+            Self::Usage => "usage",
+        }
+    }
+
+    pub(crate) fn wrap(self, err: anyhow::Error) -> anyhow::Error {
+        anyhow::Error::new(Categorized {
+            category: self,
+            inner: err,
+        })
+    }
+}
+
+/// Transparent wrapper: displays and chains exactly like `inner`.
+struct Categorized {
+    category: ErrorCategory,
+    inner: anyhow::Error,
+}
+
+impl fmt::Display for Categorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.inner, f)
+    }
+}
+
+impl fmt::Debug for Categorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl std::error::Error for Categorized {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+/// The outermost category in `err`'s chain: when several are attached, the one added last (furthest
+/// up the stack) wins.
+pub(crate) fn category_of(err: &anyhow::Error) -> Option<ErrorCategory> {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<Categorized>())
+        .map(|c| c.category)
+}
+
+/// Returns the exit code for `err`: that of its outermost category, or 1.
+pub(crate) fn for_error(err: &anyhow::Error) -> u8 {
+    category_of(err).map_or(1, ErrorCategory::exit_code)
+}
+
+/// `{"error": {"kind": ..., "message": ...}}` for `err`; uncategorized errors have kind `error`.
+pub(crate) fn json_envelope(err: &anyhow::Error) -> serde_json::Value {
+    serde_json::json!({
+        "error": {
+            "kind": category_of(err).map_or("error", ErrorCategory::kind),
+            "message": format!("{err:#}"),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn category_survives_context_and_keeps_message() {
+        let err = ErrorCategory::Config.wrap(anyhow::anyhow!("missing [gateway] section"));
+        let err = Err::<(), _>(err).context("loading config").unwrap_err();
+
+        assert_eq!(for_error(&err), 3);
+        assert_eq!(
+            format!("{err:#}"),
+            "loading config: missing [gateway] section"
+        );
+    }
+
+    #[test]
+    fn outermost_category_wins() {
+        let err = ErrorCategory::Auth.wrap(anyhow::anyhow!("token rejected"));
+        let err = ErrorCategory::NoAccounts.wrap(err.context("selecting an account"));
+
+        assert_eq!(for_error(&err), 2);
+    }
+
+    #[test]
+    fn uncategorized_errors_exit_with_one() {
+        assert_eq!(for_error(&anyhow::anyhow!("boom")), 1);
+    }
+
+    #[test]
+    fn json_envelope_reports_kind_and_full_message() {
+        let err = ErrorCategory::Auth.wrap(anyhow::anyhow!("missing refresh_token"));
+        let err = Err::<(), _>(err).context("login").unwrap_err();
+
+        assert_eq!(
+            json_envelope(&err),
+            serde_json::json!({
+                "error": {"kind": "auth", "message": "login: missing refresh_token"}
+            })
+        );
+        assert_eq!(
+            json_envelope(&anyhow::anyhow!("boom"))["error"]["kind"],
+            "error"
+        );
+    }
+}
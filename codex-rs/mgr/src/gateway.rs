@@ -2,12 +2,20 @@ use anyhow::Context;
 use base64::Engine;
 use rand::TryRngCore;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::path::Path;
 
+use crate::account_token_provider;
+use crate::accounts;
+use crate::audit;
+use crate::audit::AuditEntry;
 use crate::config;
+use crate::exit_code::ErrorCategory;
 use crate::gateway_sessions;
 use crate::redis_conn;
-use crate::time::now_ms;
+use crate::routing;
+use crate::time::Clock;
+use crate::time::SystemClock;
 
 const DEFAULT_SESSION_TTL_SECONDS: i64 = 31_536_000;
 
@@ -19,6 +27,10 @@ struct GatewaySessionRow {
     expires_at_ms: i64,
     expires_in_seconds: i64,
     note: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    allowed_paths: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    preview: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -29,19 +41,218 @@ struct GatewayIssueOut {
     expires_at_ms: i64,
     ttl_seconds: i64,
     note: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    allowed_paths: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    preview: bool,
 }
 
-pub(crate) async fn issue(
-    state_root: &Path,
+#[derive(Debug, Clone, Serialize)]
+struct GatewayInspectOut {
+    token: String,
     pool_id: String,
-    ttl_seconds: Option<i64>,
+    policy_key: Option<String>,
+    issued_at_ms: i64,
+    expires_at_ms: i64,
+    expires_in_seconds: i64,
     note: Option<String>,
-    json: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    allowed_paths: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    preview: bool,
+    /// Live sticky conversation bindings in the session's pool, keyed by account label.
+    sticky_accounts: BTreeMap<String, i64>,
+}
+
+pub(crate) struct IssueOptions {
+    /// Falls back to `gateway.default_pool` when omitted.
+    pub(crate) pool_id: Option<String>,
+    pub(crate) ttl_seconds: Option<i64>,
+    pub(crate) note: Option<String>,
+    pub(crate) allowed_paths: Vec<String>,
+    pub(crate) json: bool,
+    pub(crate) example: bool,
+    /// Refuse to issue unless at least one pool member has working (or refreshable) auth.
+    pub(crate) verify: bool,
+    pub(crate) preview: bool,
+}
+
+pub(crate) async fn issue(
+    state_root: &Path,
+    config_path: &Path,
+    accounts_root: &Path,
+    options: IssueOptions,
 ) -> anyhow::Result<()> {
-    let cfg = config::load(state_root)?;
+    let IssueOptions {
+        pool_id,
+        ttl_seconds,
+        note,
+        allowed_paths,
+        json,
+        example,
+        verify,
+        preview,
+    } = options;
+
+    let cfg = config::load(config_path)?;
+    let pool_id = match pool_id {
+        Some(pool_id) => pool_id,
+        None => configured_default_pool(&cfg)?,
+    };
+    if verify {
+        verify_pool_auth(state_root, accounts_root, &cfg, &pool_id).await?;
+    }
+    let mut conn = redis_conn::connect(&cfg.gateway).await?;
+    let IssuedSession {
+        token,
+        session,
+        ttl_seconds,
+    } = issue_session(
+        state_root,
+        &cfg,
+        &mut conn,
+        SessionRequest {
+            pool_id,
+            ttl_seconds,
+            note,
+            allowed_paths,
+            preview,
+        },
+        &SystemClock,
+    )
+    .await?;
+    let gateway_sessions::GatewaySession {
+        account_pool_id: pool_id,
+        policy_key,
+        expires_at_ms,
+        note,
+        allowed_paths,
+        preview,
+        ..
+    } = session;
+
+    if json {
+        let out = GatewayIssueOut {
+            token,
+            pool_id,
+            policy_key,
+            expires_at_ms,
+            ttl_seconds,
+            note,
+            allowed_paths,
+            preview,
+        };
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!("{token}");
+        if example {
+            println!();
+            println!("{}", curl_example(&cfg.gateway.listen, &token));
+        }
+    }
+
+    Ok(())
+}
 
-    let policy_key = if pool_id == "default" {
-        None
+/// `gateway.default_pool`, checked against the configured pools so a stale setting fails with a
+/// message naming the setting rather than the pool.
+fn configured_default_pool(cfg: &config::ManagerConfig) -> anyhow::Result<String> {
+    let pool_id = cfg
+        .gateway
+        .default_pool
+        .clone()
+        .context("--pool is required (or set gateway.default_pool)")?;
+    let resolved = cfg.resolve_pool_alias(&pool_id);
+    if !cfg.is_implicit_default_pool(&resolved) && !cfg.pools.contains_key(&resolved) {
+        anyhow::bail!("gateway.default_pool {pool_id:?} does not exist");
+    }
+    Ok(pool_id)
+}
+
+/// Checks members in order and stops at the first whose token loads (refreshing it if it is
+/// about to expire), so a healthy pool costs a single `auth.json` read.
+async fn verify_pool_auth(
+    state_root: &Path,
+    accounts_root: &Path,
+    cfg: &config::ManagerConfig,
+    pool_id: &str,
+) -> anyhow::Result<()> {
+    let pool_id = cfg.resolve_pool_alias(pool_id);
+    let labels = if cfg.is_implicit_default_pool(&pool_id) {
+        accounts::list_unreserved_labels(accounts_root, state_root)?
+    } else {
+        cfg.pools
+            .get(&pool_id)
+            .with_context(|| format!("pool {pool_id:?} does not exist"))?
+            .labels
+            .clone()
+    };
+
+    let mut failures = Vec::with_capacity(labels.len());
+    for label in &labels {
+        match account_token_provider::load_from_auth(
+            accounts_root,
+            label,
+            cfg.gateway.token_safety_window_seconds,
+            &SystemClock,
+        )
+        .await
+        {
+            Ok(_) => return Ok(()),
+            Err(err) => failures.push(format!("{label}: {err:#}")),
+        }
+    }
+    if failures.is_empty() {
+        anyhow::bail!("pool {pool_id:?} has no labels configured");
+    }
+    Err(ErrorCategory::Auth.wrap(anyhow::anyhow!(
+        "--verify: no member of pool {pool_id:?} has usable auth ({}); re-login with `codex-mgr login --label ...`",
+        failures.join("; ")
+    )))
+}
+
+pub(crate) struct SessionRequest {
+    pub(crate) pool_id: String,
+    pub(crate) ttl_seconds: Option<i64>,
+    pub(crate) note: Option<String>,
+    pub(crate) allowed_paths: Vec<String>,
+    /// Non-renewable session whose TTL is capped at `PREVIEW_MAX_TTL_SECONDS`.
+    pub(crate) preview: bool,
+}
+
+pub(crate) struct IssuedSession {
+    pub(crate) token: String,
+    pub(crate) session: gateway_sessions::GatewaySession,
+    pub(crate) ttl_seconds: i64,
+}
+
+/// Validates the request against `cfg`, enforces the issuance rate limit, and stores a new
+/// session under a fresh token, issued and expiring by `clock`. Shared by `gateway issue` and the
+/// library API.
+pub(crate) async fn issue_session(
+    state_root: &Path,
+    cfg: &config::ManagerConfig,
+    conn: &mut redis_conn::RedisConnection,
+    request: SessionRequest,
+    clock: &dyn Clock,
+) -> anyhow::Result<IssuedSession> {
+    let SessionRequest {
+        pool_id,
+        ttl_seconds,
+        note,
+        allowed_paths,
+        preview,
+    } = request;
+    for path in &allowed_paths {
+        if !path.starts_with('/') {
+            anyhow::bail!("--allow-path {path:?} must start with '/'");
+        }
+    }
+
+    let pool_id = cfg.resolve_pool_alias(&pool_id);
+
+    let (policy_key, note) = if cfg.is_implicit_default_pool(&pool_id) {
+        (None, note)
     } else {
         let pool = cfg
             .pools
@@ -50,56 +261,101 @@ pub(crate) async fn issue(
         if pool.labels.is_empty() {
             anyhow::bail!("pool {pool_id:?} has no labels configured");
         }
-        pool.policy_key.clone()
+        (
+            pool.policy_key.clone(),
+            note.or_else(|| pool.default_note.clone()),
+        )
     };
 
-    let ttl_seconds = ttl_seconds.unwrap_or(DEFAULT_SESSION_TTL_SECONDS);
-    if ttl_seconds <= 0 {
-        anyhow::bail!("--ttl-seconds must be > 0");
-    }
+    let ttl_seconds = session_ttl_seconds(
+        ttl_seconds,
+        preview.then_some(gateway_sessions::PREVIEW_MAX_TTL_SECONDS),
+    )?;
 
     let token = generate_gateway_token()?;
-    let now_ms = now_ms();
-    let expires_at_ms = now_ms.saturating_add(ttl_seconds.saturating_mul(1000));
+    let issued_at_ms = clock.now_ms();
+    let expires_at_ms = issued_at_ms.saturating_add(ttl_seconds.saturating_mul(1000));
 
     let session = gateway_sessions::GatewaySession {
         account_pool_id: pool_id.clone(),
-        policy_key: policy_key.clone(),
-        issued_at_ms: now_ms,
+        policy_key,
+        issued_at_ms,
         expires_at_ms,
-        note: note.clone(),
+        note,
+        allowed_paths,
+        preview,
     };
 
-    let mut conn = redis_conn::connect(&cfg.gateway.redis_url).await?;
-    gateway_sessions::put(&mut conn, &token, &session, ttl_seconds).await?;
+    if let Some(max_issues_per_minute) = cfg.gateway.max_issues_per_minute
+        && let Some(retry_after_seconds) =
+            gateway_sessions::record_issue(conn, &pool_id, max_issues_per_minute, clock).await?
+    {
+        anyhow::bail!(
+            "issuance rate limit exceeded for pool {pool_id:?} ({max_issues_per_minute}/min); retry in {retry_after_seconds}s"
+        );
+    }
+    gateway_sessions::put(conn, &token, &session, ttl_seconds).await?;
+    audit::record(
+        state_root,
+        AuditEntry::new("gateway issue")
+            .pool(&pool_id)
+            .token(&token),
+    );
 
-    if json {
-        let out = GatewayIssueOut {
-            token,
-            pool_id,
-            policy_key: policy_key.clone(),
-            expires_at_ms,
-            ttl_seconds,
-            note,
-        };
-        println!("{}", serde_json::to_string_pretty(&out)?);
-    } else {
-        println!("{token}");
+    Ok(IssuedSession {
+        token,
+        session,
+        ttl_seconds,
+    })
+}
+
+/// The session TTL to store. With a `cap` (preview sessions), the TTL defaults to and is clamped
+/// at the cap.
+fn session_ttl_seconds(ttl_seconds: Option<i64>, cap: Option<i64>) -> anyhow::Result<i64> {
+    let ttl_seconds = ttl_seconds.unwrap_or(cap.unwrap_or(DEFAULT_SESSION_TTL_SECONDS));
+    if ttl_seconds <= 0 {
+        anyhow::bail!("--ttl-seconds must be > 0");
+    }
+    if let Some(cap) = cap
+        && ttl_seconds > cap
+    {
+        tracing::warn!(
+            requested_ttl_seconds = ttl_seconds,
+            cap_seconds = cap,
+            "preview session TTL clamped to the preview cap"
+        );
+        return Ok(cap);
     }
+    Ok(ttl_seconds)
+}
 
-    Ok(())
+fn curl_example(listen: &str, token: &str) -> String {
+    // Wildcard listen addresses are not dialable; point the example at loopback instead.
+    let addr = match listen.rsplit_once(':') {
+        Some(("0.0.0.0", port)) => format!("127.0.0.1:{port}"),
+        Some(("[::]", port)) => format!("[::1]:{port}"),
+        _ => listen.to_string(),
+    };
+    let body = r#"{"model":"gpt-5.4","instructions":"You are a helpful assistant.","input":[{"role":"user","content":"Say hello"}],"stream":true,"store":false}"#;
+    [
+        format!("curl -N http://{addr}/responses \\"),
+        format!("  -H 'Authorization: Bearer {token}' \\"),
+        "  -H 'Content-Type: application/json' \\".to_string(),
+        "  -H 'Accept: text/event-stream' \\".to_string(),
+        format!("  -d '{body}'"),
+    ]
+    .join("\n")
 }
 
-pub(crate) async fn list(state_root: &Path, json: bool) -> anyhow::Result<()> {
-    let cfg = config::load(state_root)?;
-    let mut conn = redis_conn::connect(&cfg.gateway.redis_url).await?;
+pub(crate) async fn list(config_path: &Path, json: bool) -> anyhow::Result<()> {
+    let cfg = config::load(config_path)?;
+    let mut conn = redis_conn::connect(&cfg.gateway).await?;
     let sessions = gateway_sessions::list(&mut conn).await?;
 
-    let now_ms = now_ms();
     let mut rows: Vec<GatewaySessionRow> = sessions
         .into_iter()
         .map(|(token, session)| {
-            let expires_in_seconds = (session.expires_at_ms - now_ms) / 1000;
+            let expires_in_seconds = session.expires_in_seconds(&SystemClock);
             GatewaySessionRow {
                 token,
                 pool_id: session.account_pool_id,
@@ -107,6 +363,8 @@ pub(crate) async fn list(state_root: &Path, json: bool) -> anyhow::Result<()> {
                 expires_at_ms: session.expires_at_ms,
                 expires_in_seconds,
                 note: session.note,
+                allowed_paths: session.allowed_paths,
+                preview: session.preview,
             }
         })
         .collect();
@@ -168,16 +426,194 @@ pub(crate) async fn list(state_root: &Path, json: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub(crate) async fn revoke(state_root: &Path, token: String) -> anyhow::Result<()> {
-    let cfg = config::load(state_root)?;
-    let mut conn = redis_conn::connect(&cfg.gateway.redis_url).await?;
-    let removed = gateway_sessions::del(&mut conn, &token).await?;
-    if !removed {
+pub(crate) async fn inspect(config_path: &Path, token: String, json: bool) -> anyhow::Result<()> {
+    let cfg = config::load(config_path)?;
+    let mut conn = redis_conn::connect(&cfg.gateway).await?;
+    let session = gateway_sessions::get(&mut conn, &token, &SystemClock)
+        .await?
+        .with_context(|| format!("gateway session not found for token {token:?}"))?;
+    let sticky_accounts = routing::sticky_bindings(&mut conn, &session.account_pool_id).await?;
+
+    let expires_in_seconds = session.expires_in_seconds(&SystemClock);
+    let out = GatewayInspectOut {
+        token,
+        pool_id: session.account_pool_id,
+        policy_key: session.policy_key,
+        issued_at_ms: session.issued_at_ms,
+        expires_at_ms: session.expires_at_ms,
+        expires_in_seconds,
+        note: session.note,
+        allowed_paths: session.allowed_paths,
+        preview: session.preview,
+        sticky_accounts,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    let expires_in = if out.expires_in_seconds <= 0 {
+        "expired".to_string()
+    } else {
+        format!("{}s", out.expires_in_seconds)
+    };
+    let allowed_paths = if out.allowed_paths.is_empty() {
+        "-".to_string()
+    } else {
+        out.allowed_paths.join(",")
+    };
+    println!("token: {}", out.token);
+    println!("pool: {}", out.pool_id);
+    println!("policy_key: {}", out.policy_key.as_deref().unwrap_or("-"));
+    println!("issued_at_ms: {}", out.issued_at_ms);
+    println!("expires_at_ms: {}", out.expires_at_ms);
+    println!("expires_in: {expires_in}");
+    println!("note: {}", out.note.as_deref().unwrap_or("-"));
+    println!("allowed_paths: {allowed_paths}");
+    if out.preview {
+        println!("preview: yes (non-renewable)");
+    }
+    if out.sticky_accounts.is_empty() {
+        println!("sticky: -");
+    } else {
+        println!("sticky (pool-wide conversations per account):");
+        for (label, count) in &out.sticky_accounts {
+            println!("  {label}: {count}");
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn revoke(
+    state_root: &Path,
+    config_path: &Path,
+    token: String,
+) -> anyhow::Result<()> {
+    let cfg = config::load(config_path)?;
+    let mut conn = redis_conn::connect(&cfg.gateway).await?;
+    if !revoke_session(state_root, &mut conn, &token).await? {
         anyhow::bail!("gateway session not found for token {token:?}");
     }
     Ok(())
 }
 
+/// Deletes the session for `token`, returning false when there was none.
+pub(crate) async fn revoke_session(
+    state_root: &Path,
+    conn: &mut redis_conn::RedisConnection,
+    token: &str,
+) -> anyhow::Result<bool> {
+    let removed = gateway_sessions::del(conn, token).await?;
+    if removed {
+        audit::record(state_root, AuditEntry::new("gateway revoke").token(token));
+    }
+    Ok(removed)
+}
+
+pub(crate) enum StickyAction {
+    Get,
+    Set {
+        label: String,
+        ttl_seconds: Option<i64>,
+    },
+    Clear,
+}
+
+pub(crate) struct StickyOptions {
+    pub(crate) pool_id: String,
+    pub(crate) conversation_id: String,
+    pub(crate) action: StickyAction,
+    pub(crate) json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct StickyOut {
+    pool_id: String,
+    sticky_key: String,
+    binding: Option<routing::StickyBinding>,
+}
+
+/// Reads, rebinds or releases the sticky account for one conversation in a pool. Keys are built
+/// with the same hashing as routing, so changes take effect on the conversation's next request.
+pub(crate) async fn sticky(
+    state_root: &Path,
+    config_path: &Path,
+    accounts_root: &Path,
+    options: StickyOptions,
+) -> anyhow::Result<()> {
+    let StickyOptions {
+        pool_id,
+        conversation_id,
+        action,
+        json,
+    } = options;
+    let cfg = config::load(config_path)?;
+    let pool_id = cfg.resolve_pool_alias(&pool_id);
+    let (labels, policy_key) = if cfg.is_implicit_default_pool(&pool_id) {
+        (
+            accounts::list_unreserved_labels(accounts_root, state_root)?,
+            None,
+        )
+    } else {
+        let pool = cfg
+            .pools
+            .get(&pool_id)
+            .with_context(|| format!("pool {pool_id:?} does not exist"))?;
+        (pool.labels.clone(), pool.policy_key.clone())
+    };
+
+    let sticky_key = routing::sticky_key(
+        cfg.gateway.redis_mode,
+        &pool_id,
+        policy_key.as_deref(),
+        &conversation_id,
+    );
+    let mut conn = redis_conn::connect(&cfg.gateway).await?;
+    match action {
+        StickyAction::Get => {}
+        StickyAction::Set { label, ttl_seconds } => {
+            if !labels.contains(&label) {
+                anyhow::bail!("account {label:?} is not a member of pool {pool_id:?}");
+            }
+            let ttl_seconds = ttl_seconds.unwrap_or(cfg.gateway.sticky_ttl_seconds);
+            routing::write_sticky_binding(&mut conn, &sticky_key, &label, ttl_seconds).await?;
+        }
+        StickyAction::Clear => {
+            if !routing::clear_sticky_binding(&mut conn, &sticky_key).await? {
+                anyhow::bail!("conversation {conversation_id:?} is not bound in pool {pool_id:?}");
+            }
+        }
+    }
+    let binding = routing::read_sticky_binding(&mut conn, &sticky_key).await?;
+
+    let out = StickyOut {
+        pool_id,
+        sticky_key,
+        binding,
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+    match &out.binding {
+        Some(binding) => println!(
+            "{}: {} (expires in {}s)",
+            out.sticky_key, binding.label, binding.ttl_seconds
+        ),
+        None => println!("{}: unbound", out.sticky_key),
+    }
+    if let Some(binding) = &out.binding
+        && !labels.contains(&binding.label)
+    {
+        println!(
+            "warning: {:?} is no longer in pool {:?}; routing will re-select on the next request",
+            binding.label, out.pool_id
+        );
+    }
+    Ok(())
+}
+
 fn generate_gateway_token() -> anyhow::Result<String> {
     let mut bytes = [0u8; 32];
     let mut rng = rand::rngs::OsRng;
@@ -186,3 +622,129 @@ fn generate_gateway_token() -> anyhow::Result<String> {
     let encoded = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
     Ok(format!("gw_{encoded}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn curl_example_targets_loopback_for_wildcard_listen() {
+        let example = curl_example("0.0.0.0:8787", "gw_test");
+
+        assert_eq!(
+            example.lines().next(),
+            Some("curl -N http://127.0.0.1:8787/responses \\")
+        );
+        assert!(example.contains("-H 'Authorization: Bearer gw_test'"));
+    }
+
+    #[test]
+    fn preview_sessions_are_capped_regardless_of_requested_ttl() {
+        let cap = gateway_sessions::PREVIEW_MAX_TTL_SECONDS;
+        assert_eq!(session_ttl_seconds(None, Some(cap)).expect("default"), cap);
+        assert_eq!(session_ttl_seconds(Some(60), Some(cap)).expect("short"), 60);
+        assert_eq!(
+            session_ttl_seconds(Some(DEFAULT_SESSION_TTL_SECONDS), Some(cap)).expect("clamped"),
+            cap
+        );
+        assert_eq!(
+            session_ttl_seconds(None, None).expect("regular"),
+            DEFAULT_SESSION_TTL_SECONDS
+        );
+        assert!(session_ttl_seconds(Some(0), Some(cap)).is_err());
+    }
+
+    #[test]
+    fn configured_default_pool_must_name_an_existing_pool() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let config_path = config::default_path(dir.path());
+        let write = |default_pool: &str| {
+            std::fs::write(
+                &config_path,
+                format!(
+                    "[gateway]\ndefault_pool = \"{default_pool}\"\n\n[pools.team-a]\nlabels = [\"work\"]\n\n[pool_aliases]\nteam = \"team-a\"\n"
+                ),
+            )
+            .expect("write config");
+            config::load(&config_path).expect("load config")
+        };
+
+        assert_eq!(
+            configured_default_pool(&write("team")).expect("alias"),
+            "team"
+        );
+        assert_eq!(
+            configured_default_pool(&write("default")).expect("implicit default pool"),
+            "default"
+        );
+        assert!(configured_default_pool(&write("missing")).is_err());
+    }
+
+    /// Creates `label`'s account home, with an `auth.json` whose access token expires in 2100
+    /// when `live`, or none at all.
+    fn write_account(accounts_root: &Path, label: &str, live: bool) {
+        let home = accounts_root.join(label);
+        std::fs::create_dir_all(&home).expect("create account home");
+        if live {
+            let auth = serde_json::json!({
+                "OPENAI_API_KEY": null,
+                "tokens": {
+                    "id_token": "e30.e30.sig",
+                    // Payload is {"exp":4102444800}.
+                    "access_token": "e30.eyJleHAiOjQxMDI0NDQ4MDB9.sig",
+                    "refresh_token": "rt",
+                },
+            });
+            std::fs::write(home.join("auth.json"), auth.to_string()).expect("write auth.json");
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_pool_auth_needs_one_member_with_usable_auth() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let accounts_root = dir.path().join("accounts");
+        write_account(&accounts_root, "dead", false);
+        write_account(&accounts_root, "live", true);
+        let config_path = config::default_path(dir.path());
+        std::fs::write(
+            &config_path,
+            "[gateway]\n\n[pools.dead-only]\nlabels = [\"dead\"]\n\n[pools.mixed]\nlabels = [\"dead\", \"live\"]\n",
+        )
+        .expect("write config");
+        let cfg = config::load(&config_path).expect("load config");
+
+        let err = verify_pool_auth(dir.path(), &accounts_root, &cfg, "dead-only")
+            .await
+            .expect_err("no usable member");
+        assert_eq!(
+            crate::exit_code::category_of(&err),
+            Some(ErrorCategory::Auth)
+        );
+        assert!(format!("{err:#}").contains("dead:"));
+        verify_pool_auth(dir.path(), &accounts_root, &cfg, "mixed")
+            .await
+            .expect("live member");
+    }
+
+    #[tokio::test]
+    async fn verify_pool_auth_checks_every_account_for_the_implicit_default_pool() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let accounts_root = dir.path().join("accounts");
+        write_account(&accounts_root, "dead", false);
+        let config_path = config::default_path(dir.path());
+        std::fs::write(&config_path, "[gateway]\n").expect("write config");
+        let cfg = config::load(&config_path).expect("load config");
+        assert!(cfg.is_implicit_default_pool("default"));
+
+        assert!(
+            verify_pool_auth(dir.path(), &accounts_root, &cfg, "default")
+                .await
+                .is_err()
+        );
+        write_account(&accounts_root, "live", true);
+        verify_pool_auth(dir.path(), &accounts_root, &cfg, "default")
+            .await
+            .expect("live account");
+    }
+}
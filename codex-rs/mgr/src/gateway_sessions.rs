@@ -2,9 +2,76 @@ use anyhow::Context;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::redis_conn;
+use crate::time::Clock;
+
 const SESSION_KEY_PREFIX: &str = "gw:session:";
 const SESSION_KEY_PATTERN: &str = "gw:session:*";
 const SESSION_SCAN_COUNT: i64 = 1000;
+const ISSUE_RATE_KEY_PREFIX: &str = "gw:issue_rate:";
+/// A drained issuance bucket refills completely over this long.
+const ISSUE_RATE_WINDOW_MS: i64 = 60_000;
+/// Compare-and-set rounds before `record_issue` gives up on a bucket other issuers keep updating.
+const ISSUE_RATE_MAX_ATTEMPTS: usize = 5;
+/// Hard cap on a preview session's lifetime, whatever TTL was requested.
+pub(crate) const PREVIEW_MAX_TTL_SECONDS: i64 = 86_400;
+
+// Stores the bucket computed by `IssueBucket::take` if nobody else wrote it since it was read
+// (`version` is still ARGV[1]), and returns an empty reply. Otherwise nothing is written and the
+// current `tokens`, `updated_ms`, `version` come back for another round. The fixed-window limiter
+// this replaced kept a plain counter under the same key; it is dropped here, which forgets at
+// most the rest of one window during a rolling deploy. An idle bucket is full again after
+// ARGV[4] ms, so the key expires then.
+const ISSUE_RATE_SCRIPT: &str = r#"
+if redis.call('TYPE', KEYS[1]).ok ~= 'hash' then
+  redis.call('DEL', KEYS[1])
+end
+local current = redis.call('HMGET', KEYS[1], 'tokens', 'updated_ms', 'version')
+if (current[3] or '0') ~= ARGV[1] then
+  return current
+end
+redis.call('HSET', KEYS[1], 'tokens', ARGV[2], 'updated_ms', ARGV[3], 'version', tonumber(ARGV[1]) + 1)
+redis.call('PEXPIRE', KEYS[1], ARGV[4])
+return {}
+"#;
+
+/// One pool's issuance token bucket: up to `max_issues_per_minute` tokens, refilled continuously
+/// at that many per minute, so a burst cannot straddle a window boundary for twice the budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IssueBucket {
+    tokens: f64,
+    updated_ms: i64,
+}
+
+impl IssueBucket {
+    /// Refills `current` (a missing bucket is full) up to `now_ms`, then takes one token. The
+    /// second value is how many ms until a token is available when none was left to take.
+    fn take(current: Option<Self>, capacity: i64, now_ms: i64) -> (Self, Option<i64>) {
+        let capacity = capacity as f64;
+        let window_ms = ISSUE_RATE_WINDOW_MS as f64;
+        let (tokens, updated_ms) = match current {
+            Some(bucket) => {
+                let elapsed_ms = now_ms.saturating_sub(bucket.updated_ms).max(0);
+                (
+                    (bucket.tokens + elapsed_ms as f64 * capacity / window_ms).min(capacity),
+                    bucket.updated_ms.max(now_ms),
+                )
+            }
+            None => (capacity, now_ms),
+        };
+        if tokens >= 1.0 {
+            return (
+                Self {
+                    tokens: tokens - 1.0,
+                    updated_ms,
+                },
+                None,
+            );
+        }
+        let retry_ms = ((1.0 - tokens) * window_ms / capacity).ceil() as i64;
+        (Self { tokens, updated_ms }, Some(retry_ms.max(1)))
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct GatewaySession {
@@ -13,6 +80,78 @@ pub(crate) struct GatewaySession {
     pub(crate) issued_at_ms: i64,
     pub(crate) expires_at_ms: i64,
     pub(crate) note: Option<String>,
+    /// Path prefixes this token may call; empty means unrestricted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) allowed_paths: Vec<String>,
+    /// Preview sessions are written once and never renewed or extended; see [`put`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) preview: bool,
+}
+
+impl GatewaySession {
+    /// Seconds left before the session expires by `clock`; zero or negative once expired.
+    pub(crate) fn expires_in_seconds(&self, clock: &dyn Clock) -> i64 {
+        self.expires_at_ms.saturating_sub(clock.now_ms()) / 1000
+    }
+
+    /// Whether `expires_at_ms` has passed by `clock`. Redis expires keys on its own clock and to
+    /// the second, so a session can briefly outlive its recorded expiry there.
+    pub(crate) fn is_expired(&self, clock: &dyn Clock) -> bool {
+        self.expires_at_ms <= clock.now_ms()
+    }
+
+    /// Matches whole path segments, so `/models` allows `/models/gpt-5.4` but not `/modelsx`.
+    /// Paths with `.`/`..` segments are refused outright, even for unrestricted sessions: the
+    /// upstream URL join would resolve them, so `/models/../responses` would escape `/models`.
+    ///
+    /// The path is percent-decoded once, as upstream will read it, and the decoded value is what
+    /// gets checked: `/models%2Fgpt-5.4` is allowed by `/models`, and `%2F..%2F` is a dot
+    /// segment. Malformed escapes and non-UTF-8 paths are refused.
+    pub(crate) fn allows_path(&self, path: &str) -> bool {
+        if has_dot_segment(path) {
+            return false;
+        }
+        let Some(path) = percent_decode_path(path) else {
+            return false;
+        };
+        if has_dot_segment(&path) {
+            return false;
+        }
+        self.allowed_paths.is_empty()
+            || self.allowed_paths.iter().any(|prefix| {
+                let prefix = prefix.trim_end_matches('/');
+                prefix.is_empty()
+                    || path
+                        .strip_prefix(prefix)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+}
+
+/// `path` with every `%XX` escape decoded; `None` for a malformed escape or a result that is not
+/// UTF-8.
+fn percent_decode_path(path: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let high = char::from(bytes.next()?).to_digit(16)?;
+        let low = char::from(bytes.next()?).to_digit(16)?;
+        decoded.push(u8::try_from(high * 16 + low).ok()?);
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Whether any segment of `path` is `.` or `..`, including percent-encoded forms (`%2e%2E`).
+/// Backslashes count as separators because URL parsing treats them as `/` for http(s).
+fn has_dot_segment(path: &str) -> bool {
+    path.split(['/', '\\']).any(|segment| {
+        let decoded = segment.to_ascii_lowercase().replace("%2e", ".");
+        decoded == "." || decoded == ".."
+    })
 }
 
 pub(crate) fn key_for_token(token: &str) -> String {
@@ -23,22 +162,24 @@ pub(crate) fn token_from_key(key: &str) -> Option<&str> {
     key.strip_prefix(SESSION_KEY_PREFIX)
 }
 
+/// The session stored for `token`, unless it is missing or already expired by `clock`.
 pub(crate) async fn get(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     token: &str,
+    clock: &dyn Clock,
 ) -> anyhow::Result<Option<GatewaySession>> {
     let key = key_for_token(token);
-    let value: Option<String> = redis::cmd("GET").arg(&key).query_async(conn).await?;
-    match value {
-        Some(value) => serde_json::from_str(&value)
-            .with_context(|| format!("parsing redis session value for {key:?}"))
-            .map(Some),
-        None => Ok(None),
-    }
+    let value: Option<String> = conn.query_idempotent(redis::cmd("GET").arg(&key)).await?;
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let session: GatewaySession = serde_json::from_str(&value)
+        .with_context(|| format!("parsing redis session value for {key:?}"))?;
+    Ok((!session.is_expired(clock)).then_some(session))
 }
 
 pub(crate) async fn put(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     token: &str,
     session: &GatewaySession,
     ttl_seconds: i64,
@@ -48,18 +189,41 @@ pub(crate) async fn put(
     }
     let key = key_for_token(token);
     let value = serde_json::to_string(session).context("serializing GatewaySession")?;
-    let _: () = redis::cmd("SET")
+    if !session.preview {
+        let _: () = redis::cmd("SET")
+            .arg(&key)
+            .arg(value)
+            .arg("EX")
+            .arg(ttl_seconds)
+            .query_async(conn)
+            .await?;
+        return Ok(());
+    }
+
+    // Preview sessions are only ever created: the cap bounds their whole lifetime and `NX`
+    // keeps any later write from resetting the key's expiry.
+    let max_expires_at_ms = session
+        .issued_at_ms
+        .saturating_add(PREVIEW_MAX_TTL_SECONDS * 1000);
+    if ttl_seconds > PREVIEW_MAX_TTL_SECONDS || session.expires_at_ms > max_expires_at_ms {
+        anyhow::bail!("preview sessions cannot outlive {PREVIEW_MAX_TTL_SECONDS}s");
+    }
+    let created: Option<String> = redis::cmd("SET")
         .arg(&key)
         .arg(value)
         .arg("EX")
         .arg(ttl_seconds)
+        .arg("NX")
         .query_async(conn)
         .await?;
+    if created.is_none() {
+        anyhow::bail!("preview session for token {token:?} already exists and cannot be renewed");
+    }
     Ok(())
 }
 
 pub(crate) async fn del(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     token: &str,
 ) -> anyhow::Result<bool> {
     let key = key_for_token(token);
@@ -67,26 +231,50 @@ pub(crate) async fn del(
     Ok(deleted > 0)
 }
 
-pub(crate) async fn list(
-    conn: &mut redis::aio::ConnectionManager,
-) -> anyhow::Result<Vec<(String, GatewaySession)>> {
-    let mut cursor = "0".to_string();
-    let mut keys = Vec::new();
-    loop {
-        let (next_cursor, mut batch): (String, Vec<String>) = redis::cmd("SCAN")
-            .arg(&cursor)
-            .arg("MATCH")
-            .arg(SESSION_KEY_PATTERN)
-            .arg("COUNT")
-            .arg(SESSION_SCAN_COUNT)
+/// Takes one session issuance from `pool_id`'s [`IssueBucket`], timed by `clock`. Returns the
+/// number of seconds until an issuance is available again when the bucket is empty.
+pub(crate) async fn record_issue(
+    conn: &mut redis_conn::RedisConnection,
+    pool_id: &str,
+    max_issues_per_minute: i64,
+    clock: &dyn Clock,
+) -> anyhow::Result<Option<i64>> {
+    let key = format!("{ISSUE_RATE_KEY_PREFIX}{pool_id}");
+    let mut current: Option<IssueBucket> = None;
+    let mut version = 0_i64;
+    for _ in 0..ISSUE_RATE_MAX_ATTEMPTS {
+        let (next, retry_ms) = IssueBucket::take(current, max_issues_per_minute, clock.now_ms());
+        let conflict: Vec<Option<String>> = redis::cmd("EVAL")
+            .arg(ISSUE_RATE_SCRIPT)
+            .arg(1)
+            .arg(&key)
+            .arg(version)
+            .arg(next.tokens)
+            .arg(next.updated_ms)
+            .arg(ISSUE_RATE_WINDOW_MS)
             .query_async(conn)
             .await?;
-        keys.append(&mut batch);
-        cursor = next_cursor;
-        if cursor == "0" {
-            break;
-        }
+        let [tokens, updated_ms, current_version] = conflict.as_slice() else {
+            return Ok(retry_ms.map(|ms| (ms + 999) / 1000));
+        };
+        let parse = |field: &Option<String>| field.as_deref().and_then(|v| v.parse::<f64>().ok());
+        current = parse(tokens)
+            .zip(parse(updated_ms))
+            .map(|(tokens, updated_ms)| IssueBucket {
+                tokens,
+                updated_ms: updated_ms as i64,
+            });
+        version = parse(current_version).map_or(0, |v| v as i64);
     }
+    anyhow::bail!("issuance rate limit for pool {pool_id:?} is contended; try again")
+}
+
+pub(crate) async fn list(
+    conn: &mut redis_conn::RedisConnection,
+) -> anyhow::Result<Vec<(String, GatewaySession)>> {
+    let keys = conn
+        .scan_match(SESSION_KEY_PATTERN, SESSION_SCAN_COUNT)
+        .await?;
 
     let mut out = Vec::new();
     for key in keys {
@@ -109,3 +297,123 @@ pub(crate) async fn list(
     });
     Ok(out)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MockClock;
+
+    #[test]
+    fn issue_bucket_allows_a_burst_then_refills_at_the_per_minute_rate() {
+        let clock = MockClock::new(1_000_000);
+        let mut bucket = None;
+        for _ in 0..3 {
+            let (next, retry_ms) = IssueBucket::take(bucket, 3, clock.now_ms());
+            assert_eq!(retry_ms, None);
+            bucket = Some(next);
+        }
+
+        // Empty: one token per 20s at 3/min, none of it elapsed yet.
+        let (next, retry_ms) = IssueBucket::take(bucket, 3, clock.now_ms());
+        assert_eq!(retry_ms, Some(20_000));
+        bucket = Some(next);
+
+        clock.advance_ms(10_000);
+        let (next, retry_ms) = IssueBucket::take(bucket, 3, clock.now_ms());
+        assert_eq!(retry_ms, Some(10_000));
+        bucket = Some(next);
+
+        clock.advance_ms(10_000);
+        let (next, retry_ms) = IssueBucket::take(bucket, 3, clock.now_ms());
+        assert_eq!(retry_ms, None);
+        bucket = Some(next);
+        assert_eq!(IssueBucket::take(bucket, 3, clock.now_ms()).1, Some(20_000));
+
+        // A long idle period refills to capacity, never beyond it.
+        clock.advance_ms(10 * ISSUE_RATE_WINDOW_MS);
+        let (refilled, retry_ms) = IssueBucket::take(bucket, 3, clock.now_ms());
+        assert_eq!(retry_ms, None);
+        assert_eq!(refilled.tokens, 2.0);
+
+        // A clock behind the stored bucket (another issuer's) neither refills nor rewinds it.
+        let (behind, _) = IssueBucket::take(Some(refilled), 3, clock.now_ms() - 5_000);
+        assert_eq!(behind.updated_ms, refilled.updated_ms);
+        assert_eq!(behind.tokens, 1.0);
+    }
+
+    fn session(allowed_paths: &[&str]) -> GatewaySession {
+        GatewaySession {
+            account_pool_id: "default".to_string(),
+            policy_key: None,
+            issued_at_ms: 0,
+            expires_at_ms: 0,
+            note: None,
+            allowed_paths: allowed_paths.iter().map(ToString::to_string).collect(),
+            preview: false,
+        }
+    }
+
+    #[test]
+    fn allows_path_matches_whole_segments() {
+        assert!(session(&[]).allows_path("/responses"));
+
+        let scoped = session(&["/models"]);
+        assert!(scoped.allows_path("/models"));
+        assert!(scoped.allows_path("/models/gpt-5.4"));
+        assert!(!scoped.allows_path("/modelsx"));
+        assert!(!scoped.allows_path("/responses"));
+    }
+
+    #[test]
+    fn allows_path_refuses_dot_segments_in_any_encoding() {
+        let scoped = session(&["/models"]);
+        assert!(!scoped.allows_path("/models/../responses"));
+        assert!(!scoped.allows_path("/models/%2e%2e/responses"));
+        assert!(!scoped.allows_path("/models/%2E./responses"));
+        assert!(!scoped.allows_path("/models/./gpt-5.4"));
+        assert!(!scoped.allows_path("/models\\..\\responses"));
+        assert!(!session(&[]).allows_path("/models/../responses"));
+
+        assert!(scoped.allows_path("/models/gpt-5.4..preview"));
+    }
+
+    #[test]
+    fn allows_path_checks_the_percent_decoded_path() {
+        let scoped = session(&["/models"]);
+        assert!(scoped.allows_path("/models%2Fgpt-5.4"));
+        assert!(scoped.allows_path("/%6Dodels/gpt-5.4"));
+        assert!(!scoped.allows_path("/models%2F..%2Fresponses"));
+        assert!(!scoped.allows_path("/models%2f%2e%2e%2fresponses"));
+        assert!(!scoped.allows_path("/models/%252e%252e/responses"));
+        assert!(!scoped.allows_path("/models%2Fx%2"));
+        assert!(!scoped.allows_path("/models/%zz"));
+        assert!(!scoped.allows_path("/models/%ff"));
+        assert!(!session(&[]).allows_path("/responses%2F..%2Fadmin"));
+
+        assert_eq!(
+            percent_decode_path("/a%20b/%2Fc").as_deref(),
+            Some("/a b//c")
+        );
+    }
+
+    #[test]
+    fn expires_in_seconds_counts_down_with_the_clock() {
+        let clock = MockClock::new(1_000_000);
+        let session = GatewaySession {
+            issued_at_ms: 1_000_000,
+            expires_at_ms: 1_060_000,
+            ..session(&[])
+        };
+        assert_eq!(session.expires_in_seconds(&clock), 60);
+
+        clock.advance_ms(59_500);
+        assert_eq!(session.expires_in_seconds(&clock), 0);
+        assert!(!session.is_expired(&clock));
+
+        clock.advance_ms(500);
+        assert!(session.is_expired(&clock));
+
+        clock.advance_ms(5_000);
+        assert!(session.expires_in_seconds(&clock) < 0);
+    }
+}
@@ -1,13 +1,72 @@
+use anyhow::Context;
 use axum::http::HeaderMap;
 use axum::http::HeaderName;
+use axum::http::HeaderValue;
 use axum::http::header;
+use std::collections::BTreeMap;
+
+/// Operator-configured headers dropped on top of the built-in policy
+/// (`gateway.strip_request_headers` / `gateway.strip_response_headers`).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HeaderStripLists {
+    pub(crate) request: Vec<HeaderName>,
+    pub(crate) response: Vec<HeaderName>,
+}
+
+impl HeaderStripLists {
+    pub(crate) fn parse(request: &[String], response: &[String]) -> anyhow::Result<Self> {
+        Ok(Self {
+            request: parse_header_names(request, "gateway.strip_request_headers")?,
+            response: parse_header_names(response, "gateway.strip_response_headers")?,
+        })
+    }
+}
+
+fn parse_header_names(names: &[String], field: &str) -> anyhow::Result<Vec<HeaderName>> {
+    names
+        .iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.trim().to_ascii_lowercase().as_bytes())
+                .with_context(|| format!("invalid header name {name:?} in {field}"))
+        })
+        .collect()
+}
+
+/// Parses `gateway.upstream_api_headers`, the API version/beta headers sent on every upstream
+/// request. Headers the gateway manages itself (credentials, routing, framing) cannot be pinned.
+pub(crate) fn parse_pinned_headers(
+    headers: &BTreeMap<String, String>,
+) -> anyhow::Result<HeaderMap> {
+    let field = "gateway.upstream_api_headers";
+    let mut out = HeaderMap::new();
+    for (name, value) in headers {
+        let header_name = HeaderName::from_bytes(name.trim().to_ascii_lowercase().as_bytes())
+            .with_context(|| format!("invalid header name {name:?} in {field}"))?;
+        if should_drop_request_header(&header_name, &[])
+            || header_name.as_str() == "chatgpt-account-id"
+        {
+            anyhow::bail!("{field} cannot set {name:?}; the gateway manages that header");
+        }
+        let header_value = HeaderValue::from_str(value)
+            .with_context(|| format!("invalid value for {name:?} in {field}"))?;
+        out.insert(header_name, header_value);
+    }
+    Ok(out)
+}
 
-pub(crate) fn forward_request_headers(headers: &HeaderMap) -> HeaderMap {
+/// Replaces any client-sent values of the pinned headers with the configured ones.
+pub(crate) fn apply_pinned_headers(headers: &mut HeaderMap, pinned: &HeaderMap) {
+    for (name, value) in pinned {
+        headers.insert(name.clone(), value.clone());
+    }
+}
+
+pub(crate) fn forward_request_headers(headers: &HeaderMap, strip: &[HeaderName]) -> HeaderMap {
     let mut out = HeaderMap::new();
     let connection_hops = connection_hop_headers(headers);
 
     for (name, value) in headers.iter() {
-        if should_drop_request_header(name, &connection_hops) {
+        if should_drop_request_header(name, &connection_hops) || strip.contains(name) {
             continue;
         }
         out.append(name.clone(), value.clone());
@@ -16,12 +75,12 @@ pub(crate) fn forward_request_headers(headers: &HeaderMap) -> HeaderMap {
     out
 }
 
-pub(crate) fn forward_response_headers(headers: &HeaderMap) -> HeaderMap {
+pub(crate) fn forward_response_headers(headers: &HeaderMap, strip: &[HeaderName]) -> HeaderMap {
     let mut out = HeaderMap::new();
     let connection_hops = connection_hop_headers(headers);
 
     for (name, value) in headers.iter() {
-        if should_drop_response_header(name, &connection_hops) {
+        if should_drop_response_header(name, &connection_hops) || strip.contains(name) {
             continue;
         }
         out.append(name.clone(), value.clone());
@@ -57,6 +116,9 @@ fn should_drop_request_header(name: &HeaderName, connection_hops: &[HeaderName])
     if name_str == "x-real-ip" {
         return true;
     }
+    if name_str == crate::routing::ACCOUNT_OVERRIDE_HEADER {
+        return true;
+    }
 
     false
 }
@@ -107,3 +169,65 @@ fn connection_hop_headers(headers: &HeaderMap) -> Vec<HeaderName> {
 
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn configured_strip_lists_drop_headers_in_both_directions() {
+        let strip = HeaderStripLists::parse(
+            &["X-Internal-Debug".to_string()],
+            &["set-cookie".to_string()],
+        )
+        .expect("parse strip lists");
+
+        let mut request = HeaderMap::new();
+        request.insert("x-internal-debug", HeaderValue::from_static("1"));
+        request.insert("openai-beta", HeaderValue::from_static("responses=v1"));
+        request.insert("x-codex-mgr-account", HeaderValue::from_static("work"));
+        let forwarded = forward_request_headers(&request, &strip.request);
+        assert_eq!(forwarded.get("x-internal-debug"), None);
+        assert_eq!(forwarded.get("x-codex-mgr-account"), None);
+        assert_eq!(
+            forwarded.get("openai-beta"),
+            Some(&HeaderValue::from_static("responses=v1"))
+        );
+
+        let mut response = HeaderMap::new();
+        response.insert(header::SET_COOKIE, HeaderValue::from_static("a=b"));
+        response.insert("x-oai-request-id", HeaderValue::from_static("req"));
+        let forwarded = forward_response_headers(&response, &strip.response);
+        assert_eq!(forwarded.get(header::SET_COOKIE), None);
+        assert_eq!(
+            forwarded.get("x-oai-request-id"),
+            Some(&HeaderValue::from_static("req"))
+        );
+    }
+
+    #[test]
+    fn pinned_headers_override_client_values_and_reject_managed_headers() {
+        let pinned = parse_pinned_headers(&BTreeMap::from([(
+            "OpenAI-Beta".to_string(),
+            "responses=experimental".to_string(),
+        )]))
+        .expect("parse pinned headers");
+
+        let mut headers = HeaderMap::new();
+        headers.append("openai-beta", HeaderValue::from_static("responses=v1"));
+        headers.append("openai-beta", HeaderValue::from_static("other"));
+        apply_pinned_headers(&mut headers, &pinned);
+        let values: Vec<&HeaderValue> = headers.get_all("openai-beta").iter().collect();
+        assert_eq!(
+            values,
+            vec![&HeaderValue::from_static("responses=experimental")]
+        );
+
+        for managed in ["Authorization", "host", "ChatGPT-Account-ID", "connection"] {
+            let err =
+                parse_pinned_headers(&BTreeMap::from([(managed.to_string(), "x".to_string())]));
+            assert!(err.is_err(), "{managed} should be rejected");
+        }
+    }
+}
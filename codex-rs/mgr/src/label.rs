@@ -1,3 +1,6 @@
+use std::path::Component;
+use std::path::Path;
+
 const LABEL_MAX_LEN: i64 = 64;
 
 pub(crate) fn validate_label(label: &str) -> anyhow::Result<()> {
@@ -18,6 +21,7 @@ pub(crate) fn validate_label(label: &str) -> anyhow::Result<()> {
         .chars()
         .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
         && !label.starts_with('.')
+        && is_single_path_component(label)
     {
         return Ok(());
     }
@@ -26,3 +30,67 @@ pub(crate) fn validate_label(label: &str) -> anyhow::Result<()> {
         "invalid label {label:?}; use only ASCII letters/numbers plus '-', '_' or '.', and do not start with '.'"
     );
 }
+
+/// Returns true when `name` joins onto a directory as exactly one normal child entry, so it can
+/// never name the directory itself, its parent, or anything deeper. Backs up the charset checks
+/// for labels and pool ids, which become directory and key names.
+pub(crate) fn is_single_path_component(name: &str) -> bool {
+    if name.is_empty() || name.bytes().any(|b| b == 0 || b == b'/' || b == b'\\') {
+        return false;
+    }
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(part)), None) if part == name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn rejects_labels_that_could_escape_accounts_root() {
+        for label in [
+            "",
+            ".",
+            "..",
+            "../x",
+            "a/b",
+            "a/../b",
+            "/abs",
+            "a\\b",
+            "..\\x",
+            "a\0b",
+            ".hidden",
+            "a\u{2215}b",
+            "a\u{ff0f}b",
+            "\u{ff0e}\u{ff0e}",
+            "caf\u{e9}",
+        ] {
+            assert!(validate_label(label).is_err(), "accepted {label:?}");
+        }
+    }
+
+    #[test]
+    fn accepted_labels_stay_directly_under_accounts_root() {
+        let root = Path::new("/accounts");
+        for label in ["a", "a..b", "a.b", "work-1_x", "x."] {
+            validate_label(label).expect("valid label");
+            assert_eq!(root.join(label).parent(), Some(root));
+        }
+    }
+
+    #[test]
+    fn single_path_component_rejects_separators_and_dot_entries() {
+        assert_eq!(is_single_path_component("plain"), true);
+        assert_eq!(is_single_path_component("a..b"), true);
+        assert_eq!(is_single_path_component(".."), false);
+        assert_eq!(is_single_path_component("."), false);
+        assert_eq!(is_single_path_component("a/b"), false);
+        assert_eq!(is_single_path_component("a\\b"), false);
+        assert_eq!(is_single_path_component("a\0"), false);
+        assert_eq!(is_single_path_component(""), false);
+    }
+}
@@ -1,28 +1,53 @@
 use anyhow::Context;
+use serde::Serialize;
+use std::io::Read;
+use std::io::Write;
 use std::path::Path;
 
 #[cfg(unix)]
 use std::os::unix::fs as unix_fs;
 
+const DRIFT_PREVIEW_BYTES: u64 = 200;
+
+/// Shared entries that hold credentials; drift on these reports size and type, never contents.
+const SECRET_ENTRIES: &[&str] = &[".credentials.json"];
+
+/// Entries in each account home that are symlinked into `shared_root`, and whether each one is a
+/// directory.
+const SHARED_ENTRIES: [(&str, bool); 12] = [
+    ("config.toml", false),
+    ("managed_config.toml", false),
+    ("history.jsonl", false),
+    ("prompts", true),
+    ("log", true),
+    ("memories", true),
+    ("sessions", true),
+    ("archived_sessions", true),
+    ("skills", true),
+    ("models_cache.json", false),
+    (".credentials.json", false),
+    ("version.json", false),
+];
+
+/// A shared entry in an account home that is not a symlink to its `shared_root` counterpart.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct SharedLayoutDrift {
+    pub(crate) entry: &'static str,
+    /// One of `file`, `directory`, or `symlink`.
+    pub(crate) found: &'static str,
+    pub(crate) detail: String,
+}
+
+/// Links every shared entry in `account_home` to `shared_root`.
+///
+/// Link targets are absolute because `shared_root` and `accounts_root` can be configured
+/// independently, so no relative path is stable. When `shared_root` moves, the old links dangle;
+/// a dangling absolute link to an entry of the same name is treated as pointing at a previous
+/// `shared_root` and is rewritten. Links to anything that still exists are left for the operator.
 pub(crate) fn ensure_shared_layout(account_home: &Path, shared_root: &Path) -> anyhow::Result<()> {
     #[cfg(unix)]
     {
-        let entries: [(&str, bool); 12] = [
-            ("config.toml", false),
-            ("managed_config.toml", false),
-            ("history.jsonl", false),
-            ("prompts", true),
-            ("log", true),
-            ("memories", true),
-            ("sessions", true),
-            ("archived_sessions", true),
-            ("skills", true),
-            ("models_cache.json", false),
-            (".credentials.json", false),
-            ("version.json", false),
-        ];
-
-        for (name, is_dir) in entries {
+        for (name, is_dir) in SHARED_ENTRIES {
             let link_path = account_home.join(name);
             let target = shared_root.join(name);
 
@@ -85,12 +110,26 @@ pub(crate) fn ensure_shared_layout(account_home: &Path, shared_root: &Path) -> a
 
                 let actual_target = std::fs::read_link(&link_path)
                     .with_context(|| format!("readlink {link_path:?}"))?;
-                if actual_target != target {
-                    anyhow::bail!(
-                        "expected symlink {link_path:?} -> {target:?}, but found {actual_target:?}"
+                if actual_target == target {
+                    continue;
+                }
+                if is_stale_shared_link(&actual_target, name) {
+                    tracing::info!(
+                        link = %link_path.display(),
+                        old_target = %actual_target.display(),
+                        new_target = %target.display(),
+                        "relinking shared entry left dangling by a moved shared_root"
                     );
+                    if is_dir {
+                        std::fs::create_dir_all(&target)
+                            .with_context(|| format!("creating shared dir {target:?}"))?;
+                    }
+                    replace_symlink(&link_path, &target)?;
+                    continue;
                 }
-                continue;
+                anyhow::bail!(
+                    "expected symlink {link_path:?} -> {target:?}, but found {actual_target:?}"
+                );
             }
 
             if is_dir {
@@ -111,7 +150,315 @@ pub(crate) fn ensure_shared_layout(account_home: &Path, shared_root: &Path) -> a
     }
 }
 
-pub(crate) fn ensure_shared_config(shared_root: &Path) -> anyhow::Result<()> {
+/// Whether [`retarget_shared_links`] rewrites the links it finds or only reports them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Retarget {
+    Rewrite,
+    DryRun,
+}
+
+/// Rewrites shared-entry symlinks in `account_home` that point at an entry under
+/// `old_shared_root` so they point at the same entry under `shared_root`. Returns the rewritten
+/// entries; with [`Retarget::DryRun`] nothing is changed and the entries that would be rewritten
+/// are returned.
+pub(crate) fn retarget_shared_links(
+    account_home: &Path,
+    old_shared_root: &Path,
+    shared_root: &Path,
+    retarget: Retarget,
+) -> anyhow::Result<Vec<&'static str>> {
+    let mut out = Vec::new();
+    for (name, _is_dir) in SHARED_ENTRIES {
+        let link_path = account_home.join(name);
+        let is_symlink =
+            std::fs::symlink_metadata(&link_path).is_ok_and(|meta| meta.file_type().is_symlink());
+        if !is_symlink {
+            continue;
+        }
+        let actual_target =
+            std::fs::read_link(&link_path).with_context(|| format!("readlink {link_path:?}"))?;
+        if actual_target != old_shared_root.join(name) {
+            continue;
+        }
+        out.push(name);
+        if retarget == Retarget::DryRun {
+            continue;
+        }
+        replace_symlink(&link_path, &shared_root.join(name))?;
+    }
+    Ok(out)
+}
+
+/// Removes `account_home` entirely. Shared-entry symlinks are unlinked, never followed, and the
+/// purge is refused while any shared entry is materialized as a real file or directory (that
+/// would be shared data living only in this home) or when the home overlaps `shared_root`.
+pub(crate) fn purge_account_home(account_home: &Path, shared_root: &Path) -> anyhow::Result<()> {
+    let home = std::fs::canonicalize(account_home)
+        .with_context(|| format!("resolving {account_home:?}"))?;
+    if let Ok(shared) = std::fs::canonicalize(shared_root)
+        && (home.starts_with(&shared) || shared.starts_with(&home))
+    {
+        anyhow::bail!(
+            "refusing to purge {account_home:?}: it overlaps shared_root {shared_root:?}"
+        );
+    }
+
+    // Names and types only: the drift detail previews file contents, which may be secrets.
+    let materialized: Vec<String> = inspect_shared_layout(account_home, shared_root)?
+        .into_iter()
+        .filter(|drift| drift.found != "symlink")
+        .map(|drift| format!("{} ({})", drift.entry, drift.found))
+        .collect();
+    if !materialized.is_empty() {
+        anyhow::bail!(
+            "refusing to purge {account_home:?}: shared entries are real files, not links into shared_root: {}",
+            materialized.join("; ")
+        );
+    }
+
+    for (name, _is_dir) in SHARED_ENTRIES {
+        let link_path = account_home.join(name);
+        let is_symlink =
+            std::fs::symlink_metadata(&link_path).is_ok_and(|meta| meta.file_type().is_symlink());
+        if is_symlink {
+            std::fs::remove_file(&link_path).with_context(|| format!("remove {link_path:?}"))?;
+        }
+    }
+    // Whatever is left is account-local; `remove_dir_all` unlinks any other symlinks without
+    // following them.
+    std::fs::remove_dir_all(account_home)
+        .with_context(|| format!("removing account home {account_home:?}"))
+}
+
+/// True when `actual_target` is an absolute link to `name` under some other directory and no
+/// longer resolves, which is what every link looks like after `shared_root` is moved.
+fn is_stale_shared_link(actual_target: &Path, name: &str) -> bool {
+    actual_target.is_absolute()
+        && actual_target
+            .file_name()
+            .is_some_and(|file_name| file_name == name)
+        && matches!(
+            std::fs::symlink_metadata(actual_target),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound
+        )
+}
+
+fn replace_symlink(link_path: &Path, target: &Path) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        std::fs::remove_file(link_path).with_context(|| format!("remove {link_path:?}"))?;
+        unix_fs::symlink(target, link_path)
+            .with_context(|| format!("creating symlink {link_path:?} -> {target:?}"))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (link_path, target);
+        anyhow::bail!("unsupported platform (v1 supports unix only)");
+    }
+}
+
+/// Reports shared entries that diverged from `shared_root` without repairing them. Missing entries
+/// are not drift; `ensure_shared_layout` creates them on demand.
+pub(crate) fn inspect_shared_layout(
+    account_home: &Path,
+    shared_root: &Path,
+) -> anyhow::Result<Vec<SharedLayoutDrift>> {
+    let mut out = Vec::new();
+    for (name, _is_dir) in SHARED_ENTRIES {
+        let link_path = account_home.join(name);
+        let target = shared_root.join(name);
+        let metadata = match std::fs::symlink_metadata(&link_path) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).with_context(|| format!("stat {link_path:?}")),
+        };
+
+        if metadata.file_type().is_symlink() {
+            let actual_target = std::fs::read_link(&link_path)
+                .with_context(|| format!("readlink {link_path:?}"))?;
+            if actual_target != target {
+                let repair = if is_stale_shared_link(&actual_target, name) {
+                    " (dangling; relinked on next use)"
+                } else {
+                    ""
+                };
+                out.push(SharedLayoutDrift {
+                    entry: name,
+                    found: "symlink",
+                    detail: format!("points at {actual_target:?} instead of {target:?}{repair}"),
+                });
+            }
+            continue;
+        }
+
+        if metadata.is_dir() {
+            let entries = std::fs::read_dir(&link_path)
+                .with_context(|| format!("read_dir {link_path:?}"))?
+                .count();
+            out.push(SharedLayoutDrift {
+                entry: name,
+                found: "directory",
+                detail: format!("{entries} entries"),
+            });
+            continue;
+        }
+
+        if SECRET_ENTRIES.contains(&name) {
+            out.push(SharedLayoutDrift {
+                entry: name,
+                found: "file",
+                detail: format!("{} bytes", metadata.len()),
+            });
+            continue;
+        }
+        let mut preview = Vec::new();
+        std::fs::File::open(&link_path)
+            .and_then(|file| file.take(DRIFT_PREVIEW_BYTES).read_to_end(&mut preview))
+            .with_context(|| format!("reading {link_path:?}"))?;
+        let preview = String::from_utf8_lossy(&preview).replace('\n', "\\n");
+        out.push(SharedLayoutDrift {
+            entry: name,
+            found: "file",
+            detail: format!("{} bytes: {preview}", metadata.len()),
+        });
+    }
+    Ok(out)
+}
+
+/// Mode for account homes, which hold refresh tokens.
+#[cfg(unix)]
+const ACCOUNT_HOME_MODE: u32 = 0o700;
+/// Mode for `auth.json` inside an account home.
+#[cfg(unix)]
+const AUTH_FILE_MODE: u32 = 0o600;
+
+/// An account home or `auth.json` that group or other users can access.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct LoosePermission {
+    /// `.` for the account home itself, otherwise the file name.
+    pub(crate) entry: &'static str,
+    /// Octal permission bits, e.g. `755`.
+    pub(crate) mode: String,
+    /// The mode `restrict_account_permissions` would set.
+    pub(crate) expected: String,
+}
+
+/// Creates `account_home` (and any missing parents) with mode 0700, so it is never open to other
+/// users between creation and [`restrict_account_permissions`].
+pub(crate) fn create_account_home(account_home: &Path) -> anyhow::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(ACCOUNT_HOME_MODE);
+    }
+    builder
+        .create(account_home)
+        .with_context(|| format!("creating account home {account_home:?}"))
+}
+
+/// Writes a new `auth.json` into `account_home`, created with mode 0600 rather than tightened
+/// after the tokens are already on disk.
+pub(crate) fn write_auth_file(account_home: &Path, contents: &str) -> anyhow::Result<()> {
+    let auth_path = account_home.join("auth.json");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(AUTH_FILE_MODE);
+    }
+    options
+        .open(&auth_path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .with_context(|| format!("writing {auth_path:?}"))
+}
+
+/// Restricts `account_home` to 0700 and its `auth.json` (when present) to 0600. A no-op off unix.
+pub(crate) fn restrict_account_permissions(account_home: &Path) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(
+            account_home,
+            std::fs::Permissions::from_mode(ACCOUNT_HOME_MODE),
+        )
+        .with_context(|| format!("restricting permissions on {account_home:?}"))?;
+        let auth_path = account_home.join("auth.json");
+        if auth_path.exists() {
+            std::fs::set_permissions(&auth_path, std::fs::Permissions::from_mode(AUTH_FILE_MODE))
+                .with_context(|| format!("restricting permissions on {auth_path:?}"))?;
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = account_home;
+    }
+    Ok(())
+}
+
+/// Reports whether `account_home` or its `auth.json` grant any access to group or other users.
+/// Always empty off unix.
+pub(crate) fn inspect_account_permissions(
+    account_home: &Path,
+) -> anyhow::Result<Vec<LoosePermission>> {
+    let mut out = Vec::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        for (entry, path, expected) in [
+            (".", account_home.to_path_buf(), ACCOUNT_HOME_MODE),
+            ("auth.json", account_home.join("auth.json"), AUTH_FILE_MODE),
+        ] {
+            let metadata = match std::fs::metadata(&path) {
+                Ok(meta) => meta,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err).with_context(|| format!("stat {path:?}")),
+            };
+            let mode = metadata.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                out.push(LoosePermission {
+                    entry,
+                    mode: format!("{mode:o}"),
+                    expected: format!("{expected:o}"),
+                });
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = account_home;
+    }
+    Ok(out)
+}
+
+/// How `ensure_shared_config` registers the current directory under `[projects]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub(crate) enum ProjectTrust {
+    /// Add the directory with `trust_level = "trusted"`.
+    #[default]
+    Trusted,
+    /// Add the directory with `trust_level = "untrusted"`.
+    Untrusted,
+    /// Do not add a project entry.
+    Skip,
+}
+
+impl ProjectTrust {
+    fn trust_level(self) -> Option<&'static str> {
+        match self {
+            Self::Trusted => Some("trusted"),
+            Self::Untrusted => Some("untrusted"),
+            Self::Skip => None,
+        }
+    }
+}
+
+/// Forces file-based auth storage in the shared config and registers the current directory as a
+/// project per `trust`. Existing project entries are never overwritten.
+pub(crate) fn ensure_shared_config(shared_root: &Path, trust: ProjectTrust) -> anyhow::Result<()> {
     let path = shared_root.join("config.toml");
     let cwd = std::env::current_dir().context("resolving current directory")?;
 
@@ -156,16 +503,19 @@ pub(crate) fn ensure_shared_config(shared_root: &Path) -> anyhow::Result<()> {
             .context("shared config projects is not a table")?;
 
         let key = cwd.to_string_lossy().to_string();
-        if projects.contains_key(&key) && !auth_store_changed {
+        let add_project = trust.trust_level().filter(|_| !projects.contains_key(&key));
+        if add_project.is_none() && !auth_store_changed {
             return Ok(());
         }
 
-        let mut t = toml::map::Map::new();
-        t.insert(
-            "trust_level".to_string(),
-            toml::Value::String("trusted".to_string()),
-        );
-        projects.insert(key, toml::Value::Table(t));
+        if let Some(trust_level) = add_project {
+            let mut t = toml::map::Map::new();
+            t.insert(
+                "trust_level".to_string(),
+                toml::Value::String(trust_level.to_string()),
+            );
+            projects.insert(key, toml::Value::Table(t));
+        }
 
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
@@ -227,7 +577,7 @@ mod tests {
         )
         .expect("write config");
 
-        ensure_shared_config(&shared_root).expect("ensure shared config");
+        ensure_shared_config(&shared_root, ProjectTrust::Trusted).expect("ensure shared config");
 
         let config =
             std::fs::read_to_string(shared_root.join("config.toml")).expect("read shared config");
@@ -256,7 +606,7 @@ mod tests {
         )
         .expect("write config");
 
-        ensure_shared_config(&shared_root).expect("ensure shared config");
+        ensure_shared_config(&shared_root, ProjectTrust::Trusted).expect("ensure shared config");
 
         let config =
             std::fs::read_to_string(shared_root.join("config.toml")).expect("read shared config");
@@ -268,4 +618,259 @@ mod tests {
             Some("file")
         );
     }
+
+    #[test]
+    fn ensure_shared_config_honors_project_trust() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let cwd = std::env::current_dir().expect("current dir");
+        let cwd = cwd.to_string_lossy().to_string();
+
+        let trust_level_for = |name: &str, trust: ProjectTrust| {
+            let shared_root = temp.path().join(name);
+            ensure_shared_config(&shared_root, trust).expect("ensure shared config");
+            let config = std::fs::read_to_string(shared_root.join("config.toml"))
+                .expect("read shared config");
+            let parsed: toml::Value = toml::from_str(&config).expect("parse shared config");
+            parsed
+                .get("projects")
+                .and_then(|projects| projects.get(&cwd))
+                .and_then(|project| project.get("trust_level"))
+                .and_then(toml::Value::as_str)
+                .map(str::to_string)
+        };
+
+        assert_eq!(
+            trust_level_for("untrusted", ProjectTrust::Untrusted),
+            Some("untrusted".to_string())
+        );
+        assert_eq!(trust_level_for("skip", ProjectTrust::Skip), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inspect_shared_layout_reports_materialized_entries() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let shared_root = temp.path().join("shared");
+        let account_home = temp.path().join("accounts").join("demo");
+        std::fs::create_dir_all(&account_home).expect("create account home");
+        std::fs::create_dir_all(shared_root.join("sessions")).expect("create shared dir");
+
+        std::fs::write(account_home.join("config.toml"), "model = \"o3\"\n")
+            .expect("write drifted config");
+        unix_fs::symlink(shared_root.join("sessions"), account_home.join("sessions"))
+            .expect("create symlink");
+
+        let drift = inspect_shared_layout(&account_home, &shared_root).expect("inspect layout");
+
+        assert_eq!(
+            drift,
+            vec![SharedLayoutDrift {
+                entry: "config.toml",
+                found: "file",
+                detail: "13 bytes: model = \"o3\"\\n".to_string(),
+            }]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inspect_shared_layout_never_previews_credentials() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let shared_root = temp.path().join("shared");
+        let account_home = temp.path().join("accounts").join("demo");
+        std::fs::create_dir_all(&account_home).expect("create account home");
+        std::fs::write(
+            account_home.join(".credentials.json"),
+            "{\"token\":\"sk-secret\"}",
+        )
+        .expect("write drifted credentials");
+
+        let drift = inspect_shared_layout(&account_home, &shared_root).expect("inspect layout");
+
+        assert_eq!(
+            drift,
+            vec![SharedLayoutDrift {
+                entry: ".credentials.json",
+                found: "file",
+                detail: "21 bytes".to_string(),
+            }]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn purge_account_home_unlinks_shared_entries_and_refuses_materialized_ones() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let shared_root = temp.path().join("shared");
+        let account_home = temp.path().join("accounts").join("demo");
+        std::fs::create_dir_all(&account_home).expect("create account home");
+        ensure_shared_layout(&account_home, &shared_root).expect("layout");
+        std::fs::write(shared_root.join("history.jsonl"), "shared\n").expect("write history");
+        std::fs::write(account_home.join("auth.json"), "{}").expect("write auth");
+
+        std::fs::remove_file(account_home.join("config.toml")).expect("remove config link");
+        std::fs::write(account_home.join("config.toml"), "api_key = \"sk-local\"")
+            .expect("materialize config");
+        let err = purge_account_home(&account_home, &shared_root)
+            .expect_err("materialized entry")
+            .to_string();
+        assert!(err.contains("config.toml (file)"), "{err}");
+        assert!(!err.contains("sk-local"), "{err}");
+        assert_eq!(account_home.join("auth.json").exists(), true);
+
+        std::fs::remove_file(account_home.join("config.toml")).expect("remove config");
+        purge_account_home(&account_home, &shared_root).expect("purge");
+        assert_eq!(account_home.exists(), false);
+        assert_eq!(
+            std::fs::read_to_string(shared_root.join("history.jsonl")).expect("read history"),
+            "shared\n"
+        );
+        assert_eq!(shared_root.join("sessions").is_dir(), true);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retarget_shared_links_moves_links_off_the_old_shared_root() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let old_shared = temp.path().join("old-shared");
+        let shared_root = temp.path().join("shared");
+        let account_home = temp.path().join("accounts").join("demo");
+        std::fs::create_dir_all(&account_home).expect("create account home");
+        std::fs::create_dir_all(&shared_root).expect("create shared root");
+        unix_fs::symlink(
+            old_shared.join("history.jsonl"),
+            account_home.join("history.jsonl"),
+        )
+        .expect("link history");
+        unix_fs::symlink(
+            shared_root.join("config.toml"),
+            account_home.join("config.toml"),
+        )
+        .expect("link config");
+
+        let planned =
+            retarget_shared_links(&account_home, &old_shared, &shared_root, Retarget::DryRun)
+                .expect("dry run");
+        assert_eq!(planned, vec!["history.jsonl"]);
+        assert_eq!(
+            std::fs::read_link(account_home.join("history.jsonl")).expect("read link"),
+            old_shared.join("history.jsonl")
+        );
+
+        let rewritten =
+            retarget_shared_links(&account_home, &old_shared, &shared_root, Retarget::Rewrite)
+                .expect("retarget");
+        assert_eq!(rewritten, vec!["history.jsonl"]);
+        assert_eq!(
+            std::fs::read_link(account_home.join("history.jsonl")).expect("read link"),
+            shared_root.join("history.jsonl")
+        );
+        ensure_shared_layout(&account_home, &shared_root).expect("layout is consistent");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_shared_layout_relinks_after_shared_root_moves() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let old_shared = temp.path().join("old-shared");
+        let shared_root = temp.path().join("shared");
+        let account_home = temp.path().join("accounts").join("demo");
+        std::fs::create_dir_all(&account_home).expect("create account home");
+        ensure_shared_layout(&account_home, &old_shared).expect("initial layout");
+        std::fs::write(old_shared.join("history.jsonl"), "{}\n").expect("write history");
+        std::fs::rename(&old_shared, &shared_root).expect("move shared root");
+
+        let drift = inspect_shared_layout(&account_home, &shared_root).expect("inspect");
+        assert_eq!(drift.len(), SHARED_ENTRIES.len());
+        assert!(
+            drift[0]
+                .detail
+                .ends_with("(dangling; relinked on next use)")
+        );
+
+        ensure_shared_layout(&account_home, &shared_root).expect("relink layout");
+        assert_eq!(
+            inspect_shared_layout(&account_home, &shared_root).expect("inspect"),
+            Vec::new()
+        );
+        assert_eq!(
+            std::fs::read_to_string(account_home.join("history.jsonl")).expect("read history"),
+            "{}\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_shared_layout_keeps_failing_on_live_foreign_links() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let other = temp.path().join("other");
+        let shared_root = temp.path().join("shared");
+        let account_home = temp.path().join("accounts").join("demo");
+        std::fs::create_dir_all(&account_home).expect("create account home");
+        std::fs::create_dir_all(&other).expect("create other dir");
+        std::fs::write(other.join("history.jsonl"), "").expect("write foreign history");
+        unix_fs::symlink(
+            other.join("history.jsonl"),
+            account_home.join("history.jsonl"),
+        )
+        .expect("link foreign history");
+
+        assert!(ensure_shared_layout(&account_home, &shared_root).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restrict_account_permissions_clears_group_and_other_bits() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let account_home = temp.path().join("demo");
+        std::fs::create_dir_all(&account_home).expect("create account home");
+        std::fs::write(account_home.join("auth.json"), "{}").expect("write auth");
+        std::fs::set_permissions(&account_home, std::fs::Permissions::from_mode(0o755))
+            .expect("loosen home");
+        std::fs::set_permissions(
+            account_home.join("auth.json"),
+            std::fs::Permissions::from_mode(0o644),
+        )
+        .expect("loosen auth");
+
+        assert_eq!(
+            inspect_account_permissions(&account_home).expect("inspect"),
+            vec![
+                LoosePermission {
+                    entry: ".",
+                    mode: "755".to_string(),
+                    expected: "700".to_string(),
+                },
+                LoosePermission {
+                    entry: "auth.json",
+                    mode: "644".to_string(),
+                    expected: "600".to_string(),
+                },
+            ]
+        );
+
+        restrict_account_permissions(&account_home).expect("restrict");
+        assert_eq!(
+            inspect_account_permissions(&account_home).expect("inspect"),
+            Vec::new()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_account_homes_and_auth_files_start_restricted() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let account_home = temp.path().join("demo");
+
+        create_account_home(&account_home).expect("create account home");
+        write_auth_file(&account_home, "{}").expect("write auth");
+
+        let mode =
+            |path: &Path| std::fs::metadata(path).expect("stat").permissions().mode() & 0o777;
+        assert_eq!(mode(&account_home), 0o700);
+        assert_eq!(mode(&account_home.join("auth.json")), 0o600);
+        assert!(write_auth_file(&account_home, "{}").is_err());
+    }
 }
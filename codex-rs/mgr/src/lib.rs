@@ -1,23 +1,41 @@
+mod account_override;
 mod account_token_provider;
 mod accounts;
+mod admin;
+mod aliases;
 pub mod app;
+mod audit;
+mod capture;
 mod config;
 mod default_pool_labels;
+mod doctor;
+mod exit_code;
 mod gateway;
 mod gateway_sessions;
 mod header_policy;
 mod label;
 mod layout;
+pub mod manager;
+mod migrate;
 mod observability;
+#[cfg(feature = "otlp")]
+mod otlp;
 mod pools;
+mod pools_validate;
 mod proxy;
+mod redact;
 mod redis_conn;
 mod routing;
 mod run_cmd;
+mod selection;
 mod serve;
+mod shadow;
 mod state;
+mod state_show;
 mod time;
 mod upstream;
+mod upstream_health;
 mod usage;
+mod usage_refresher;
 mod websocket_proxy;
 mod ws_header_policy;
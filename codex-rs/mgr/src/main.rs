@@ -1,5 +1,7 @@
+use std::process::ExitCode;
+
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     codex_utils_rustls_provider::ensure_rustls_crypto_provider();
-    codex_mgr::app::run().await
+    codex_mgr::app::main().await
 }
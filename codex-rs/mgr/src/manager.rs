@@ -0,0 +1,351 @@
+//! Library entry point for embedding codex-mgr's multi-account logic in another service.
+//!
+//! [`Manager`] covers what the CLI does against the local state directory (listing accounts,
+//! fetching usage, picking the best account). [`Gateway`] adds the Redis-backed pieces the
+//! gateway uses: routing a request to an account and managing gateway sessions.
+
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::accounts;
+use crate::config;
+use crate::gateway;
+use crate::gateway_sessions;
+use crate::observability;
+use crate::redis_conn;
+use crate::routing;
+use crate::selection;
+use crate::time::SystemClock;
+use crate::usage;
+
+pub use crate::usage::Score;
+
+/// Account state rooted at a codex-mgr state directory, laid out as the CLI lays it out.
+#[derive(Debug, Clone)]
+pub struct Manager {
+    shared_root: PathBuf,
+    accounts_root: PathBuf,
+    state_root: PathBuf,
+    config_path: PathBuf,
+}
+
+/// How usage is gathered before scoring or selecting accounts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageOptions {
+    /// Refresh account tokens before fetching usage.
+    pub force_refresh: bool,
+    /// Fetch usage even when a fresh cached snapshot exists.
+    pub ignore_cache: bool,
+    /// When a fetch fails, fall back to the account's expired cached snapshot.
+    pub allow_stale: bool,
+}
+
+impl UsageOptions {
+    fn scan_options(self) -> usage::ScanOptions<'static> {
+        usage::ScanOptions {
+            force_refresh: self.force_refresh,
+            ignore_cache: self.ignore_cache,
+            allow_stale: self.allow_stale,
+            ..usage::ScanOptions::default()
+        }
+    }
+}
+
+impl Manager {
+    /// Uses `state_root/shared` and `state_root/accounts`, the CLI's defaults.
+    pub fn new(state_root: impl Into<PathBuf>) -> Self {
+        let state_root = state_root.into();
+        Self {
+            shared_root: state_root.join("shared"),
+            accounts_root: state_root.join("accounts"),
+            config_path: config::default_path(&state_root),
+            state_root,
+        }
+    }
+
+    /// Equivalent to `--shared-root`, `--accounts-root` and `--state-root`.
+    pub fn with_roots(
+        shared_root: impl Into<PathBuf>,
+        accounts_root: impl Into<PathBuf>,
+        state_root: impl Into<PathBuf>,
+    ) -> Self {
+        let state_root = state_root.into();
+        Self {
+            shared_root: shared_root.into(),
+            accounts_root: accounts_root.into(),
+            config_path: config::default_path(&state_root),
+            state_root,
+        }
+    }
+
+    /// Equivalent to `--config`: reads gateway, pool and selection config from `config_path`
+    /// instead of `state_root/config.toml`.
+    pub fn with_config_path(mut self, config_path: impl Into<PathBuf>) -> Self {
+        self.config_path = config_path.into();
+        self
+    }
+
+    pub fn state_root(&self) -> &Path {
+        &self.state_root
+    }
+
+    pub fn accounts_root(&self) -> &Path {
+        &self.accounts_root
+    }
+
+    /// Account labels, sorted.
+    pub fn labels(&self) -> anyhow::Result<Vec<String>> {
+        accounts::list_labels(&self.accounts_root)
+    }
+
+    /// The account home codex should run with for `label`.
+    pub fn account_home(&self, label: &str) -> PathBuf {
+        self.accounts_root.join(label)
+    }
+
+    /// Usage scores for every account whose usage could be read, updating the usage cache.
+    pub async fn usage_scores(
+        &self,
+        options: UsageOptions,
+    ) -> anyhow::Result<HashMap<String, Score>> {
+        usage::scan_and_update_usage(
+            &self.shared_root,
+            &self.accounts_root,
+            &self.state_root,
+            &self.config_path,
+            options.scan_options(),
+        )
+        .await
+    }
+
+    /// Picks an account the way `run --auto` does, using the configured selection strategy.
+    pub async fn select_best_label(&self, options: UsageOptions) -> anyhow::Result<String> {
+        let strategy = selection::resolve(&config::selection_config(&self.config_path)?)?;
+        usage::select_best_label(
+            &self.shared_root,
+            &self.accounts_root,
+            &self.state_root,
+            &self.config_path,
+            options.scan_options(),
+            strategy.as_ref(),
+        )
+        .await?
+        .map_err(usage::UnusableAccounts::into_error)
+    }
+
+    /// Loads the gateway config and connects to its Redis.
+    pub async fn connect_gateway(&self) -> anyhow::Result<Gateway> {
+        let cfg = config::load(&self.config_path)?;
+        let conn = redis_conn::connect(&cfg.gateway).await?;
+        Ok(Gateway {
+            accounts_root: self.accounts_root.clone(),
+            state_root: self.state_root.clone(),
+            cfg,
+            conn,
+        })
+    }
+}
+
+/// Routing and session management backed by the gateway's Redis.
+///
+/// Cheap to clone; clones share the underlying connection.
+#[derive(Clone)]
+pub struct Gateway {
+    accounts_root: PathBuf,
+    state_root: PathBuf,
+    cfg: config::ManagerConfig,
+    conn: redis_conn::RedisConnection,
+}
+
+/// Where a request for a pool should go.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub pool_id: String,
+    /// Account labels to try in order; the first is the preferred account.
+    pub candidates: Vec<String>,
+    pub conversation_id: Option<String>,
+}
+
+/// Parameters for [`Gateway::issue_session`]; mirrors `gateway issue`.
+#[derive(Debug, Clone, Default)]
+pub struct IssueSession {
+    pub pool_id: String,
+    /// Defaults to the CLI's session TTL.
+    pub ttl_seconds: Option<i64>,
+    /// Defaults to the pool's `default_note`.
+    pub note: Option<String>,
+    /// Path prefixes the token may call; empty means unrestricted.
+    pub allowed_paths: Vec<String>,
+    /// Issue a non-renewable session with a capped TTL; mirrors `gateway issue --preview`.
+    pub preview: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub pool_id: String,
+    pub policy_key: Option<String>,
+    pub issued_at_ms: i64,
+    pub expires_at_ms: i64,
+    pub note: Option<String>,
+    pub allowed_paths: Vec<String>,
+    pub preview: bool,
+}
+
+impl From<gateway_sessions::GatewaySession> for Session {
+    fn from(session: gateway_sessions::GatewaySession) -> Self {
+        Self {
+            pool_id: session.account_pool_id,
+            policy_key: session.policy_key,
+            issued_at_ms: session.issued_at_ms,
+            expires_at_ms: session.expires_at_ms,
+            note: session.note,
+            allowed_paths: session.allowed_paths,
+            preview: session.preview,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IssuedSession {
+    pub token: String,
+    pub ttl_seconds: i64,
+    pub session: Session,
+}
+
+impl Gateway {
+    /// Orders `pool_id`'s accounts for a request, the way the gateway's routing middleware does.
+    ///
+    /// With a `conversation_id` in a sticky pool, the binding is read from (or written to)
+    /// Redis so every gateway and embedder sharing it agrees on the account.
+    pub async fn route(
+        &mut self,
+        pool_id: &str,
+        conversation_id: Option<&str>,
+        usage_scores: &HashMap<String, Score>,
+    ) -> anyhow::Result<Route> {
+        let pool_id = self.cfg.resolve_pool_alias(pool_id);
+        let (labels, policy_key, sticky) = if self.cfg.is_implicit_default_pool(&pool_id) {
+            let labels = accounts::list_unreserved_labels(&self.accounts_root, &self.state_root)?;
+            (labels, None, true)
+        } else {
+            let pool = self
+                .cfg
+                .pools
+                .get(&pool_id)
+                .with_context(|| format!("pool {pool_id:?} does not exist"))?;
+            (pool.labels.clone(), pool.policy_key.clone(), pool.sticky)
+        };
+
+        let non_sticky_key = observability::new_request_id();
+        let route_info = routing::route_account(
+            &mut self.conn,
+            routing::RouteAccountArgs {
+                account_pool_id: &pool_id,
+                labels: &labels,
+                policy_key: policy_key.as_deref(),
+                sticky,
+                sticky_ttl_seconds: self.cfg.gateway.sticky_ttl_seconds,
+                conversation_id: conversation_id.map(ToString::to_string),
+                non_sticky_key: &non_sticky_key,
+                tenant: None,
+                usage_scores,
+            },
+        )
+        .await?;
+        Ok(Route {
+            pool_id: route_info.account_pool_id,
+            candidates: route_info.candidates,
+            conversation_id: route_info.conversation_id,
+        })
+    }
+
+    /// Issues a gateway token, with the same validation, rate limit and audit entry as
+    /// `gateway issue`.
+    pub async fn issue_session(&mut self, request: IssueSession) -> anyhow::Result<IssuedSession> {
+        let IssueSession {
+            pool_id,
+            ttl_seconds,
+            note,
+            allowed_paths,
+            preview,
+        } = request;
+        let issued = gateway::issue_session(
+            &self.state_root,
+            &self.cfg,
+            &mut self.conn,
+            gateway::SessionRequest {
+                pool_id,
+                ttl_seconds,
+                note,
+                allowed_paths,
+                preview,
+            },
+            &SystemClock,
+        )
+        .await?;
+        Ok(IssuedSession {
+            token: issued.token,
+            ttl_seconds: issued.ttl_seconds,
+            session: issued.session.into(),
+        })
+    }
+
+    /// The live session for `token`, if any.
+    pub async fn session(&mut self, token: &str) -> anyhow::Result<Option<Session>> {
+        Ok(gateway_sessions::get(&mut self.conn, token, &SystemClock)
+            .await?
+            .map(Session::from))
+    }
+
+    /// Revokes `token`; returns false when there was no such session.
+    pub async fn revoke_session(&mut self, token: &str) -> anyhow::Result<bool> {
+        gateway::revoke_session(&self.state_root, &mut self.conn, token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn new_uses_the_cli_default_layout() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::create_dir_all(dir.path().join("accounts/work")).expect("mkdir");
+        std::fs::create_dir_all(dir.path().join("accounts/personal")).expect("mkdir");
+
+        let manager = Manager::new(dir.path());
+        assert_eq!(manager.accounts_root(), dir.path().join("accounts"));
+        assert_eq!(
+            manager.labels().expect("labels"),
+            vec!["personal".to_string(), "work".to_string()]
+        );
+        assert_eq!(
+            manager.account_home("work"),
+            dir.path().join("accounts/work")
+        );
+    }
+
+    #[tokio::test]
+    async fn with_config_path_reads_selection_config_from_that_file() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::create_dir_all(dir.path().join("accounts")).expect("mkdir");
+        let config_path = dir.path().join("elsewhere.toml");
+        std::fs::write(&config_path, "[selection]\nstrategy = \"nope\"\n").expect("write config");
+
+        let err = Manager::new(dir.path())
+            .select_best_label(UsageOptions::default())
+            .await
+            .expect_err("no accounts");
+        assert!(format!("{err:#}").contains("no accounts found"));
+
+        let err = Manager::new(dir.path())
+            .with_config_path(&config_path)
+            .select_best_label(UsageOptions::default())
+            .await
+            .expect_err("bad strategy");
+        assert!(format!("{err:#}").contains("unknown selection strategy"));
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::Context;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::accounts;
+use crate::layout::Retarget;
+use crate::layout::ensure_shared_layout;
+use crate::layout::retarget_shared_links;
+
+const LEGACY_SHARED_DIRNAME: &str = ".codex-shared";
+const LEGACY_ACCOUNTS_DIRNAME: &str = ".codex-accounts";
+
+pub(crate) struct MigrateOptions {
+    pub(crate) home: PathBuf,
+    pub(crate) shared_root: PathBuf,
+    pub(crate) accounts_root: PathBuf,
+    pub(crate) dry_run: bool,
+}
+
+/// Moves the legacy `~/.codex-shared` and `~/.codex-accounts` directories to `shared_root` and
+/// `accounts_root`, then points every account's shared-entry symlinks at the new `shared_root`.
+pub(crate) fn run(options: MigrateOptions) -> anyhow::Result<()> {
+    let MigrateOptions {
+        home,
+        shared_root,
+        accounts_root,
+        dry_run,
+    } = options;
+    let legacy_shared = home.join(LEGACY_SHARED_DIRNAME);
+    let legacy_accounts = home.join(LEGACY_ACCOUNTS_DIRNAME);
+    let prefix = if dry_run { "would " } else { "" };
+    let retarget = if dry_run {
+        Retarget::DryRun
+    } else {
+        Retarget::Rewrite
+    };
+
+    let move_shared = plan_move(&legacy_shared, &shared_root)?;
+    let move_accounts = plan_move(&legacy_accounts, &accounts_root)?;
+    if !move_shared && !move_accounts {
+        println!("nothing to migrate: no legacy directories found");
+        return Ok(());
+    }
+
+    for (moving, from, to) in [
+        (move_shared, &legacy_shared, &shared_root),
+        (move_accounts, &legacy_accounts, &accounts_root),
+    ] {
+        if !moving {
+            continue;
+        }
+        if !dry_run {
+            move_dir(from, to)?;
+        }
+        println!("{prefix}move {from:?} -> {to:?}");
+    }
+
+    // In a dry run nothing moved yet, so inspect the accounts where they currently live.
+    let scan_root = if dry_run && move_accounts {
+        &legacy_accounts
+    } else {
+        &accounts_root
+    };
+    for label in accounts::list_labels(scan_root)? {
+        let account_home = scan_root.join(&label);
+        let relinked =
+            retarget_shared_links(&account_home, &legacy_shared, &shared_root, retarget)?;
+        if !dry_run {
+            ensure_shared_layout(&account_home, &shared_root)
+                .with_context(|| format!("repairing shared layout for account {label:?}"))?;
+        }
+        if relinked.is_empty() {
+            println!("{label}: links already point at {shared_root:?}");
+        } else {
+            println!("{label}: {prefix}relink {}", relinked.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `from` should move to `to`. `to` may exist only as an empty directory, which
+/// startup creates before any command runs.
+fn plan_move(from: &Path, to: &Path) -> anyhow::Result<bool> {
+    if !from.exists() {
+        return Ok(false);
+    }
+    match std::fs::read_dir(to) {
+        Ok(mut entries) => {
+            if entries.next().is_some() {
+                anyhow::bail!(
+                    "cannot migrate {from:?}: destination {to:?} already exists and is not empty"
+                );
+            }
+            Ok(true)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(err).with_context(|| format!("reading {to:?}")),
+    }
+}
+
+fn move_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if to.exists() {
+        std::fs::remove_dir(to).with_context(|| format!("removing empty {to:?}"))?;
+    } else if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("creating {parent:?}"))?;
+    }
+    std::fs::rename(from, to).with_context(|| {
+        format!("moving {from:?} -> {to:?} (moves across filesystems are not supported; copy it manually)")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[cfg(unix)]
+    #[test]
+    fn moves_legacy_dirs_and_relinks_accounts() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let home = temp.path().to_path_buf();
+        let legacy_shared = home.join(LEGACY_SHARED_DIRNAME);
+        let legacy_home = home.join(LEGACY_ACCOUNTS_DIRNAME).join("demo");
+        std::fs::create_dir_all(&legacy_home).expect("create legacy account");
+        ensure_shared_layout(&legacy_home, &legacy_shared).expect("legacy layout");
+        std::fs::write(legacy_shared.join("history.jsonl"), "{}\n").expect("write history");
+
+        let state_root = home.join(".codex-mgr");
+        let shared_root = state_root.join("shared");
+        let accounts_root = state_root.join("accounts");
+        std::fs::create_dir_all(&shared_root).expect("create empty shared root");
+        std::fs::create_dir_all(&accounts_root).expect("create empty accounts root");
+
+        let options = |dry_run| MigrateOptions {
+            home: home.clone(),
+            shared_root: shared_root.clone(),
+            accounts_root: accounts_root.clone(),
+            dry_run,
+        };
+        run(options(true)).expect("dry run");
+        assert_eq!(legacy_shared.exists(), true);
+
+        run(options(false)).expect("migrate");
+        assert_eq!(legacy_shared.exists(), false);
+        let account_home = accounts_root.join("demo");
+        assert_eq!(
+            std::fs::read_link(account_home.join("history.jsonl")).expect("read link"),
+            shared_root.join("history.jsonl")
+        );
+        assert_eq!(
+            std::fs::read_to_string(account_home.join("history.jsonl")).expect("read history"),
+            "{}\n"
+        );
+    }
+
+    #[test]
+    fn refuses_non_empty_destination() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let from = temp.path().join("legacy");
+        let to = temp.path().join("new");
+        std::fs::create_dir_all(&from).expect("create legacy");
+        std::fs::create_dir_all(&to).expect("create destination");
+        std::fs::write(to.join("keep.txt"), "data").expect("write file");
+
+        assert!(plan_move(&from, &to).is_err());
+    }
+}
@@ -1,25 +1,65 @@
 use base64::Engine;
 use rand::TryRngCore;
 use sha2::Digest;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::sync::PoisonError;
 use std::sync::atomic::AtomicI64;
 use std::sync::atomic::Ordering;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::fmt;
+use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::prelude::*;
 
-pub(crate) fn init_tracing() {
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TracingOptions {
+    /// Every span logs its busy and idle time when it closes; `serve` opens one per request
+    /// phase, so this shows where a slow request spent its time.
+    pub(crate) span_timings: bool,
+    /// Emit this crate's DEBUG events (e.g. the routing decision trail) on top of `RUST_LOG`;
+    /// dependencies stay at whatever `RUST_LOG` gives them, INFO by default.
+    pub(crate) debug: bool,
+}
+
+pub(crate) fn init_tracing(options: TracingOptions) {
     static INIT: std::sync::Once = std::sync::Once::new();
     INIT.call_once(|| {
+        let span_events = if options.span_timings {
+            FmtSpan::CLOSE
+        } else {
+            FmtSpan::NONE
+        };
         let layer = fmt::layer()
             .with_writer(std::io::stderr)
             .with_target(false)
+            .with_span_events(span_events)
             .compact();
+        let rust_log = std::env::var(EnvFilter::DEFAULT_ENV).ok();
         let subscriber = tracing_subscriber::registry()
             .with(layer)
-            .with(tracing_subscriber::filter::LevelFilter::INFO);
+            .with(env_filter(rust_log.as_deref(), options.debug));
         let _ = tracing::subscriber::set_global_default(subscriber);
     });
 }
 
+/// INFO unless `rust_log` says otherwise, e.g. `codex_mgr::routing=debug` for the routing
+/// decision trail alone; `debug` adds this whole crate at DEBUG.
+fn env_filter(rust_log: Option<&str>, debug: bool) -> EnvFilter {
+    let mut directives = rust_log
+        .into_iter()
+        .filter(|directives| !directives.trim().is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if debug {
+        directives.push(format!("{}=debug", env!("CARGO_CRATE_NAME")));
+    }
+    EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .parse_lossy(directives.join(","))
+}
+
 pub(crate) fn new_request_id() -> String {
     let mut bytes = [0u8; 16];
     let mut rng = rand::rngs::OsRng;
@@ -43,25 +83,83 @@ fn sha256_bytes(input: &[u8]) -> [u8; 32] {
     hasher.finalize().into()
 }
 
+/// Upper bounds, in ms, of the `codex_mgr_gateway_sse_ttfb_ms_bucket` counters.
+pub(crate) const SSE_TTFB_BUCKETS_MS: [i64; 9] =
+    [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000];
+/// Upper bounds, in ms, of the `codex_mgr_gateway_sse_stream_duration_ms_bucket` counters.
+pub(crate) const SSE_STREAM_DURATION_BUCKETS_MS: [i64; 8] = [
+    1_000, 5_000, 15_000, 30_000, 60_000, 120_000, 300_000, 600_000,
+];
+
+/// Cumulative Prometheus `_bucket{le=...}` counts over `N` fixed bounds, so a percentile can be
+/// read off the distribution instead of only the mean from `_sum`/`_count`.
+#[derive(Debug)]
+pub(crate) struct MsBuckets<const N: usize>([AtomicI64; N]);
+
+impl<const N: usize> Default for MsBuckets<N> {
+    fn default() -> Self {
+        Self(std::array::from_fn(|_| AtomicI64::new(0)))
+    }
+}
+
+impl<const N: usize> MsBuckets<N> {
+    /// Counts `elapsed` in every bucket whose bound it does not exceed.
+    pub(crate) fn observe(&self, bounds: &[i64; N], elapsed: std::time::Duration) {
+        let ms = i64::try_from(elapsed.as_millis()).unwrap_or(i64::MAX);
+        for (bound, count) in bounds.iter().zip(&self.0) {
+            if ms <= *bound {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// `name{le="..."}` for each bound, then `le="+Inf"` as `total`, the histogram's `_count`.
+    fn render(&self, name: &str, help: &str, bounds: &[i64; N], total: i64) -> String {
+        let mut out = format!("# HELP {name} {help}\n# TYPE {name} counter\n");
+        for (bound, count) in bounds.iter().zip(&self.0) {
+            let count = count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(out, "{name}{{le=\"+Inf\"}} {total}");
+        out
+    }
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct GatewayMetrics {
     pub(crate) requests_total: AtomicI64,
     pub(crate) requests_inflight: AtomicI64,
     pub(crate) requests_unauthorized_total: AtomicI64,
     pub(crate) requests_5xx_total: AtomicI64,
+    pub(crate) requests_shed_total: AtomicI64,
     pub(crate) redis_errors_total: AtomicI64,
     pub(crate) routing_errors_total: AtomicI64,
     pub(crate) token_errors_total: AtomicI64,
+    pub(crate) token_cache_hits_total: AtomicI64,
+    pub(crate) token_cache_misses_total: AtomicI64,
+    pub(crate) token_refresh_lock_acquired_total: AtomicI64,
+    pub(crate) token_refresh_wait_total: AtomicI64,
     pub(crate) upstream_requests_total: AtomicI64,
     pub(crate) upstream_errors_total: AtomicI64,
     pub(crate) upstream_responses_2xx_total: AtomicI64,
     pub(crate) upstream_responses_3xx_total: AtomicI64,
     pub(crate) upstream_responses_4xx_total: AtomicI64,
     pub(crate) upstream_responses_5xx_total: AtomicI64,
+    /// Upstream 401/403 responses keyed by account label.
+    upstream_auth_failures_total: Mutex<BTreeMap<String, i64>>,
     pub(crate) upstream_latency_ms_sum: AtomicI64,
     pub(crate) upstream_latency_ms_count: AtomicI64,
     pub(crate) sse_streams_inflight: AtomicI64,
     pub(crate) sse_streams_total: AtomicI64,
+    pub(crate) sse_client_disconnects_total: AtomicI64,
+    pub(crate) sse_idle_timeouts_total: AtomicI64,
+    pub(crate) sse_stream_errors_total: AtomicI64,
+    pub(crate) sse_ttfb_ms_sum: AtomicI64,
+    pub(crate) sse_ttfb_ms_count: AtomicI64,
+    pub(crate) sse_ttfb_ms_bucket: MsBuckets<9>,
+    pub(crate) sse_stream_duration_ms_sum: AtomicI64,
+    pub(crate) sse_stream_duration_ms_count: AtomicI64,
+    pub(crate) sse_stream_duration_ms_bucket: MsBuckets<8>,
     pub(crate) websocket_connections_total: AtomicI64,
     pub(crate) websocket_connections_inflight: AtomicI64,
     pub(crate) websocket_connect_failures_total: AtomicI64,
@@ -69,17 +167,54 @@ pub(crate) struct GatewayMetrics {
     pub(crate) websocket_relay_errors_total: AtomicI64,
     pub(crate) request_duration_ms_sum: AtomicI64,
     pub(crate) request_duration_ms_count: AtomicI64,
+    pub(crate) usage_refresh_success_total: AtomicI64,
+    pub(crate) usage_refresh_failures_total: AtomicI64,
+    pub(crate) usage_refresh_last_success_ms: AtomicI64,
 }
 
 impl GatewayMetrics {
+    pub(crate) fn record_upstream_auth_failure(&self, account_id: &str) {
+        let mut failures = self
+            .upstream_auth_failures_total
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *failures.entry(account_id.to_string()).or_default() += 1;
+    }
+
+    fn render_upstream_auth_failures(&self) -> String {
+        let failures = self
+            .upstream_auth_failures_total
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut out = String::from(
+            "# HELP codex_mgr_gateway_upstream_auth_failures_total Upstream 401/403 responses by account.\n\
+# TYPE codex_mgr_gateway_upstream_auth_failures_total counter\n",
+        );
+        for (account_id, count) in failures.iter() {
+            let account_id = account_id.replace('\\', "\\\\").replace('"', "\\\"");
+            let _ = writeln!(
+                out,
+                "codex_mgr_gateway_upstream_auth_failures_total{{account=\"{account_id}\"}} {count}"
+            );
+        }
+        out
+    }
+
     pub(crate) fn render_prometheus(&self) -> String {
         let requests_total = self.requests_total.load(Ordering::Relaxed);
         let requests_inflight = self.requests_inflight.load(Ordering::Relaxed);
         let requests_unauthorized_total = self.requests_unauthorized_total.load(Ordering::Relaxed);
         let requests_5xx_total = self.requests_5xx_total.load(Ordering::Relaxed);
+        let requests_shed_total = self.requests_shed_total.load(Ordering::Relaxed);
         let redis_errors_total = self.redis_errors_total.load(Ordering::Relaxed);
         let routing_errors_total = self.routing_errors_total.load(Ordering::Relaxed);
         let token_errors_total = self.token_errors_total.load(Ordering::Relaxed);
+        let token_cache_hits_total = self.token_cache_hits_total.load(Ordering::Relaxed);
+        let token_cache_misses_total = self.token_cache_misses_total.load(Ordering::Relaxed);
+        let token_refresh_lock_acquired_total = self
+            .token_refresh_lock_acquired_total
+            .load(Ordering::Relaxed);
+        let token_refresh_wait_total = self.token_refresh_wait_total.load(Ordering::Relaxed);
         let upstream_requests_total = self.upstream_requests_total.load(Ordering::Relaxed);
         let upstream_errors_total = self.upstream_errors_total.load(Ordering::Relaxed);
         let upstream_responses_2xx_total =
@@ -94,6 +229,15 @@ impl GatewayMetrics {
         let upstream_latency_ms_count = self.upstream_latency_ms_count.load(Ordering::Relaxed);
         let sse_streams_inflight = self.sse_streams_inflight.load(Ordering::Relaxed);
         let sse_streams_total = self.sse_streams_total.load(Ordering::Relaxed);
+        let sse_client_disconnects_total =
+            self.sse_client_disconnects_total.load(Ordering::Relaxed);
+        let sse_idle_timeouts_total = self.sse_idle_timeouts_total.load(Ordering::Relaxed);
+        let sse_stream_errors_total = self.sse_stream_errors_total.load(Ordering::Relaxed);
+        let sse_ttfb_ms_sum = self.sse_ttfb_ms_sum.load(Ordering::Relaxed);
+        let sse_ttfb_ms_count = self.sse_ttfb_ms_count.load(Ordering::Relaxed);
+        let sse_stream_duration_ms_sum = self.sse_stream_duration_ms_sum.load(Ordering::Relaxed);
+        let sse_stream_duration_ms_count =
+            self.sse_stream_duration_ms_count.load(Ordering::Relaxed);
         let websocket_connections_total = self.websocket_connections_total.load(Ordering::Relaxed);
         let websocket_connections_inflight =
             self.websocket_connections_inflight.load(Ordering::Relaxed);
@@ -107,6 +251,24 @@ impl GatewayMetrics {
             self.websocket_relay_errors_total.load(Ordering::Relaxed);
         let request_duration_ms_sum = self.request_duration_ms_sum.load(Ordering::Relaxed);
         let request_duration_ms_count = self.request_duration_ms_count.load(Ordering::Relaxed);
+        let usage_refresh_success_total = self.usage_refresh_success_total.load(Ordering::Relaxed);
+        let usage_refresh_failures_total =
+            self.usage_refresh_failures_total.load(Ordering::Relaxed);
+        let usage_refresh_last_success_ms =
+            self.usage_refresh_last_success_ms.load(Ordering::Relaxed);
+        let upstream_auth_failures = self.render_upstream_auth_failures();
+        let sse_ttfb_ms_bucket = self.sse_ttfb_ms_bucket.render(
+            "codex_mgr_gateway_sse_ttfb_ms_bucket",
+            "SSE streams whose first chunk arrived within `le` ms of sending upstream.",
+            &SSE_TTFB_BUCKETS_MS,
+            sse_ttfb_ms_count,
+        );
+        let sse_stream_duration_ms_bucket = self.sse_stream_duration_ms_bucket.render(
+            "codex_mgr_gateway_sse_stream_duration_ms_bucket",
+            "SSE streams that ended within `le` ms of sending upstream.",
+            &SSE_STREAM_DURATION_BUCKETS_MS,
+            sse_stream_duration_ms_count,
+        );
 
         format!(
             "\
@@ -122,6 +284,9 @@ codex_mgr_gateway_requests_unauthorized_total {requests_unauthorized_total}\n\
 # HELP codex_mgr_gateway_requests_5xx_total Requests returning 5xx.\n\
 # TYPE codex_mgr_gateway_requests_5xx_total counter\n\
 codex_mgr_gateway_requests_5xx_total {requests_5xx_total}\n\
+# HELP codex_mgr_gateway_requests_shed_total Requests rejected with 503 because max_concurrent_requests was reached.\n\
+# TYPE codex_mgr_gateway_requests_shed_total counter\n\
+codex_mgr_gateway_requests_shed_total {requests_shed_total}\n\
 # HELP codex_mgr_gateway_redis_errors_total Redis errors encountered in the data plane.\n\
 # TYPE codex_mgr_gateway_redis_errors_total counter\n\
 codex_mgr_gateway_redis_errors_total {redis_errors_total}\n\
@@ -131,6 +296,18 @@ codex_mgr_gateway_routing_errors_total {routing_errors_total}\n\
 # HELP codex_mgr_gateway_token_errors_total Token/provider errors (non-Redis).\n\
 # TYPE codex_mgr_gateway_token_errors_total counter\n\
 codex_mgr_gateway_token_errors_total {token_errors_total}\n\
+# HELP codex_mgr_gateway_token_cache_hits_total Account token lookups served from the Redis cache.\n\
+# TYPE codex_mgr_gateway_token_cache_hits_total counter\n\
+codex_mgr_gateway_token_cache_hits_total {token_cache_hits_total}\n\
+# HELP codex_mgr_gateway_token_cache_misses_total Account token lookups that missed the cache or hit the safety window.\n\
+# TYPE codex_mgr_gateway_token_cache_misses_total counter\n\
+codex_mgr_gateway_token_cache_misses_total {token_cache_misses_total}\n\
+# HELP codex_mgr_gateway_token_refresh_lock_acquired_total Cache misses that acquired the refresh lock and loaded auth.\n\
+# TYPE codex_mgr_gateway_token_refresh_lock_acquired_total counter\n\
+codex_mgr_gateway_token_refresh_lock_acquired_total {token_refresh_lock_acquired_total}\n\
+# HELP codex_mgr_gateway_token_refresh_wait_total Cache misses that waited on another refresh holder.\n\
+# TYPE codex_mgr_gateway_token_refresh_wait_total counter\n\
+codex_mgr_gateway_token_refresh_wait_total {token_refresh_wait_total}\n\
 # HELP codex_mgr_gateway_upstream_requests_total Requests sent to upstream.\n\
 # TYPE codex_mgr_gateway_upstream_requests_total counter\n\
 codex_mgr_gateway_upstream_requests_total {upstream_requests_total}\n\
@@ -149,6 +326,7 @@ codex_mgr_gateway_upstream_responses_4xx_total {upstream_responses_4xx_total}\n\
 # HELP codex_mgr_gateway_upstream_responses_5xx_total Upstream responses in the 5xx range.\n\
 # TYPE codex_mgr_gateway_upstream_responses_5xx_total counter\n\
 codex_mgr_gateway_upstream_responses_5xx_total {upstream_responses_5xx_total}\n\
+{upstream_auth_failures}\
 # HELP codex_mgr_gateway_upstream_latency_ms_sum Upstream latency sum in ms (time-to-headers).\n\
 # TYPE codex_mgr_gateway_upstream_latency_ms_sum counter\n\
 codex_mgr_gateway_upstream_latency_ms_sum {upstream_latency_ms_sum}\n\
@@ -161,6 +339,29 @@ codex_mgr_gateway_sse_streams_inflight {sse_streams_inflight}\n\
 # HELP codex_mgr_gateway_sse_streams_total Total SSE streams started.\n\
 # TYPE codex_mgr_gateway_sse_streams_total counter\n\
 codex_mgr_gateway_sse_streams_total {sse_streams_total}\n\
+# HELP codex_mgr_gateway_sse_client_disconnects_total SSE streams abandoned by the client before upstream finished.\n\
+# TYPE codex_mgr_gateway_sse_client_disconnects_total counter\n\
+codex_mgr_gateway_sse_client_disconnects_total {sse_client_disconnects_total}\n\
+# HELP codex_mgr_gateway_sse_idle_timeouts_total SSE streams ended because upstream sent nothing within gateway.sse_idle_timeout_ms.\n\
+# TYPE codex_mgr_gateway_sse_idle_timeouts_total counter\n\
+codex_mgr_gateway_sse_idle_timeouts_total {sse_idle_timeouts_total}\n\
+# HELP codex_mgr_gateway_sse_stream_errors_total SSE streams cut short by an upstream read error.\n\
+# TYPE codex_mgr_gateway_sse_stream_errors_total counter\n\
+codex_mgr_gateway_sse_stream_errors_total {sse_stream_errors_total}\n\
+# HELP codex_mgr_gateway_sse_ttfb_ms_sum Time from sending upstream to its first non-empty SSE chunk, sum in ms.\n\
+# TYPE codex_mgr_gateway_sse_ttfb_ms_sum counter\n\
+codex_mgr_gateway_sse_ttfb_ms_sum {sse_ttfb_ms_sum}\n\
+# HELP codex_mgr_gateway_sse_ttfb_ms_count SSE streams that produced a first chunk.\n\
+# TYPE codex_mgr_gateway_sse_ttfb_ms_count counter\n\
+codex_mgr_gateway_sse_ttfb_ms_count {sse_ttfb_ms_count}\n\
+{sse_ttfb_ms_bucket}\
+# HELP codex_mgr_gateway_sse_stream_duration_ms_sum Time from sending upstream until the relayed SSE stream ended, sum in ms.\n\
+# TYPE codex_mgr_gateway_sse_stream_duration_ms_sum counter\n\
+codex_mgr_gateway_sse_stream_duration_ms_sum {sse_stream_duration_ms_sum}\n\
+# HELP codex_mgr_gateway_sse_stream_duration_ms_count SSE streams ended, however they ended.\n\
+# TYPE codex_mgr_gateway_sse_stream_duration_ms_count counter\n\
+codex_mgr_gateway_sse_stream_duration_ms_count {sse_stream_duration_ms_count}\n\
+{sse_stream_duration_ms_bucket}\
 # HELP codex_mgr_gateway_websocket_connections_total Total websocket relay sessions started.\n\
 # TYPE codex_mgr_gateway_websocket_connections_total counter\n\
 codex_mgr_gateway_websocket_connections_total {websocket_connections_total}\n\
@@ -182,6 +383,15 @@ codex_mgr_gateway_request_duration_ms_sum {request_duration_ms_sum}\n\
 # HELP codex_mgr_gateway_request_duration_ms_count Request duration sample count.\n\
 # TYPE codex_mgr_gateway_request_duration_ms_count counter\n\
 codex_mgr_gateway_request_duration_ms_count {request_duration_ms_count}\n\
+# HELP codex_mgr_gateway_usage_refresh_success_total Background usage refresh passes that completed.\n\
+# TYPE codex_mgr_gateway_usage_refresh_success_total counter\n\
+codex_mgr_gateway_usage_refresh_success_total {usage_refresh_success_total}\n\
+# HELP codex_mgr_gateway_usage_refresh_failures_total Background usage refresh passes that failed.\n\
+# TYPE codex_mgr_gateway_usage_refresh_failures_total counter\n\
+codex_mgr_gateway_usage_refresh_failures_total {usage_refresh_failures_total}\n\
+# HELP codex_mgr_gateway_usage_refresh_last_success_ms Unix time in ms of the last completed usage refresh (0 = never).\n\
+# TYPE codex_mgr_gateway_usage_refresh_last_success_ms gauge\n\
+codex_mgr_gateway_usage_refresh_last_success_ms {usage_refresh_last_success_ms}\n\
 "
         )
     }
@@ -191,6 +401,26 @@ codex_mgr_gateway_request_duration_ms_count {request_duration_ms_count}\n\
 mod tests {
     use super::*;
 
+    #[test]
+    fn env_filter_defaults_to_info_and_takes_rust_log_targets() {
+        assert_eq!(
+            env_filter(None, false).max_level_hint(),
+            Some(LevelFilter::INFO)
+        );
+        assert_eq!(
+            env_filter(Some(" "), false).max_level_hint(),
+            Some(LevelFilter::INFO)
+        );
+
+        let routing = env_filter(Some("codex_mgr::routing=debug"), false);
+        assert_eq!(routing.max_level_hint(), Some(LevelFilter::DEBUG));
+        assert!(!routing.to_string().contains("codex_mgr=debug"));
+
+        let with_debug = env_filter(Some("codex_mgr::routing=debug"), true).to_string();
+        assert!(with_debug.contains("codex_mgr::routing=debug"));
+        assert!(with_debug.contains("codex_mgr=debug"));
+    }
+
     #[test]
     fn prometheus_output_includes_websocket_metrics() {
         let rendered = GatewayMetrics::default().render_prometheus();
@@ -201,4 +431,66 @@ mod tests {
         assert!(rendered.contains("codex_mgr_gateway_websocket_upstream_handshake_failures_total"));
         assert!(rendered.contains("codex_mgr_gateway_websocket_relay_errors_total"));
     }
+
+    #[test]
+    fn prometheus_output_includes_token_cache_metrics() {
+        let metrics = GatewayMetrics::default();
+        metrics
+            .token_cache_hits_total
+            .fetch_add(3, Ordering::Relaxed);
+
+        let rendered = metrics.render_prometheus();
+
+        assert!(rendered.contains("codex_mgr_gateway_token_cache_hits_total 3\n"));
+        assert!(rendered.contains("codex_mgr_gateway_token_cache_misses_total 0\n"));
+        assert!(rendered.contains("codex_mgr_gateway_token_refresh_lock_acquired_total 0\n"));
+        assert!(rendered.contains("codex_mgr_gateway_token_refresh_wait_total 0\n"));
+    }
+
+    #[test]
+    fn prometheus_output_labels_upstream_auth_failures_by_account() {
+        let metrics = GatewayMetrics::default();
+        metrics.record_upstream_auth_failure("work");
+        metrics.record_upstream_auth_failure("work");
+        metrics.record_upstream_auth_failure("personal");
+
+        let rendered = metrics.render_prometheus();
+
+        assert!(
+            rendered.contains("# TYPE codex_mgr_gateway_upstream_auth_failures_total counter\n")
+        );
+        assert!(
+            rendered.contains(
+                "codex_mgr_gateway_upstream_auth_failures_total{account=\"personal\"} 1\n"
+            )
+        );
+        assert!(
+            rendered
+                .contains("codex_mgr_gateway_upstream_auth_failures_total{account=\"work\"} 2\n")
+        );
+        assert!(rendered.contains("codex_mgr_gateway_upstream_latency_ms_sum 0\n"));
+    }
+
+    #[test]
+    fn prometheus_output_includes_cumulative_sse_ttfb_buckets() {
+        let metrics = GatewayMetrics::default();
+        for ms in [40, 300, 60_000] {
+            metrics
+                .sse_ttfb_ms_bucket
+                .observe(&SSE_TTFB_BUCKETS_MS, std::time::Duration::from_millis(ms));
+            metrics.sse_ttfb_ms_count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let rendered = metrics.render_prometheus();
+
+        assert!(rendered.contains("# TYPE codex_mgr_gateway_sse_ttfb_ms_bucket counter\n"));
+        assert!(rendered.contains("codex_mgr_gateway_sse_ttfb_ms_bucket{le=\"50\"} 1\n"));
+        assert!(rendered.contains("codex_mgr_gateway_sse_ttfb_ms_bucket{le=\"250\"} 1\n"));
+        assert!(rendered.contains("codex_mgr_gateway_sse_ttfb_ms_bucket{le=\"500\"} 2\n"));
+        assert!(rendered.contains("codex_mgr_gateway_sse_ttfb_ms_bucket{le=\"30000\"} 2\n"));
+        assert!(rendered.contains("codex_mgr_gateway_sse_ttfb_ms_bucket{le=\"+Inf\"} 3\n"));
+        assert!(
+            rendered.contains("codex_mgr_gateway_sse_stream_duration_ms_bucket{le=\"+Inf\"} 0\n")
+        );
+    }
 }
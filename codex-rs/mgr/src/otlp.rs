@@ -0,0 +1,235 @@
+//! Periodic OTLP/HTTP (JSON encoding) export of the gateway metrics.
+//!
+//! The exporter parses the same Prometheus text served on `/metrics`, so both outputs always
+//! carry the same series without a second list of metric names to keep in sync.
+
+use serde_json::Value;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::observability::GatewayMetrics;
+use crate::time::now_ms;
+
+const SCOPE_NAME: &str = "codex-mgr";
+/// `AGGREGATION_TEMPORALITY_CUMULATIVE`: every counter is a running total since startup.
+const CUMULATIVE: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Series {
+    name: String,
+    description: String,
+    kind: Kind,
+    points: Vec<(Vec<(String, String)>, f64)>,
+}
+
+pub(crate) fn spawn_exporter(
+    endpoint: String,
+    interval: Duration,
+    http: reqwest::Client,
+    metrics: Arc<GatewayMetrics>,
+) {
+    let start_unix_nano = unix_nano(now_ms());
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick fires immediately; skip it so the first push carries real traffic.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let series = parse_prometheus(&metrics.render_prometheus());
+            let body = export_request(&series, &start_unix_nano, &unix_nano(now_ms()));
+            let result = http
+                .post(&endpoint)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string())
+                .timeout(interval)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(err) = result {
+                tracing::warn!(error = %err, endpoint = %endpoint, "OTLP metrics export failed");
+            }
+        }
+    });
+}
+
+fn unix_nano(ms: i64) -> String {
+    (i128::from(ms) * 1_000_000).to_string()
+}
+
+/// Parses the gateway's own Prometheus exposition output. Only the subset it emits is handled:
+/// `# HELP`/`# TYPE` lines followed by samples with optional `{k="v",...}` labels.
+fn parse_prometheus(text: &str) -> Vec<Series> {
+    let mut series: Vec<Series> = Vec::new();
+    let mut description = String::new();
+    for line in text.lines() {
+        if let Some(rest) = line.strip_prefix("# HELP ") {
+            description = rest
+                .split_once(' ')
+                .map(|(_, help)| help.to_string())
+                .unwrap_or_default();
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let Some((name, kind)) = rest.split_once(' ') else {
+                continue;
+            };
+            let kind = if kind == "gauge" {
+                Kind::Gauge
+            } else {
+                Kind::Counter
+            };
+            series.push(Series {
+                name: name.to_string(),
+                description: std::mem::take(&mut description),
+                kind,
+                points: Vec::new(),
+            });
+            continue;
+        }
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let Some((head, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let Ok(value) = value.parse::<f64>() else {
+            continue;
+        };
+        let (name, labels) = match head.split_once('{') {
+            Some((name, labels)) => (name, parse_labels(labels.trim_end_matches('}'))),
+            None => (head, Vec::new()),
+        };
+        if let Some(series) = series.iter_mut().rev().find(|s| s.name == name) {
+            series.points.push((labels, value));
+        }
+    }
+    series
+}
+
+fn parse_labels(labels: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut rest = labels;
+    while let Some((key, after)) = rest.split_once("=\"") {
+        let mut value = String::new();
+        let mut chars = after.char_indices();
+        let mut end = after.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        value.push(escaped);
+                    }
+                }
+                '"' => {
+                    end = i + 1;
+                    break;
+                }
+                c => value.push(c),
+            }
+        }
+        out.push((key.trim_start_matches(',').to_string(), value));
+        rest = &after[end..];
+    }
+    out
+}
+
+fn export_request(series: &[Series], start_unix_nano: &str, now_unix_nano: &str) -> Value {
+    let metrics: Vec<Value> = series
+        .iter()
+        .filter(|s| !s.points.is_empty())
+        .map(|s| {
+            let data_points: Vec<Value> = s
+                .points
+                .iter()
+                .map(|(labels, value)| {
+                    let attributes: Vec<Value> = labels
+                        .iter()
+                        .map(|(key, value)| json!({"key": key, "value": {"stringValue": value}}))
+                        .collect();
+                    let mut point = json!({
+                        "attributes": attributes,
+                        "startTimeUnixNano": start_unix_nano,
+                        "timeUnixNano": now_unix_nano,
+                    });
+                    // OTLP JSON encodes int64 as a string.
+                    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+                        point["asInt"] = json!((*value as i64).to_string());
+                    } else {
+                        point["asDouble"] = json!(value);
+                    }
+                    point
+                })
+                .collect();
+            let mut metric = json!({"name": s.name, "description": s.description});
+            match s.kind {
+                Kind::Counter => {
+                    metric["sum"] = json!({
+                        "dataPoints": data_points,
+                        "aggregationTemporality": CUMULATIVE,
+                        "isMonotonic": true,
+                    });
+                }
+                Kind::Gauge => metric["gauge"] = json!({"dataPoints": data_points}),
+            }
+            metric
+        })
+        .collect();
+
+    json!({"resourceMetrics": [{
+        "resource": {"attributes": [
+            {"key": "service.name", "value": {"stringValue": SCOPE_NAME}},
+        ]},
+        "scopeMetrics": [{
+            "scope": {"name": SCOPE_NAME},
+            "metrics": metrics,
+        }],
+    }]})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_counters_gauges_and_labels() {
+        let text = "\
+# HELP codex_mgr_gateway_requests_total Total HTTP requests.\n\
+# TYPE codex_mgr_gateway_requests_total counter\n\
+codex_mgr_gateway_requests_total 12\n\
+# HELP codex_mgr_gateway_requests_inflight In flight.\n\
+# TYPE codex_mgr_gateway_requests_inflight gauge\n\
+codex_mgr_gateway_requests_inflight 3\n\
+# HELP codex_mgr_gateway_upstream_auth_failures_total By account.\n\
+# TYPE codex_mgr_gateway_upstream_auth_failures_total counter\n\
+codex_mgr_gateway_upstream_auth_failures_total{account=\"a\\\"b\"} 2\n";
+
+        let series = parse_prometheus(text);
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].kind, Kind::Counter);
+        assert_eq!(series[0].description, "Total HTTP requests.");
+        assert_eq!(series[0].points, vec![(Vec::new(), 12.0)]);
+        assert_eq!(series[1].kind, Kind::Gauge);
+        assert_eq!(
+            series[2].points,
+            vec![(vec![("account".to_string(), "a\"b".to_string())], 2.0)]
+        );
+
+        let body = export_request(&series, "1", "2");
+        let metrics = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["sum"]["dataPoints"][0]["asInt"], json!("12"));
+        assert_eq!(metrics[0]["sum"]["isMonotonic"], json!(true));
+        assert_eq!(metrics[1]["gauge"]["dataPoints"][0]["asInt"], json!("3"));
+        assert_eq!(
+            metrics[2]["sum"]["dataPoints"][0]["attributes"][0]["value"]["stringValue"],
+            json!("a\"b")
+        );
+    }
+}
@@ -1,48 +1,208 @@
 use anyhow::Context;
+use base64::Engine;
 use codex_login::AuthDotJson;
+use rand::TryRngCore;
 use serde::Serialize;
+use similar::ChangeTag;
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::path::Path;
 
+use crate::accounts;
+use crate::aliases;
+use crate::audit;
+use crate::audit::AuditEntry;
 use crate::config;
+use crate::exit_code::ErrorCategory;
+use crate::label::is_single_path_component;
 use crate::label::validate_label;
+use crate::routing;
 
 const POOL_ID_MAX_LEN: i64 = 64;
+pub(crate) const DEFAULT_DISTRIBUTION_SAMPLES: i64 = 1000;
+pub(crate) const DEFAULT_BENCH_SAMPLES: i64 = 100_000;
+pub(crate) const DEFAULT_BENCH_MAX_DEVIATION_PERCENT: f64 = 5.0;
 
 #[derive(Debug, Clone, Serialize)]
 struct PoolRow {
     pool_id: String,
     labels: Vec<String>,
     policy_key: Option<String>,
+    default_note: Option<String>,
+    sticky: bool,
+}
+
+pub(crate) struct SetOptions {
+    pub(crate) pool_id: String,
+    pub(crate) labels: Vec<String>,
+    pub(crate) policy_key: Option<String>,
+    pub(crate) default_note: Option<String>,
+    pub(crate) sticky: Option<bool>,
+    pub(crate) allow_account_override: Option<bool>,
+    pub(crate) tenant_header: Option<String>,
+    /// Also include every account tagged with this (see `accounts tag`).
+    pub(crate) from_tag: Option<String>,
+    /// Print the resulting `[pools.<id>]` block and member checks instead of writing; fails
+    /// after printing when a member check does.
+    pub(crate) dry_run: bool,
+    /// Print a before/after line diff of the pool's block.
+    pub(crate) diff: bool,
 }
 
 pub(crate) async fn set(
     state_root: &Path,
+    config_path: &Path,
     accounts_root: &Path,
-    pool_id: String,
-    mut labels: Vec<String>,
-    policy_key: Option<String>,
+    options: SetOptions,
 ) -> anyhow::Result<()> {
+    let SetOptions {
+        pool_id,
+        mut labels,
+        policy_key,
+        default_note,
+        sticky,
+        allow_account_override,
+        tenant_header,
+        from_tag,
+        dry_run,
+        diff,
+    } = options;
     validate_pool_id(&pool_id)?;
+    labels = aliases::resolve_all(state_root, labels);
+    let duplicates = duplicate_labels(&labels);
+    if !duplicates.is_empty() {
+        tracing::warn!("--labels lists {} more than once", duplicates.join(", "));
+    }
+    if let Some(tag) = &from_tag {
+        let tagged = accounts::labels_with_tag(accounts_root, state_root, tag)?;
+        if tagged.is_empty() {
+            anyhow::bail!("no accounts tagged {tag:?}");
+        }
+        labels.extend(tagged);
+    }
     if labels.is_empty() {
         anyhow::bail!("--labels must not be empty");
     }
+    let mut member_errors = Vec::new();
     for label in &labels {
         validate_label(label)?;
-        ensure_auth_present(accounts_root, label)?;
+        if dry_run {
+            if let Err(err) = ensure_auth_present(accounts_root, label) {
+                member_errors.push(format!("member {label:?}: {err:#}"));
+            }
+        } else {
+            ensure_auth_present(accounts_root, label)?;
+        }
     }
 
     labels.sort();
     labels.dedup();
 
-    let mut root = config::load_value_for_update(state_root)?;
+    let mut root = config::load_value_for_update(config_path)?;
+    ensure_pool_size(config::max_labels_per_pool(&root)?, &pool_id, labels.len())?;
+    let before = render_pool_block(&root, &pool_id)?;
     config::ensure_gateway_defaults(&mut root)?;
-    config::set_pool(&mut root, &pool_id, &labels, policy_key.as_deref())?;
-    config::write_value(state_root, &root)?;
+    config::set_pool(
+        &mut root,
+        &pool_id,
+        config::PoolUpdate {
+            labels: &labels,
+            policy_key: policy_key.as_deref(),
+            default_note: default_note.as_deref(),
+            sticky,
+            allow_account_override,
+            tenant_header: tenant_header.as_deref(),
+        },
+    )?;
+    let after = render_pool_block(&root, &pool_id)?;
+
+    if diff {
+        print!("{}", line_diff(&before, &after));
+    }
+    if dry_run {
+        if !diff {
+            print!("{after}");
+        }
+        if member_errors.is_empty() {
+            println!("members: OK");
+        } else {
+            println!("members: FAIL");
+            for err in &member_errors {
+                println!("  - {err}");
+            }
+        }
+        println!("dry run: config not written");
+        if !member_errors.is_empty() {
+            return Err(ErrorCategory::Config.wrap(anyhow::anyhow!(
+                "--dry-run: {} member check(s) failed for pool {pool_id:?}",
+                member_errors.len()
+            )));
+        }
+        return Ok(());
+    }
+
+    config::write_value(config_path, &root)?;
+    audit::record(
+        state_root,
+        AuditEntry::new("pools set").pool(&pool_id).labels(&labels),
+    );
     Ok(())
 }
 
-pub(crate) async fn list(state_root: &Path, json: bool) -> anyhow::Result<()> {
-    let root = config::load_value_optional(state_root)?;
+/// Labels appearing more than once in `labels`, sorted.
+fn duplicate_labels(labels: &[String]) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    let duplicates: BTreeSet<&String> = labels
+        .iter()
+        .filter(|label| !seen.insert(label.as_str()))
+        .collect();
+    duplicates.into_iter().cloned().collect()
+}
+
+fn ensure_pool_size(max: usize, pool_id: &str, len: usize) -> anyhow::Result<()> {
+    if len > max {
+        anyhow::bail!(
+            "pool {pool_id:?} would have {len} labels, more than gateway.max_labels_per_pool ({max}); large pools slow routing and usually mean a mistake, so split it or raise the limit"
+        );
+    }
+    Ok(())
+}
+
+/// Renders just `[pools.<pool_id>]` from `root`; empty when the pool is not configured.
+fn render_pool_block(root: &toml::Value, pool_id: &str) -> anyhow::Result<String> {
+    let Some(pool) = root.get("pools").and_then(|pools| pools.get(pool_id)) else {
+        return Ok(String::new());
+    };
+    let block = toml::Table::from_iter([(
+        "pools".to_string(),
+        toml::Value::Table(toml::Table::from_iter([(
+            pool_id.to_string(),
+            pool.clone(),
+        )])),
+    )]);
+    toml::to_string_pretty(&block).context("rendering pool block")
+}
+
+/// Unified-style line diff: `-`, `+`, or two spaces for unchanged.
+fn line_diff(before: &str, after: &str) -> String {
+    let mut out = String::new();
+    for change in TextDiff::from_lines(before, after).iter_all_changes() {
+        out.push_str(match change.tag() {
+            ChangeTag::Equal => "  ",
+            ChangeTag::Delete => "- ",
+            ChangeTag::Insert => "+ ",
+        });
+        out.push_str(change.value());
+        if change.missing_newline() {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+pub(crate) async fn list(config_path: &Path, json: bool) -> anyhow::Result<()> {
+    let root = config::load_value_optional(config_path)?;
     let pools = config::extract_pools(&root)?;
 
     let mut rows: Vec<PoolRow> = pools
@@ -51,6 +211,8 @@ pub(crate) async fn list(state_root: &Path, json: bool) -> anyhow::Result<()> {
             pool_id,
             labels: pool.labels,
             policy_key: pool.policy_key,
+            default_note: pool.default_note,
+            sticky: pool.sticky,
         })
         .collect();
     rows.sort_by(|a, b| a.pool_id.cmp(&b.pool_id));
@@ -66,52 +228,346 @@ pub(crate) async fn list(state_root: &Path, json: bool) -> anyhow::Result<()> {
     }
 
     let mut pool_w = "pool".len();
+    let mut policy_w = "policy_key".len();
     for row in &rows {
         pool_w = pool_w.max(row.pool_id.len());
+        policy_w = policy_w.max(row.policy_key.as_deref().unwrap_or("-").len());
     }
 
     println!(
-        "{:<pool_w$} {:>7} policy_key",
+        "{:<pool_w$} {:>7} {:>6} {:<policy_w$} default_note",
         "pool",
         "labels",
-        pool_w = pool_w
+        "sticky",
+        "policy_key",
+        pool_w = pool_w,
+        policy_w = policy_w
     );
     for row in rows {
         let policy = row.policy_key.as_deref().unwrap_or("-");
+        let note = row.default_note.as_deref().unwrap_or("-");
         println!(
-            "{:<pool_w$} {:>7} {}",
+            "{:<pool_w$} {:>7} {:>6} {:<policy_w$} {note}",
             row.pool_id,
             row.labels.len(),
+            row.sticky,
             policy,
-            pool_w = pool_w
+            pool_w = pool_w,
+            policy_w = policy_w
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PoolShowOut {
+    #[serde(flatten)]
+    pool: PoolRow,
+    samples: i64,
+    /// First-choice account for each synthetic key under hash-ring routing.
+    distribution: BTreeMap<String, i64>,
+}
+
+pub(crate) async fn show(
+    config_path: &Path,
+    pool_id: String,
+    samples: i64,
+    json: bool,
+) -> anyhow::Result<()> {
+    if samples <= 0 {
+        anyhow::bail!("--samples must be > 0");
+    }
+    let root = config::load_value_optional(config_path)?;
+    let pool = config::extract_pools(&root)?
+        .remove(&pool_id)
+        .with_context(|| format!("pool {pool_id:?} does not exist"))?;
+    let distribution =
+        routing::ring_distribution(&pool_id, pool.policy_key.as_deref(), &pool.labels, samples)?;
+    let out = PoolShowOut {
+        pool: PoolRow {
+            pool_id,
+            labels: pool.labels,
+            policy_key: pool.policy_key,
+            default_note: pool.default_note,
+            sticky: pool.sticky,
+        },
+        samples,
+        distribution,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    println!("pool: {}", out.pool.pool_id);
+    println!(
+        "policy_key: {}",
+        out.pool.policy_key.as_deref().unwrap_or("-")
+    );
+    println!("sticky: {}", out.pool.sticky);
+    println!(
+        "default_note: {}",
+        out.pool.default_note.as_deref().unwrap_or("-")
+    );
+    println!(
+        "distribution ({} synthetic keys, hash-ring routing):",
+        out.samples
+    );
+    let label_w = out.distribution.keys().map(String::len).max().unwrap_or(0);
+    for (label, count) in &out.distribution {
+        let percent = *count as f64 * 100.0 / out.samples as f64;
+        println!("  {label:<label_w$} {count:>6} {percent:>5.1}%");
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RoutingBenchOut {
+    pool_id: String,
+    samples: i64,
+    expected_per_label: f64,
+    counts: BTreeMap<String, i64>,
+    chi_square: f64,
+    degrees_of_freedom: usize,
+    max_deviation_percent: f64,
+    max_allowed_deviation_percent: f64,
+    pass: bool,
+}
+
+/// Hashes `samples` synthetic conversation keys through the pool's ring and compares the
+/// first-choice counts with an even split; fails when any label strays further than
+/// `max_deviation_percent`.
+pub(crate) async fn bench(
+    config_path: &Path,
+    pool_id: String,
+    samples: i64,
+    max_deviation_percent: f64,
+    json: bool,
+) -> anyhow::Result<()> {
+    if samples <= 0 {
+        anyhow::bail!("--samples must be > 0");
+    }
+    if max_deviation_percent.is_nan() || max_deviation_percent <= 0.0 {
+        anyhow::bail!("--max-deviation-percent must be > 0");
+    }
+    let root = config::load_value_optional(config_path)?;
+    let pool = config::extract_pools(&root)?
+        .remove(&pool_id)
+        .with_context(|| format!("pool {pool_id:?} does not exist"))?;
+    if pool.labels.is_empty() {
+        anyhow::bail!("pool {pool_id:?} has no labels configured");
+    }
+    let counts =
+        routing::ring_distribution(&pool_id, pool.policy_key.as_deref(), &pool.labels, samples)?;
+    let deviation = routing::uniform_deviation(&counts);
+    let out = RoutingBenchOut {
+        pool_id,
+        samples,
+        expected_per_label: samples as f64 / counts.len() as f64,
+        degrees_of_freedom: counts.len().saturating_sub(1),
+        counts,
+        chi_square: deviation.chi_square,
+        max_deviation_percent: deviation.max_deviation_percent,
+        max_allowed_deviation_percent: max_deviation_percent,
+        pass: deviation.max_deviation_percent <= max_deviation_percent,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!(
+            "pool {:?}: {} synthetic keys, {:.1} expected per label",
+            out.pool_id, out.samples, out.expected_per_label
+        );
+        let label_w = out.counts.keys().map(String::len).max().unwrap_or(0);
+        for (label, count) in &out.counts {
+            let deviation =
+                (*count as f64 - out.expected_per_label) * 100.0 / out.expected_per_label;
+            println!("  {label:<label_w$} {count:>8} {deviation:>+7.2}%");
+        }
+        println!(
+            "chi-square: {:.3} ({} degrees of freedom)",
+            out.chi_square, out.degrees_of_freedom
+        );
+        println!(
+            "max deviation: {:.2}% (allowed {:.2}%): {}",
+            out.max_deviation_percent,
+            out.max_allowed_deviation_percent,
+            if out.pass { "PASS" } else { "FAIL" }
+        );
+    }
+
+    if !out.pass {
+        anyhow::bail!(
+            "routing distribution for pool {:?} deviates {:.2}% from uniform (allowed {:.2}%)",
+            out.pool_id,
+            out.max_deviation_percent,
+            out.max_allowed_deviation_percent
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RotatePolicyOut {
+    pool_id: String,
+    old_policy_key: Option<String>,
+    new_policy_key: String,
+    samples: i64,
+    /// Share of synthetic conversation keys whose first-choice account changes.
+    moved_percent: f64,
+    before: BTreeMap<String, i64>,
+    after: BTreeMap<String, i64>,
+    dry_run: bool,
+}
+
+pub(crate) struct RotatePolicyOptions {
+    pub(crate) pool_id: String,
+    /// The new policy_key; a random one when `None`.
+    pub(crate) new_key: Option<String>,
+    pub(crate) samples: i64,
+    /// Report the expected change without writing config.toml.
+    pub(crate) dry_run: bool,
+    pub(crate) json: bool,
+}
+
+/// Replaces a pool's policy_key and reports how hash-ring routing shifts. Sticky keys fold in
+/// the policy_key, so existing bindings are simply no longer found: each conversation re-selects
+/// once and the old keys expire after `sticky_ttl_seconds`.
+pub(crate) async fn rotate_policy(
+    state_root: &Path,
+    config_path: &Path,
+    options: RotatePolicyOptions,
+) -> anyhow::Result<()> {
+    let RotatePolicyOptions {
+        pool_id,
+        new_key,
+        samples,
+        dry_run,
+        json,
+    } = options;
+    validate_pool_id(&pool_id)?;
+    if samples <= 0 {
+        anyhow::bail!("--samples must be > 0");
+    }
+    let mut root = config::load_value_for_update(config_path)?;
+    let pool = config::extract_pools(&root)?
+        .remove(&pool_id)
+        .with_context(|| format!("pool {pool_id:?} does not exist"))?;
+    if pool.labels.is_empty() {
+        anyhow::bail!("pool {pool_id:?} has no labels configured");
+    }
+    let new_key = match new_key {
+        Some(key) if key.trim().is_empty() => anyhow::bail!("--new-key must not be empty"),
+        Some(key) => key,
+        None => generate_policy_key()?,
+    };
+    if pool.policy_key.as_deref() == Some(new_key.as_str()) {
+        anyhow::bail!("pool {pool_id:?} already uses that policy_key");
+    }
+
+    let old_key = pool.policy_key.as_deref();
+    let moved = routing::ring_moves(&pool_id, old_key, Some(&new_key), &pool.labels, samples)?;
+    let out = RotatePolicyOut {
+        before: routing::ring_distribution(&pool_id, old_key, &pool.labels, samples)?,
+        after: routing::ring_distribution(&pool_id, Some(&new_key), &pool.labels, samples)?,
+        moved_percent: moved as f64 * 100.0 / samples as f64,
+        old_policy_key: pool.policy_key.clone(),
+        new_policy_key: new_key,
+        pool_id,
+        samples,
+        dry_run,
+    };
+
+    if !dry_run {
+        config::set_pool(
+            &mut root,
+            &out.pool_id,
+            config::PoolUpdate {
+                labels: &pool.labels,
+                policy_key: Some(&out.new_policy_key),
+                default_note: None,
+                sticky: None,
+                allow_account_override: None,
+                tenant_header: None,
+            },
+        )?;
+        config::write_value(config_path, &root)?;
+        audit::record(
+            state_root,
+            AuditEntry::new("pools rotate-policy").pool(&out.pool_id),
         );
     }
 
+    if json {
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+    println!(
+        "pool {:?}: policy_key {} -> {}",
+        out.pool_id,
+        out.old_policy_key.as_deref().unwrap_or("-"),
+        out.new_policy_key
+    );
+    println!(
+        "{:.1}% of {} synthetic keys change first-choice account (hash-ring routing):",
+        out.moved_percent, out.samples
+    );
+    let label_w = out.before.keys().map(String::len).max().unwrap_or(0);
+    for (label, before) in &out.before {
+        let after = out.after.get(label).copied().unwrap_or(0);
+        println!("  {label:<label_w$} {before:>6} -> {after:>6}");
+    }
+    if out.dry_run {
+        println!("dry run: config not written");
+    } else if pool.sticky {
+        println!(
+            "existing sticky bindings are abandoned; conversations re-select once and old bindings expire after sticky_ttl_seconds"
+        );
+    }
     Ok(())
 }
 
-pub(crate) async fn del(state_root: &Path, pool_id: String) -> anyhow::Result<()> {
+fn generate_policy_key() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 12];
+    let mut rng = rand::rngs::OsRng;
+    rng.try_fill_bytes(&mut bytes)
+        .context("generating secure random bytes")?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes))
+}
+
+pub(crate) async fn del(
+    state_root: &Path,
+    config_path: &Path,
+    pool_id: String,
+) -> anyhow::Result<()> {
     validate_pool_id(&pool_id)?;
-    let mut root = config::load_value_for_update(state_root)?;
+    let mut root = config::load_value_for_update(config_path)?;
     let removed = config::remove_pool(&mut root, &pool_id)?;
     if !removed {
         anyhow::bail!("pool {pool_id:?} does not exist");
     }
-    config::write_value(state_root, &root)?;
+    config::write_value(config_path, &root)?;
+    audit::record(state_root, AuditEntry::new("pools del").pool(&pool_id));
     Ok(())
 }
 
 pub(crate) async fn add_member(
     state_root: &Path,
+    config_path: &Path,
     accounts_root: &Path,
     pool_id: String,
     label: String,
 ) -> anyhow::Result<()> {
     validate_pool_id(&pool_id)?;
     validate_label(&label)?;
+    let label = aliases::resolve(state_root, label);
     ensure_auth_present(accounts_root, &label)?;
 
-    let mut root = config::load_value_for_update(state_root)?;
+    let mut root = config::load_value_for_update(config_path)?;
+    let max_labels = config::max_labels_per_pool(&root)?;
     // We need to fetch existing pool definition.
     // config module doesn't expose get_pool easily for update, it exposes set_pool and extract_pools.
     // We can extract, find, modify, then set.
@@ -143,6 +599,7 @@ pub(crate) async fn add_member(
 
     let label_val = toml::Value::String(label.clone());
     if !labels_array.contains(&label_val) {
+        ensure_pool_size(max_labels, &pool_id, labels_array.len() + 1)?;
         labels_array.push(label_val);
         // Sort for consistency?
         labels_array.sort_by(|a, b| {
@@ -150,7 +607,13 @@ pub(crate) async fn add_member(
             let s_b = b.as_str().unwrap_or("");
             s_a.cmp(s_b)
         });
-        config::write_value(state_root, &root)?;
+        config::write_value(config_path, &root)?;
+        audit::record(
+            state_root,
+            AuditEntry::new("pools add-member")
+                .pool(&pool_id)
+                .labels(std::slice::from_ref(&label)),
+        );
         println!("Added {label:?} to pool {pool_id:?}");
     } else {
         println!("{label:?} is already in pool {pool_id:?}");
@@ -161,13 +624,15 @@ pub(crate) async fn add_member(
 
 pub(crate) async fn remove_member(
     state_root: &Path,
+    config_path: &Path,
     pool_id: String,
     label: String,
 ) -> anyhow::Result<()> {
     validate_pool_id(&pool_id)?;
     // No need to validate label format strictly, just remove it if matches string.
+    let label = aliases::resolve(state_root, label);
 
-    let mut root = config::load_value_for_update(state_root)?;
+    let mut root = config::load_value_for_update(config_path)?;
     let pools_table = root
         .as_table_mut()
         .and_then(|t| t.get_mut("pools"))
@@ -195,7 +660,13 @@ pub(crate) async fn remove_member(
             anyhow::bail!("cannot remove last member {label:?} from pool {pool_id:?}");
         }
         labels_array.remove(pos);
-        config::write_value(state_root, &root)?;
+        config::write_value(config_path, &root)?;
+        audit::record(
+            state_root,
+            AuditEntry::new("pools remove-member")
+                .pool(&pool_id)
+                .labels(std::slice::from_ref(&label)),
+        );
         println!("Removed {label:?} from pool {pool_id:?}");
     } else {
         anyhow::bail!("member {label:?} not found in pool {pool_id:?}");
@@ -204,57 +675,6 @@ pub(crate) async fn remove_member(
     Ok(())
 }
 
-pub(crate) async fn validate(
-    state_root: &Path,
-    accounts_root: &Path,
-    target_pool_id: Option<String>,
-) -> anyhow::Result<()> {
-    let root = config::load_value_optional(state_root)?;
-    let pools = config::extract_pools(&root)?;
-
-    if pools.is_empty() {
-        println!("No pools configured to validate.");
-        return Ok(());
-    }
-
-    let mut all_ok = true;
-
-    for (pool_id, pool) in pools {
-        if let Some(target) = &target_pool_id
-            && &pool_id != target
-        {
-            continue;
-        }
-
-        print!("Validating pool {pool_id:?}... ");
-        let mut pool_errors = Vec::new();
-
-        for label in pool.labels {
-            match ensure_auth_present(accounts_root, &label) {
-                Ok(_) => {}
-                Err(e) => {
-                    pool_errors.push(format!("member {label:?}: {e}"));
-                }
-            }
-        }
-
-        if pool_errors.is_empty() {
-            println!("OK");
-        } else {
-            all_ok = false;
-            println!("FAIL");
-            for err in pool_errors {
-                println!("  - {err}");
-            }
-        }
-    }
-
-    if !all_ok {
-        anyhow::bail!("validation failed");
-    }
-    Ok(())
-}
-
 fn validate_pool_id(pool_id: &str) -> anyhow::Result<()> {
     if pool_id.is_empty() {
         anyhow::bail!("pool_id must not be empty");
@@ -270,6 +690,7 @@ fn validate_pool_id(pool_id: &str) -> anyhow::Result<()> {
         .chars()
         .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
         && !pool_id.starts_with('.')
+        && is_single_path_component(pool_id)
     {
         return Ok(());
     }
@@ -278,7 +699,11 @@ fn validate_pool_id(pool_id: &str) -> anyhow::Result<()> {
     );
 }
 
-fn ensure_auth_present(accounts_root: &Path, label: &str) -> anyhow::Result<()> {
+pub(crate) fn ensure_auth_present(accounts_root: &Path, label: &str) -> anyhow::Result<()> {
+    read_member_auth(accounts_root, label).map_err(|err| ErrorCategory::Auth.wrap(err))
+}
+
+fn read_member_auth(accounts_root: &Path, label: &str) -> anyhow::Result<()> {
     let auth_path = accounts_root.join(label).join("auth.json");
     let text = std::fs::read_to_string(&auth_path)
         .with_context(|| format!("reading {auth_path:?} for pool member {label:?}"))?;
@@ -293,3 +718,84 @@ fn ensure_auth_present(accounts_root: &Path, label: &str) -> anyhow::Result<()>
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn set_dry_run_fails_on_member_checks_without_writing() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        std::fs::create_dir_all(accounts_root.join("a")).expect("create account");
+        std::fs::write(
+            accounts_root.join("a").join("auth.json"),
+            r#"{"OPENAI_API_KEY":null,"tokens":{"id_token":"e30.e30.sig","access_token":"e30.e30.sig","refresh_token":"rt"}}"#,
+        )
+        .expect("write auth.json");
+        let config_path = config::default_path(temp.path());
+        std::fs::write(&config_path, "[gateway]\n").expect("write config");
+        let dry_run = |labels: &[&str]| SetOptions {
+            pool_id: "team".to_string(),
+            labels: labels.iter().map(ToString::to_string).collect(),
+            policy_key: None,
+            default_note: None,
+            sticky: None,
+            allow_account_override: None,
+            tenant_header: None,
+            from_tag: None,
+            dry_run: true,
+            diff: false,
+        };
+
+        set(temp.path(), &config_path, &accounts_root, dry_run(&["a"]))
+            .await
+            .expect("members pass");
+        let err = set(
+            temp.path(),
+            &config_path,
+            &accounts_root,
+            dry_run(&["a", "ghost"]),
+        )
+        .await
+        .expect_err("missing member");
+        assert_eq!(
+            crate::exit_code::category_of(&err),
+            Some(ErrorCategory::Config)
+        );
+        assert_eq!(
+            std::fs::read_to_string(&config_path).expect("read config"),
+            "[gateway]\n"
+        );
+    }
+
+    #[test]
+    fn duplicate_labels_and_pool_size_limit() {
+        let labels: Vec<String> = ["b", "a", "b", "c", "a", "b"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            duplicate_labels(&labels),
+            vec!["a".to_string(), "b".to_string()]
+        );
+        assert!(duplicate_labels(&labels[..2]).is_empty());
+
+        assert!(ensure_pool_size(3, "team", 3).is_ok());
+        let err = ensure_pool_size(3, "team", 4).expect_err("oversized pool");
+        assert!(err.to_string().contains("max_labels_per_pool (3)"));
+    }
+
+    #[test]
+    fn line_diff_marks_changed_pool_lines() {
+        let before = "[pools.team]\nlabels = [\"a\", \"b\"]\nsticky = true\n";
+        let after = "[pools.team]\nlabels = [\"a\", \"c\"]\nsticky = true\n";
+        assert_eq!(
+            line_diff(before, after),
+            "  [pools.team]\n- labels = [\"a\", \"b\"]\n+ labels = [\"a\", \"c\"]\n  sticky = true\n"
+        );
+        assert_eq!(line_diff("", "x = 1\n"), "+ x = 1\n");
+        assert_eq!(line_diff("x = 1", "x = 2"), "- x = 1\n+ x = 2\n");
+    }
+}
@@ -0,0 +1,236 @@
+//! `pools validate`: preflight checks of the configured pools' members.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use toml::Value;
+
+use crate::account_token_provider;
+use crate::config;
+use crate::pools;
+use crate::time::SystemClock;
+
+/// Preflight for every configured pool (or just `target_pool_id`): members must exist, hold
+/// usable auth (refreshing expired access tokens), and map to distinct ChatGPT accounts. Reads
+/// the config document rather than [`config::load`], so an unrelated `[gateway]` mistake does not
+/// hide the pool report.
+pub(crate) async fn validate(
+    config_path: &Path,
+    accounts_root: &Path,
+    target_pool_id: Option<String>,
+) -> anyhow::Result<()> {
+    let root = config::load_value_optional(config_path)?;
+    let pools = config::extract_pools(&root)?;
+    let target_pool_id = target_pool_id.map(|name| resolve_pool_alias(&root, &name));
+    if let Some(target) = &target_pool_id
+        && !pools.contains_key(target)
+    {
+        anyhow::bail!("pool {target:?} not found");
+    }
+
+    if pools.is_empty() {
+        println!("No pools configured to validate.");
+        return Ok(());
+    }
+
+    let token_safety_window_seconds = token_safety_window_seconds(&root)?;
+    let mut failed = Vec::new();
+
+    for (pool_id, pool) in &pools {
+        if let Some(target) = &target_pool_id
+            && pool_id != target
+        {
+            continue;
+        }
+
+        let pool_errors = validate_pool(accounts_root, pool, token_safety_window_seconds).await;
+        if pool_errors.is_empty() {
+            println!("pool {pool_id:?}: OK");
+        } else {
+            println!("pool {pool_id:?}: FAIL");
+            for err in pool_errors {
+                println!("  - {err}");
+            }
+            failed.push(pool_id.clone());
+        }
+    }
+
+    if !failed.is_empty() {
+        anyhow::bail!("validation failed for pool(s): {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+async fn validate_pool(
+    accounts_root: &Path,
+    pool: &config::PoolConfig,
+    token_safety_window_seconds: i64,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    if pool.labels.is_empty() {
+        errors.push("pool has no members".to_string());
+    }
+
+    let mut account_ids = Vec::new();
+    for label in &pool.labels {
+        if !accounts_root.join(label).is_dir() {
+            errors.push(format!("member {label:?}: account does not exist"));
+            continue;
+        }
+        if let Err(err) = pools::ensure_auth_present(accounts_root, label) {
+            errors.push(format!("member {label:?}: {err:#}"));
+            continue;
+        }
+        match account_token_provider::load_from_auth(
+            accounts_root,
+            label,
+            token_safety_window_seconds,
+            &SystemClock,
+        )
+        .await
+        {
+            Ok(material) => account_ids.push((label.clone(), material.chatgpt_account_id)),
+            Err(err) => errors.push(format!("member {label:?}: {err:#}")),
+        }
+    }
+
+    errors.extend(shared_account_id_errors(&account_ids));
+    errors
+}
+
+/// `name` mapped through the document's `[pool_aliases]`, as
+/// [`config::ManagerConfig::resolve_pool_alias`] maps it.
+fn resolve_pool_alias(root: &Value, name: &str) -> String {
+    root.get("pool_aliases")
+        .and_then(|aliases| aliases.get(name))
+        .and_then(Value::as_str)
+        .map_or_else(|| name.to_string(), str::to_string)
+}
+
+/// `gateway.token_safety_window_seconds` as [`config::load`] settles it: defaulted, then raised
+/// to `gateway.min_token_safety_window_seconds`.
+fn token_safety_window_seconds(root: &Value) -> anyhow::Result<i64> {
+    let setting = |name: &str, default: i64| -> anyhow::Result<i64> {
+        match root.get("gateway").and_then(|gateway| gateway.get(name)) {
+            None => Ok(default),
+            Some(Value::Integer(seconds)) if *seconds >= 0 => Ok(*seconds),
+            Some(_) => anyhow::bail!("gateway.{name} must be an integer >= 0"),
+        }
+    };
+    let window = setting(
+        "token_safety_window_seconds",
+        config::DEFAULT_TOKEN_SAFETY_WINDOW_SECONDS,
+    )?;
+    let floor = setting(
+        "min_token_safety_window_seconds",
+        config::DEFAULT_MIN_TOKEN_SAFETY_WINDOW_SECONDS,
+    )?;
+    Ok(window.max(floor))
+}
+
+/// Reports every `chatgpt_account_id` claimed by more than one member label.
+fn shared_account_id_errors(account_ids: &[(String, Option<String>)]) -> Vec<String> {
+    let mut owners: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (label, account_id) in account_ids {
+        if let Some(account_id) = account_id {
+            owners.entry(account_id).or_default().push(label);
+        }
+    }
+    owners
+        .into_iter()
+        .filter(|(_, labels)| labels.len() > 1)
+        .map(|(account_id, labels)| {
+            format!("members {labels:?} share chatgpt_account_id {account_id:?}")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn validate_pool_reports_empty_pool_and_missing_members() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let pool = |labels: &[&str]| config::PoolConfig {
+            labels: labels.iter().map(ToString::to_string).collect(),
+            policy_key: None,
+            default_note: None,
+            sticky: true,
+            allow_account_override: false,
+            tenant_header: None,
+        };
+
+        assert_eq!(
+            validate_pool(temp.path(), &pool(&[]), 120).await,
+            vec!["pool has no members".to_string()]
+        );
+        assert_eq!(
+            validate_pool(temp.path(), &pool(&["ghost"]), 120).await,
+            vec!["member \"ghost\": account does not exist".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_reports_pools_despite_an_invalid_gateway_section() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let config_path = config::default_path(temp.path());
+        std::fs::write(
+            &config_path,
+            "[gateway]\nredis_command_timeout_ms = 0\n\n[pools.team]\nlabels = []\n\n[pool_aliases]\nt = \"team\"\n",
+        )
+        .expect("write config");
+        assert!(config::load(&config_path).is_err());
+
+        let err = validate(&config_path, temp.path(), Some("t".to_string()))
+            .await
+            .expect_err("empty pool");
+        assert_eq!(err.to_string(), "validation failed for pool(s): team");
+        assert!(
+            validate(&config_path, temp.path(), Some("missing".to_string()))
+                .await
+                .is_err()
+        );
+
+        std::fs::write(&config_path, "").expect("write config");
+        validate(&config_path, temp.path(), None)
+            .await
+            .expect("no pools");
+    }
+
+    #[test]
+    fn token_safety_window_is_defaulted_and_floored_like_load() {
+        let window = |gateway: &str| {
+            let root: Value = toml::from_str(&format!("[gateway]\n{gateway}")).expect("parse");
+            token_safety_window_seconds(&root)
+        };
+        assert_eq!(
+            window("").expect("default"),
+            config::DEFAULT_TOKEN_SAFETY_WINDOW_SECONDS
+        );
+        assert_eq!(
+            window("token_safety_window_seconds = 0\n").expect("floored"),
+            config::DEFAULT_MIN_TOKEN_SAFETY_WINDOW_SECONDS
+        );
+        assert_eq!(
+            window("token_safety_window_seconds = 0\nmin_token_safety_window_seconds = 0\n")
+                .expect("no floor"),
+            0
+        );
+        assert!(window("token_safety_window_seconds = -1\n").is_err());
+    }
+
+    #[test]
+    fn shared_account_id_errors_flags_duplicates_only() {
+        let account_ids = vec![
+            ("a".to_string(), Some("acct-1".to_string())),
+            ("b".to_string(), Some("acct-2".to_string())),
+            ("c".to_string(), Some("acct-1".to_string())),
+            ("d".to_string(), None),
+        ];
+        assert_eq!(
+            shared_account_id_errors(&account_ids),
+            vec![r#"members ["a", "c"] share chatgpt_account_id "acct-1""#.to_string()]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use axum::body::Body;
 use axum::http::HeaderMap;
+use axum::http::Method;
 use axum::http::StatusCode;
 use axum::http::header;
 use axum::http::header::HeaderValue;
@@ -7,16 +8,23 @@ use axum::http::request::Parts;
 use axum::response::Response;
 use bytes::Bytes;
 use futures::Stream;
+use futures::StreamExt;
 use serde::Serialize;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicI64;
 use std::sync::atomic::Ordering;
 use std::task::Context;
 use std::task::Poll;
 use std::time::Instant;
 
+use crate::config::UpstreamHosts;
 use crate::header_policy;
+use crate::header_policy::HeaderStripLists;
 use crate::observability::GatewayMetrics;
+use crate::observability::SSE_STREAM_DURATION_BUCKETS_MS;
+use crate::observability::SSE_TTFB_BUCKETS_MS;
 
 pub(crate) const MAX_REQUEST_BODY_BYTES: usize = 250 * 1024 * 1024;
 
@@ -39,6 +47,20 @@ impl GatewayError {
         }
     }
 
+    pub(crate) fn gateway_timeout(detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::GATEWAY_TIMEOUT,
+            detail: detail.into(),
+        }
+    }
+
+    pub(crate) fn payload_too_large(detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            detail: detail.into(),
+        }
+    }
+
     pub(crate) fn status(&self) -> StatusCode {
         self.status
     }
@@ -77,25 +99,140 @@ pub(crate) fn json_error_response(status: StatusCode, detail: impl Into<String>)
     response
 }
 
+/// How an incoming request body is read before forwarding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BodyMode {
+    /// Bodyless `GET`/`HEAD`/`DELETE`: nothing is read and nothing is sent upstream.
+    Empty,
+    /// Buffered once (up to `MAX_REQUEST_BODY_BYTES`) so retries can replay it.
+    Buffer,
+    /// Large uploads relayed as they arrive, still capped at `MAX_REQUEST_BODY_BYTES`;
+    /// single-use, so no retry on another account.
+    Stream,
+}
+
+impl BodyMode {
+    pub(crate) fn for_request(method: &Method, headers: &HeaderMap) -> Self {
+        let declares_body = headers.contains_key(header::TRANSFER_ENCODING)
+            || headers
+                .get(header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .is_some_and(|len| len > 0);
+        if !declares_body && matches!(*method, Method::GET | Method::HEAD | Method::DELETE) {
+            return Self::Empty;
+        }
+        let streaming_content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .is_some_and(|v| {
+                v.starts_with("application/octet-stream") || v.starts_with("multipart/")
+            });
+        if streaming_content_type {
+            Self::Stream
+        } else {
+            Self::Buffer
+        }
+    }
+}
+
+pub(crate) enum ForwardBody {
+    Empty,
+    Buffered(Bytes),
+    Streaming(Body),
+}
+
+impl ForwardBody {
+    /// A copy for one more upstream attempt; `None` for streams, which can only be sent once.
+    pub(crate) fn try_clone(&self) -> Option<Self> {
+        match self {
+            Self::Empty => Some(Self::Empty),
+            Self::Buffered(bytes) => Some(Self::Buffered(bytes.clone())),
+            Self::Streaming(_) => None,
+        }
+    }
+
+    /// Bytes known up front, for logging; `None` while streaming.
+    pub(crate) fn buffered_len(&self) -> Option<usize> {
+        match self {
+            Self::Empty => Some(0),
+            Self::Buffered(bytes) => Some(bytes.len()),
+            Self::Streaming(_) => None,
+        }
+    }
+}
+
 pub(crate) struct ForwardRequest<'a> {
     pub(crate) parts: Parts,
-    pub(crate) body_bytes: Bytes,
+    pub(crate) body: ForwardBody,
     pub(crate) authorization: &'a str,
     pub(crate) chatgpt_account_id: Option<&'a str>,
+    /// Send `Accept-Encoding: identity` upstream for `text/event-stream` requests so compressed
+    /// responses cannot stall incremental streaming.
+    pub(crate) identity_encoding_for_sse: bool,
+    /// `gateway.upstream_api_headers`, overriding client-sent values.
+    pub(crate) pinned_headers: &'a HeaderMap,
+    /// Instant by which a non-streaming exchange, response body included, must finish. Ignored
+    /// when the request accepts `text/event-stream`, since those deliver incrementally.
+    pub(crate) deadline: Option<tokio::time::Instant>,
+    /// Longest gap allowed between chunks of a relayed SSE stream before it is cut off.
+    pub(crate) sse_idle_timeout: Option<std::time::Duration>,
+    /// Checked against the final upstream URL before any credential is attached.
+    pub(crate) allowed_hosts: &'a UpstreamHosts,
 }
 
 pub(crate) async fn forward(
     http: &reqwest::Client,
     upstream_base_url: &str,
     request: ForwardRequest<'_>,
+    header_strip: &HeaderStripLists,
+    metrics: Arc<GatewayMetrics>,
+    debug: bool,
+) -> Result<Response, GatewayError> {
+    let deadline = request
+        .deadline
+        .filter(|_| !request_accepts_event_stream(&request.parts.headers));
+    let exchange = forward_unbounded(
+        http,
+        upstream_base_url,
+        request,
+        header_strip,
+        metrics,
+        debug,
+    );
+    let Some(deadline) = deadline else {
+        return exchange.await;
+    };
+    // Dropping the exchange on expiry aborts both the upstream request and the buffered read.
+    tokio::time::timeout_at(deadline, exchange)
+        .await
+        .unwrap_or_else(|_| {
+            tracing::warn!("upstream exchange exceeded gateway.non_streaming_deadline_ms");
+            Err(GatewayError::gateway_timeout(
+                "upstream did not complete within the non-streaming deadline",
+            ))
+        })
+}
+
+async fn forward_unbounded(
+    http: &reqwest::Client,
+    upstream_base_url: &str,
+    request: ForwardRequest<'_>,
+    header_strip: &HeaderStripLists,
     metrics: Arc<GatewayMetrics>,
     debug: bool,
 ) -> Result<Response, GatewayError> {
     let ForwardRequest {
         parts,
-        body_bytes,
+        body,
         authorization,
         chatgpt_account_id,
+        identity_encoding_for_sse,
+        pinned_headers,
+        deadline: _,
+        sse_idle_timeout,
+        allowed_hosts,
     } = request;
 
     if debug {
@@ -114,8 +251,13 @@ pub(crate) async fn forward(
         .unwrap_or_else(|| parts.uri.path());
     let base = upstream_base_url.trim().trim_end_matches('/');
     let upstream_url = format!("{base}{path_and_query}");
+    if let Err(err) = allowed_hosts.check(&upstream_url) {
+        tracing::error!(error = %err, "refusing to send account credentials upstream");
+        return Err(GatewayError::bad_gateway(format!("{err:#}")));
+    }
 
-    let mut headers = header_policy::forward_request_headers(&parts.headers);
+    let mut headers = header_policy::forward_request_headers(&parts.headers, &header_strip.request);
+    header_policy::apply_pinned_headers(&mut headers, pinned_headers);
     let auth = HeaderValue::from_str(authorization).map_err(|_| {
         GatewayError::bad_gateway("failed to construct upstream authorization header")
     })?;
@@ -126,6 +268,12 @@ pub(crate) async fn forward(
         })?;
         let _ = headers.insert("ChatGPT-Account-ID", account_id);
     }
+    if wants_event_stream && identity_encoding_for_sse {
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static("identity"),
+        );
+    }
 
     if debug {
         tracing::info!("--- [DEBUG] Outgoing Request Headers ---");
@@ -138,14 +286,26 @@ pub(crate) async fn forward(
         .upstream_requests_total
         .fetch_add(1, Ordering::Relaxed);
     let upstream_start = Instant::now();
-    let response = match http
-        .request(parts.method, upstream_url)
-        .headers(headers)
-        .body(body_bytes)
-        .send()
-        .await
-    {
+    let upstream_request = http.request(parts.method, upstream_url).headers(headers);
+    let body_exceeded = Arc::new(AtomicBool::new(false));
+    let upstream_request = match body {
+        ForwardBody::Empty => upstream_request,
+        ForwardBody::Buffered(bytes) => upstream_request.body(bytes),
+        ForwardBody::Streaming(body) => upstream_request.body(reqwest::Body::wrap_stream(
+            limited_data_stream(body, MAX_REQUEST_BODY_BYTES, Arc::clone(&body_exceeded)),
+        )),
+    };
+    let response = match upstream_request.send().await {
         Ok(response) => response,
+        Err(_) if body_exceeded.load(Ordering::Relaxed) => {
+            tracing::warn!(
+                request_body_limit_bytes = MAX_REQUEST_BODY_BYTES,
+                "streamed request body exceeded the limit"
+            );
+            return Err(GatewayError::payload_too_large(format!(
+                "request body exceeds {MAX_REQUEST_BODY_BYTES} bytes"
+            )));
+        }
         Err(err) => {
             tracing::warn!(error = %err, "upstream request failed");
             metrics
@@ -162,12 +322,22 @@ pub(crate) async fn forward(
     record_upstream_latency_ms(&metrics, upstream_start.elapsed());
 
     let upstream_headers = response.headers().clone();
-    let headers = header_policy::forward_response_headers(&upstream_headers);
+    let headers =
+        header_policy::forward_response_headers(&upstream_headers, &header_strip.response);
+    // Upstream HTTP/2 trailers are not relayed: `bytes_stream` and `bytes` yield data frames only
+    // and skip trailer frames, and the `Trailer` announcement header is stripped by the header
+    // policy so downstream clients never wait for trailers that will not arrive.
     let body = if should_stream_upstream_response(wants_event_stream, status, &upstream_headers) {
         metrics.sse_streams_total.fetch_add(1, Ordering::Relaxed);
         metrics.sse_streams_inflight.fetch_add(1, Ordering::Relaxed);
         let guard = InflightGuard { metrics };
-        Body::from_stream(GuardedBytesStream::new(response.bytes_stream(), guard))
+        Body::from_stream(GuardedBytesStream::new(
+            response.bytes_stream(),
+            guard,
+            sse_idle_timeout,
+            upstream_start,
+            StreamEncoding::of(&upstream_headers),
+        ))
     } else {
         let response_body = response.bytes().await.map_err(|err| {
             tracing::warn!(error = %err, "upstream response body read failed");
@@ -194,6 +364,32 @@ pub(crate) async fn forward(
     Ok(out)
 }
 
+/// Relays `body` as it arrives and fails the stream once more than `limit` bytes have passed,
+/// raising `exceeded` so the caller can answer 413 rather than a generic upstream failure.
+fn limited_data_stream(
+    body: Body,
+    limit: usize,
+    exceeded: Arc<AtomicBool>,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Send + 'static {
+    body.into_data_stream().scan(0usize, move |total, chunk| {
+        let item = match chunk {
+            Ok(chunk) => {
+                *total = total.saturating_add(chunk.len());
+                if *total > limit {
+                    exceeded.store(true, Ordering::Relaxed);
+                    Err(std::io::Error::other(format!(
+                        "request body exceeds {limit} bytes"
+                    )))
+                } else {
+                    Ok(chunk)
+                }
+            }
+            Err(err) => Err(std::io::Error::other(err)),
+        };
+        futures::future::ready(Some(item))
+    })
+}
+
 fn should_stream_upstream_response(
     wants_event_stream: bool,
     status: reqwest::StatusCode,
@@ -261,15 +457,20 @@ fn record_upstream_status(metrics: &GatewayMetrics, status: reqwest::StatusCode)
 }
 
 fn record_upstream_latency_ms(metrics: &GatewayMetrics, elapsed: std::time::Duration) {
+    record_ms(
+        &metrics.upstream_latency_ms_sum,
+        &metrics.upstream_latency_ms_count,
+        elapsed,
+    );
+}
+
+/// Adds one sample to a `_ms_sum`/`_ms_count` pair.
+fn record_ms(sum: &AtomicI64, count: &AtomicI64, elapsed: std::time::Duration) {
     let Ok(ms) = i64::try_from(elapsed.as_millis()) else {
         return;
     };
-    metrics
-        .upstream_latency_ms_sum
-        .fetch_add(ms, Ordering::Relaxed);
-    metrics
-        .upstream_latency_ms_count
-        .fetch_add(1, Ordering::Relaxed);
+    sum.fetch_add(ms, Ordering::Relaxed);
+    count.fetch_add(1, Ordering::Relaxed);
 }
 
 struct InflightGuard {
@@ -284,87 +485,204 @@ impl Drop for InflightGuard {
     }
 }
 
+/// Relays an upstream SSE body while holding the in-flight guard.
+///
+/// When the client disconnects, hyper drops the response body and with it this stream. Dropping
+/// the inner reqwest stream drops the upstream response, which closes the HTTP/1 connection or
+/// resets the HTTP/2 stream, so the upstream stops generating instead of running to completion.
+///
+/// With an idle timeout, the stream ends early when upstream goes quiet for longer than the
+/// window; the timer restarts on every chunk, so slow streams that keep progressing are kept.
+/// The cut-off is reported like any other upstream failure, below.
+///
+/// An upstream read error is turned into a final `event: error` so the client can tell a failed
+/// stream from one that completed, instead of seeing the body just stop. A compressed body cannot
+/// take spliced-in bytes, so it fails with a body error instead, which aborts the response.
 struct GuardedBytesStream {
     inner: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
-    _guard: InflightGuard,
+    /// Set once upstream ends or errors; dropping before then means the client went away.
+    finished: bool,
+    /// Whether the bytes relayed so far end on an event boundary (a blank line).
+    at_event_boundary: bool,
+    idle: Option<IdleTimer>,
+    /// When the upstream request was sent; time to first chunk and stream duration count from it.
+    upstream_start: Instant,
+    first_chunk_seen: bool,
+    encoding: StreamEncoding,
+    guard: InflightGuard,
+}
+
+/// What the relayed body bytes are, which decides how a failed stream is terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamEncoding {
+    /// Plain SSE text, so a gateway-made `event: error` can be appended.
+    Identity,
+    /// Compressed by upstream (`Content-Encoding` other than `identity`).
+    Encoded,
+}
+
+impl StreamEncoding {
+    fn of(headers: &HeaderMap) -> Self {
+        let encoded = headers
+            .get_all(header::CONTENT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .any(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"));
+        if encoded {
+            Self::Encoded
+        } else {
+            Self::Identity
+        }
+    }
+}
+
+struct IdleTimer {
+    timeout: std::time::Duration,
+    sleep: Pin<Box<tokio::time::Sleep>>,
 }
 
 impl GuardedBytesStream {
     fn new(
         inner: impl Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static,
         guard: InflightGuard,
+        idle_timeout: Option<std::time::Duration>,
+        upstream_start: Instant,
+        encoding: StreamEncoding,
     ) -> Self {
         Self {
             inner: Box::pin(inner),
-            _guard: guard,
+            finished: false,
+            at_event_boundary: true,
+            idle: idle_timeout.map(|timeout| IdleTimer {
+                timeout,
+                sleep: Box::pin(tokio::time::sleep(timeout)),
+            }),
+            upstream_start,
+            first_chunk_seen: false,
+            encoding,
+            guard,
+        }
+    }
+
+    /// Ends the stream after a failure: with a final `event: error` for plain SSE, otherwise
+    /// with a body error, since bytes appended to a compressed body would corrupt it.
+    fn fail(&mut self, message: &str) -> Poll<Option<Result<Bytes, std::io::Error>>> {
+        self.finished = true;
+        match self.encoding {
+            StreamEncoding::Identity => {
+                Poll::Ready(Some(Ok(sse_error_event(self.at_event_boundary, message))))
+            }
+            StreamEncoding::Encoded => {
+                Poll::Ready(Some(Err(std::io::Error::other(message.to_string()))))
+            }
         }
     }
 }
 
 impl Stream for GuardedBytesStream {
-    type Item = Result<Bytes, reqwest::Error>;
+    type Item = Result<Bytes, std::io::Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
-        this.inner.as_mut().poll_next(cx)
+        if this.finished {
+            return Poll::Ready(None);
+        }
+        let poll = this.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll
+            && !chunk.is_empty()
+        {
+            this.at_event_boundary = chunk.ends_with(b"\n\n") || chunk.ends_with(b"\r\n\r\n");
+            if !this.first_chunk_seen {
+                this.first_chunk_seen = true;
+                let metrics = &this.guard.metrics;
+                let ttfb = this.upstream_start.elapsed();
+                record_ms(&metrics.sse_ttfb_ms_sum, &metrics.sse_ttfb_ms_count, ttfb);
+                metrics
+                    .sse_ttfb_ms_bucket
+                    .observe(&SSE_TTFB_BUCKETS_MS, ttfb);
+            }
+        }
+        match (&poll, this.idle.as_mut()) {
+            (Poll::Ready(Some(Err(err))), _) => {
+                this.guard
+                    .metrics
+                    .sse_stream_errors_total
+                    .fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    event = %"sse_stream_error",
+                    error = %err,
+                    encoding = ?this.encoding,
+                    "upstream SSE stream failed mid-response; ending the relayed stream"
+                );
+                let message = format!("upstream stream failed: {err}");
+                return this.fail(&message);
+            }
+            (Poll::Ready(None), _) => this.finished = true,
+            (Poll::Ready(Some(Ok(_))), Some(idle)) => {
+                let next = tokio::time::Instant::now() + idle.timeout;
+                idle.sleep.as_mut().reset(next);
+            }
+            (Poll::Pending, Some(idle)) if idle.sleep.as_mut().poll(cx).is_ready() => {
+                let idle_timeout_ms = idle.timeout.as_millis();
+                // Drop the upstream response now rather than when hyper drops the body.
+                this.inner = Box::pin(futures::stream::empty());
+                this.guard
+                    .metrics
+                    .sse_idle_timeouts_total
+                    .fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    event = %"sse_idle_timeout",
+                    idle_timeout_ms,
+                    "no SSE chunk from upstream within gateway.sse_idle_timeout_ms; ending stream"
+                );
+                let message = format!("upstream sent nothing for {idle_timeout_ms} ms");
+                return this.fail(&message);
+            }
+            _ => {}
+        }
+        poll.map(|item| item.map(|chunk| chunk.map_err(std::io::Error::other)))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::json_error_response;
-    use super::should_stream_upstream_response;
-    use axum::body;
-    use axum::http::HeaderMap;
-    use axum::http::StatusCode;
-    use axum::http::header;
-    use axum::http::header::HeaderValue;
-    use bytes::Bytes;
-    use pretty_assertions::assert_eq;
-
-    #[test]
-    fn json_error_response_contains_detail_body() {
-        let response = json_error_response(StatusCode::BAD_REQUEST, "bad request");
-
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-        assert_eq!(
-            response.headers().get(header::CONTENT_TYPE),
-            Some(&HeaderValue::from_static("application/json"))
-        );
-
-        let runtime = tokio::runtime::Runtime::new().expect("runtime");
-        let body = runtime.block_on(body::to_bytes(response.into_body(), usize::MAX));
-        let body = body.expect("body bytes");
-        assert_eq!(body, Bytes::from_static(br#"{"detail":"bad request"}"#));
-    }
+/// A terminal SSE `error` event. A blank line first ends any event upstream left half-sent, so
+/// this one is not merged into it.
+fn sse_error_event(at_event_boundary: bool, message: &str) -> Bytes {
+    let data = serde_json::json!({
+        "type": "error",
+        "error": {"type": "upstream_stream_error", "message": message},
+    });
+    let separator = if at_event_boundary { "" } else { "\n\n" };
+    Bytes::from(format!("{separator}event: error\ndata: {data}\n\n"))
+}
 
-    #[test]
-    fn streams_successful_event_stream_responses() {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            HeaderValue::from_static("text/event-stream; charset=utf-8"),
+impl Drop for GuardedBytesStream {
+    fn drop(&mut self) {
+        let metrics = &self.guard.metrics;
+        let duration = self.upstream_start.elapsed();
+        record_ms(
+            &metrics.sse_stream_duration_ms_sum,
+            &metrics.sse_stream_duration_ms_count,
+            duration,
         );
-
-        assert!(should_stream_upstream_response(
-            true,
-            reqwest::StatusCode::OK,
-            &headers
-        ));
-    }
-
-    #[test]
-    fn does_not_stream_error_json_responses() {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            HeaderValue::from_static("application/json"),
+        metrics
+            .sse_stream_duration_ms_bucket
+            .observe(&SSE_STREAM_DURATION_BUCKETS_MS, duration);
+        if self.finished {
+            return;
+        }
+        self.guard
+            .metrics
+            .sse_client_disconnects_total
+            .fetch_add(1, Ordering::Relaxed);
+        tracing::info!(
+            event = %"client_disconnect",
+            "client disconnected before the upstream stream finished; aborting upstream"
         );
-
-        assert!(!should_stream_upstream_response(
-            true,
-            reqwest::StatusCode::BAD_REQUEST,
-            &headers
-        ));
     }
 }
+
+#[cfg(test)]
+#[path = "proxy_tests.rs"]
+mod tests;
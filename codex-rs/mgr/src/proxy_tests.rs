@@ -0,0 +1,623 @@
+use super::BodyMode;
+use super::ForwardBody;
+use super::ForwardRequest;
+use super::GatewayError;
+use super::HeaderStripLists;
+use super::forward;
+use super::json_error_response;
+use super::limited_data_stream;
+use super::should_stream_upstream_response;
+use crate::config::UpstreamHosts;
+use crate::observability::GatewayMetrics;
+use axum::body;
+use axum::body::Body;
+use axum::http::HeaderMap;
+use axum::http::HeaderName;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::http::header::HeaderValue;
+use axum::http::request::Parts;
+use axum::response::Response;
+use bytes::Bytes;
+use futures::Stream;
+use futures::StreamExt;
+use pretty_assertions::assert_eq;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// Serves `upstream` on a loopback port for the rest of the test and returns its base URL.
+async fn spawn_upstream(upstream: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, upstream).await;
+    });
+    format!("http://{addr}")
+}
+
+/// An upstream whose `POST /responses` answers `text/event-stream` with the chunks from
+/// `chunks`, called once per request.
+fn event_stream_upstream<F, S>(chunks: F) -> axum::Router
+where
+    F: Fn() -> S + Clone + Send + Sync + 'static,
+    S: Stream<Item = Result<Bytes, std::io::Error>> + Send + 'static,
+{
+    axum::Router::new().route(
+        "/responses",
+        axum::routing::post(move || {
+            let chunks = chunks();
+            async move {
+                axum::http::Response::builder()
+                    .header(header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from_stream(chunks))
+                    .expect("upstream response")
+            }
+        }),
+    )
+}
+
+/// A client request plus the [`ForwardRequest`] settings the tests vary; the rest are fixed.
+struct TestRequest {
+    parts: Parts,
+    body: ForwardBody,
+    identity_encoding_for_sse: bool,
+    deadline: Option<tokio::time::Instant>,
+    sse_idle_timeout: Option<Duration>,
+    allowed_hosts: UpstreamHosts,
+}
+
+impl TestRequest {
+    fn new(method: &str, uri: &str) -> Self {
+        let (parts, ()) = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .expect("request")
+            .into_parts();
+        Self {
+            parts,
+            body: ForwardBody::Buffered(Bytes::new()),
+            identity_encoding_for_sse: true,
+            deadline: None,
+            sse_idle_timeout: None,
+            allowed_hosts: UpstreamHosts::Any,
+        }
+    }
+
+    fn header(mut self, name: HeaderName, value: &'static str) -> Self {
+        self.parts
+            .headers
+            .insert(name, HeaderValue::from_static(value));
+        self
+    }
+
+    /// Marks the request as an SSE request the way Codex sends them.
+    fn event_stream(self) -> Self {
+        self.header(header::ACCEPT, "text/event-stream")
+    }
+}
+
+async fn forward_to(
+    base_url: &str,
+    request: TestRequest,
+    metrics: Arc<GatewayMetrics>,
+) -> Result<Response, GatewayError> {
+    forward(
+        &reqwest::Client::new(),
+        base_url,
+        ForwardRequest {
+            parts: request.parts,
+            body: request.body,
+            authorization: "Bearer test",
+            chatgpt_account_id: None,
+            identity_encoding_for_sse: request.identity_encoding_for_sse,
+            pinned_headers: &HeaderMap::new(),
+            deadline: request.deadline,
+            sse_idle_timeout: request.sse_idle_timeout,
+            allowed_hosts: &request.allowed_hosts,
+        },
+        &HeaderStripLists::default(),
+        metrics,
+        false,
+    )
+    .await
+}
+
+#[test]
+fn json_error_response_contains_detail_body() {
+    let response = json_error_response(StatusCode::BAD_REQUEST, "bad request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE),
+        Some(&HeaderValue::from_static("application/json"))
+    );
+
+    let runtime = tokio::runtime::Runtime::new().expect("runtime");
+    let body = runtime.block_on(body::to_bytes(response.into_body(), usize::MAX));
+    let body = body.expect("body bytes");
+    assert_eq!(body, Bytes::from_static(br#"{"detail":"bad request"}"#));
+}
+
+#[test]
+fn streams_successful_event_stream_responses() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/event-stream; charset=utf-8"),
+    );
+
+    assert!(should_stream_upstream_response(
+        true,
+        reqwest::StatusCode::OK,
+        &headers
+    ));
+}
+
+#[test]
+fn does_not_stream_error_json_responses() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+
+    assert!(!should_stream_upstream_response(
+        true,
+        reqwest::StatusCode::BAD_REQUEST,
+        &headers
+    ));
+}
+
+#[tokio::test]
+async fn forwards_chunked_event_stream_without_content_length() {
+    let base_url = spawn_upstream(event_stream_upstream(|| {
+        futures::stream::iter([
+            Ok::<_, std::io::Error>(Bytes::from_static(b"data: one\n\n")),
+            Ok(Bytes::from_static(b"data: two\n\n")),
+        ])
+    }))
+    .await;
+
+    let response = forward_to(
+        &base_url,
+        TestRequest::new("POST", "/responses").event_stream(),
+        Arc::new(GatewayMetrics::default()),
+    )
+    .await
+    .expect("forward");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get(header::CONTENT_LENGTH), None);
+    let body = tokio::time::timeout(
+        Duration::from_secs(5),
+        body::to_bytes(response.into_body(), usize::MAX),
+    )
+    .await
+    .expect("streamed body should complete")
+    .expect("body bytes");
+    assert_eq!(body, Bytes::from_static(b"data: one\n\ndata: two\n\n"));
+}
+
+#[tokio::test]
+async fn dropping_streamed_body_counts_client_disconnect() {
+    let base_url = spawn_upstream(event_stream_upstream(|| {
+        futures::stream::iter([Ok::<_, std::io::Error>(Bytes::from_static(
+            b"data: one\n\n",
+        ))])
+        .chain(futures::stream::pending())
+    }))
+    .await;
+
+    let metrics = Arc::new(GatewayMetrics::default());
+    let response = forward_to(
+        &base_url,
+        TestRequest::new("POST", "/responses").event_stream(),
+        Arc::clone(&metrics),
+    )
+    .await
+    .expect("forward");
+
+    let mut stream = response.into_body().into_data_stream();
+    let first = tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .expect("first chunk")
+        .expect("stream item")
+        .expect("chunk bytes");
+    assert_eq!(first, Bytes::from_static(b"data: one\n\n"));
+    assert_eq!(metrics.sse_streams_total.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.sse_streams_inflight.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.sse_ttfb_ms_count.load(Ordering::Relaxed), 1);
+    assert!(
+        metrics
+            .render_prometheus()
+            .contains("codex_mgr_gateway_sse_ttfb_ms_bucket{le=\"30000\"} 1\n")
+    );
+    assert_eq!(
+        metrics.sse_stream_duration_ms_count.load(Ordering::Relaxed),
+        0
+    );
+
+    drop(stream);
+
+    assert_eq!(metrics.sse_streams_inflight.load(Ordering::Relaxed), 0);
+    assert_eq!(
+        metrics.sse_stream_duration_ms_count.load(Ordering::Relaxed),
+        1
+    );
+    assert_eq!(
+        metrics.sse_client_disconnects_total.load(Ordering::Relaxed),
+        1
+    );
+}
+
+#[tokio::test]
+async fn stalled_stream_ends_after_sse_idle_timeout() {
+    let base_url = spawn_upstream(event_stream_upstream(|| {
+        futures::stream::iter([Ok::<_, std::io::Error>(Bytes::from_static(
+            b"data: one\n\n",
+        ))])
+        .chain(futures::stream::pending())
+    }))
+    .await;
+
+    let metrics = Arc::new(GatewayMetrics::default());
+    let mut request = TestRequest::new("POST", "/responses").event_stream();
+    request.sse_idle_timeout = Some(Duration::from_millis(50));
+    let response = forward_to(&base_url, request, Arc::clone(&metrics))
+        .await
+        .expect("forward");
+
+    let mut stream = response.into_body().into_data_stream();
+    let first = tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .expect("first chunk")
+        .expect("stream item")
+        .expect("chunk bytes");
+    assert_eq!(first, Bytes::from_static(b"data: one\n\n"));
+    assert_eq!(metrics.sse_streams_total.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.sse_streams_inflight.load(Ordering::Relaxed), 1);
+
+    let error_event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .expect("stream ends instead of hanging")
+        .expect("an error event before the end")
+        .expect("chunk bytes");
+    let error_event = String::from_utf8(error_event.to_vec()).expect("utf-8 event");
+    assert!(error_event.starts_with("event: error\ndata: "));
+    assert!(error_event.contains("upstream sent nothing for 50 ms"));
+    assert!(stream.next().await.is_none());
+    assert_eq!(metrics.sse_idle_timeouts_total.load(Ordering::Relaxed), 1);
+
+    drop(stream);
+
+    assert_eq!(metrics.sse_streams_inflight.load(Ordering::Relaxed), 0);
+    assert_eq!(
+        metrics.sse_client_disconnects_total.load(Ordering::Relaxed),
+        0
+    );
+}
+
+#[tokio::test]
+async fn upstream_stream_error_ends_with_sse_error_event() {
+    let base_url = spawn_upstream(event_stream_upstream(|| {
+        futures::stream::iter([
+            Ok(Bytes::from_static(b"data: one\n\n")),
+            Err(std::io::Error::other("upstream went away")),
+        ])
+    }))
+    .await;
+
+    let metrics = Arc::new(GatewayMetrics::default());
+    let response = forward_to(
+        &base_url,
+        TestRequest::new("POST", "/responses").event_stream(),
+        Arc::clone(&metrics),
+    )
+    .await
+    .expect("forward");
+
+    let body = tokio::time::timeout(
+        Duration::from_secs(5),
+        body::to_bytes(response.into_body(), usize::MAX),
+    )
+    .await
+    .expect("streamed body should complete")
+    .expect("body bytes");
+    let body = String::from_utf8(body.to_vec()).expect("utf-8 body");
+    assert!(body.starts_with("data: one\n\nevent: error\ndata: "));
+    assert!(body.ends_with("\n\n"));
+    assert!(body.contains("upstream_stream_error"));
+    assert_eq!(metrics.sse_stream_errors_total.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.sse_streams_inflight.load(Ordering::Relaxed), 0);
+    assert_eq!(
+        metrics.sse_client_disconnects_total.load(Ordering::Relaxed),
+        0
+    );
+}
+
+#[tokio::test]
+async fn compressed_stream_error_aborts_the_body_instead_of_appending_an_event() {
+    let base_url = spawn_upstream(axum::Router::new().route(
+        "/responses",
+        axum::routing::post(|| async {
+            let chunks = futures::stream::iter([
+                Ok(Bytes::from_static(b"\x1f\x8bpartial")),
+                Err(std::io::Error::other("upstream went away")),
+            ]);
+            axum::http::Response::builder()
+                .header(header::CONTENT_TYPE, "text/event-stream")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .body(Body::from_stream(chunks))
+                .expect("upstream response")
+        }),
+    ))
+    .await;
+
+    let metrics = Arc::new(GatewayMetrics::default());
+    let response = forward_to(
+        &base_url,
+        TestRequest::new("POST", "/responses").event_stream(),
+        Arc::clone(&metrics),
+    )
+    .await
+    .expect("forward");
+
+    let mut stream = response.into_body().into_data_stream();
+    let mut relayed = Vec::new();
+    let err = loop {
+        match tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("stream ends instead of hanging")
+        {
+            Some(Ok(chunk)) => relayed.extend_from_slice(&chunk),
+            Some(Err(err)) => break err,
+            None => panic!("a failed compressed stream must not end cleanly"),
+        }
+    };
+    assert!(err.to_string().contains("upstream stream failed"));
+    assert_eq!(relayed, b"\x1f\x8bpartial");
+    assert_eq!(metrics.sse_stream_errors_total.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn forward_refuses_hosts_outside_the_allowlist() {
+    let metrics = Arc::new(GatewayMetrics::default());
+    let mut request = TestRequest::new("GET", "/models");
+    request.body = ForwardBody::Empty;
+    request.allowed_hosts = UpstreamHosts::Only(vec!["chatgpt.com".to_string()]);
+    let err = forward_to("http://127.0.0.1:9", request, Arc::clone(&metrics))
+        .await
+        .expect_err("host is not allowlisted");
+
+    assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+    assert!(err.detail().contains("allowed_upstream_hosts"));
+    assert_eq!(metrics.upstream_requests_total.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn forward_records_upstream_request_and_latency_metrics() {
+    let base_url = spawn_upstream(
+        axum::Router::new().route("/responses", axum::routing::post(|| async { "ok" })),
+    )
+    .await;
+
+    let metrics = Arc::new(GatewayMetrics::default());
+    let response = forward_to(
+        &base_url,
+        TestRequest::new("POST", "/responses"),
+        Arc::clone(&metrics),
+    )
+    .await
+    .expect("forward");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(metrics.upstream_requests_total.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.upstream_latency_ms_count.load(Ordering::Relaxed), 1);
+    // Total request duration belongs to the serve middleware, not `forward`.
+    assert_eq!(metrics.request_duration_ms_count.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn forward_classifies_upstream_statuses_and_transport_errors() {
+    let base_url = spawn_upstream(
+        axum::Router::new()
+            .route(
+                "/missing",
+                axum::routing::post(|| async { StatusCode::NOT_FOUND }),
+            )
+            .route(
+                "/broken",
+                axum::routing::post(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+            ),
+    )
+    .await;
+    let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind closed port");
+    let closed_addr = closed.local_addr().expect("closed addr");
+    drop(closed);
+
+    let metrics = Arc::new(GatewayMetrics::default());
+    for (base, path) in [
+        (base_url.clone(), "/missing"),
+        (base_url, "/broken"),
+        (format!("http://{closed_addr}"), "/missing"),
+    ] {
+        let _ = forward_to(&base, TestRequest::new("POST", path), Arc::clone(&metrics)).await;
+    }
+
+    assert_eq!(metrics.upstream_requests_total.load(Ordering::Relaxed), 3);
+    assert_eq!(
+        metrics.upstream_responses_4xx_total.load(Ordering::Relaxed),
+        1
+    );
+    assert_eq!(
+        metrics.upstream_responses_5xx_total.load(Ordering::Relaxed),
+        1
+    );
+    assert_eq!(metrics.upstream_errors_total.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.sse_streams_total.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn event_stream_requests_ask_upstream_for_identity_encoding() {
+    let base_url = spawn_upstream(axum::Router::new().route(
+        "/responses",
+        axum::routing::post(|headers: HeaderMap| async move {
+            headers
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("-")
+                .to_string()
+        }),
+    ))
+    .await;
+
+    for (accept, identity_encoding_for_sse, expected) in [
+        ("text/event-stream", true, "identity"),
+        ("text/event-stream", false, "gzip, br"),
+        ("application/json", true, "gzip, br"),
+    ] {
+        let mut request = TestRequest::new("POST", "/responses")
+            .header(header::ACCEPT, accept)
+            .header(header::ACCEPT_ENCODING, "gzip, br");
+        request.identity_encoding_for_sse = identity_encoding_for_sse;
+        let response = forward_to(&base_url, request, Arc::new(GatewayMetrics::default()))
+            .await
+            .expect("forward");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body bytes");
+        assert_eq!(body, Bytes::from(expected), "accept={accept}");
+    }
+}
+
+#[tokio::test]
+async fn non_streaming_deadline_returns_gateway_timeout() {
+    let base_url = spawn_upstream(axum::Router::new().route(
+        "/responses",
+        axum::routing::post(|| async {
+            tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+            "late"
+        }),
+    ))
+    .await;
+
+    let mut request = TestRequest::new("POST", "/responses");
+    request.deadline = Some(tokio::time::Instant::now() + std::time::Duration::from_millis(50));
+    let err = forward_to(&base_url, request, Arc::new(GatewayMetrics::default()))
+        .await
+        .expect_err("deadline should expire");
+
+    assert_eq!(err.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[test]
+fn body_mode_skips_buffering_for_bodyless_reads_and_streams_uploads() {
+    let headers = |pairs: &[(&'static str, &'static str)]| {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(*value));
+        }
+        map
+    };
+
+    assert_eq!(
+        BodyMode::for_request(&Method::GET, &headers(&[])),
+        BodyMode::Empty
+    );
+    assert_eq!(
+        BodyMode::for_request(&Method::HEAD, &headers(&[("content-length", "0")])),
+        BodyMode::Empty
+    );
+    assert_eq!(
+        BodyMode::for_request(&Method::DELETE, &headers(&[("content-length", "12")])),
+        BodyMode::Buffer
+    );
+    assert_eq!(
+        BodyMode::for_request(
+            &Method::POST,
+            &headers(&[("content-type", "application/json")])
+        ),
+        BodyMode::Buffer
+    );
+    assert_eq!(
+        BodyMode::for_request(
+            &Method::POST,
+            &headers(&[("content-type", "multipart/form-data; boundary=x")])
+        ),
+        BodyMode::Stream
+    );
+    assert_eq!(
+        BodyMode::for_request(
+            &Method::PUT,
+            &headers(&[("content-type", "application/octet-stream")])
+        ),
+        BodyMode::Stream
+    );
+}
+
+#[tokio::test]
+async fn empty_and_streaming_bodies_reach_upstream_unchanged() {
+    let base_url = spawn_upstream(axum::Router::new().route(
+        "/echo",
+        axum::routing::any(|headers: HeaderMap, body: Bytes| async move {
+            let length = headers
+                .get(header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("none")
+                .to_string();
+            format!("{length}:{}", String::from_utf8_lossy(&body))
+        }),
+    ))
+    .await;
+
+    let cases = [
+        ("GET", ForwardBody::Empty, "none:"),
+        (
+            "POST",
+            ForwardBody::Streaming(Body::from_stream(futures::stream::iter(vec![
+                Ok::<_, std::io::Error>(Bytes::from_static(b"up")),
+                Ok(Bytes::from_static(b"load")),
+            ]))),
+            "none:upload",
+        ),
+    ];
+    for (method, body, expected) in cases {
+        let mut request = TestRequest::new(method, "/echo");
+        request.body = body;
+        let response = forward_to(&base_url, request, Arc::new(GatewayMetrics::default()))
+            .await
+            .expect("forward");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body bytes");
+        assert_eq!(body, Bytes::from(expected), "method={method}");
+    }
+}
+
+#[tokio::test]
+async fn streamed_bodies_fail_once_they_pass_the_limit() {
+    let body = Body::from_stream(futures::stream::iter(vec![
+        Ok::<_, std::io::Error>(Bytes::from_static(b"abc")),
+        Ok(Bytes::from_static(b"de")),
+        Ok(Bytes::from_static(b"f")),
+    ]));
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let chunks: Vec<_> = limited_data_stream(body, 5, Arc::clone(&exceeded))
+        .collect()
+        .await;
+
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(chunks[0].as_ref().expect("first chunk"), "abc");
+    assert_eq!(chunks[1].as_ref().expect("at the limit"), "de");
+    assert!(chunks[2].is_err(), "past the limit");
+    assert!(exceeded.load(Ordering::Relaxed));
+}
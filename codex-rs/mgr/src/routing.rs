@@ -3,11 +3,20 @@ use axum::http::HeaderMap;
 use base64::Engine;
 use sha2::Digest;
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 
+use crate::config::ConversationIdConflict;
+use crate::config::RedisMode;
+use crate::observability::hash_opaque_id;
+use crate::redis_conn;
 use crate::usage;
 
 const STICKY_KEY_PREFIX: &str = "gw:sticky:";
+/// Request header naming the pool member a request must use, for pools with
+/// `allow_account_override`. Consumed by the gateway and never forwarded upstream.
+pub(crate) const ACCOUNT_OVERRIDE_HEADER: &str = "x-codex-mgr-account";
+const STICKY_SCAN_COUNT: i64 = 1000;
 
 #[derive(Debug, Clone)]
 pub(crate) struct RouteInfo {
@@ -20,23 +29,28 @@ pub(crate) struct RouteAccountArgs<'a> {
     pub(crate) account_pool_id: &'a str,
     pub(crate) labels: &'a [String],
     pub(crate) policy_key: Option<&'a str>,
+    pub(crate) sticky: bool,
     pub(crate) sticky_ttl_seconds: i64,
     pub(crate) conversation_id: Option<String>,
     pub(crate) non_sticky_key: &'a str,
+    /// Value of the pool's `tenant_header`, when configured and present on the request.
+    pub(crate) tenant: Option<&'a str>,
     pub(crate) usage_scores: &'a HashMap<String, usage::Score>,
 }
 
 pub(crate) async fn route_account(
-    conn: &mut redis::aio::ConnectionManager,
+    conn: &mut redis_conn::RedisConnection,
     args: RouteAccountArgs<'_>,
 ) -> anyhow::Result<RouteInfo> {
     let RouteAccountArgs {
         account_pool_id,
         labels,
         policy_key,
+        sticky,
         sticky_ttl_seconds,
         conversation_id,
         non_sticky_key,
+        tenant,
         usage_scores,
     } = args;
 
@@ -47,11 +61,40 @@ pub(crate) async fn route_account(
         anyhow::bail!("sticky_ttl_seconds must be > 0");
     }
 
-    let candidates = match conversation_id.as_deref() {
+    // Tenant affinity overrides both sticky bindings and usage ranking: the tenant alone picks
+    // the first choice, and the rest of its ring is the failover order.
+    if let Some(tenant) = tenant {
+        let candidates = tenant_candidates(account_pool_id, policy_key, tenant, labels)?;
+        tracing::debug!(
+            event = %"routing_tenant",
+            pool = %account_pool_id,
+            tenant_hash = %hash_opaque_id(tenant),
+            selected = %candidates[0],
+        );
+        return Ok(RouteInfo {
+            account_pool_id: account_pool_id.to_string(),
+            candidates,
+            conversation_id,
+        });
+    }
+
+    // Non-sticky pools route every request independently, even when a conversation id is present.
+    let sticky_conversation_id = conversation_id.as_deref().filter(|_| sticky);
+    let candidates = match sticky_conversation_id {
         Some(conversation_id) => {
-            let sticky_key = sticky_key(account_pool_id, conversation_id);
-            let existing: Option<String> =
-                redis::cmd("GET").arg(&sticky_key).query_async(conn).await?;
+            let sticky_key = sticky_key(conn.mode(), account_pool_id, policy_key, conversation_id);
+            let existing: Option<String> = conn
+                .query_idempotent(redis::cmd("GET").arg(&sticky_key))
+                .await?;
+            // Field values are only evaluated when DEBUG is enabled, so the hash costs nothing
+            // otherwise.
+            tracing::debug!(
+                event = %"routing_sticky_lookup",
+                pool = %account_pool_id,
+                conversation_hash = %hash_opaque_id(conversation_id),
+                existing = existing.as_deref().unwrap_or("-"),
+                existing_is_member = existing.as_ref().is_some_and(|e| labels.contains(e)),
+            );
             match existing {
                 Some(existing) if labels.iter().any(|l| l == &existing) => {
                     // Start with sticky, then append others in a deterministic order (relying on select_candidates logic)
@@ -83,6 +126,13 @@ pub(crate) async fn route_account(
                         .arg(sticky_ttl_seconds)
                         .query_async(conn)
                         .await?;
+                    tracing::debug!(
+                        event = %"routing_sticky_rebind",
+                        pool = %account_pool_id,
+                        conversation_hash = %hash_opaque_id(conversation_id),
+                        selected = %selected,
+                        "sticky account left the pool; rebound the conversation"
+                    );
                     list
                 }
                 None => {
@@ -104,12 +154,27 @@ pub(crate) async fn route_account(
                         .query_async(conn)
                         .await?;
 
+                    tracing::debug!(
+                        event = %"routing_sticky_bind",
+                        pool = %account_pool_id,
+                        conversation_hash = %hash_opaque_id(conversation_id),
+                        selected = %selected,
+                        nx_won = set.is_some(),
+                    );
                     if set.is_some() {
                         list
                     } else {
                         // Race condition: someone else set it. Read it back.
-                        let current: Option<String> =
-                            redis::cmd("GET").arg(&sticky_key).query_async(conn).await?;
+                        let current: Option<String> = conn
+                            .query_idempotent(redis::cmd("GET").arg(&sticky_key))
+                            .await?;
+                        tracing::debug!(
+                            event = %"routing_sticky_race",
+                            pool = %account_pool_id,
+                            conversation_hash = %hash_opaque_id(conversation_id),
+                            winner = current.as_deref().unwrap_or("-"),
+                            winner_is_member = current.as_ref().is_some_and(|c| labels.contains(c)),
+                        );
                         match current {
                             Some(c) if labels.contains(&c) => {
                                 let mut list = Vec::with_capacity(labels.len());
@@ -143,8 +208,123 @@ pub(crate) async fn route_account(
     })
 }
 
-pub(crate) fn extract_conversation_id(headers: &HeaderMap) -> Option<String> {
-    read_header(headers, "conversation_id").or_else(|| read_header(headers, "session_id"))
+/// Counts live sticky conversation bindings in `account_pool_id`, keyed by account label.
+///
+/// Bindings are shared by every gateway token issued for the pool.
+pub(crate) async fn sticky_bindings(
+    conn: &mut redis_conn::RedisConnection,
+    account_pool_id: &str,
+) -> anyhow::Result<BTreeMap<String, i64>> {
+    let pattern = format!("{}:*", sticky_key_prefix(conn.mode(), account_pool_id));
+    let keys = conn.scan_match(&pattern, STICKY_SCAN_COUNT).await?;
+    let mut counts = BTreeMap::new();
+    // In cluster mode the pool's hash tag keeps its keys in one slot, so each MGET stays
+    // single-slot.
+    for chunk in keys.chunks(usize::try_from(STICKY_SCAN_COUNT).unwrap_or(1000)) {
+        let labels: Vec<Option<String>> = redis::cmd("MGET").arg(chunk).query_async(conn).await?;
+        for label in labels.into_iter().flatten() {
+            *counts.entry(label).or_default() += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// A conversation's current sticky account and the seconds left before the binding lapses.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct StickyBinding {
+    pub(crate) label: String,
+    pub(crate) ttl_seconds: i64,
+}
+
+pub(crate) async fn read_sticky_binding(
+    conn: &mut redis_conn::RedisConnection,
+    sticky_key: &str,
+) -> anyhow::Result<Option<StickyBinding>> {
+    let (label, ttl_seconds): (Option<String>, i64) = redis::pipe()
+        .cmd("GET")
+        .arg(sticky_key)
+        .cmd("TTL")
+        .arg(sticky_key)
+        .query_async(conn)
+        .await?;
+    Ok(label.map(|label| StickyBinding { label, ttl_seconds }))
+}
+
+/// Pins `sticky_key` to `label`, replacing any existing binding.
+pub(crate) async fn write_sticky_binding(
+    conn: &mut redis_conn::RedisConnection,
+    sticky_key: &str,
+    label: &str,
+    ttl_seconds: i64,
+) -> anyhow::Result<()> {
+    if ttl_seconds <= 0 {
+        anyhow::bail!("sticky ttl must be > 0");
+    }
+    let _: () = redis::cmd("SET")
+        .arg(sticky_key)
+        .arg(label)
+        .arg("EX")
+        .arg(ttl_seconds)
+        .query_async(conn)
+        .await?;
+    Ok(())
+}
+
+/// Releases a binding so the next request re-selects an account; returns whether one existed.
+pub(crate) async fn clear_sticky_binding(
+    conn: &mut redis_conn::RedisConnection,
+    sticky_key: &str,
+) -> anyhow::Result<bool> {
+    let removed: i64 = redis::cmd("DEL").arg(sticky_key).query_async(conn).await?;
+    Ok(removed > 0)
+}
+
+/// Deterministically places `split_key` in the canary bucket for `canary_percent` (0-100) of
+/// keys, so a conversation keeps hitting the same upstream.
+pub(crate) fn is_canary(split_key: &str, canary_percent: i64) -> bool {
+    let digest = sha256_bytes(split_key.as_bytes());
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    let bucket = u64::from_be_bytes(prefix) % 100;
+    u64::try_from(canary_percent).is_ok_and(|percent| bucket < percent)
+}
+
+pub(crate) fn extract_account_override(headers: &HeaderMap) -> Option<String> {
+    read_header(headers, ACCOUNT_OVERRIDE_HEADER)
+}
+
+/// Both ids a request sent, when they differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConversationIdMismatch {
+    pub(crate) conversation_id: String,
+    pub(crate) session_id: String,
+}
+
+/// The id sticky routing keys on: `conversation_id`, falling back to `session_id`. When both are
+/// sent and differ, `conflict` picks one, or (`strict`) the mismatch is returned as an error.
+pub(crate) fn extract_conversation_id(
+    headers: &HeaderMap,
+    conflict: ConversationIdConflict,
+) -> Result<Option<String>, ConversationIdMismatch> {
+    let Some(mismatch) = conversation_id_mismatch(headers) else {
+        return Ok(
+            read_header(headers, "conversation_id").or_else(|| read_header(headers, "session_id"))
+        );
+    };
+    match conflict {
+        ConversationIdConflict::Strict => Err(mismatch),
+        ConversationIdConflict::PreferConversation => Ok(Some(mismatch.conversation_id)),
+        ConversationIdConflict::PreferSession => Ok(Some(mismatch.session_id)),
+    }
+}
+
+pub(crate) fn conversation_id_mismatch(headers: &HeaderMap) -> Option<ConversationIdMismatch> {
+    let conversation_id = read_header(headers, "conversation_id")?;
+    let session_id = read_header(headers, "session_id")?;
+    (conversation_id != session_id).then_some(ConversationIdMismatch {
+        conversation_id,
+        session_id,
+    })
 }
 
 fn read_header(headers: &HeaderMap, name: &'static str) -> Option<String> {
@@ -156,10 +336,39 @@ fn read_header(headers: &HeaderMap, name: &'static str) -> Option<String> {
         .map(str::to_string)
 }
 
-fn sticky_key(account_pool_id: &str, conversation_id: &str) -> String {
-    let digest = sha256_bytes(conversation_id.as_bytes());
+/// Folds the pool's policy_key into the sticky key so rotating it starts fresh bindings. Pools
+/// without a policy_key keep the original conversation-only digest.
+pub(crate) fn sticky_key(
+    redis_mode: RedisMode,
+    account_pool_id: &str,
+    policy_key: Option<&str>,
+    conversation_id: &str,
+) -> String {
+    let digest: [u8; 32] = match policy_key {
+        Some(policy_key) => {
+            let mut hasher = sha2::Sha256::new();
+            hasher.update(policy_key.as_bytes());
+            hasher.update([0]);
+            hasher.update(conversation_id.as_bytes());
+            hasher.finalize().into()
+        }
+        None => sha256_bytes(conversation_id.as_bytes()),
+    };
     let encoded = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest);
-    format!("{STICKY_KEY_PREFIX}{account_pool_id}:{encoded}")
+    format!(
+        "{}:{encoded}",
+        sticky_key_prefix(redis_mode, account_pool_id)
+    )
+}
+
+/// `gw:sticky:<pool>`, or `gw:sticky:{<pool>}` against a cluster. The braces are a Redis Cluster
+/// hash tag: every binding in a pool hashes to the same slot, so pool-wide reads work there.
+/// Single and sentinel deployments keep the untagged keys they have always used.
+fn sticky_key_prefix(redis_mode: RedisMode, account_pool_id: &str) -> String {
+    match redis_mode {
+        RedisMode::Cluster => format!("{STICKY_KEY_PREFIX}{{{account_pool_id}}}"),
+        RedisMode::Single | RedisMode::Sentinel => format!("{STICKY_KEY_PREFIX}{account_pool_id}"),
+    }
 }
 
 fn select_candidates(
@@ -237,6 +446,92 @@ fn select_candidates(
     Ok(candidates)
 }
 
+/// Ring order for `tenant` within a pool; the same tenant always gets the same order.
+fn tenant_candidates(
+    account_pool_id: &str,
+    policy_key: Option<&str>,
+    tenant: &str,
+    labels: &[String],
+) -> anyhow::Result<Vec<String>> {
+    select_candidates_ring(
+        account_pool_id,
+        policy_key,
+        &format!("tenant:{tenant}"),
+        labels,
+    )
+}
+
+/// Counts which account `samples` synthetic conversation keys land on first when usage scores
+/// are unavailable, to show how a policy_key spreads a pool.
+pub(crate) fn ring_distribution(
+    account_pool_id: &str,
+    policy_key: Option<&str>,
+    labels: &[String],
+    samples: i64,
+) -> anyhow::Result<BTreeMap<String, i64>> {
+    let mut counts: BTreeMap<String, i64> = labels.iter().map(|l| (l.clone(), 0)).collect();
+    for i in 0..samples {
+        let key = format!("sample-{i}");
+        let ring = select_candidates_ring(account_pool_id, policy_key, &key, labels)?;
+        if let Some(first) = ring.into_iter().next() {
+            *counts.entry(first).or_default() += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Counts how many of `samples` synthetic conversation keys change first choice when the pool's
+/// policy_key goes from `old` to `new`, assuming hash-ring routing.
+pub(crate) fn ring_moves(
+    account_pool_id: &str,
+    old: Option<&str>,
+    new: Option<&str>,
+    labels: &[String],
+    samples: i64,
+) -> anyhow::Result<i64> {
+    let mut moved = 0;
+    for i in 0..samples {
+        let key = format!("sample-{i}");
+        let before = select_candidates_ring(account_pool_id, old, &key, labels)?;
+        let after = select_candidates_ring(account_pool_id, new, &key, labels)?;
+        if before.first() != after.first() {
+            moved += 1;
+        }
+    }
+    Ok(moved)
+}
+
+/// How far a first-choice distribution strays from an even split across its labels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct UniformDeviation {
+    /// Pearson's chi-square statistic, with `labels - 1` degrees of freedom.
+    pub(crate) chi_square: f64,
+    /// Largest `|count - expected| / expected` across labels, as a percentage.
+    pub(crate) max_deviation_percent: f64,
+}
+
+pub(crate) fn uniform_deviation(counts: &BTreeMap<String, i64>) -> UniformDeviation {
+    let total: i64 = counts.values().sum();
+    if counts.is_empty() || total <= 0 {
+        return UniformDeviation {
+            chi_square: 0.0,
+            max_deviation_percent: 0.0,
+        };
+    }
+    let expected = total as f64 / counts.len() as f64;
+    let mut chi_square = 0.0;
+    let mut max_deviation_percent: f64 = 0.0;
+    for count in counts.values() {
+        let delta = *count as f64 - expected;
+        chi_square += delta * delta / expected;
+        max_deviation_percent = max_deviation_percent.max(delta.abs() * 100.0 / expected);
+    }
+    UniformDeviation {
+        chi_square,
+        max_deviation_percent,
+    }
+}
+
 fn select_candidates_ring(
     account_pool_id: &str,
     policy_key: Option<&str>,
@@ -379,4 +674,139 @@ mod tests {
         assert_eq!(candidates[0], "a");
         assert_eq!(candidates[1], "b");
     }
+
+    #[test]
+    fn extract_conversation_id_resolves_disagreeing_headers_by_policy() {
+        let mut headers = HeaderMap::new();
+        headers.insert("session_id", "s1".parse().expect("header value"));
+        assert_eq!(
+            extract_conversation_id(&headers, ConversationIdConflict::Strict),
+            Ok(Some("s1".to_string()))
+        );
+
+        headers.insert("conversation_id", "c1".parse().expect("header value"));
+        assert_eq!(
+            extract_conversation_id(&headers, ConversationIdConflict::PreferConversation),
+            Ok(Some("c1".to_string()))
+        );
+        assert_eq!(
+            extract_conversation_id(&headers, ConversationIdConflict::PreferSession),
+            Ok(Some("s1".to_string()))
+        );
+        assert_eq!(
+            extract_conversation_id(&headers, ConversationIdConflict::Strict),
+            Err(ConversationIdMismatch {
+                conversation_id: "c1".to_string(),
+                session_id: "s1".to_string(),
+            })
+        );
+
+        headers.insert("session_id", "c1".parse().expect("header value"));
+        assert_eq!(conversation_id_mismatch(&headers), None);
+    }
+
+    #[test]
+    fn sticky_key_changes_with_policy_key() {
+        let legacy = sticky_key(RedisMode::Single, "pool", None, "conv");
+        let v1 = sticky_key(RedisMode::Single, "pool", Some("v1"), "conv");
+        let v2 = sticky_key(RedisMode::Single, "pool", Some("v2"), "conv");
+
+        assert_eq!(
+            legacy,
+            format!(
+                "{STICKY_KEY_PREFIX}pool:{}",
+                base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(sha256_bytes(b"conv"))
+            )
+        );
+        assert_ne!(legacy, v1);
+        assert_ne!(v1, v2);
+        assert_eq!(
+            v1,
+            sticky_key(RedisMode::Single, "pool", Some("v1"), "conv")
+        );
+    }
+
+    #[test]
+    fn sticky_key_hash_tags_the_pool_only_in_cluster_mode() {
+        let digest = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(sha256_bytes(b"conv"));
+
+        assert_eq!(
+            sticky_key(RedisMode::Cluster, "pool", None, "conv"),
+            format!("{STICKY_KEY_PREFIX}{{pool}}:{digest}")
+        );
+        assert_eq!(
+            sticky_key(RedisMode::Sentinel, "pool", None, "conv"),
+            sticky_key(RedisMode::Single, "pool", None, "conv")
+        );
+    }
+
+    #[test]
+    fn ring_moves_is_zero_for_the_same_key_and_nonzero_on_rotation() {
+        let labels: Vec<String> = ["a", "b", "c"].iter().map(ToString::to_string).collect();
+
+        assert_eq!(
+            ring_moves("pool", Some("v1"), Some("v1"), &labels, 100).expect("moves"),
+            0
+        );
+        let moved = ring_moves("pool", Some("v1"), Some("v2"), &labels, 300).expect("moves");
+        assert!(moved > 0 && moved < 300, "moved {moved}");
+    }
+
+    #[test]
+    fn tenant_candidates_are_stable_per_tenant() {
+        let labels: Vec<String> = ["a", "b", "c", "d"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let first = tenant_candidates("pool", None, "acme", &labels).expect("ring");
+
+        assert_eq!(
+            tenant_candidates("pool", None, "acme", &labels).expect("ring"),
+            first
+        );
+        assert_eq!(first.len(), labels.len());
+        let spread: std::collections::BTreeSet<String> = (0..64)
+            .map(|i| {
+                tenant_candidates("pool", None, &format!("tenant-{i}"), &labels).expect("ring")[0]
+                    .clone()
+            })
+            .collect();
+        assert_eq!(spread.len(), labels.len());
+    }
+
+    #[test]
+    fn is_canary_respects_percent_bounds_and_is_stable() {
+        assert!(!is_canary("conv", 0));
+        assert!(is_canary("conv", 100));
+        assert_eq!(is_canary("conv", 37), is_canary("conv", 37));
+
+        let canary = (0..1000)
+            .filter(|i| is_canary(&format!("conv-{i}"), 10))
+            .count();
+        assert!((50..150).contains(&canary), "canary count {canary}");
+    }
+
+    #[test]
+    fn uniform_deviation_measures_spread_from_even_split() {
+        let counts = |pairs: &[(&str, i64)]| -> BTreeMap<String, i64> {
+            pairs.iter().map(|(l, c)| (l.to_string(), *c)).collect()
+        };
+        let even = uniform_deviation(&counts(&[("a", 50), ("b", 50)]));
+        assert_eq!(even.chi_square, 0.0);
+        assert_eq!(even.max_deviation_percent, 0.0);
+
+        let skewed = uniform_deviation(&counts(&[("a", 60), ("b", 40)]));
+        assert_eq!(skewed.chi_square, 4.0);
+        assert_eq!(skewed.max_deviation_percent, 20.0);
+    }
+
+    #[test]
+    fn ring_distribution_covers_every_label() {
+        let labels = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let counts = ring_distribution("pool", Some("v1"), &labels, 300).unwrap();
+
+        assert_eq!(counts.values().sum::<i64>(), 300);
+        assert_eq!(counts.keys().cloned().collect::<Vec<_>>(), labels);
+        assert!(counts.values().all(|count| *count > 0));
+    }
 }
@@ -1,20 +1,56 @@
 use anyhow::Context;
+use std::collections::BTreeSet;
 use std::ffi::OsString;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
+use crate::accounts;
+use crate::aliases;
+use crate::config;
+use crate::exit_code::ErrorCategory;
 use crate::label::validate_label;
+use crate::layout::ProjectTrust;
 use crate::layout::ensure_shared_config;
 use crate::layout::ensure_shared_layout;
+use crate::layout::inspect_account_permissions;
+use crate::layout::inspect_shared_layout;
+use crate::selection;
 use crate::upstream;
 use crate::usage;
 
 pub(crate) struct RunOptions {
     pub(crate) auto: bool,
     pub(crate) label: Option<String>,
+    pub(crate) select: SelectOptions,
+    pub(crate) trust: ProjectTrust,
+    pub(crate) upstream_args: Vec<OsString>,
+}
+
+/// Auto-selection settings shared by `run` and `pick`.
+pub(crate) struct SelectOptions {
+    /// Restrict auto selection to the labels of this `[pools.<id>]` entry.
+    pub(crate) pool: Option<String>,
     pub(crate) refresh: bool,
     pub(crate) no_cache: bool,
-    pub(crate) upstream_args: Vec<OsString>,
+    /// Select from cached usage only, never fetching; see [`usage::select_cached_label`].
+    pub(crate) no_usage_fetch: bool,
+    pub(crate) concurrency: i64,
+    /// Fall back to expired cached usage when every usage fetch fails.
+    pub(crate) allow_stale: bool,
+    /// Upper bound on the whole auto-selection phase; slower fetches are abandoned.
+    pub(crate) select_timeout: Option<Duration>,
+    /// Print the per-account reasons as JSON when auto selection finds no usable account.
+    pub(crate) json: bool,
+}
+
+/// What `pick` prints for the selected account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum PickOutput {
+    /// The account home, suitable for `CODEX_HOME`.
+    Home,
+    /// The account label.
+    Label,
 }
 
 pub(crate) async fn run(
@@ -22,6 +58,7 @@ pub(crate) async fn run(
     shared_root: &Path,
     accounts_root: &Path,
     state_root: &Path,
+    config_path: &Path,
     args: RunOptions,
 ) -> anyhow::Result<()> {
     let codex = upstream::resolve_codex_binary(codex_path);
@@ -35,16 +72,16 @@ pub(crate) async fn run(
         anyhow::bail!("upstream `codex login` is disabled; use `codex-mgr login --label ...`");
     }
 
-    ensure_shared_config(shared_root).context("ensure shared config")?;
+    ensure_shared_config(shared_root, args.trust).context("ensure shared config")?;
 
     let pinned = args.label.is_some();
     let label = if args.auto || !pinned {
-        usage::select_best_label(
+        select_auto(
             shared_root,
             accounts_root,
             state_root,
-            args.refresh,
-            args.no_cache,
+            config_path,
+            args.select,
         )
         .await?
     } else {
@@ -52,10 +89,42 @@ pub(crate) async fn run(
             .label
             .context("label is required unless --auto is used")?;
         validate_label(&label)?;
-        label
+        aliases::resolve(state_root, label)
     };
 
     let account_home = accounts_root.join(&label);
+    match inspect_shared_layout(&account_home, shared_root) {
+        Ok(drift) => {
+            for drift in drift {
+                // Not `detail`: for files it previews their contents, which may be secrets.
+                tracing::warn!(
+                    %label,
+                    entry = drift.entry,
+                    found = drift.found,
+                    "account shared entry drifted from shared_root"
+                );
+            }
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, %label, "failed to inspect shared layout");
+        }
+    }
+    match inspect_account_permissions(&account_home) {
+        Ok(loose) => {
+            for loose in loose {
+                tracing::warn!(
+                    %label,
+                    entry = loose.entry,
+                    mode = %loose.mode,
+                    expected = %loose.expected,
+                    "account home is accessible to group or other users; run `chmod -R go-rwx` on it"
+                );
+            }
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, %label, "failed to inspect account permissions");
+        }
+    }
     ensure_shared_layout(&account_home, shared_root).context("ensure shared layout")?;
 
     if upstream::is_logout_command(&args.upstream_args) && !pinned {
@@ -64,6 +133,124 @@ pub(crate) async fn run(
         );
     }
 
-    upstream::exec_upstream(codex, Some(account_home), args.upstream_args)?;
+    let account_env = upstream::load_account_env(&account_home)?;
+    if !account_env.is_empty() {
+        let keys = account_env
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        tracing::debug!(%label, keys = %keys, "applying per-account environment overrides");
+    }
+    upstream::exec_upstream(
+        codex,
+        Some(upstream::UpstreamAccount {
+            home: account_home,
+            label,
+            env: account_env,
+        }),
+        args.upstream_args,
+    )?;
     Ok(())
 }
+
+/// Selects an account and prints only its home or label to stdout; diagnostics go to stderr.
+pub(crate) async fn pick(
+    shared_root: &Path,
+    accounts_root: &Path,
+    state_root: &Path,
+    config_path: &Path,
+    options: SelectOptions,
+    output: PickOutput,
+    trust: ProjectTrust,
+) -> anyhow::Result<()> {
+    ensure_shared_config(shared_root, trust).context("ensure shared config")?;
+    let label = select_auto(shared_root, accounts_root, state_root, config_path, options).await?;
+    let account_home = accounts_root.join(&label);
+    // The caller runs codex against this home directly, so it must be usable as-is.
+    ensure_shared_layout(&account_home, shared_root).context("ensure shared layout")?;
+    match output {
+        PickOutput::Home => println!("{}", account_home.display()),
+        PickOutput::Label => println!("{label}"),
+    }
+    Ok(())
+}
+
+/// Runs auto selection, printing the per-account reasons to stderr with `json` when none is
+/// usable, so stdout only ever carries the selection.
+async fn select_auto(
+    shared_root: &Path,
+    accounts_root: &Path,
+    state_root: &Path,
+    config_path: &Path,
+    options: SelectOptions,
+) -> anyhow::Result<String> {
+    let selection_config = config::selection_config(config_path)?;
+    let strategy = selection::resolve(&selection_config)?;
+    let only_labels = match &options.pool {
+        Some(pool_id) => Some(pool_candidates(accounts_root, config_path, pool_id)?),
+        None => None,
+    };
+    let selected = if options.no_usage_fetch {
+        usage::select_cached_label(
+            accounts_root,
+            state_root,
+            only_labels.as_ref(),
+            strategy.as_ref(),
+            selection_config.no_fetch_fallback,
+        )?
+    } else {
+        usage::select_best_label(
+            shared_root,
+            accounts_root,
+            state_root,
+            config_path,
+            usage::ScanOptions {
+                force_refresh: options.refresh,
+                ignore_cache: options.no_cache,
+                concurrency: options.concurrency,
+                allow_stale: options.allow_stale,
+                deadline: options
+                    .select_timeout
+                    .map(|timeout| tokio::time::Instant::now() + timeout),
+                only_labels,
+                ..usage::ScanOptions::default()
+            },
+            strategy.as_ref(),
+        )
+        .await?
+    };
+    match selected {
+        Ok(label) => Ok(label),
+        Err(unusable) => {
+            if options.json {
+                eprintln!("{}", serde_json::to_string_pretty(&unusable)?);
+            }
+            Err(unusable.into_error())
+        }
+    }
+}
+
+/// Labels of `[pools.<pool_id>]` that have an account home, for `run --pool`.
+fn pool_candidates(
+    accounts_root: &Path,
+    config_path: &Path,
+    pool_id: &str,
+) -> anyhow::Result<BTreeSet<String>> {
+    let root = config::load_value_optional(config_path)?;
+    let pool = config::extract_pools(&root)?
+        .remove(pool_id)
+        .with_context(|| format!("pool {pool_id:?} does not exist"))
+        .map_err(|err| ErrorCategory::Config.wrap(err))?;
+    let members: BTreeSet<String> = pool.labels.into_iter().collect();
+    let candidates: BTreeSet<String> =
+        usage::restrict_labels(accounts::list_labels(accounts_root)?, Some(&members))
+            .into_iter()
+            .collect();
+    if candidates.is_empty() {
+        return Err(ErrorCategory::NoAccounts.wrap(anyhow::anyhow!(
+            "pool {pool_id:?} has no logged-in accounts; check its labels with `codex-mgr pools show {pool_id}`"
+        )));
+    }
+    Ok(candidates)
+}
@@ -0,0 +1,273 @@
+use rand::Rng;
+
+use crate::config::SelectionConfig;
+use crate::config::SelectionPriority;
+use crate::usage::Score;
+
+pub(crate) const DEFAULT_STRATEGY: &str = "most_remaining";
+
+/// Chooses which account `run --auto` launches, given the usage score of every usable label.
+pub(crate) trait SelectionStrategy: Send + Sync {
+    /// `scores` is sorted by label. Returns `None` when no candidate should be used.
+    fn pick(&self, scores: &[(String, Score)]) -> Option<String>;
+}
+
+/// Prefers accounts with a known window in the `priority` order and the most headroom in it; by
+/// default a known weekly window, then the most weekly headroom, then a known five-hour window
+/// and its headroom. Ties go to the lexicographically smallest label.
+#[derive(Default)]
+pub(crate) struct MostRemaining {
+    pub(crate) priority: SelectionPriority,
+}
+
+impl MostRemaining {
+    fn key(&self, s: &Score) -> (i32, f64, i32, f64) {
+        match self.priority {
+            SelectionPriority::Weekly => (
+                i32::from(s.weekly_present),
+                s.weekly_remaining,
+                i32::from(s.five_present),
+                s.five_remaining,
+            ),
+            SelectionPriority::FiveHour => (
+                i32::from(s.five_present),
+                s.five_remaining,
+                i32::from(s.weekly_present),
+                s.weekly_remaining,
+            ),
+            // Every score has at least one known window, so the binding one leads; the weekly
+            // window breaks ties.
+            SelectionPriority::Balanced => (
+                0,
+                binding_remaining(s),
+                i32::from(s.weekly_present),
+                s.weekly_remaining,
+            ),
+        }
+    }
+
+    /// The headroom [`WeightedSpread`] weights by under this priority.
+    fn headroom(&self, s: &Score) -> f64 {
+        match self.priority {
+            SelectionPriority::Weekly if s.weekly_present => s.weekly_remaining,
+            SelectionPriority::Weekly => s.five_remaining,
+            SelectionPriority::FiveHour if s.five_present => s.five_remaining,
+            SelectionPriority::FiveHour => s.weekly_remaining,
+            SelectionPriority::Balanced => binding_remaining(s),
+        }
+    }
+
+    /// Accounts `WeightedSpread` may draw from alongside `best`: the same known-window tier.
+    fn same_tier(&self, s: &Score, best: &Score) -> bool {
+        match self.priority {
+            SelectionPriority::Weekly => s.weekly_present == best.weekly_present,
+            SelectionPriority::FiveHour => s.five_present == best.five_present,
+            SelectionPriority::Balanced => true,
+        }
+    }
+}
+
+/// The smaller remaining percentage among the known windows.
+fn binding_remaining(s: &Score) -> f64 {
+    match (s.weekly_present, s.five_present) {
+        (true, true) => s.weekly_remaining.min(s.five_remaining),
+        (true, false) => s.weekly_remaining,
+        _ => s.five_remaining,
+    }
+}
+
+impl SelectionStrategy for MostRemaining {
+    fn pick(&self, scores: &[(String, Score)]) -> Option<String> {
+        let key = |s: &Score| self.key(s);
+
+        let mut best: Option<(&str, Score)> = None;
+        for (label, score) in scores {
+            best = match best {
+                Some((best_label, best_score)) => {
+                    let best_key = key(&best_score);
+                    let new_key = key(score);
+                    if new_key > best_key || (new_key == best_key && label.as_str() < best_label) {
+                        Some((label.as_str(), *score))
+                    } else {
+                        Some((best_label, best_score))
+                    }
+                }
+                None => Some((label.as_str(), *score)),
+            };
+        }
+        best.map(|(label, _)| label.to_string())
+    }
+}
+
+/// [`MostRemaining`], except that every account within `spread_percent` of the best account's
+/// headroom is a candidate and one is drawn at random, weighted by headroom. This spreads
+/// depletion across near-equal accounts instead of draining the best one first.
+///
+/// Headroom follows `ranking.priority`: by default weekly remaining when the weekly window is
+/// known, otherwise five-hour remaining, and only accounts in the same weekly-known tier as the
+/// best account are considered.
+pub(crate) struct WeightedSpread {
+    pub(crate) spread_percent: f64,
+    pub(crate) ranking: MostRemaining,
+}
+
+impl WeightedSpread {
+    /// `roll` is a uniform sample from `[0, 1)`.
+    fn pick_with_roll(&self, scores: &[(String, Score)], roll: f64) -> Option<String> {
+        let best_label = self.ranking.pick(scores)?;
+        let (_, best) = scores.iter().find(|(label, _)| *label == best_label)?;
+        let headroom = |s: &Score| self.ranking.headroom(s);
+        let floor = headroom(best) * (1.0 - self.spread_percent / 100.0);
+        let candidates: Vec<(&str, f64)> = scores
+            .iter()
+            .filter(|(_, s)| self.ranking.same_tier(s, best))
+            .map(|(label, s)| (label.as_str(), headroom(s)))
+            .filter(|(_, h)| *h > 0.0 && *h >= floor)
+            .collect();
+        let total: f64 = candidates.iter().map(|(_, h)| h).sum();
+        if total <= 0.0 {
+            return Some(best_label);
+        }
+
+        let mut target = roll * total;
+        for (label, h) in &candidates {
+            if target < *h {
+                return Some((*label).to_string());
+            }
+            target -= h;
+        }
+        Some(best_label)
+    }
+}
+
+impl SelectionStrategy for WeightedSpread {
+    fn pick(&self, scores: &[(String, Score)]) -> Option<String> {
+        self.pick_with_roll(scores, rand::rng().random::<f64>())
+    }
+}
+
+/// Resolves a strategy from `[selection]`: `strategy` names it (default [`DEFAULT_STRATEGY`]),
+/// a positive `spread_percent` turns `most_remaining` into [`WeightedSpread`], and `priority`
+/// picks the window both rank by.
+pub(crate) fn resolve(config: &SelectionConfig) -> anyhow::Result<Box<dyn SelectionStrategy>> {
+    let spread_percent = config.spread_percent.filter(|spread| *spread > 0.0);
+    let ranking = MostRemaining {
+        priority: config.priority,
+    };
+    match config.strategy.as_deref().unwrap_or(DEFAULT_STRATEGY) {
+        "most_remaining" => match spread_percent {
+            Some(spread_percent) => Ok(Box::new(WeightedSpread {
+                spread_percent,
+                ranking,
+            })),
+            None => Ok(Box::new(ranking)),
+        },
+        other => anyhow::bail!(
+            "unknown selection strategy {other:?}; expected one of: {DEFAULT_STRATEGY}"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::UsageSnapshot;
+    use crate::state::WindowSnapshot;
+    use crate::usage::usage_score;
+    use pretty_assertions::assert_eq;
+
+    fn window(remaining_percent: f64) -> WindowSnapshot {
+        WindowSnapshot {
+            used_percent: 100.0 - remaining_percent,
+            remaining_percent,
+            window_minutes: None,
+            resets_at: None,
+        }
+    }
+
+    fn scored(label: &str, weekly: Option<f64>, five_hour: Option<f64>) -> (String, Score) {
+        let snapshot = UsageSnapshot {
+            five_hour: five_hour.map(window),
+            weekly: weekly.map(window),
+            primary: None,
+            secondary: None,
+        };
+        let score = usage_score(&snapshot).expect("snapshot has at least one window");
+        (label.to_string(), score)
+    }
+
+    #[test]
+    fn most_remaining_prefers_weekly_headroom_then_five_hour() {
+        let scores = vec![
+            scored("a", Some(40.0), Some(90.0)),
+            scored("b", Some(60.0), Some(10.0)),
+            scored("c", Some(60.0), Some(50.0)),
+            scored("d", None, Some(100.0)),
+        ];
+
+        assert_eq!(
+            MostRemaining::default().pick(&scores),
+            Some("c".to_string())
+        );
+    }
+
+    #[test]
+    fn priority_reorders_the_ranking() {
+        let scores = vec![
+            scored("a", Some(90.0), Some(20.0)),
+            scored("b", Some(50.0), Some(60.0)),
+            scored("c", Some(30.0), Some(95.0)),
+        ];
+        let pick = |priority| MostRemaining { priority }.pick(&scores);
+
+        assert_eq!(pick(SelectionPriority::Weekly), Some("a".to_string()));
+        assert_eq!(pick(SelectionPriority::FiveHour), Some("c".to_string()));
+        // Binding windows: a 20, b 50, c 30.
+        assert_eq!(pick(SelectionPriority::Balanced), Some("b".to_string()));
+    }
+
+    #[test]
+    fn most_remaining_breaks_ties_by_label() {
+        let scores = vec![
+            scored("b", Some(50.0), Some(50.0)),
+            scored("a", Some(50.0), Some(50.0)),
+        ];
+
+        assert_eq!(
+            MostRemaining::default().pick(&scores),
+            Some("a".to_string())
+        );
+        assert_eq!(MostRemaining::default().pick(&[]), None);
+    }
+
+    #[test]
+    fn weighted_spread_draws_only_near_best_accounts() {
+        let scores = vec![
+            scored("a", Some(80.0), Some(50.0)),
+            scored("b", Some(20.0), Some(50.0)),
+            scored("c", Some(75.0), Some(50.0)),
+        ];
+        let spread = WeightedSpread {
+            spread_percent: 10.0,
+            ranking: MostRemaining::default(),
+        };
+
+        // Candidates are a (80) and c (75); b is outside the spread.
+        assert_eq!(spread.pick_with_roll(&scores, 0.0), Some("a".to_string()));
+        assert_eq!(spread.pick_with_roll(&scores, 0.5), Some("a".to_string()));
+        assert_eq!(spread.pick_with_roll(&scores, 0.6), Some("c".to_string()));
+        assert_eq!(spread.pick_with_roll(&scores, 0.999), Some("c".to_string()));
+    }
+
+    #[test]
+    fn resolve_rejects_unknown_strategies() {
+        let config = |strategy: Option<&str>| SelectionConfig {
+            strategy: strategy.map(str::to_string),
+            spread_percent: None,
+            ..SelectionConfig::default()
+        };
+        assert!(resolve(&config(None)).is_ok());
+        assert!(resolve(&config(Some("most_remaining"))).is_ok());
+        assert!(resolve(&config(Some("nope"))).is_err());
+    }
+}
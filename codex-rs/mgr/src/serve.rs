@@ -4,6 +4,7 @@ use axum::body::Body;
 use axum::extract::Extension;
 use axum::extract::State;
 use axum::extract::ws::WebSocketUpgrade;
+use axum::http::HeaderMap;
 use axum::http::Request;
 use axum::http::StatusCode;
 use axum::http::header;
@@ -13,6 +14,7 @@ use axum::middleware::Next;
 use axum::response::Response;
 use axum::routing::any;
 use axum::routing::get;
+use futures::StreamExt;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::path::Path;
@@ -22,61 +24,138 @@ use std::sync::OnceLock;
 use std::sync::atomic::Ordering;
 use std::time::Instant;
 use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio::sync::MutexGuard;
 use tokio::sync::RwLock;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
 
+use crate::account_override;
+use crate::account_override::OverrideRejected;
 use crate::account_token_provider;
 use crate::accounts;
+use crate::admin;
+use crate::capture;
 use crate::config;
 use crate::default_pool_labels::DefaultPoolLabels;
 use crate::gateway_sessions;
+use crate::header_policy;
 use crate::observability;
 use crate::proxy;
+use crate::redact;
 use crate::redis_conn;
 use crate::routing;
+use crate::shadow;
+use crate::time::Clock;
+use crate::time::SystemClock;
+use crate::upstream_health::UpstreamHealthCheck;
 use crate::usage;
+use crate::usage_refresher;
 use crate::websocket_proxy;
 
 #[derive(Clone)]
 pub(crate) struct ServeState {
-    pub(crate) redis: redis::aio::ConnectionManager,
+    pub(crate) redis: redis_conn::RedisConnection,
     pub(crate) upstream_base_url: String,
+    pub(crate) upstream_hosts: config::UpstreamHosts,
+    pub(crate) canary: Option<CanaryUpstream>,
+    pub(crate) shadow: Option<shadow::ShadowAccount>,
     pub(crate) http: reqwest::Client,
     pub(crate) pools: BTreeMap<String, config::PoolConfig>,
     pub(crate) sticky_ttl_seconds: i64,
     pub(crate) accounts_root: PathBuf,
     pub(crate) default_pool_labels: DefaultPoolLabels,
     pub(crate) token_safety_window_seconds: i64,
+    pub(crate) max_token_cache_seconds: i64,
+    pub(crate) header_strip: header_policy::HeaderStripLists,
+    pub(crate) upstream_api_headers: HeaderMap,
     pub(crate) metrics: Arc<observability::GatewayMetrics>,
     pub(crate) usage_scores: Arc<RwLock<HashMap<String, usage::Score>>>,
+    pub(crate) admin_token: Option<String>,
+    /// Armed by `POST /admin/capture`; records matching exchanges to `captures.jsonl`.
+    pub(crate) capture: Arc<capture::RequestCapture>,
+    pub(crate) request_limiter: Option<Arc<Semaphore>>,
+    pub(crate) sse_identity_encoding: bool,
+    pub(crate) conversation_id_conflict: config::ConversationIdConflict,
+    pub(crate) non_streaming_deadline: Option<std::time::Duration>,
+    pub(crate) sse_idle_timeout: Option<std::time::Duration>,
+    pub(crate) upstream_health: Option<UpstreamHealthCheck>,
+    pub(crate) clock: Arc<dyn Clock>,
     pub(crate) debug: bool,
 }
 
+#[derive(Clone)]
+pub(crate) struct CanaryUpstream {
+    pub(crate) base_url: String,
+    pub(crate) percent: i64,
+}
+
+pub(crate) struct ServeOptions {
+    pub(crate) debug: bool,
+    /// Where to write the bound listen address once the socket is open.
+    pub(crate) addr_file: Option<PathBuf>,
+}
+
 pub(crate) async fn run(
     state_root: &Path,
+    config_path: &Path,
     shared_root: &Path,
     accounts_root: &Path,
-    debug: bool,
+    options: ServeOptions,
 ) -> anyhow::Result<()> {
-    let config_path = config::config_path(state_root);
-    let cfg = config::load(state_root)?;
+    let ServeOptions { debug, addr_file } = options;
+    let started = std::time::Instant::now();
+    let cfg = config::load(config_path)?;
 
     tracing::info!(
         event = %"serve_start",
         config = %config_path.display(),
         listen = %cfg.gateway.listen,
         upstream_base_url = %cfg.gateway.upstream_base_url,
-        redis_url = %redact_url(&cfg.gateway.redis_url),
+        redis_url = %redact::redact_url(&cfg.gateway.redis_url),
+        redis_mode = ?cfg.gateway.redis_mode,
+        redis_command_timeout_ms = cfg.gateway.redis_command_timeout_ms,
+        redis_retry_max = cfg.gateway.redis_retry_max,
         sticky_ttl_seconds = cfg.gateway.sticky_ttl_seconds,
         token_safety_window_seconds = cfg.gateway.token_safety_window_seconds,
+        max_token_cache_seconds = cfg.gateway.max_token_cache_seconds,
+        token_prewarm_stagger_ms = cfg.gateway.token_prewarm_stagger_ms,
+        token_prewarm_jitter_ms = cfg.gateway.token_prewarm_jitter_ms,
+        usage_refresh_interval_seconds = cfg.gateway.usage_refresh_interval_seconds,
+        usage_cache_ttl_seconds = cfg.gateway.usage_cache_ttl_seconds,
+        canary_upstream_base_url = cfg.gateway.canary_upstream_base_url.as_deref().unwrap_or("-"),
+        canary_percent = cfg.gateway.canary_percent,
+        allowed_upstream_hosts = %if cfg.gateway.allow_any_upstream_host {
+            "*".to_string()
+        } else {
+            cfg.gateway.allowed_upstream_hosts.join(",")
+        },
+        shadow_account = cfg.gateway.shadow_account.as_deref().unwrap_or("-"),
+        shadow_percent = cfg.gateway.shadow_percent,
+        max_concurrent_requests = cfg.gateway.max_concurrent_requests.unwrap_or(0),
+        non_streaming_deadline_ms = cfg.gateway.non_streaming_deadline_ms.unwrap_or(0),
+        sse_idle_timeout_ms = cfg.gateway.sse_idle_timeout_ms.unwrap_or(0),
+        upstream_health_path = cfg.gateway.upstream_health_path.as_deref().unwrap_or("-"),
+        admin_enabled = cfg.gateway.admin_token.is_some(),
     );
     warn_if_upstream_base_url_is_suspicious(&cfg.gateway.upstream_base_url);
 
+    let header_strip = header_policy::HeaderStripLists::parse(
+        &cfg.gateway.strip_request_headers,
+        &cfg.gateway.strip_response_headers,
+    )?;
+    let upstream_api_headers =
+        header_policy::parse_pinned_headers(&cfg.gateway.upstream_api_headers)?;
+
     let listener = TcpListener::bind(&cfg.gateway.listen)
         .await
         .with_context(|| format!("binding to {}", cfg.gateway.listen))?;
     let addr = listener.local_addr().context("getting bound address")?;
 
     tracing::info!(event = %"serve_listening", addr = %addr);
+    if let Some(addr_file) = &addr_file {
+        write_addr_file(addr_file, addr)?;
+    }
 
     let gateway_metrics = Arc::new(observability::GatewayMetrics::default());
     let state_root_clone = state_root.to_path_buf();
@@ -88,6 +167,8 @@ pub(crate) async fn run(
     // 2. http2_keep_alive_interval: Send PING frames to keep H2 streams alive and detect broken connections.
     // 3. connect_timeout: Fail fast if TCP handshake hangs.
     // 4. No request timeout (default): Necessary for long-lived SSE streams.
+    // 5. HTTP/2 is negotiated via ALPN when upstream offers it, so concurrent requests multiplex
+    //    over one connection; plain HTTP/1.1 upstreams keep working unchanged.
     let http_client = reqwest::Client::builder()
         .tcp_keepalive(std::time::Duration::from_secs(60))
         .http2_keep_alive_interval(std::time::Duration::from_secs(30))
@@ -100,57 +181,110 @@ pub(crate) async fn run(
     let usage_scores = Arc::new(RwLock::new(HashMap::new()));
     let usage_scores_bg = Arc::clone(&usage_scores);
     let default_pool_labels = DefaultPoolLabels::new(
-        accounts::list_labels(accounts_root).context("loading default pool labels")?,
+        accounts::list_unreserved_labels(accounts_root, state_root)
+            .context("loading default pool labels")?,
     );
-    default_pool_labels.spawn_refresh_task(accounts_root.to_path_buf());
+    default_pool_labels.spawn_refresh_task(accounts_root.to_path_buf(), state_root.to_path_buf());
 
-    tokio::spawn(async move {
-        tracing::info!("usage background fetcher started");
-        loop {
-            // Fetch usage
-            match usage::scan_and_update_usage(
-                &shared_root,
-                &accounts_root_clone,
-                &state_root_clone,
-                false,
-                false,
-            )
-            .await
-            {
-                Ok(scores) => {
-                    tracing::info!(count = scores.len(), "updated usage scores");
-                    *usage_scores_bg.write().await = scores;
-                }
-                Err(err) => {
-                    tracing::error!(error = %err, "failed to update usage scores");
-                }
-            }
-            // Sleep for 1 minute.
-            // Note: internal `usage` logic respects 15m cache, so calling this every minute is fine.
-            // It will only hit network if cache is expired or new accounts appear.
-            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
-        }
-    });
+    let redis = redis_conn::connect(&cfg.gateway).await?;
+    let usage_scan_lock = Arc::new(Mutex::new(()));
+    usage_refresher::spawn(
+        usage_refresher::UsageRefresher {
+            shared_root,
+            accounts_root: accounts_root_clone,
+            state_root: state_root_clone,
+            config_path: config_path.to_path_buf(),
+            interval_seconds: cfg.gateway.usage_refresh_interval_seconds,
+            fetch_timeout_seconds: cfg.gateway.usage_fetch_timeout_seconds,
+            cache_ttl_seconds: cfg.gateway.usage_cache_ttl_seconds,
+        },
+        redis.clone(),
+        Arc::clone(&usage_scan_lock),
+        usage_scores_bg,
+        Arc::clone(&gateway_metrics),
+    );
+
+    spawn_otlp_exporter(&cfg.gateway, &http_client, &gateway_metrics);
 
     let state = Arc::new(ServeState {
-        redis: redis_conn::connect(&cfg.gateway.redis_url).await?,
+        redis,
         upstream_base_url: cfg.gateway.upstream_base_url.clone(),
+        upstream_hosts: cfg.gateway.upstream_hosts(),
+        canary: cfg
+            .gateway
+            .canary_upstream_base_url
+            .clone()
+            .filter(|_| cfg.gateway.canary_percent > 0)
+            .map(|base_url| CanaryUpstream {
+                base_url,
+                percent: cfg.gateway.canary_percent,
+            }),
+        shadow: cfg
+            .gateway
+            .shadow_account
+            .clone()
+            .filter(|_| cfg.gateway.shadow_percent > 0)
+            .map(|label| shadow::ShadowAccount::new(label, cfg.gateway.shadow_percent)),
         http: http_client,
         pools: cfg.pools.clone(),
         sticky_ttl_seconds: cfg.gateway.sticky_ttl_seconds,
         accounts_root: accounts_root.to_path_buf(),
         default_pool_labels,
         token_safety_window_seconds: cfg.gateway.token_safety_window_seconds,
+        max_token_cache_seconds: cfg.gateway.max_token_cache_seconds,
+        header_strip,
+        upstream_api_headers,
         metrics: gateway_metrics,
         usage_scores,
+        admin_token: cfg.gateway.admin_token.clone(),
+        capture: Arc::new(capture::RequestCapture::new(state_root)),
+        request_limiter: cfg
+            .gateway
+            .max_concurrent_requests
+            .and_then(|max| usize::try_from(max).ok())
+            .map(|max| Arc::new(Semaphore::new(max))),
+        sse_identity_encoding: cfg.gateway.sse_identity_encoding,
+        conversation_id_conflict: cfg.gateway.conversation_id_conflict,
+        non_streaming_deadline: cfg
+            .gateway
+            .non_streaming_deadline_ms
+            .and_then(|ms| u64::try_from(ms).ok())
+            .map(std::time::Duration::from_millis),
+        sse_idle_timeout: cfg
+            .gateway
+            .sse_idle_timeout_ms
+            .and_then(|ms| u64::try_from(ms).ok())
+            .map(std::time::Duration::from_millis),
+        upstream_health: cfg
+            .gateway
+            .upstream_health_path
+            .as_deref()
+            .map(|path| UpstreamHealthCheck::new(&cfg.gateway.upstream_base_url, path)),
+        clock: Arc::new(SystemClock),
         debug,
     });
 
+    spawn_state_dump_on_signal(
+        Arc::clone(&state),
+        redact::redacted_config(cfg.clone()).gateway,
+    );
+    if let Some(stagger_ms) = cfg.gateway.token_prewarm_stagger_ms {
+        spawn_token_prewarm(
+            Arc::clone(&state),
+            account_token_provider::PrewarmSchedule {
+                stagger_ms,
+                jitter_ms: cfg.gateway.token_prewarm_jitter_ms,
+            },
+        );
+    }
+
     let router = Router::new()
         .route("/healthz", get(|| async { "ok\n" }))
+        .route("/healthz/quota", get(quota_handler))
         .route("/readyz", get(readyz_handler))
         .route("/metrics", get(metrics_handler))
         .route("/authz", get(authz))
+        .nest("/admin", admin::router())
         .route("/responses", any(responses_entry))
         .route("/ws", any(websocket_entry))
         .fallback(proxy_non_streaming)
@@ -162,24 +296,194 @@ pub(crate) async fn run(
             state.clone(),
             require_gateway_session,
         ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_concurrency,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             with_request_context,
         ))
-        .with_state(state);
+        .with_state(Arc::clone(&state));
 
+    // Returns once the listener is closed and every open connection, including long-lived SSE
+    // and WebSocket streams, has finished.
     axum::serve(listener, router)
         .with_graceful_shutdown(shutdown_signal())
         .await?;
+
+    let flush_timeout = std::time::Duration::from_secs(
+        u64::try_from(cfg.gateway.usage_fetch_timeout_seconds).unwrap_or(1),
+    ) + USAGE_SCAN_FLUSH_GRACE;
+    // Held until `run` returns, so no new scan starts after the flush.
+    let _usage_scan = flush_on_shutdown(&usage_scan_lock, flush_timeout).await;
+    tracing::info!(
+        event = %"serve_stop",
+        requests_total = state.metrics.requests_total.load(Ordering::Relaxed),
+        upstream_requests_total = state.metrics.upstream_requests_total.load(Ordering::Relaxed),
+        uptime_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+    );
+    Ok(())
+}
+
+/// Extra time beyond the per-account fetch timeout to let a running usage scan write
+/// `state.json` during shutdown.
+const USAGE_SCAN_FLUSH_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Runs after the connections have drained. Usage snapshots only live in memory until the scan
+/// that fetched them saves `state.json`, so a scan still in progress is given `timeout` to finish
+/// instead of being dropped with the runtime. Metrics have no on-disk form; their totals go into
+/// the `serve_stop` event.
+async fn flush_on_shutdown(
+    usage_scan_lock: &Mutex<()>,
+    timeout: std::time::Duration,
+) -> Option<MutexGuard<'_, ()>> {
+    let guard = tokio::time::timeout(timeout, usage_scan_lock.lock())
+        .await
+        .ok();
+    if guard.is_none() {
+        tracing::warn!(
+            ?timeout,
+            "usage scan still running at shutdown; its snapshots were not saved"
+        );
+    }
+    guard
+}
+
+/// Publishes the bound address (useful with an ephemeral `:0` port) by writing a sibling temp
+/// file and renaming it into place, so readers polling for the file never see a partial write.
+fn write_addr_file(path: &Path, addr: std::net::SocketAddr) -> anyhow::Result<()> {
+    let Some(file_name) = path.file_name() else {
+        anyhow::bail!("invalid --addr-file path {path:?}");
+    };
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    std::fs::write(&tmp, format!("{addr}\n")).with_context(|| format!("writing temp {tmp:?}"))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("writing addr file {path:?}"))?;
     Ok(())
 }
 
+/// Pushes gateway metrics to `gateway.otlp_endpoint` on a fixed interval, when one is set.
+#[cfg(feature = "otlp")]
+fn spawn_otlp_exporter(
+    gateway: &config::GatewayConfig,
+    http: &reqwest::Client,
+    metrics: &Arc<observability::GatewayMetrics>,
+) {
+    if let Some(endpoint) = gateway.otlp_endpoint.clone() {
+        let interval = std::time::Duration::from_secs(
+            u64::try_from(gateway.otlp_interval_seconds).unwrap_or(60),
+        );
+        tracing::info!(endpoint = %endpoint, ?interval, "exporting metrics over OTLP");
+        crate::otlp::spawn_exporter(endpoint, interval, http.clone(), Arc::clone(metrics));
+    }
+}
+
+/// Without the `otlp` feature there is nothing to export, so a configured endpoint only warns.
+#[cfg(not(feature = "otlp"))]
+fn spawn_otlp_exporter(
+    gateway: &config::GatewayConfig,
+    _http: &reqwest::Client,
+    _metrics: &Arc<observability::GatewayMetrics>,
+) {
+    if gateway.otlp_endpoint.is_some() {
+        tracing::warn!(
+            "gateway.otlp_endpoint is set but this build lacks the `otlp` feature; metrics are only served on /metrics"
+        );
+    }
+}
+
+/// Loads each pool member's access token into the Redis cache one account at a time, paced by
+/// `schedule`, so a restart does not send every refresh to the auth backend at once. Accounts
+/// already cached by another gateway are cache hits and cost nothing.
+fn spawn_token_prewarm(state: Arc<ServeState>, schedule: account_token_provider::PrewarmSchedule) {
+    tokio::spawn(async move {
+        let mut labels: std::collections::BTreeSet<String> = state
+            .pools
+            .values()
+            .flat_map(|pool| pool.labels.iter().cloned())
+            .collect();
+        labels.extend(state.default_pool_labels.snapshot().await);
+        tracing::info!(
+            accounts = labels.len(),
+            stagger_ms = schedule.stagger_ms,
+            jitter_ms = schedule.jitter_ms,
+            "token prewarm started"
+        );
+
+        let mut conn = state.redis.clone();
+        let mut failed = 0usize;
+        for (index, label) in labels.iter().enumerate() {
+            tokio::time::sleep(schedule.delay(index)).await;
+            if let Err(err) = account_token_provider::get(
+                &mut conn,
+                &state.accounts_root,
+                label,
+                state.token_safety_window_seconds,
+                state.max_token_cache_seconds,
+                &state.metrics,
+                state.clock.as_ref(),
+            )
+            .await
+            {
+                failed += 1;
+                tracing::warn!(error = %err, account = %label, "token prewarm failed");
+            }
+        }
+        tracing::info!(
+            event = %"token_prewarm_done",
+            accounts = labels.len(),
+            failed,
+            "token prewarm finished"
+        );
+    });
+}
+
+/// Sheds load once `gateway.max_concurrent_requests` requests are in flight. A permit is held
+/// until the response body finishes, so long-lived SSE streams count against the limit; health
+/// and metrics paths are exempt so probes keep answering under load.
+async fn limit_concurrency(
+    State(state): State<Arc<ServeState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(limiter) = state.request_limiter.as_ref() else {
+        return next.run(request).await;
+    };
+    if is_public_path(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let Ok(permit) = Arc::clone(limiter).try_acquire_owned() else {
+        state
+            .metrics
+            .requests_shed_total
+            .fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(path = %request.uri().path(), "max_concurrent_requests reached; shedding request");
+        let mut response = proxy::json_error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "gateway is at capacity; retry shortly",
+        );
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        return response;
+    };
+
+    let (parts, body) = next.run(request).await.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _held = &permit;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
 async fn require_gateway_session(
     State(state): State<Arc<ServeState>>,
     mut request: Request<Body>,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    if is_public_path(request.uri().path()) {
+    if bypasses_gateway_session(request.uri().path()) {
         return Ok(next.run(request).await);
     }
 
@@ -194,7 +498,8 @@ async fn require_gateway_session(
         })?;
 
     let mut conn = state.redis.clone();
-    let session = gateway_sessions::get(&mut conn, token)
+    let session = gateway_sessions::get(&mut conn, token, state.clock.as_ref())
+        .instrument(tracing::info_span!("session_lookup"))
         .await
         .map_err(|err| {
             tracing::error!(error = %err, "redis error in session lookup");
@@ -208,6 +513,14 @@ async fn require_gateway_session(
             tracing::warn!("gateway session not found");
             StatusCode::UNAUTHORIZED
         })?;
+    if !session.allows_path(request.uri().path()) {
+        tracing::warn!(
+            path = %request.uri().path(),
+            pool_id = %session.account_pool_id,
+            "gateway session is not allowed to access path"
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
     if let Some(trace_data) = request.extensions().get::<Arc<RequestTraceData>>() {
         let _ = trace_data.pool_id.set(session.account_pool_id.clone());
     }
@@ -224,6 +537,8 @@ async fn proxy_non_streaming(
 
     // Request bodies are single-use streams, so retries require buffering once up front.
     // Keep the buffer bounded so retries stay replayable without unbounded memory growth.
+    // Bodyless requests skip buffering, and streaming uploads are relayed without it at the cost
+    // of trying only the first candidate; they are held to the same byte limit as they stream.
 
     let (parts, body) = request.into_parts();
     let declared_body_bytes = parts
@@ -232,8 +547,25 @@ async fn proxy_non_streaming(
         .and_then(|value| value.to_str().ok())
         .and_then(|value| value.parse::<usize>().ok());
     let trace_data = parts.extensions.get::<Arc<RequestTraceData>>().cloned();
-    let body_bytes = match axum::body::to_bytes(body, proxy::MAX_REQUEST_BODY_BYTES).await {
-        Ok(bytes) => bytes,
+    let body_mode = proxy::BodyMode::for_request(&parts.method, &parts.headers);
+    let request_body = match body_mode {
+        proxy::BodyMode::Empty => Ok(proxy::ForwardBody::Empty),
+        proxy::BodyMode::Stream
+            if declared_body_bytes.is_some_and(|length| length > proxy::MAX_REQUEST_BODY_BYTES) =>
+        {
+            return Ok(proxy::GatewayError::payload_too_large(format!(
+                "request body exceeds {} bytes",
+                proxy::MAX_REQUEST_BODY_BYTES
+            ))
+            .into_response());
+        }
+        proxy::BodyMode::Stream => Ok(proxy::ForwardBody::Streaming(body)),
+        proxy::BodyMode::Buffer => axum::body::to_bytes(body, proxy::MAX_REQUEST_BODY_BYTES)
+            .await
+            .map(proxy::ForwardBody::Buffered),
+    };
+    let mut request_body = match request_body {
+        Ok(request_body) => Some(request_body),
         Err(err) => {
             let status = if declared_body_bytes
                 .is_some_and(|length| length > proxy::MAX_REQUEST_BODY_BYTES)
@@ -262,6 +594,37 @@ async fn proxy_non_streaming(
         }
     };
 
+    // Split on the conversation when present so a conversation sticks to one upstream; otherwise
+    // fall back to the per-request id.
+    let split_key = route_info
+        .conversation_id
+        .as_deref()
+        .or_else(|| trace_data.as_deref().map(|t| t.request_id.as_str()));
+    let (upstream_base_url, upstream_name) = match (&state.canary, split_key) {
+        (Some(canary), Some(split_key)) if routing::is_canary(split_key, canary.percent) => {
+            (canary.base_url.as_str(), "canary")
+        }
+        _ => (state.upstream_base_url.as_str(), "primary"),
+    };
+
+    // One budget covers every candidate attempt, so retries cannot stretch it.
+    let deadline = state
+        .non_streaming_deadline
+        .map(|budget| tokio::time::Instant::now() + budget);
+
+    let request_body_bytes = request_body
+        .as_ref()
+        .and_then(proxy::ForwardBody::buffered_len);
+
+    // Sampled per request and mirrored at most once, after the first attempt. The body is only
+    // copied once a shadow slot is free; streamed uploads cannot be sent twice, so they are never
+    // mirrored.
+    let mut shadow_sampled = state
+        .shadow
+        .as_ref()
+        .zip(trace_data.as_deref())
+        .is_some_and(|(shadow, trace)| shadow.samples(&trace.request_id));
+
     for (i, account_id) in route_info.candidates.iter().enumerate() {
         let is_last = i == route_info.candidates.len() - 1;
 
@@ -270,17 +633,28 @@ async fn proxy_non_streaming(
             &state.accounts_root,
             account_id,
             state.token_safety_window_seconds,
+            state.max_token_cache_seconds,
+            &state.metrics,
+            state.clock.as_ref(),
         )
+        .instrument(tracing::info_span!("token_provision", account = %account_id))
         .await;
 
         let auth = match auth_result {
             Ok(a) => a,
             Err(err) => {
                 if err.downcast_ref::<redis::RedisError>().is_some() {
-                    // Redis errors are likely global/systemic, so retrying another account might not help
-                    // unless it's a specific key issue. But let's log and maybe fail fast?
-                    // Actually, account_token_provider uses redis for caching.
+                    // Redis failures (including command timeouts) are systemic, so trying the
+                    // next account would only stall again.
                     tracing::error!(error = %err, "redis error in account token provider");
+                    state
+                        .metrics
+                        .redis_errors_total
+                        .fetch_add(1, Ordering::Relaxed);
+                    return Ok(proxy::json_error_response(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "redis unavailable",
+                    ));
                 } else {
                     tracing::warn!(error = %err, %account_id, "token provider error");
                 }
@@ -302,44 +676,136 @@ async fn proxy_non_streaming(
             let _ = trace_data.account_id.set(account_id.clone());
         }
 
+        let body = match request_body
+            .as_ref()
+            .and_then(proxy::ForwardBody::try_clone)
+        {
+            Some(body) => body,
+            None => match request_body.take() {
+                Some(body) => body,
+                // The streamed body went to an earlier attempt and cannot be replayed.
+                None => break,
+            },
+        };
+        // Nothing left to send can retry a streamed upload on the next candidate.
+        let is_last = is_last || request_body.is_none();
+        let mut capture = state.capture.begin(capture::CaptureAttempt {
+            pool_id: &route_info.account_pool_id,
+            account: account_id,
+            request_id: trace_data.as_deref().map(|t| t.request_id.as_str()),
+            parts: &parts,
+            body: &body,
+        });
+
+        let attempt_started = Instant::now();
         let result = proxy::forward(
             &state.http,
-            &state.upstream_base_url,
+            upstream_base_url,
             proxy::ForwardRequest {
                 parts: parts.clone(),
-                body_bytes: body_bytes.clone(),
+                body,
                 authorization: &auth.authorization,
                 chatgpt_account_id: auth.chatgpt_account_id.as_deref(),
+                identity_encoding_for_sse: state.sse_identity_encoding,
+                pinned_headers: &state.upstream_api_headers,
+                deadline,
+                sse_idle_timeout: state.sse_idle_timeout,
+                allowed_hosts: &state.upstream_hosts,
             },
+            &state.header_strip,
             Arc::clone(&state.metrics),
             state.debug,
         )
+        // Closes once response headers arrive; relaying the body happens outside this span.
+        .instrument(tracing::info_span!(
+            "upstream_forward",
+            account = %account_id,
+            upstream = upstream_name
+        ))
         .await;
 
+        if std::mem::take(&mut shadow_sampled)
+            && let Some(permit) = state
+                .shadow
+                .as_ref()
+                .and_then(|shadow| shadow.try_acquire(account_id))
+            && let Some(body) = request_body
+                .as_ref()
+                .and_then(proxy::ForwardBody::try_clone)
+        {
+            shadow::spawn(
+                Arc::clone(&state),
+                shadow::ShadowRequest {
+                    parts: parts.clone(),
+                    body,
+                    upstream_base_url: upstream_base_url.to_string(),
+                    primary_account: account_id.clone(),
+                    primary_status: result
+                        .as_ref()
+                        .map_or_else(proxy::GatewayError::status, Response::status),
+                    primary_latency: attempt_started.elapsed(),
+                    permit,
+                },
+            );
+        }
+
         match result {
-            Ok(response) => {
+            Ok(mut response) => {
+                response.headers_mut().insert(
+                    "x-codex-mgr-upstream",
+                    HeaderValue::from_static(upstream_name),
+                );
                 let status = response.status();
+                if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+                    state.metrics.record_upstream_auth_failure(account_id);
+                    tracing::warn!(
+                        %status,
+                        %account_id,
+                        "upstream rejected account credentials"
+                    );
+                }
                 if status == StatusCode::TOO_MANY_REQUESTS
                     || status == StatusCode::UNAUTHORIZED
                     || status == StatusCode::FORBIDDEN
                 {
-                    tracing::warn!(%status, %account_id, "upstream error, retrying with next candidate if available");
-                    if is_last {
-                        return Ok(response);
+                    tracing::warn!(%status, %account_id, upstream = upstream_name, "upstream error, retrying with next candidate if available");
+                    if !is_last {
+                        if let Some(mut record) = capture.take() {
+                            record.response_head(&response);
+                            state.capture.write(&record);
+                        }
+                        continue;
                     }
-                    continue;
                 }
-                return Ok(response);
+                return Ok(match capture.take() {
+                    Some(record) => state.capture.relay(record, response),
+                    None => response,
+                });
             }
             Err(err) => {
                 let status = err.status();
+                if let Some(mut record) = capture.take() {
+                    record.failed(status, err.detail());
+                    state.capture.write(&record);
+                }
                 tracing::warn!(
                     %status,
                     %account_id,
-                    request_body_bytes = body_bytes.len(),
+                    upstream = upstream_name,
+                    request_body_bytes,
                     detail = %err.detail(),
                     "proxy attempt failed"
                 );
+                if status == StatusCode::GATEWAY_TIMEOUT {
+                    let request_id = trace_data
+                        .as_deref()
+                        .map(|t| t.request_id.as_str())
+                        .unwrap_or("-");
+                    return Ok(proxy::json_error_response(
+                        status,
+                        format!("{} (request_id {request_id})", err.detail()),
+                    ));
+                }
                 if status.is_client_error() || is_last {
                     return Ok(err.into_response());
                 }
@@ -385,7 +851,7 @@ async fn ensure_routing(
     mut request: Request<Body>,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    if is_public_path(request.uri().path()) {
+    if bypasses_gateway_session(request.uri().path()) {
         return Ok(next.run(request).await);
     }
 
@@ -395,18 +861,68 @@ async fn ensure_routing(
         .cloned()
         .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let (labels, policy_key) = if session.account_pool_id == "default" {
-        let labels = state.default_pool_labels.snapshot().await;
-        (labels, None)
-    } else {
-        let pool = state
-            .pools
-            .get(&session.account_pool_id)
-            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-        (pool.labels.clone(), pool.policy_key.clone())
+    // Same precedence as `gateway issue`: a configured `[pools.default]` wins over the implicit
+    // all-accounts pool.
+    let (labels, policy_key, sticky, allow_account_override, tenant_header) = match state
+        .pools
+        .get(&session.account_pool_id)
+    {
+        Some(pool) => (
+            pool.labels.clone(),
+            pool.policy_key.clone(),
+            pool.sticky,
+            pool.allow_account_override,
+            pool.tenant_header.clone(),
+        ),
+        None if session.account_pool_id == config::DEFAULT_POOL_ID => {
+            let labels = state.default_pool_labels.snapshot().await;
+            (labels, None, true, false, None)
+        }
+        None => {
+            tracing::error!(
+                pool = %session.account_pool_id,
+                "gateway session names a pool that is not configured; re-issue the token or restore the pool"
+            );
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
     };
 
-    let conversation_id = routing::extract_conversation_id(request.headers());
+    let conversation_id =
+        routing::extract_conversation_id(request.headers(), state.conversation_id_conflict)
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+    // A pinned account skips scoring and sticky bindings entirely: the request goes to that
+    // account or fails, and it never rebinds the conversation.
+    let pinned = account_override::pinned_account(
+        request.headers(),
+        allow_account_override.then_some(labels.as_slice()),
+    );
+    let pinned = match pinned {
+        Ok(pinned) => pinned,
+        Err(OverrideRejected::NotAllowed(account)) => {
+            tracing::warn!(
+                pool = %session.account_pool_id,
+                account = %account,
+                "account override header rejected: pool does not allow overrides"
+            );
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Err(OverrideRejected::NotAMember(account)) => {
+            tracing::warn!(
+                pool = %session.account_pool_id,
+                account = %account,
+                "account override header rejected: account is not in the pool"
+            );
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    if let Some(account) = pinned {
+        request.extensions_mut().insert(routing::RouteInfo {
+            account_pool_id: session.account_pool_id,
+            candidates: vec![account],
+            conversation_id,
+        });
+        return Ok(next.run(request).await);
+    }
     let path_and_query = request
         .uri()
         .path_and_query()
@@ -414,21 +930,34 @@ async fn ensure_routing(
         .unwrap_or_else(|| request.uri().path());
     let method = request.method();
     let non_sticky_key = format!("non-sticky:{method} {path_and_query}");
+    let tenant = tenant_header.as_deref().and_then(|name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    });
 
     let mut conn = state.redis.clone();
-    let scores_guard = state.usage_scores.read().await;
+    // Snapshot the scores so the lock is not held across Redis round trips.
+    let usage_scores = state.usage_scores.read().await.clone();
     let route_info = routing::route_account(
         &mut conn,
         routing::RouteAccountArgs {
             account_pool_id: &session.account_pool_id,
             labels: &labels,
             policy_key: policy_key.as_deref(),
+            sticky,
             sticky_ttl_seconds: state.sticky_ttl_seconds,
             conversation_id,
             non_sticky_key: &non_sticky_key,
-            usage_scores: &scores_guard,
+            tenant: tenant.as_deref(),
+            usage_scores: &usage_scores,
         },
     )
+    .instrument(tracing::info_span!("routing", pool = %session.account_pool_id))
     .await
     .map_err(|err| {
         if err.downcast_ref::<redis::RedisError>().is_some() {
@@ -461,7 +990,7 @@ async fn authz(Extension(route_info): Extension<routing::RouteInfo>) -> String {
     format!("ok\npool: {pool_id}\ncandidates: {candidates}\nconversation_id: {conversation_id}\n")
 }
 
-fn parse_bearer_token(value: &str) -> Option<&str> {
+pub(crate) fn parse_bearer_token(value: &str) -> Option<&str> {
     let mut parts = value.split_whitespace();
     let scheme = parts.next()?;
     if !scheme.eq_ignore_ascii_case("bearer") {
@@ -474,22 +1003,77 @@ async fn shutdown_signal() {
     let _ = tokio::signal::ctrl_c().await;
 }
 
-fn redact_url(url: &str) -> String {
-    let Some(scheme_end) = url.find("://") else {
-        return url.to_string();
-    };
+/// Logs a structured snapshot of the live gateway state whenever the process receives SIGUSR1.
+/// Failover is decided per request, so there is no circuit-breaker or cooldown state to report.
+/// `gateway` is logged as given, so pass it through [`redact::redacted_config`] first.
+#[cfg(unix)]
+fn spawn_state_dump_on_signal(state: Arc<ServeState>, gateway: config::GatewayConfig) {
+    use tokio::signal::unix::SignalKind;
+    use tokio::signal::unix::signal;
 
-    let scheme_end = scheme_end + "://".len();
-    let Some(at) = url[scheme_end..].find('@').map(|i| i + scheme_end) else {
-        return url.to_string();
-    };
-    let userinfo = &url[scheme_end..at];
-    let rest = &url[at..];
+    tokio::spawn(async move {
+        let mut signals = match signal(SignalKind::user_defined1()) {
+            Ok(signals) => signals,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to install SIGUSR1 handler; state dumps disabled");
+                return;
+            }
+        };
+        while signals.recv().await.is_some() {
+            log_state_dump(&state, &gateway).await;
+        }
+    });
+}
 
-    match userinfo.split_once(':') {
-        Some((user, _password)) => format!("{}{}:****{}", &url[..scheme_end], user, rest),
-        None => url.to_string(),
+#[cfg(not(unix))]
+fn spawn_state_dump_on_signal(state: Arc<ServeState>, gateway: config::GatewayConfig) {
+    let _ = (state, gateway);
+}
+
+#[cfg(unix)]
+async fn log_state_dump(state: &ServeState, gateway: &config::GatewayConfig) {
+    let pools = state
+        .pools
+        .iter()
+        .map(|(pool_id, pool)| format!("{pool_id}=[{}]", pool.labels.join(",")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let default_pool_labels = state.default_pool_labels.snapshot().await.join(",");
+    let usage_scores = state.usage_scores.read().await.len();
+    let metrics = &state.metrics;
+
+    tracing::info!(
+        event = %"state_dump",
+        listen = %gateway.listen,
+        upstream_base_url = %gateway.upstream_base_url,
+        redis_url = %gateway.redis_url,
+        redis_nodes = %gateway.redis_nodes.join(","),
+        sticky_ttl_seconds = gateway.sticky_ttl_seconds,
+        token_safety_window_seconds = gateway.token_safety_window_seconds,
+        max_token_cache_seconds = gateway.max_token_cache_seconds,
+        pools = %pools,
+        default_pool_labels = %default_pool_labels,
+        usage_scores,
+        requests_inflight = metrics.requests_inflight.load(Ordering::Relaxed),
+        sse_streams_inflight = metrics.sse_streams_inflight.load(Ordering::Relaxed),
+        websocket_connections_inflight =
+            metrics.websocket_connections_inflight.load(Ordering::Relaxed),
+    );
+}
+
+/// Prints the config `run` would serve with, after defaults are applied, with credentials masked
+/// by [`redact::redacted_config`].
+pub(crate) fn print_effective_config(config_path: &Path, json: bool) -> anyhow::Result<()> {
+    let cfg = redact::redacted_config(config::load(config_path)?);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&cfg)?);
+    } else {
+        print!(
+            "{}",
+            toml::to_string_pretty(&cfg).context("serializing effective config")?
+        );
     }
+    Ok(())
 }
 
 fn warn_if_upstream_base_url_is_suspicious(upstream_base_url: &str) {
@@ -526,7 +1110,20 @@ async fn with_request_context(
     }
 
     let request_id = observability::new_request_id();
-    let conversation_id = routing::extract_conversation_id(request.headers());
+    if let Some(mismatch) = routing::conversation_id_mismatch(request.headers()) {
+        tracing::warn!(
+            event = %"conversation_id_mismatch",
+            conversation_hash = %observability::hash_opaque_id(&mismatch.conversation_id),
+            session_hash = %observability::hash_opaque_id(&mismatch.session_id),
+            policy = ?state.conversation_id_conflict,
+            "conversation_id and session_id headers disagree"
+        );
+    }
+    // Under `strict` a mismatch is rejected with `400` by `ensure_routing`.
+    let conversation_id =
+        routing::extract_conversation_id(request.headers(), state.conversation_id_conflict)
+            .ok()
+            .flatten();
     let conversation_hash = conversation_id
         .as_deref()
         .map(observability::hash_opaque_id);
@@ -540,7 +1137,14 @@ async fn with_request_context(
 
     let method = request.method().clone();
     let path = request.uri().path().to_string();
-    let mut response = next.run(request).await;
+    // Parent of the per-phase spans below, so each of them carries the request id.
+    let span = tracing::info_span!(
+        "request",
+        request_id = %trace_data.request_id,
+        %method,
+        %path
+    );
+    let mut response = next.run(request).instrument(span).await;
 
     let elapsed = start.elapsed();
     if !public_path {
@@ -614,27 +1218,132 @@ fn record_request_duration_ms(
         .fetch_add(1, Ordering::Relaxed);
 }
 
-fn duration_ms(elapsed: std::time::Duration) -> i64 {
+pub(crate) fn duration_ms(elapsed: std::time::Duration) -> i64 {
     i64::try_from(elapsed.as_millis()).unwrap_or(i64::MAX)
 }
 
 fn is_public_path(path: &str) -> bool {
-    matches!(path, "/healthz" | "/readyz" | "/metrics")
+    matches!(path, "/healthz" | "/healthz/quota" | "/readyz" | "/metrics")
+}
+
+/// Public paths plus `/admin/*`, which authenticates with the admin token instead of a gateway
+/// session and is never routed to an account.
+fn bypasses_gateway_session(path: &str) -> bool {
+    is_public_path(path) || path.starts_with("/admin/")
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 async fn readyz_handler(State(state): State<Arc<ServeState>>) -> Result<String, StatusCode> {
     let mut conn = state.redis.clone();
     let pong: redis::RedisResult<String> = redis::cmd("PING").query_async(&mut conn).await;
-    match pong {
-        Ok(_) => Ok("ok\n".to_string()),
-        Err(err) => {
-            state
-                .metrics
-                .redis_errors_total
-                .fetch_add(1, Ordering::Relaxed);
-            tracing::error!(error = %err, "redis PING failed");
-            Err(StatusCode::SERVICE_UNAVAILABLE)
+    if let Err(err) = pong {
+        state
+            .metrics
+            .redis_errors_total
+            .fetch_add(1, Ordering::Relaxed);
+        tracing::error!(error = %err, "redis PING failed");
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    if let Some(check) = &state.upstream_health
+        && !check.is_reachable(&state.http).await
+    {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    Ok("ok\n".to_string())
+}
+
+/// Aggregate usage headroom across one pool's accounts, from the in-process usage scores.
+#[derive(Debug, Default, serde::Serialize)]
+struct PoolQuota {
+    accounts: usize,
+    /// Accounts with a usage snapshot; the remaining fields only count these.
+    accounts_with_usage: usize,
+    weekly_remaining_percent_sum: f64,
+    weekly_remaining_percent_max: Option<f64>,
+    five_hour_remaining_percent_sum: f64,
+    five_hour_remaining_percent_max: Option<f64>,
+}
+
+fn pool_quota(labels: &[String], usage_scores: &HashMap<String, usage::Score>) -> PoolQuota {
+    let mut quota = PoolQuota {
+        accounts: labels.len(),
+        ..PoolQuota::default()
+    };
+    for score in labels.iter().filter_map(|label| usage_scores.get(label)) {
+        quota.accounts_with_usage += 1;
+        if score.weekly_present {
+            quota.weekly_remaining_percent_sum += score.weekly_remaining;
+            quota.weekly_remaining_percent_max = Some(
+                quota
+                    .weekly_remaining_percent_max
+                    .map_or(score.weekly_remaining, |max| {
+                        max.max(score.weekly_remaining)
+                    }),
+            );
+        }
+        if score.five_present {
+            quota.five_hour_remaining_percent_sum += score.five_remaining;
+            quota.five_hour_remaining_percent_max = Some(
+                quota
+                    .five_hour_remaining_percent_max
+                    .map_or(score.five_remaining, |max| max.max(score.five_remaining)),
+            );
+        }
+    }
+    quota
+}
+
+/// Public, read-only quota summary per pool. Served from this replica's in-process scores, as
+/// of its last usage refresh, so it never triggers upstream fetches. Refreshes share snapshots
+/// through Redis, so replicas agree to within one refresh interval; the body says which replica
+/// view it is with `scope` and `refreshed_at_ms`.
+async fn quota_handler(State(state): State<Arc<ServeState>>) -> Response {
+    let default_labels = state.default_pool_labels.snapshot().await;
+    let mut pools = BTreeMap::new();
+    {
+        let usage_scores = state.usage_scores.read().await;
+        // Overwritten below when `[pools.default]` is configured.
+        pools.insert(
+            config::DEFAULT_POOL_ID.to_string(),
+            pool_quota(&default_labels, &usage_scores),
+        );
+        for (pool_id, pool) in &state.pools {
+            pools.insert(pool_id.clone(), pool_quota(&pool.labels, &usage_scores));
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct QuotaBody {
+        /// Always `"replica"`: the numbers are this process's view, not a cluster-wide one.
+        scope: &'static str,
+        /// When this replica's usage refresher last succeeded; `None` before the first time.
+        refreshed_at_ms: Option<i64>,
+        pools: BTreeMap<String, PoolQuota>,
+    }
+    let refreshed_at_ms = state
+        .metrics
+        .usage_refresh_last_success_ms
+        .load(Ordering::Relaxed);
+    match serde_json::to_vec(&QuotaBody {
+        scope: "replica",
+        refreshed_at_ms: (refreshed_at_ms > 0).then_some(refreshed_at_ms),
+        pools,
+    }) {
+        Ok(body) => {
+            let mut out = Response::new(Body::from(body));
+            let _ = out.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            out
         }
+        Err(err) => proxy::json_error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to serialize quota summary: {err}"),
+        ),
     }
 }
 
@@ -0,0 +1,171 @@
+//! Mirrors a sample of gateway requests to `gateway.shadow_account` and logs how its answer
+//! compares with the account that served the client.
+
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+use axum::response::Response;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+use crate::account_token_provider;
+use crate::observability;
+use crate::proxy;
+use crate::routing;
+use crate::serve::ServeState;
+use crate::serve::duration_ms;
+
+/// Shadow requests in flight at once; a sampled request past this is not mirrored, so a slow
+/// shadow account cannot pile up background work.
+const MAX_SHADOW_REQUESTS_IN_FLIGHT: usize = 32;
+
+#[derive(Clone)]
+pub(crate) struct ShadowAccount {
+    label: String,
+    percent: i64,
+    in_flight: Arc<Semaphore>,
+}
+
+impl ShadowAccount {
+    pub(crate) fn new(label: String, percent: i64) -> Self {
+        Self {
+            label,
+            percent,
+            in_flight: Arc::new(Semaphore::new(MAX_SHADOW_REQUESTS_IN_FLIGHT)),
+        }
+    }
+
+    /// Whether the request with `request_id` falls in the sampled `percent`. Checked before the
+    /// body is ever copied.
+    pub(crate) fn samples(&self, request_id: &str) -> bool {
+        routing::is_canary(request_id, self.percent)
+    }
+
+    /// A slot for mirroring an attempt served by `primary_account`: `None` when that account is
+    /// the shadow account itself, or when every slot is taken.
+    pub(crate) fn try_acquire(&self, primary_account: &str) -> Option<OwnedSemaphorePermit> {
+        if self.label == primary_account {
+            return None;
+        }
+        match Arc::clone(&self.in_flight).try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                tracing::debug!(
+                    shadow_account = %self.label,
+                    "shadow requests saturated; not mirroring this request"
+                );
+                None
+            }
+        }
+    }
+}
+
+/// A copy of one client request for `gateway.shadow_account`, plus how the account that served
+/// the client answered it.
+pub(crate) struct ShadowRequest {
+    pub(crate) parts: Parts,
+    pub(crate) body: proxy::ForwardBody,
+    pub(crate) upstream_base_url: String,
+    pub(crate) primary_account: String,
+    pub(crate) primary_status: StatusCode,
+    pub(crate) primary_latency: std::time::Duration,
+    /// From [`ShadowAccount::try_acquire`]; released when the shadow exchange ends.
+    pub(crate) permit: OwnedSemaphorePermit,
+}
+
+/// Sends `shadow` as the shadow account in the background and logs a `shadow_compare` event.
+/// The response is dropped once its headers arrive, so latency on both sides is time to headers;
+/// the shadow side is timed from its upstream send, after its token is in hand.
+/// Shadow traffic is kept out of the gateway metrics.
+pub(crate) fn spawn(state: Arc<ServeState>, shadow: ShadowRequest) {
+    let Some(shadow_account) = state.shadow.as_ref().map(|s| s.label.clone()) else {
+        return;
+    };
+    tokio::spawn(async move {
+        let ShadowRequest {
+            parts,
+            body,
+            upstream_base_url,
+            primary_account,
+            primary_status,
+            primary_latency,
+            permit: _permit,
+        } = shadow;
+        let mut conn = state.redis.clone();
+        let auth = match account_token_provider::get(
+            &mut conn,
+            &state.accounts_root,
+            &shadow_account,
+            state.token_safety_window_seconds,
+            state.max_token_cache_seconds,
+            &state.metrics,
+            state.clock.as_ref(),
+        )
+        .await
+        {
+            Ok(auth) => auth,
+            Err(err) => {
+                tracing::warn!(error = %err, %shadow_account, "shadow account token unavailable");
+                return;
+            }
+        };
+        let path = parts.uri.path().to_string();
+        let started = Instant::now();
+        let result = proxy::forward(
+            &state.http,
+            &upstream_base_url,
+            proxy::ForwardRequest {
+                parts,
+                body,
+                authorization: &auth.authorization,
+                chatgpt_account_id: auth.chatgpt_account_id.as_deref(),
+                identity_encoding_for_sse: state.sse_identity_encoding,
+                pinned_headers: &state.upstream_api_headers,
+                deadline: state
+                    .non_streaming_deadline
+                    .map(|budget| tokio::time::Instant::now() + budget),
+                sse_idle_timeout: state.sse_idle_timeout,
+                allowed_hosts: &state.upstream_hosts,
+            },
+            &state.header_strip,
+            Arc::new(observability::GatewayMetrics::default()),
+            false,
+        )
+        .await;
+        let shadow_latency = started.elapsed();
+        let shadow_status = result
+            .as_ref()
+            .map_or_else(proxy::GatewayError::status, Response::status);
+        tracing::info!(
+            event = %"shadow_compare",
+            path = %path,
+            primary_account = %primary_account,
+            primary_status = primary_status.as_u16(),
+            primary_latency_ms = duration_ms(primary_latency),
+            shadow_account = %shadow_account,
+            shadow_status = shadow_status.as_u16(),
+            shadow_latency_ms = duration_ms(shadow_latency),
+            status_matches = primary_status == shadow_status,
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_acquire_skips_the_shadow_account_and_caps_in_flight_requests() {
+        let shadow = ShadowAccount::new("shadow".to_string(), 100);
+        assert!(shadow.try_acquire("shadow").is_none());
+
+        let permits = (0..MAX_SHADOW_REQUESTS_IN_FLIGHT)
+            .map(|_| shadow.try_acquire("primary").expect("free slot"))
+            .collect::<Vec<_>>();
+        assert!(shadow.try_acquire("primary").is_none());
+
+        drop(permits);
+        assert!(shadow.try_acquire("primary").is_some());
+    }
+}
@@ -1,13 +1,51 @@
+use anyhow::Context;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fs::File;
+use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
 
+/// Persisted manager state (`state.json`).
+///
+/// Compatibility policy: every field added after v1 must be `Option` or carry `#[serde(default)]`
+/// so older files keep loading, and unknown fields are ignored (never `deny_unknown_fields`) so a
+/// downgraded binary can still read a file written by a newer one. Renames and type changes
+/// need a serde alias or a migration instead.
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub(crate) struct ManagerState {
+    #[serde(default)]
     pub(crate) usage_cache: BTreeMap<String, CachedUsage>,
+    /// Identity fields parsed from each account's `auth.json`, keyed by label. Only `accounts
+    /// list` and `state show` read it; `whoami`, `pools validate` and the gateway's token loads
+    /// parse `auth.json` themselves, since they need its tokens (or fields not kept here) anyway.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) auth_index: BTreeMap<String, AuthIndexEntry>,
+    /// Break-glass labels skipped by automatic selection; usable only when named explicitly.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) reserved: BTreeSet<String>,
+    /// Free-form tags per label, set with `accounts tag`, for operating on groups of accounts.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) tags: BTreeMap<String, BTreeSet<String>>,
+    /// Alternate names for accounts (`alias -> label`), set with `accounts alias`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) aliases: BTreeMap<String, String>,
+    /// Last account picked by the `round_robin` fallback of `run --no-usage-fetch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_no_fetch_pick: Option<String>,
+}
+
+/// Cached identity for one account, valid while `auth.json` keeps the same mtime and size.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct AuthIndexEntry {
+    pub(crate) auth_mtime_ms: i64,
+    pub(crate) auth_len: u64,
+    #[serde(default)]
+    pub(crate) email: Option<String>,
+    #[serde(default)]
+    pub(crate) chatgpt_account_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -18,15 +56,25 @@ pub(crate) struct CachedUsage {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub(crate) struct UsageSnapshot {
+    #[serde(default)]
     pub(crate) five_hour: Option<WindowSnapshot>,
+    #[serde(default)]
     pub(crate) weekly: Option<WindowSnapshot>,
+    /// With `rate_limit_windows = "raw"`: the windows exactly as reported, in place of the
+    /// buckets above.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) primary: Option<WindowSnapshot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) secondary: Option<WindowSnapshot>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub(crate) struct WindowSnapshot {
     pub(crate) used_percent: f64,
     pub(crate) remaining_percent: f64,
+    #[serde(default)]
     pub(crate) window_minutes: Option<i64>,
+    #[serde(default)]
     pub(crate) resets_at: Option<i64>,
 }
 
@@ -53,3 +101,135 @@ pub(crate) fn save_state(state_root: &Path, state: &ManagerState) -> anyhow::Res
     std::fs::rename(tmp, path)?;
     Ok(())
 }
+
+/// Applies `update` to a freshly read `state.json` and saves the result if it changed, holding an
+/// exclusive lock on `state.json.lock` throughout. Every writer goes through here, so a usage
+/// scan finishing while an operator reserves or tags an account merges with that change instead
+/// of overwriting it with a copy read before its fetches. Nothing is written when the file cannot
+/// be parsed or `update` fails.
+pub(crate) fn update_state<T>(
+    state_root: &Path,
+    update: impl FnOnce(&mut ManagerState) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let lock_path = state_root.join("state.json.lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("opening {lock_path:?}"))?;
+    lock.lock()
+        .with_context(|| format!("locking {lock_path:?}"))?;
+
+    let mut state = load_state(state_root)?;
+    let before = state.clone();
+    let out = update(&mut state)?;
+    if state != before {
+        save_state(state_root, &state)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn load_state_accepts_v1_files_and_unknown_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("state.json"),
+            r#"{
+  "usage_cache": {
+    "work": {
+      "captured_at_ms": 1700000000000,
+      "snapshot": {
+        "weekly": { "used_percent": 40.0, "remaining_percent": 60.0 },
+        "added_in_a_future_version": true
+      }
+    }
+  },
+  "added_in_a_future_version": {}
+}
+"#,
+        )
+        .unwrap();
+
+        let state = load_state(dir.path()).unwrap();
+
+        let mut usage_cache = BTreeMap::new();
+        usage_cache.insert(
+            "work".to_string(),
+            CachedUsage {
+                captured_at_ms: 1_700_000_000_000,
+                snapshot: UsageSnapshot {
+                    five_hour: None,
+                    weekly: Some(WindowSnapshot {
+                        used_percent: 40.0,
+                        remaining_percent: 60.0,
+                        window_minutes: None,
+                        resets_at: None,
+                    }),
+                    primary: None,
+                    secondary: None,
+                },
+            },
+        );
+        assert_eq!(
+            state,
+            ManagerState {
+                usage_cache,
+                ..ManagerState::default()
+            }
+        );
+    }
+
+    #[test]
+    fn load_state_accepts_empty_object() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("state.json"), "{}\n").unwrap();
+
+        assert_eq!(load_state(dir.path()).unwrap(), ManagerState::default());
+    }
+
+    #[test]
+    fn update_state_rereads_so_concurrent_writers_do_not_lose_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let state_root = dir.path().to_path_buf();
+                std::thread::spawn(move || {
+                    update_state(&state_root, |state| {
+                        state.reserved.insert(format!("acct-{i}"));
+                        Ok(())
+                    })
+                    .unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(load_state(dir.path()).unwrap().reserved.len(), 8);
+    }
+
+    #[test]
+    fn update_state_leaves_an_unparseable_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("state.json"), "{not json").unwrap();
+
+        assert!(
+            update_state(dir.path(), |state| {
+                state.reserved.insert("a".to_string());
+                Ok(())
+            })
+            .is_err()
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("state.json")).unwrap(),
+            "{not json"
+        );
+    }
+}
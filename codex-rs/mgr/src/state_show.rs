@@ -0,0 +1,201 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::accounts;
+use crate::state::CachedUsage;
+use crate::state::ManagerState;
+use crate::state::load_state;
+use crate::usage::usage_score;
+
+/// One label known to the account homes or to any section of `state.json`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+struct StateRow {
+    label: String,
+    /// Whether an account home exists; `false` means `state.json` still mentions a removed label.
+    on_disk: bool,
+    reserved: bool,
+    tags: Vec<String>,
+    email: Option<String>,
+    usage: Option<UsageRow>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+struct UsageRow {
+    #[serde(flatten)]
+    cached: CachedUsage,
+    age_seconds: i64,
+    /// Young enough to be used without a fetch (`[usage] cache_ttl_seconds`).
+    fresh: bool,
+    /// What selection ranks this account by; `None` when the snapshot has no usable window.
+    score: Option<ScoreRow>,
+}
+
+/// [`crate::usage::Score`], with absent windows as `None` instead of `-1`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+struct ScoreRow {
+    weekly_remaining: Option<f64>,
+    five_hour_remaining: Option<f64>,
+}
+
+pub(crate) fn show(
+    accounts_root: &Path,
+    state_root: &Path,
+    config_path: &Path,
+    json: bool,
+) -> anyhow::Result<()> {
+    let state = load_state(state_root)?;
+    let on_disk = accounts::list_labels(accounts_root)?;
+    let cache_ttl_ms = crate::config::usage_cache_ttl_ms(config_path)?;
+    let rows = state_rows(&state, &on_disk, crate::time::now_ms(), cache_ttl_ms);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!("no accounts or state");
+        return Ok(());
+    }
+    for row in rows {
+        let mut flags = Vec::new();
+        if !row.on_disk {
+            flags.push("not on disk");
+        }
+        if row.reserved {
+            flags.push("reserved");
+        }
+        let flags = if flags.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", flags.join(", "))
+        };
+        println!("{}{flags}", row.label);
+        println!("  email: {}", row.email.as_deref().unwrap_or("unknown"));
+        if !row.tags.is_empty() {
+            println!("  tags: {}", row.tags.join(","));
+        }
+        let Some(usage) = row.usage else {
+            println!("  usage: none cached");
+            continue;
+        };
+        let freshness = if usage.fresh { "fresh" } else { "stale" };
+        println!("  usage: {}s old ({freshness})", usage.age_seconds);
+        match usage.score {
+            Some(score) => {
+                let percent = |p: Option<f64>| {
+                    p.map_or_else(|| "unknown".to_string(), |p| format!("{p:.1}%"))
+                };
+                println!(
+                    "  score: weekly {} 5h {}",
+                    percent(score.weekly_remaining),
+                    percent(score.five_hour_remaining)
+                );
+            }
+            None => println!("  score: none (no usage windows)"),
+        }
+    }
+    Ok(())
+}
+
+fn state_rows(
+    state: &ManagerState,
+    on_disk: &[String],
+    now_ms: i64,
+    cache_ttl_ms: i64,
+) -> Vec<StateRow> {
+    let labels: BTreeSet<&String> = on_disk
+        .iter()
+        .chain(state.usage_cache.keys())
+        .chain(state.auth_index.keys())
+        .chain(state.reserved.iter())
+        .chain(state.tags.keys())
+        .collect();
+    labels
+        .into_iter()
+        .map(|label| StateRow {
+            label: label.clone(),
+            on_disk: on_disk.contains(label),
+            reserved: state.reserved.contains(label),
+            tags: state
+                .tags
+                .get(label)
+                .map(|tags| tags.iter().cloned().collect())
+                .unwrap_or_default(),
+            email: state
+                .auth_index
+                .get(label)
+                .and_then(|entry| entry.email.clone()),
+            usage: state
+                .usage_cache
+                .get(label)
+                .map(|cached| usage_row(cached, now_ms, cache_ttl_ms)),
+        })
+        .collect()
+}
+
+fn usage_row(cached: &CachedUsage, now_ms: i64, cache_ttl_ms: i64) -> UsageRow {
+    let age_ms = now_ms - cached.captured_at_ms;
+    UsageRow {
+        cached: cached.clone(),
+        age_seconds: age_ms / 1000,
+        fresh: age_ms <= cache_ttl_ms,
+        score: usage_score(&cached.snapshot).map(|score| ScoreRow {
+            weekly_remaining: score.weekly_present.then_some(score.weekly_remaining),
+            five_hour_remaining: score.five_present.then_some(score.five_remaining),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::UsageSnapshot;
+    use crate::state::WindowSnapshot;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn state_rows_merge_disk_and_state_labels_with_scores() {
+        let mut state = ManagerState::default();
+        state.usage_cache.insert(
+            "work".to_string(),
+            CachedUsage {
+                captured_at_ms: 1_000,
+                snapshot: UsageSnapshot {
+                    five_hour: None,
+                    weekly: Some(WindowSnapshot {
+                        used_percent: 25.0,
+                        remaining_percent: 75.0,
+                        window_minutes: Some(10_080),
+                        resets_at: None,
+                    }),
+                    primary: None,
+                    secondary: None,
+                },
+            },
+        );
+        state.reserved.insert("gone".to_string());
+
+        let rows = state_rows(&state, &["work".to_string()], 61_000, 900_000);
+
+        assert_eq!(
+            rows.iter()
+                .map(|row| row.label.as_str())
+                .collect::<Vec<_>>(),
+            vec!["gone", "work"]
+        );
+        assert!(!rows[0].on_disk);
+        assert!(rows[0].reserved);
+        assert_eq!(rows[0].usage, None);
+        let usage = rows[1].usage.as_ref().expect("work has usage");
+        assert_eq!(usage.age_seconds, 60);
+        assert!(usage.fresh);
+        assert_eq!(
+            usage.score,
+            Some(ScoreRow {
+                weekly_remaining: Some(75.0),
+                five_hour_remaining: None,
+            })
+        );
+    }
+}
@@ -7,3 +7,45 @@ pub(crate) fn now_ms() -> i64 {
         .map(|d| d.as_millis() as i64)
         .unwrap_or(0)
 }
+
+/// Wall-clock source for expiry and TTL decisions, so they can be tested without sleeping.
+pub(crate) trait Clock: std::fmt::Debug + Send + Sync {
+    fn now_ms(&self) -> i64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> i64 {
+        now_ms()
+    }
+}
+
+/// A clock that only moves when told to.
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct MockClock {
+    now_ms: std::sync::atomic::AtomicI64,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub(crate) fn new(now_ms: i64) -> Self {
+        Self {
+            now_ms: std::sync::atomic::AtomicI64::new(now_ms),
+        }
+    }
+
+    pub(crate) fn advance_ms(&self, ms: i64) {
+        self.now_ms
+            .fetch_add(ms, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now_ms(&self) -> i64 {
+        self.now_ms.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
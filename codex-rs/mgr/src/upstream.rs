@@ -2,6 +2,7 @@ use anyhow::Context;
 use std::ffi::OsString;
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 
@@ -11,21 +12,71 @@ pub(crate) fn resolve_codex_binary(codex_path: Option<&PathBuf>) -> PathBuf {
         .unwrap_or_else(|| PathBuf::from("codex"))
 }
 
+/// Per-account environment overrides, stored as a flat table of string values.
+const ACCOUNT_ENV_FILE: &str = "env.toml";
+
+/// Loads `<account_home>/env.toml`, returning an empty list when the file does not exist.
+///
+/// Values override the ambient environment for the upstream process. `CODEX_HOME` is rejected
+/// because the account home is always authoritative.
+pub(crate) fn load_account_env(account_home: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let path = account_home.join(ACCOUNT_ENV_FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("read {}", path.display())),
+    };
+    parse_account_env(&contents).with_context(|| format!("parse {}", path.display()))
+}
+
+fn parse_account_env(contents: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let table: toml::Table = toml::from_str(contents)?;
+    let mut env = Vec::with_capacity(table.len());
+    for (key, value) in table {
+        if key.is_empty() || key.contains('=') || key.contains('\0') {
+            anyhow::bail!("invalid environment variable name {key:?}");
+        }
+        if key == "CODEX_HOME" {
+            anyhow::bail!("CODEX_HOME cannot be overridden; it is always the account home");
+        }
+        let toml::Value::String(value) = value else {
+            anyhow::bail!("environment variable {key:?} must be a string");
+        };
+        env.push((key, value));
+    }
+    Ok(env)
+}
+
+/// Environment variable set on the upstream process to the selected account label.
+const ACCOUNT_LABEL_ENV: &str = "CODEX_MGR_ACCOUNT_LABEL";
+
+/// The account an upstream process runs as.
+pub(crate) struct UpstreamAccount {
+    pub(crate) home: PathBuf,
+    pub(crate) label: String,
+    /// Overrides loaded from the account's `env.toml`.
+    pub(crate) env: Vec<(String, String)>,
+}
+
+/// Replaces the current process with upstream `codex`.
+///
+/// Precedence, lowest to highest: the ambient environment, the account's `env.toml`, then
+/// `CODEX_MGR_ACCOUNT_LABEL` and `CODEX_HOME`.
 pub(crate) fn exec_upstream(
     codex: PathBuf,
-    codex_home: Option<PathBuf>,
+    account: Option<UpstreamAccount>,
     args: Vec<OsString>,
 ) -> anyhow::Result<()> {
     #[cfg(unix)]
     {
-        let mut cmd = build_upstream_command(codex, codex_home, args);
+        let mut cmd = build_upstream_command(codex, account, args);
         let err = cmd.exec();
         Err(err).context("exec upstream codex")
     }
 
     #[cfg(not(unix))]
     {
-        let mut cmd = build_upstream_command(codex, codex_home, args);
+        let mut cmd = build_upstream_command(codex, account, args);
         let status = cmd.status().context("running upstream codex")?;
         if status.success() {
             Ok(())
@@ -37,12 +88,14 @@ pub(crate) fn exec_upstream(
 
 fn build_upstream_command(
     codex: PathBuf,
-    codex_home: Option<PathBuf>,
+    account: Option<UpstreamAccount>,
     args: Vec<OsString>,
 ) -> Command {
     let mut cmd = Command::new(codex);
-    if let Some(home) = codex_home {
-        cmd.env("CODEX_HOME", home);
+    if let Some(account) = account {
+        cmd.envs(account.env);
+        cmd.env(ACCOUNT_LABEL_ENV, account.label);
+        cmd.env("CODEX_HOME", account.home);
     }
     cmd.args(args);
     cmd
@@ -67,28 +120,67 @@ mod tests {
     use pretty_assertions::assert_eq;
 
     #[test]
-    fn build_upstream_command_sets_program_args_and_codex_home() {
+    fn build_upstream_command_sets_program_and_args() {
         let codex = PathBuf::from("/tmp/codex");
-        let codex_home = Some(PathBuf::from("/tmp/home"));
         let args = vec![
             OsString::from("run"),
             OsString::from("--model"),
             OsString::from("gpt-5.4"),
         ];
 
-        let cmd = build_upstream_command(codex.clone(), codex_home.clone(), args.clone());
+        let cmd = build_upstream_command(codex.clone(), None, args.clone());
 
         assert_eq!(cmd.get_program(), codex.as_os_str());
         assert_eq!(
             cmd.get_args().collect::<Vec<_>>(),
             args.iter().collect::<Vec<_>>()
         );
+        assert_eq!(cmd.get_envs().count(), 0);
+    }
+
+    #[test]
+    fn build_upstream_command_sets_account_env_label_and_codex_home() {
+        let env = parse_account_env("HTTPS_PROXY = \"http://proxy:3128\"\n").unwrap();
+        assert_eq!(
+            env,
+            vec![("HTTPS_PROXY".to_string(), "http://proxy:3128".to_string())]
+        );
+
+        let cmd = build_upstream_command(
+            PathBuf::from("/tmp/codex"),
+            Some(UpstreamAccount {
+                home: PathBuf::from("/tmp/home"),
+                label: "work".to_string(),
+                env,
+            }),
+            Vec::new(),
+        );
+        let envs = cmd
+            .get_envs()
+            .map(|(key, value)| (key.to_os_string(), value.map(OsString::from)))
+            .collect::<Vec<_>>();
         assert_eq!(
-            cmd.get_envs().collect::<Vec<_>>(),
-            vec![(
-                OsString::from("CODEX_HOME").as_os_str(),
-                codex_home.as_ref().map(|path| path.as_path().as_os_str()),
-            )]
+            envs,
+            vec![
+                (
+                    OsString::from("CODEX_HOME"),
+                    Some(OsString::from("/tmp/home"))
+                ),
+                (
+                    OsString::from("CODEX_MGR_ACCOUNT_LABEL"),
+                    Some(OsString::from("work"))
+                ),
+                (
+                    OsString::from("HTTPS_PROXY"),
+                    Some(OsString::from("http://proxy:3128"))
+                ),
+            ]
         );
     }
+
+    #[test]
+    fn account_env_rejects_codex_home_and_non_strings() {
+        assert!(parse_account_env("CODEX_HOME = \"/elsewhere\"\n").is_err());
+        assert!(parse_account_env("PORT = 8080\n").is_err());
+    }
 }
@@ -0,0 +1,136 @@
+//! The `/readyz` check of `gateway.upstream_health_path`.
+
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+/// How long one upstream probe result answers `/readyz`.
+const UPSTREAM_HEALTH_CACHE_TTL: Duration = Duration::from_secs(5);
+const UPSTREAM_HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The `/readyz` probe of `gateway.upstream_health_path`. Results are cached for
+/// `UPSTREAM_HEALTH_CACHE_TTL`, and the lock is held while probing so concurrent readiness
+/// checks share one upstream request.
+pub(crate) struct UpstreamHealthCheck {
+    url: String,
+    last: Mutex<Option<(Instant, bool)>>,
+}
+
+impl UpstreamHealthCheck {
+    pub(crate) fn new(upstream_base_url: &str, path: &str) -> Self {
+        Self {
+            url: format!("{}{path}", upstream_base_url.trim().trim_end_matches('/')),
+            last: Mutex::new(None),
+        }
+    }
+
+    #[expect(
+        clippy::await_holding_invalid_type,
+        reason = "the lock is held across the probe so concurrent readiness checks share one upstream request"
+    )]
+    pub(crate) async fn is_reachable(&self, http: &reqwest::Client) -> bool {
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, reachable)) = *last
+            && checked_at.elapsed() < UPSTREAM_HEALTH_CACHE_TTL
+        {
+            return reachable;
+        }
+        // Any answer below 500 counts: health paths commonly reject unauthenticated callers, and
+        // that still proves the upstream is up.
+        let reachable = match http
+            .get(&self.url)
+            .timeout(UPSTREAM_HEALTH_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(resp) if !resp.status().is_server_error() => true,
+            Ok(resp) => {
+                tracing::warn!(url = %self.url, status = %resp.status(), "upstream health check failed");
+                false
+            }
+            Err(err) => {
+                tracing::warn!(url = %self.url, error = %err, "upstream health check failed");
+                false
+            }
+        };
+        *last = Some((Instant::now(), reachable));
+        reachable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicU16;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    /// Serves `GET /health` answering with the status in `status`, counting hits in `hits`.
+    async fn spawn_health_upstream(status: Arc<AtomicU16>, hits: Arc<AtomicUsize>) -> String {
+        let router = axum::Router::new().route(
+            "/health",
+            axum::routing::get(move || {
+                let status = Arc::clone(&status);
+                let hits = Arc::clone(&hits);
+                async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::from_u16(status.load(Ordering::SeqCst)).expect("status")
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind upstream");
+        let addr = listener.local_addr().expect("upstream addr");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn below_500_is_reachable_and_cached_within_the_ttl() {
+        let http = reqwest::Client::new();
+        let status = Arc::new(AtomicU16::new(200));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let base_url = spawn_health_upstream(Arc::clone(&status), Arc::clone(&hits)).await;
+
+        let check = UpstreamHealthCheck::new(&base_url, "/health");
+        assert!(check.is_reachable(&http).await);
+        // A failure inside the TTL is not seen: the cached answer is reused without a probe.
+        status.store(503, Ordering::SeqCst);
+        assert!(check.is_reachable(&http).await);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        status.store(401, Ordering::SeqCst);
+        let check = UpstreamHealthCheck::new(&base_url, "/health");
+        assert!(check.is_reachable(&http).await);
+    }
+
+    #[tokio::test]
+    async fn server_errors_and_connect_failures_are_unreachable() {
+        let http = reqwest::Client::new();
+        let status = Arc::new(AtomicU16::new(502));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let base_url = spawn_health_upstream(status, Arc::clone(&hits)).await;
+        assert!(
+            !UpstreamHealthCheck::new(&base_url, "/health")
+                .is_reachable(&http)
+                .await
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind closed port");
+        let closed_addr = closed.local_addr().expect("closed addr");
+        drop(closed);
+        assert!(
+            !UpstreamHealthCheck::new(&format!("http://{closed_addr}"), "/health")
+                .is_reachable(&http)
+                .await
+        );
+    }
+}
@@ -8,19 +8,70 @@ use codex_protocol::protocol::RateLimitWindow;
 use futures::StreamExt;
 use futures::stream;
 use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::Duration;
 
 use crate::accounts;
+use crate::config;
+use crate::config::NoFetchFallback;
+use crate::config::RateLimitWindows;
+use crate::exit_code::ErrorCategory;
 use crate::layout::ensure_shared_layout;
+use crate::selection::SelectionStrategy;
 use crate::state::CachedUsage;
 use crate::state::UsageSnapshot;
 use crate::state::WindowSnapshot;
-use crate::time::now_ms;
+use crate::time::Clock;
+use crate::time::SystemClock;
 
 const DEFAULT_CHATGPT_BASE_URL: &str = "https://chatgpt.com/backend-api/";
-pub(crate) const USAGE_CACHE_TTL_SECONDS: i64 = 900;
-const USAGE_CACHE_TTL_MS: i64 = 900_000;
-const USAGE_FETCH_CONCURRENCY: i64 = 5;
+pub(crate) const DEFAULT_USAGE_FETCH_CONCURRENCY: i64 = 5;
+const MAX_USAGE_FETCH_CONCURRENCY: i64 = 32;
+const DEFAULT_USAGE_FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Clone, Debug)]
+pub(crate) struct ScanOptions<'a> {
+    /// Refresh account tokens before fetching usage.
+    pub(crate) force_refresh: bool,
+    /// Fetch usage even when a fresh cached snapshot exists.
+    pub(crate) ignore_cache: bool,
+    /// Maximum concurrent usage fetches; clamped to `1..=32`.
+    pub(crate) concurrency: i64,
+    /// Upper bound on a single account's usage fetch; timed-out accounts are skipped.
+    pub(crate) fetch_timeout: Duration,
+    /// Time source for cache freshness and snapshot capture times.
+    pub(crate) clock: &'a dyn Clock,
+    /// When a fetch fails, fall back to the account's expired cached snapshot.
+    pub(crate) allow_stale: bool,
+    /// Stop waiting for usage fetches at this instant; accounts still in flight count as failed
+    /// fetches and the scan returns what it has so far.
+    pub(crate) deadline: Option<tokio::time::Instant>,
+    /// Only scan these labels (e.g. one pool's members); `None` scans every account.
+    pub(crate) only_labels: Option<BTreeSet<String>>,
+    /// How old a cached snapshot may be and still skip the fetch; `None` reads the CLI's
+    /// `[usage] cache_ttl_seconds`.
+    pub(crate) cache_ttl_ms: Option<i64>,
+}
+
+impl Default for ScanOptions<'_> {
+    fn default() -> Self {
+        Self {
+            force_refresh: false,
+            ignore_cache: false,
+            concurrency: DEFAULT_USAGE_FETCH_CONCURRENCY,
+            fetch_timeout: DEFAULT_USAGE_FETCH_TIMEOUT,
+            clock: &SystemClock,
+            allow_stale: false,
+            deadline: None,
+            only_labels: None,
+            cache_ttl_ms: None,
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Score {
@@ -30,9 +81,16 @@ pub struct Score {
     pub five_remaining: f64,
 }
 
-fn usage_score(snapshot: &UsageSnapshot) -> Option<Score> {
-    let weekly = snapshot.weekly.as_ref().map(|w| w.remaining_percent);
-    let five = snapshot.five_hour.as_ref().map(|w| w.remaining_percent);
+/// Raw snapshots (no buckets) score the secondary window in the weekly slot and the primary in
+/// the five-hour slot, so ranking still looks at the longer-lived limit first.
+pub(crate) fn usage_score(snapshot: &UsageSnapshot) -> Option<Score> {
+    let (weekly, five) = if snapshot.weekly.is_none() && snapshot.five_hour.is_none() {
+        (&snapshot.secondary, &snapshot.primary)
+    } else {
+        (&snapshot.weekly, &snapshot.five_hour)
+    };
+    let weekly = weekly.as_ref().map(|w| w.remaining_percent);
+    let five = five.as_ref().map(|w| w.remaining_percent);
     if weekly.is_none() && five.is_none() {
         return None;
     }
@@ -45,104 +103,294 @@ fn usage_score(snapshot: &UsageSnapshot) -> Option<Score> {
     })
 }
 
+/// Why an account was left out of auto selection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Unusable {
+    /// The account home's shared-entry symlinks could not be repaired.
+    LayoutBroken,
+    /// No `auth.json` (or no usable credentials in it).
+    AuthMissing,
+    /// `--refresh` was requested, the token refresh failed, and the usage fetch then failed too.
+    RefreshFailed,
+    /// The usage request failed or timed out.
+    FetchFailed,
+    /// Usage was fetched but reported no five-hour or weekly window.
+    NoUsageData,
+    /// A known usage window has no headroom left.
+    Exhausted,
+    /// Held back via `accounts set-reserved`; only used when selected explicitly.
+    Reserved,
+}
+
+impl Unusable {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::LayoutBroken => "layout_broken",
+            Self::AuthMissing => "auth_missing",
+            Self::RefreshFailed => "refresh_failed",
+            Self::FetchFailed => "fetch_failed",
+            Self::NoUsageData => "no_usage_data",
+            Self::Exhausted => "exhausted",
+            Self::Reserved => "reserved",
+        }
+    }
+}
+
+/// Every account was unusable; returned by [`select_best_label`] so callers can report reasons.
+#[derive(Debug, Serialize)]
+pub(crate) struct UnusableAccounts {
+    pub(crate) accounts: BTreeMap<String, Unusable>,
+}
+
+impl UnusableAccounts {
+    pub(crate) fn into_error(self) -> anyhow::Error {
+        let reasons = self
+            .accounts
+            .iter()
+            .map(|(label, reason)| format!("{label} ({})", reason.as_str()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        ErrorCategory::NoAccounts.wrap(anyhow::anyhow!(
+            "no usable accounts: {reasons}; try `codex-mgr run --refresh --auto -- <args>` or re-login"
+        ))
+    }
+}
+
+/// Usage scores for usable accounts plus the reason each remaining account was skipped.
+pub(crate) struct UsageScan {
+    pub(crate) scores: HashMap<String, Score>,
+    pub(crate) unusable: BTreeMap<String, Unusable>,
+    /// With `allow_stale`: expired cached scores for accounts whose fetch failed. These accounts
+    /// also stay in `unusable` with the failure reason.
+    pub(crate) stale_scores: HashMap<String, Score>,
+}
+
+impl Score {
+    fn is_exhausted(&self) -> bool {
+        (self.weekly_present && self.weekly_remaining <= 0.0)
+            || (self.five_present && self.five_remaining <= 0.0)
+    }
+}
+
 pub(crate) async fn select_best_label(
     shared_root: &Path,
     accounts_root: &Path,
     state_root: &Path,
-    refresh: bool,
-    no_cache: bool,
-) -> anyhow::Result<String> {
+    config_path: &Path,
+    options: ScanOptions<'_>,
+    strategy: &dyn SelectionStrategy,
+) -> anyhow::Result<Result<String, UnusableAccounts>> {
     let labels = accounts::list_labels(accounts_root)?;
     if labels.is_empty() {
-        anyhow::bail!("no accounts found; run `codex-mgr login --label ...` first");
+        return Err(ErrorCategory::NoAccounts.wrap(anyhow::anyhow!(
+            "no accounts found; run `codex-mgr login --label ...` first"
+        )));
     }
 
-    // We keep base_url simple and deterministic for v1.
-    let _chatgpt_base_url =
-        load_chatgpt_base_url(shared_root).unwrap_or_else(|_| DEFAULT_CHATGPT_BASE_URL.to_string());
-
-    let state = crate::state::load_state(state_root).unwrap_or_default();
-    let now = now_ms();
+    // `scan_usage` serves cached scores when fresh and fetches the rest, so the strategy always
+    // sees every usable account.
+    let UsageScan {
+        scores,
+        mut unusable,
+        stale_scores,
+    } = scan_usage(shared_root, accounts_root, state_root, config_path, options).await?;
+    let reserved = crate::state::load_state(state_root)
+        .unwrap_or_default()
+        .reserved;
+
+    if let Some(label) = pick_usable(scores, &reserved, &mut unusable, strategy) {
+        return Ok(Ok(label));
+    }
+    // Last resort during backend outages: rank the expired snapshots of accounts whose fetch
+    // failed, keeping their failure reasons if even that finds nothing.
+    let mut stale_unusable = BTreeMap::new();
+    if let Some(label) = pick_usable(stale_scores, &reserved, &mut stale_unusable, strategy) {
+        tracing::warn!(
+            %label,
+            "usage fetches failed; selecting from stale cached usage (--allow-stale)"
+        );
+        return Ok(Ok(label));
+    }
+    for (label, reason) in stale_unusable {
+        if reason != Unusable::Reserved {
+            unusable.insert(label, reason);
+        }
+    }
+    Ok(Err(UnusableAccounts { accounts: unusable }))
+}
 
-    let mut best: Option<(String, Score)> = None;
+/// `run --no-usage-fetch`: ranks accounts by whatever usage is cached, expired or not, without
+/// touching the network. When nothing cached is usable, `fallback` picks among the unreserved
+/// accounts that have an `auth.json` and are not known to be exhausted.
+pub(crate) fn select_cached_label(
+    accounts_root: &Path,
+    state_root: &Path,
+    only_labels: Option<&BTreeSet<String>>,
+    strategy: &dyn SelectionStrategy,
+    fallback: NoFetchFallback,
+) -> anyhow::Result<Result<String, UnusableAccounts>> {
+    let labels = restrict_labels(accounts::list_labels(accounts_root)?, only_labels);
+    if labels.is_empty() {
+        return Err(ErrorCategory::NoAccounts.wrap(anyhow::anyhow!(
+            "no accounts found; run `codex-mgr login --label ...` first"
+        )));
+    }
+    // Propagated rather than defaulted: the round-robin pick below is saved back, and saving a
+    // default state over a file that failed to parse would wipe reservations, tags and aliases.
+    let state = crate::state::load_state(state_root).context("reading state.json")?;
 
-    // First pass: check cache
-    let mut to_fetch = Vec::new();
+    let mut scores = HashMap::new();
+    let mut unusable = BTreeMap::new();
     for label in &labels {
-        let account_home = accounts_root.join(label);
-        // Ensure layout exists (fast check)
-        if ensure_shared_layout(&account_home, shared_root).is_err() {
-            continue;
+        if !accounts_root.join(label).join("auth.json").is_file() {
+            unusable.insert(label.clone(), Unusable::AuthMissing);
+        } else if let Some(score) = stale_score(&state, label) {
+            scores.insert(label.clone(), score);
         }
+    }
+    if let Some(label) = pick_usable(scores, &state.reserved, &mut unusable, strategy) {
+        return Ok(Ok(label));
+    }
 
-        if !no_cache
-            && let Some(cached) = state.usage_cache.get(label)
-            && (now - cached.captured_at_ms) <= USAGE_CACHE_TTL_MS
-            && let Some(score) = usage_score(&cached.snapshot)
-        {
-            best = pick_best(best, label.clone(), score);
-        } else {
-            to_fetch.push(label.clone());
+    let candidates: Vec<String> = labels
+        .into_iter()
+        .filter(|label| !unusable.contains_key(label) && !state.reserved.contains(label))
+        .collect();
+    let picked = match fallback {
+        NoFetchFallback::First => candidates.first().cloned(),
+        NoFetchFallback::RoundRobin => {
+            round_robin_next(&candidates, state.last_no_fetch_pick.as_deref()).cloned()
         }
+    };
+    let Some(label) = picked else {
+        for label in &state.reserved {
+            if only_labels.is_none_or(|only| only.contains(label)) {
+                unusable.entry(label.clone()).or_insert(Unusable::Reserved);
+            }
+        }
+        return Ok(Err(UnusableAccounts { accounts: unusable }));
+    };
+    tracing::info!(%label, ?fallback, "no cached usage to rank by; using the fallback pick");
+    if fallback == NoFetchFallback::RoundRobin
+        && let Err(err) = crate::state::update_state(state_root, |state| {
+            state.last_no_fetch_pick = Some(label.clone());
+            Ok(())
+        })
+    {
+        tracing::warn!(error = %err, "failed to persist the round-robin pick");
     }
+    Ok(Ok(label))
+}
 
-    // If we have a cached winner and aren't forced to refresh, we could return early.
-    // However, the original logic fetched everyone that wasn't cached.
-    // To support `serve` needing *all* scores, we should probably separate "get best" from "fetch all".
-    // For `select_best_label` (used by run command), we want the best one.
-    // Let's reuse the new `scan_and_update_usage` but specialized for this flow?
-    // Actually, let's just use `scan_and_update_usage` to get the map, then pick from it.
-
-    // But `select_best_label` had an optimization: it checked cache first.
-    // `scan_and_update_usage` should also check cache.
-
-    let usage_map =
-        scan_and_update_usage(shared_root, accounts_root, state_root, refresh, no_cache).await?;
-
-    // Because scan_and_update_usage returns a map of *all* valid accounts with scores (cached or fresh),
-    // we just iterate it to find the best.
+/// The candidate after `last` in order, wrapping around; the first one when `last` is unset or no
+/// longer a candidate.
+fn round_robin_next<'a>(candidates: &'a [String], last: Option<&str>) -> Option<&'a String> {
+    let next = last
+        .and_then(|last| candidates.iter().position(|label| label == last))
+        .map_or(0, |idx| idx + 1);
+    candidates.get(next % candidates.len().max(1))
+}
 
-    let mut best: Option<(String, Score)> = None;
-    for (label, score) in usage_map {
-        best = pick_best(best, label, score);
+/// Keeps the labels in `only`, or all of them when `only` is `None`.
+pub(crate) fn restrict_labels(
+    mut labels: Vec<String>,
+    only: Option<&BTreeSet<String>>,
+) -> Vec<String> {
+    if let Some(only) = only {
+        labels.retain(|label| only.contains(label));
     }
+    labels
+}
 
-    let Some((label, _score)) = best else {
-        anyhow::bail!(
-            "no usable accounts (usage unavailable); try `codex-mgr run --refresh --auto -- <args>` or re-login"
-        );
-    };
-    Ok(label)
+/// Lets `strategy` choose among `scores`, skipping reserved and exhausted accounts (recorded in
+/// `unusable`).
+fn pick_usable(
+    scores: HashMap<String, Score>,
+    reserved: &BTreeSet<String>,
+    unusable: &mut BTreeMap<String, Unusable>,
+    strategy: &dyn SelectionStrategy,
+) -> Option<String> {
+    let mut candidates: Vec<(String, Score)> = Vec::with_capacity(scores.len());
+    for (label, score) in scores {
+        if reserved.contains(&label) {
+            unusable.insert(label, Unusable::Reserved);
+        } else if score.is_exhausted() {
+            unusable.insert(label, Unusable::Exhausted);
+        } else {
+            candidates.push((label, score));
+        }
+    }
+    candidates.sort_by(|(a, _), (b, _)| a.cmp(b));
+    strategy.pick(&candidates)
 }
 
 pub async fn scan_and_update_usage(
     shared_root: &Path,
     accounts_root: &Path,
     state_root: &Path,
-    force_refresh: bool,
-    ignore_cache: bool,
-) -> anyhow::Result<std::collections::HashMap<String, Score>> {
-    let labels = accounts::list_labels(accounts_root)?;
+    config_path: &Path,
+    options: ScanOptions<'_>,
+) -> anyhow::Result<HashMap<String, Score>> {
+    Ok(
+        scan_usage(shared_root, accounts_root, state_root, config_path, options)
+            .await?
+            .scores,
+    )
+}
+
+pub(crate) async fn scan_usage(
+    shared_root: &Path,
+    accounts_root: &Path,
+    state_root: &Path,
+    config_path: &Path,
+    options: ScanOptions<'_>,
+) -> anyhow::Result<UsageScan> {
+    let ScanOptions {
+        force_refresh,
+        ignore_cache,
+        concurrency,
+        fetch_timeout,
+        clock,
+        allow_stale,
+        deadline,
+        only_labels,
+        cache_ttl_ms,
+    } = options;
+    let labels = restrict_labels(accounts::list_labels(accounts_root)?, only_labels.as_ref());
+    // Read here rather than passed in, so every caller writes cache entries the same way.
+    let windows = config::rate_limit_windows(config_path)?;
+    let cache_ttl_ms = match cache_ttl_ms {
+        Some(ttl_ms) => ttl_ms,
+        None => config::usage_cache_ttl_ms(config_path)?,
+    };
     let chatgpt_base_url =
         load_chatgpt_base_url(shared_root).unwrap_or_else(|_| DEFAULT_CHATGPT_BASE_URL.to_string());
 
-    let mut state = crate::state::load_state(state_root).unwrap_or_default();
-    let now = now_ms();
+    // Only read here: fetches can take a while, so new snapshots are merged into a fresh copy
+    // under the state lock at the end rather than saving this one over interim writes.
+    let state = crate::state::load_state(state_root).unwrap_or_default();
+    let now = clock.now_ms();
+    let mut fetched = BTreeMap::new();
 
-    let mut scores = std::collections::HashMap::new();
+    let mut scores = HashMap::new();
+    let mut unusable = BTreeMap::new();
+    let mut stale_scores = HashMap::new();
     let mut to_fetch = Vec::new();
 
     for label in labels {
         let account_home = accounts_root.join(&label);
         if ensure_shared_layout(&account_home, shared_root).is_err() {
+            unusable.insert(label, Unusable::LayoutBroken);
             continue;
         }
 
         if !ignore_cache
-            && let Some(cached) = state.usage_cache.get(&label)
-            && (now - cached.captured_at_ms) <= USAGE_CACHE_TTL_MS
-            && let Some(score) = usage_score(&cached.snapshot)
             && !force_refresh
+            && let Some(score) = state
+                .usage_cache
+                .get(&label)
+                .and_then(|cached| fresh_cached_score(cached, now, cache_ttl_ms))
         {
             scores.insert(label, score);
             continue;
@@ -151,10 +399,16 @@ pub async fn scan_and_update_usage(
     }
 
     if to_fetch.is_empty() {
-        return Ok(scores);
+        return Ok(UsageScan {
+            scores,
+            unusable,
+            stale_scores,
+        });
     }
 
-    let concurrency = usize::try_from(USAGE_FETCH_CONCURRENCY).unwrap_or(1);
+    let concurrency =
+        usize::try_from(concurrency.clamp(1, MAX_USAGE_FETCH_CONCURRENCY)).unwrap_or(1);
+    let mut pending: BTreeSet<String> = to_fetch.iter().cloned().collect();
     let stream = stream::iter(to_fetch.into_iter().map(|label| {
         let chatgpt_base_url = chatgpt_base_url.clone();
         let accounts_root = accounts_root.to_path_buf();
@@ -165,92 +419,163 @@ pub async fn scan_and_update_usage(
                 false,
                 AuthCredentialsStoreMode::File,
             );
-            if force_refresh {
-                let _ = auth_manager.refresh_token().await;
-            }
+            let refresh_failed = force_refresh && auth_manager.refresh_token().await.is_err();
             let Some(auth) = auth_manager.auth().await else {
-                return (label, None);
+                return (label, Err(Unusable::AuthMissing));
+            };
+            let fetch_failed = if refresh_failed {
+                Unusable::RefreshFailed
+            } else {
+                Unusable::FetchFailed
             };
 
-            let snapshot = fetch_usage_snapshot(&chatgpt_base_url, &auth).await.ok();
+            let snapshot = match tokio::time::timeout(
+                fetch_timeout,
+                fetch_usage_snapshot(&chatgpt_base_url, &auth, windows),
+            )
+            .await
+            {
+                Ok(result) => result.map_err(|_| fetch_failed),
+                Err(_) => {
+                    tracing::warn!(%label, ?fetch_timeout, "usage fetch timed out");
+                    Err(fetch_failed)
+                }
+            };
             (label, snapshot)
         }
     }))
     .buffer_unordered(concurrency);
 
     futures::pin_mut!(stream);
-    while let Some((label, snapshot)) = stream.next().await {
-        let Some(snapshot) = snapshot else { continue };
+    loop {
+        let next = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, stream.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    tracing::warn!(
+                        pending = pending.len(),
+                        "usage selection deadline reached; using the accounts scored so far"
+                    );
+                    break;
+                }
+            },
+            None => stream.next().await,
+        };
+        let Some((label, snapshot)) = next else {
+            break;
+        };
+        pending.remove(&label);
+        let snapshot = match snapshot {
+            Ok(snapshot) => snapshot,
+            Err(reason) => {
+                if allow_stale
+                    && matches!(reason, Unusable::FetchFailed | Unusable::RefreshFailed)
+                    && let Some(score) = stale_score(&state, &label)
+                {
+                    stale_scores.insert(label.clone(), score);
+                }
+                unusable.insert(label, reason);
+                continue;
+            }
+        };
 
         let score = usage_score(&snapshot);
-        state.usage_cache.insert(
+        fetched.insert(
             label.clone(),
             CachedUsage {
-                captured_at_ms: now_ms(),
+                captured_at_ms: clock.now_ms(),
                 snapshot,
             },
         );
 
-        if let Some(score) = score {
-            scores.insert(label, score);
+        match score {
+            Some(score) => {
+                scores.insert(label, score);
+            }
+            None => {
+                unusable.insert(label, Unusable::NoUsageData);
+            }
+        }
+    }
+
+    for label in pending {
+        if allow_stale && let Some(score) = stale_score(&state, &label) {
+            stale_scores.insert(label.clone(), score);
         }
+        unusable.insert(label, Unusable::FetchFailed);
     }
 
-    crate::state::save_state(state_root, &state).ok();
-    Ok(scores)
+    if !fetched.is_empty()
+        && let Err(err) = crate::state::update_state(state_root, |state| {
+            state.usage_cache.extend(fetched);
+            Ok(())
+        })
+    {
+        tracing::warn!(error = %err, "failed to persist usage cache");
+    }
+    Ok(UsageScan {
+        scores,
+        unusable,
+        stale_scores,
+    })
 }
 
-// Deprecated in favor of the full `scan_and_update_usage` logic, but kept for signature compatibility if needed (it was rewritten above).
-
-fn pick_best(
-    current: Option<(String, Score)>,
-    label: String,
-    score: Score,
-) -> Option<(String, Score)> {
-    let key = |s: &Score| {
-        (
-            i32::from(s.weekly_present),
-            s.weekly_remaining,
-            i32::from(s.five_present),
-            s.five_remaining,
-        )
-    };
+/// The cached score regardless of age, for `allow_stale`.
+fn stale_score(state: &crate::state::ManagerState, label: &str) -> Option<Score> {
+    state
+        .usage_cache
+        .get(label)
+        .and_then(|cached| usage_score(&cached.snapshot))
+}
 
-    match current {
-        Some((best_label, best_score)) => {
-            let best_key = key(&best_score);
-            let new_key = key(&score);
-            if new_key > best_key || (new_key == best_key && label < best_label) {
-                Some((label, score))
-            } else {
-                Some((best_label, best_score))
-            }
-        }
-        None => Some((label, score)),
+/// The cached score, while the snapshot is no older than `ttl_ms`.
+pub(crate) fn fresh_cached_score(cached: &CachedUsage, now_ms: i64, ttl_ms: i64) -> Option<Score> {
+    if now_ms - cached.captured_at_ms > ttl_ms {
+        return None;
     }
+    usage_score(&cached.snapshot)
 }
 
-async fn fetch_usage_snapshot(base_url: &str, auth: &CodexAuth) -> anyhow::Result<UsageSnapshot> {
+async fn fetch_usage_snapshot(
+    base_url: &str,
+    auth: &CodexAuth,
+    windows: RateLimitWindows,
+) -> anyhow::Result<UsageSnapshot> {
     let client = BackendClient::from_auth(base_url.to_string(), auth)?;
     let rl = client.get_rate_limits().await?;
-    Ok(rate_limits_to_usage_snapshot(&rl))
+    Ok(rate_limits_to_usage_snapshot(&rl, windows))
 }
 
-fn rate_limits_to_usage_snapshot(rl: &RateLimitSnapshot) -> UsageSnapshot {
+fn rate_limits_to_usage_snapshot(
+    rl: &RateLimitSnapshot,
+    windows: RateLimitWindows,
+) -> UsageSnapshot {
+    let primary = rl.primary.as_ref().map(window_snapshot);
+    let secondary = rl.secondary.as_ref().map(window_snapshot);
+    match windows {
+        RateLimitWindows::Auto => {}
+        RateLimitWindows::PrimaryFiveSecondaryWeekly => {
+            return UsageSnapshot {
+                five_hour: primary,
+                weekly: secondary,
+                primary: None,
+                secondary: None,
+            };
+        }
+        RateLimitWindows::Raw => {
+            return UsageSnapshot {
+                five_hour: None,
+                weekly: None,
+                primary,
+                secondary,
+            };
+        }
+    }
+
     let mut five_hour = None;
     let mut weekly = None;
-
-    let mut consider = |window: &RateLimitWindow| {
-        let used = window.used_percent.clamp(0.0, 100.0);
-        let remaining = (100.0 - used).clamp(0.0, 100.0);
-        let snapshot = WindowSnapshot {
-            used_percent: used,
-            remaining_percent: remaining,
-            window_minutes: window.window_minutes,
-            resets_at: window.resets_at,
-        };
-
-        match window.window_minutes {
+    for snapshot in [primary, secondary].into_iter().flatten() {
+        match snapshot.window_minutes {
             Some(minutes) if (minutes - 300).abs() <= 5 => five_hour = Some(snapshot),
             Some(minutes) if (minutes - 10_080).abs() <= 60 => weekly = Some(snapshot),
             Some(minutes) if minutes <= 24 * 60 && five_hour.is_none() => {
@@ -259,16 +584,25 @@ fn rate_limits_to_usage_snapshot(rl: &RateLimitSnapshot) -> UsageSnapshot {
             Some(minutes) if minutes <= 7 * 24 * 60 && weekly.is_none() => weekly = Some(snapshot),
             _ => {}
         }
-    };
-
-    if let Some(primary) = rl.primary.as_ref() {
-        consider(primary);
     }
-    if let Some(secondary) = rl.secondary.as_ref() {
-        consider(secondary);
+
+    UsageSnapshot {
+        five_hour,
+        weekly,
+        primary: None,
+        secondary: None,
     }
+}
 
-    UsageSnapshot { five_hour, weekly }
+fn window_snapshot(window: &RateLimitWindow) -> WindowSnapshot {
+    let used = window.used_percent.clamp(0.0, 100.0);
+    let remaining = (100.0 - used).clamp(0.0, 100.0);
+    WindowSnapshot {
+        used_percent: used,
+        remaining_percent: remaining,
+        window_minutes: window.window_minutes,
+        resets_at: window.resets_at,
+    }
 }
 
 fn load_chatgpt_base_url(shared_root: &Path) -> anyhow::Result<String> {
@@ -285,3 +619,192 @@ fn load_chatgpt_base_url(shared_root: &Path) -> anyhow::Result<String> {
         .filter(|v| !v.trim().is_empty())
         .unwrap_or_else(|| DEFAULT_CHATGPT_BASE_URL.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MockClock;
+    use pretty_assertions::assert_eq;
+
+    fn score(weekly: Option<f64>, five: Option<f64>) -> Score {
+        Score {
+            weekly_present: weekly.is_some(),
+            weekly_remaining: weekly.unwrap_or(-1.0),
+            five_present: five.is_some(),
+            five_remaining: five.unwrap_or(-1.0),
+        }
+    }
+
+    #[test]
+    fn rate_limit_windows_mode_controls_bucketing() {
+        let window = |used_percent: f64, window_minutes: Option<i64>| RateLimitWindow {
+            used_percent,
+            window_minutes,
+            resets_at: None,
+        };
+        let rl = RateLimitSnapshot {
+            limit_id: None,
+            limit_name: None,
+            primary: Some(window(30.0, Some(300))),
+            secondary: Some(window(80.0, None)),
+            credits: None,
+            plan_type: None,
+            rate_limit_reached_type: None,
+        };
+        let remaining = |w: &Option<WindowSnapshot>| w.as_ref().map(|w| w.remaining_percent);
+
+        let auto = rate_limits_to_usage_snapshot(&rl, RateLimitWindows::Auto);
+        assert_eq!(remaining(&auto.five_hour), Some(70.0));
+        assert_eq!(remaining(&auto.weekly), None);
+
+        let fixed =
+            rate_limits_to_usage_snapshot(&rl, RateLimitWindows::PrimaryFiveSecondaryWeekly);
+        assert_eq!(remaining(&fixed.five_hour), Some(70.0));
+        assert_eq!(remaining(&fixed.weekly), Some(20.0));
+
+        let raw = rate_limits_to_usage_snapshot(&rl, RateLimitWindows::Raw);
+        assert_eq!(
+            (raw.five_hour.is_none(), raw.weekly.is_none()),
+            (true, true)
+        );
+        assert_eq!(remaining(&raw.primary), Some(70.0));
+        assert_eq!(remaining(&raw.secondary), Some(20.0));
+        let score = usage_score(&raw).expect("raw windows are scored");
+        assert_eq!((score.weekly_remaining, score.five_remaining), (20.0, 70.0));
+    }
+
+    #[test]
+    fn exhausted_when_any_known_window_is_empty() {
+        assert_eq!(score(Some(0.0), Some(50.0)).is_exhausted(), true);
+        assert_eq!(score(Some(40.0), Some(0.0)).is_exhausted(), true);
+        assert_eq!(score(None, Some(10.0)).is_exhausted(), false);
+        assert_eq!(score(Some(10.0), None).is_exhausted(), false);
+    }
+
+    #[test]
+    fn unusable_accounts_error_lists_each_reason() {
+        let accounts = BTreeMap::from([
+            ("a".to_string(), Unusable::AuthMissing),
+            ("b".to_string(), Unusable::Exhausted),
+        ]);
+        let err = UnusableAccounts { accounts }.into_error();
+
+        assert_eq!(crate::exit_code::for_error(&err), 2);
+        assert_eq!(
+            err.to_string(),
+            "no usable accounts: a (auth_missing), b (exhausted); try `codex-mgr run --refresh --auto -- <args>` or re-login"
+        );
+    }
+
+    #[test]
+    fn cached_scores_expire_exactly_after_the_cache_ttl() {
+        let clock = MockClock::new(1_700_000_000_000);
+        let cached = CachedUsage {
+            captured_at_ms: clock.now_ms(),
+            snapshot: UsageSnapshot {
+                five_hour: None,
+                weekly: Some(WindowSnapshot {
+                    used_percent: 40.0,
+                    remaining_percent: 60.0,
+                    window_minutes: None,
+                    resets_at: None,
+                }),
+                primary: None,
+                secondary: None,
+            },
+        };
+
+        let ttl_ms = config::DEFAULT_USAGE_CACHE_TTL_SECONDS * 1000;
+        clock.advance_ms(ttl_ms);
+        assert!(fresh_cached_score(&cached, clock.now_ms(), ttl_ms).is_some());
+
+        clock.advance_ms(1);
+        assert!(fresh_cached_score(&cached, clock.now_ms(), ttl_ms).is_none());
+    }
+
+    #[test]
+    fn round_robin_next_wraps_and_restarts_on_unknown_last() {
+        let candidates = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        assert_eq!(round_robin_next(&candidates, None), Some(&candidates[0]));
+        assert_eq!(
+            round_robin_next(&candidates, Some("a")),
+            Some(&candidates[1])
+        );
+        assert_eq!(
+            round_robin_next(&candidates, Some("c")),
+            Some(&candidates[0])
+        );
+        assert_eq!(
+            round_robin_next(&candidates, Some("gone")),
+            Some(&candidates[0])
+        );
+        assert_eq!(round_robin_next(&[], Some("a")), None);
+    }
+
+    #[test]
+    fn restrict_labels_keeps_only_requested_accounts() {
+        let labels = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let only = BTreeSet::from(["c".to_string(), "a".to_string(), "missing".to_string()]);
+
+        assert_eq!(
+            restrict_labels(labels.clone(), Some(&only)),
+            vec!["a".to_string(), "c".to_string()]
+        );
+        assert_eq!(restrict_labels(labels.clone(), None), labels);
+    }
+
+    #[test]
+    fn select_cached_label_keeps_an_unparseable_state_file() {
+        let temp = tempfile::tempdir().expect("create temp dir");
+        let accounts_root = temp.path().join("accounts");
+        std::fs::create_dir_all(accounts_root.join("a")).expect("create account");
+        std::fs::write(accounts_root.join("a").join("auth.json"), "{}").expect("write auth");
+        std::fs::write(temp.path().join("state.json"), "{not json").expect("write state");
+
+        let result = select_cached_label(
+            &accounts_root,
+            temp.path(),
+            None,
+            &crate::selection::MostRemaining::default(),
+            NoFetchFallback::RoundRobin,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(
+            std::fs::read_to_string(temp.path().join("state.json")).expect("read state"),
+            "{not json"
+        );
+    }
+
+    #[test]
+    fn pick_usable_skips_reserved_and_exhausted_accounts() {
+        let scores = HashMap::from([
+            ("a".to_string(), score(Some(90.0), Some(90.0))),
+            ("b".to_string(), score(Some(0.0), Some(50.0))),
+            ("c".to_string(), score(Some(40.0), Some(40.0))),
+        ]);
+        let reserved = BTreeSet::from(["a".to_string()]);
+        let mut unusable = BTreeMap::new();
+
+        let picked = pick_usable(
+            scores,
+            &reserved,
+            &mut unusable,
+            &crate::selection::MostRemaining::default(),
+        );
+
+        assert_eq!(picked.as_deref(), Some("c"));
+        assert_eq!(
+            unusable,
+            BTreeMap::from([
+                ("a".to_string(), Unusable::Reserved),
+                ("b".to_string(), Unusable::Exhausted),
+            ])
+        );
+    }
+}
+
+#[cfg(test)]
+#[path = "usage_scan_tests.rs"]
+mod scan_tests;
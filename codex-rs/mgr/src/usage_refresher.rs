@@ -0,0 +1,238 @@
+use anyhow::Context;
+use rand::Rng;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::sync::RwLock;
+
+use crate::accounts;
+use crate::observability::GatewayMetrics;
+use crate::redis_conn::RedisConnection;
+use crate::state::CachedUsage;
+use crate::usage;
+
+/// One JSON `CachedUsage` per account, shared by every gateway replica on the same Redis and
+/// expiring once the snapshot is older than `gateway.usage_cache_ttl_seconds`.
+const USAGE_KEY_PREFIX: &str = "gw:usage:";
+
+pub(crate) struct UsageRefresher {
+    pub(crate) shared_root: PathBuf,
+    pub(crate) accounts_root: PathBuf,
+    pub(crate) state_root: PathBuf,
+    pub(crate) config_path: PathBuf,
+    pub(crate) interval_seconds: i64,
+    pub(crate) fetch_timeout_seconds: i64,
+    pub(crate) cache_ttl_seconds: i64,
+}
+
+/// Periodically refreshes the in-process usage scores that routing ranks candidates by.
+///
+/// Each pass first reads the snapshots other replicas published under `gw:usage:<label>`, then
+/// scans only the accounts with no fresh shared snapshot through `usage::scan_and_update_usage`
+/// (which also skips accounts fresh in `state.json`) and publishes what it scanned. Fetches are
+/// bounded by the scan concurrency and the per-account fetch timeout, and every interval is
+/// jittered so replicas started together do not keep fetching in lockstep.
+///
+/// `scan_lock` is held for the duration of each pass, up to and including its `state.json`
+/// write, so shutdown can wait for a scan in progress.
+pub(crate) fn spawn(
+    refresher: UsageRefresher,
+    redis: RedisConnection,
+    scan_lock: Arc<Mutex<()>>,
+    usage_scores: Arc<RwLock<HashMap<String, usage::Score>>>,
+    metrics: Arc<GatewayMetrics>,
+) {
+    let Ok(interval_seconds) = u64::try_from(refresher.interval_seconds) else {
+        return;
+    };
+    if interval_seconds == 0 {
+        tracing::info!("usage background refresher disabled");
+        return;
+    }
+    let options = usage::ScanOptions {
+        fetch_timeout: Duration::from_secs(
+            u64::try_from(refresher.fetch_timeout_seconds).unwrap_or(1),
+        ),
+        cache_ttl_ms: Some(refresher.cache_ttl_seconds.saturating_mul(1000)),
+        ..usage::ScanOptions::default()
+    };
+
+    tokio::spawn(
+        #[expect(
+            clippy::await_holding_invalid_type,
+            reason = "scan_lock is held across each scan and its state.json write so shutdown can wait for that scan to finish"
+        )]
+        async move {
+            tracing::info!(interval_seconds, "usage background refresher started");
+            let mut conn = redis;
+            loop {
+                let scan = scan_lock.lock().await;
+                match refresh_once(&refresher, &mut conn, &options).await {
+                    Ok(scores) => {
+                        tracing::info!(count = scores.len(), "updated usage scores");
+                        *usage_scores.write().await = scores;
+                        metrics
+                            .usage_refresh_success_total
+                            .fetch_add(1, Ordering::Relaxed);
+                        metrics
+                            .usage_refresh_last_success_ms
+                            .store(crate::time::now_ms(), Ordering::Relaxed);
+                    }
+                    Err(err) => {
+                        tracing::error!(error = %err, "failed to update usage scores");
+                        metrics
+                            .usage_refresh_failures_total
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                drop(scan);
+                tokio::time::sleep(next_pass_delay(Duration::from_secs(interval_seconds))).await;
+            }
+        },
+    );
+}
+
+/// Scores for every account: fresh shared snapshots as they are, the rest scanned and then
+/// published for the other replicas. A Redis error only costs the shared read or write for that
+/// account, so a Redis outage degrades to each replica scanning on its own.
+async fn refresh_once(
+    refresher: &UsageRefresher,
+    conn: &mut RedisConnection,
+    options: &usage::ScanOptions<'static>,
+) -> anyhow::Result<HashMap<String, usage::Score>> {
+    let ttl_ms = refresher.cache_ttl_seconds.saturating_mul(1000);
+    let now_ms = options.clock.now_ms();
+    let mut scores = HashMap::new();
+    let mut to_scan = BTreeSet::new();
+    for label in accounts::list_labels(&refresher.accounts_root)? {
+        match get_shared(conn, &label).await {
+            Ok(Some(cached)) => {
+                if let Some(score) = usage::fresh_cached_score(&cached, now_ms, ttl_ms) {
+                    scores.insert(label, score);
+                    continue;
+                }
+            }
+            Ok(None) => {}
+            Err(err) => {
+                tracing::warn!(error = %err, account = %label, "failed to read shared usage");
+            }
+        }
+        to_scan.insert(label);
+    }
+    let shared = scores.len();
+    if to_scan.is_empty() {
+        tracing::debug!(shared, "every account had a fresh shared usage snapshot");
+        return Ok(scores);
+    }
+
+    let scanned = usage::scan_and_update_usage(
+        &refresher.shared_root,
+        &refresher.accounts_root,
+        &refresher.state_root,
+        &refresher.config_path,
+        usage::ScanOptions {
+            only_labels: Some(to_scan),
+            ..options.clone()
+        },
+    )
+    .await?;
+
+    // Scanned accounts are now fresh in `state.json`, whether fetched just now or already cached.
+    let state = crate::state::load_state(&refresher.state_root).unwrap_or_default();
+    let now_ms = options.clock.now_ms();
+    for label in scanned.keys() {
+        let Some(cached) = state.usage_cache.get(label) else {
+            continue;
+        };
+        let Some(expire_ms) = shared_expire_ms(cached.captured_at_ms, now_ms, ttl_ms) else {
+            continue;
+        };
+        if let Err(err) = put_shared(conn, label, cached, expire_ms).await {
+            tracing::warn!(error = %err, account = %label, "failed to publish shared usage");
+        }
+    }
+    tracing::debug!(
+        shared,
+        scanned = scanned.len(),
+        "usage refresh pass finished"
+    );
+    scores.extend(scanned);
+    Ok(scores)
+}
+
+async fn get_shared(
+    conn: &mut RedisConnection,
+    label: &str,
+) -> anyhow::Result<Option<CachedUsage>> {
+    let key = format!("{USAGE_KEY_PREFIX}{label}");
+    let value: Option<String> = conn.query_idempotent(redis::cmd("GET").arg(&key)).await?;
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let cached =
+        serde_json::from_str(&value).with_context(|| format!("parsing shared usage {key:?}"))?;
+    Ok(Some(cached))
+}
+
+async fn put_shared(
+    conn: &mut RedisConnection,
+    label: &str,
+    cached: &CachedUsage,
+    expire_ms: i64,
+) -> anyhow::Result<()> {
+    let key = format!("{USAGE_KEY_PREFIX}{label}");
+    let value = serde_json::to_string(cached).context("serializing CachedUsage")?;
+    let _: () = redis::cmd("SET")
+        .arg(&key)
+        .arg(value)
+        .arg("PX")
+        .arg(expire_ms)
+        .query_async(conn)
+        .await?;
+    Ok(())
+}
+
+/// How much longer a snapshot captured at `captured_at_ms` stays fresh, so the shared key
+/// expires when the snapshot goes stale rather than a full TTL after it was published; `None`
+/// once it already has.
+fn shared_expire_ms(captured_at_ms: i64, now_ms: i64, ttl_ms: i64) -> Option<i64> {
+    let remaining = ttl_ms.saturating_sub(now_ms.saturating_sub(captured_at_ms));
+    (remaining > 0).then_some(remaining)
+}
+
+/// The interval plus up to a tenth of it at random, so replicas drift apart instead of reaching
+/// an expired snapshot at the same moment and fetching it twice.
+fn next_pass_delay(interval: Duration) -> Duration {
+    let jitter_ms = u64::try_from(interval.as_millis() / 10).unwrap_or(0);
+    interval + Duration::from_millis(rand::rng().random_range(0..=jitter_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn shared_entry_expires_with_the_snapshot() {
+        assert_eq!(shared_expire_ms(1_000, 1_000, 60_000), Some(60_000));
+        assert_eq!(shared_expire_ms(1_000, 31_000, 60_000), Some(30_000));
+        assert_eq!(shared_expire_ms(1_000, 61_000, 60_000), None);
+        assert_eq!(shared_expire_ms(1_000, 90_000, 60_000), None);
+    }
+
+    #[test]
+    fn pass_delay_is_the_interval_plus_bounded_jitter() {
+        let interval = Duration::from_secs(60);
+        for _ in 0..32 {
+            let delay = next_pass_delay(interval);
+            assert!(
+                delay >= interval && delay <= Duration::from_secs(66),
+                "{delay:?}"
+            );
+        }
+    }
+}
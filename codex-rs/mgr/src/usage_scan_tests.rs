@@ -0,0 +1,165 @@
+//! `scan_usage` and `select_best_label` against a local stand-in for the usage endpoint.
+
+use super::*;
+use pretty_assertions::assert_eq;
+use std::path::PathBuf;
+
+struct Fixture {
+    _temp: tempfile::TempDir,
+    shared_root: PathBuf,
+    accounts_root: PathBuf,
+    state_root: PathBuf,
+    config_path: PathBuf,
+}
+
+/// Accounts `labels`, each with an `auth.json`, whose usage is fetched from `chatgpt_base_url`.
+fn fixture(labels: &[&str], chatgpt_base_url: &str) -> Fixture {
+    let temp = tempfile::tempdir().expect("create temp dir");
+    let shared_root = temp.path().join("shared");
+    let accounts_root = temp.path().join("accounts");
+    std::fs::create_dir_all(&shared_root).expect("create shared root");
+    std::fs::write(
+        shared_root.join("config.toml"),
+        format!("chatgpt_base_url = \"{chatgpt_base_url}\"\n"),
+    )
+    .expect("write shared config");
+    let auth = serde_json::json!({
+        "OPENAI_API_KEY": null,
+        "tokens": {
+            "id_token": "e30.e30.sig",
+            "access_token": "e30.e30.sig",
+            "refresh_token": "rt",
+        },
+    });
+    for label in labels {
+        let home = accounts_root.join(label);
+        std::fs::create_dir_all(&home).expect("create account home");
+        std::fs::write(home.join("auth.json"), auth.to_string()).expect("write auth.json");
+    }
+    Fixture {
+        state_root: temp.path().to_path_buf(),
+        config_path: temp.path().join("config.toml"),
+        _temp: temp,
+        shared_root,
+        accounts_root,
+    }
+}
+
+/// A usage endpoint that accepts connections and never answers.
+async fn silent_upstream() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+    format!("http://{addr}/backend-api")
+}
+
+/// A usage endpoint that refuses connections.
+async fn refusing_upstream() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    drop(listener);
+    format!("http://{addr}/backend-api")
+}
+
+fn cached(remaining_percent: f64) -> CachedUsage {
+    let window = |window_minutes| WindowSnapshot {
+        used_percent: 100.0 - remaining_percent,
+        remaining_percent,
+        window_minutes: Some(window_minutes),
+        resets_at: None,
+    };
+    CachedUsage {
+        captured_at_ms: 0,
+        snapshot: UsageSnapshot {
+            five_hour: Some(window(300)),
+            weekly: Some(window(10_080)),
+            primary: None,
+            secondary: None,
+        },
+    }
+}
+
+#[tokio::test]
+async fn allow_stale_picks_an_expired_snapshot_when_every_fetch_fails() {
+    let fixture = fixture(&["a", "b", "c"], &refusing_upstream().await);
+    crate::state::save_state(
+        &fixture.state_root,
+        &crate::state::ManagerState {
+            usage_cache: BTreeMap::from([
+                ("a".to_string(), cached(40.0)),
+                ("b".to_string(), cached(90.0)),
+            ]),
+            reserved: BTreeSet::from(["b".to_string()]),
+            ..Default::default()
+        },
+    )
+    .expect("save state");
+    let strategy = crate::selection::MostRemaining::default();
+    let select = |allow_stale| {
+        select_best_label(
+            &fixture.shared_root,
+            &fixture.accounts_root,
+            &fixture.state_root,
+            &fixture.config_path,
+            ScanOptions {
+                allow_stale,
+                ..ScanOptions::default()
+            },
+            &strategy,
+        )
+    };
+
+    let picked = select(true).await.expect("select");
+    assert_eq!(picked.ok().as_deref(), Some("a"));
+
+    let unusable = select(false)
+        .await
+        .expect("select")
+        .expect_err("no fresh usage");
+    assert_eq!(
+        unusable.accounts,
+        BTreeMap::from([
+            ("a".to_string(), Unusable::FetchFailed),
+            ("b".to_string(), Unusable::FetchFailed),
+            ("c".to_string(), Unusable::FetchFailed),
+        ])
+    );
+}
+
+#[tokio::test]
+async fn scan_stops_waiting_at_the_deadline() {
+    let fixture = fixture(&["a", "b"], &silent_upstream().await);
+    let started = tokio::time::Instant::now();
+
+    let scan = scan_usage(
+        &fixture.shared_root,
+        &fixture.accounts_root,
+        &fixture.state_root,
+        &fixture.config_path,
+        ScanOptions {
+            deadline: Some(started + Duration::from_millis(200)),
+            ..ScanOptions::default()
+        },
+    )
+    .await
+    .expect("scan");
+
+    assert!(started.elapsed() < DEFAULT_USAGE_FETCH_TIMEOUT / 4);
+    assert!(scan.scores.is_empty());
+    assert_eq!(
+        scan.unusable,
+        BTreeMap::from([
+            ("a".to_string(), Unusable::FetchFailed),
+            ("b".to_string(), Unusable::FetchFailed),
+        ])
+    );
+}
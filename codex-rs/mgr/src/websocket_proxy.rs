@@ -5,6 +5,7 @@ use axum::extract::ws::Message as AxumMessage;
 use axum::extract::ws::WebSocket;
 use axum::extract::ws::WebSocketUpgrade;
 use axum::http::HeaderMap;
+use axum::http::HeaderName;
 use axum::http::Request;
 use axum::http::StatusCode;
 use axum::http::Uri;
@@ -24,6 +25,7 @@ use tokio_tungstenite::tungstenite::protocol::CloseFrame as TungsteniteCloseFram
 use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
 
 use crate::account_token_provider;
+use crate::header_policy;
 use crate::observability::GatewayMetrics;
 use crate::routing;
 use crate::serve::RequestTraceData;
@@ -40,6 +42,11 @@ pub(crate) async fn forward(
     let request_uri = request.uri().clone();
     let trace_data = request.extensions().get::<Arc<RequestTraceData>>().cloned();
     let mut conn = state.redis.clone();
+    // The upstream path comes from the client, but the host is always the configured base's.
+    if let Err(err) = state.upstream_hosts.check(&state.upstream_base_url) {
+        tracing::error!(error = %err, "refusing to send account credentials upstream");
+        return Err(StatusCode::BAD_GATEWAY);
+    }
 
     for (idx, account_id) in route_info.candidates.iter().enumerate() {
         let is_last = idx + 1 == route_info.candidates.len();
@@ -48,6 +55,9 @@ pub(crate) async fn forward(
             &state.accounts_root,
             account_id,
             state.token_safety_window_seconds,
+            state.max_token_cache_seconds,
+            &state.metrics,
+            state.clock.as_ref(),
         )
         .await;
 
@@ -56,6 +66,11 @@ pub(crate) async fn forward(
             Err(err) => {
                 if err.downcast_ref::<redis::RedisError>().is_some() {
                     tracing::error!(error = %err, "redis error in websocket token provider");
+                    state
+                        .metrics
+                        .redis_errors_total
+                        .fetch_add(1, Ordering::Relaxed);
+                    return Err(StatusCode::SERVICE_UNAVAILABLE);
                 } else {
                     tracing::warn!(error = %err, %account_id, "websocket token provider error");
                     state
@@ -74,6 +89,8 @@ pub(crate) async fn forward(
             &state.upstream_base_url,
             &request_uri,
             &request_headers,
+            &state.header_strip.request,
+            &state.upstream_api_headers,
             &auth.authorization,
             auth.chatgpt_account_id.as_deref(),
         )
@@ -146,6 +163,8 @@ async fn connect_upstream(
     upstream_base_url: &str,
     request_uri: &Uri,
     request_headers: &HeaderMap,
+    strip_request_headers: &[HeaderName],
+    pinned_headers: &HeaderMap,
     authorization: &str,
     chatgpt_account_id: Option<&str>,
 ) -> Result<
@@ -158,7 +177,9 @@ async fn connect_upstream(
         .as_str()
         .into_client_request()
         .map_err(UpstreamConnectError::other)?;
-    let mut headers = ws_header_policy::forward_request_headers(request_headers);
+    let mut headers =
+        ws_header_policy::forward_request_headers(request_headers, strip_request_headers);
+    header_policy::apply_pinned_headers(&mut headers, pinned_headers);
     let auth = HeaderValue::from_str(authorization).map_err(UpstreamConnectError::other)?;
     headers.insert(header::AUTHORIZATION, auth);
     if let Some(chatgpt_account_id) = chatgpt_account_id {
@@ -2,11 +2,11 @@ use axum::http::HeaderMap;
 use axum::http::HeaderName;
 use axum::http::header;
 
-pub(crate) fn forward_request_headers(headers: &HeaderMap) -> HeaderMap {
+pub(crate) fn forward_request_headers(headers: &HeaderMap, strip: &[HeaderName]) -> HeaderMap {
     let mut out = HeaderMap::new();
 
     for (name, value) in headers {
-        if should_drop_request_header(name) {
+        if should_drop_request_header(name) || strip.contains(name) {
             continue;
         }
         out.append(name.clone(), value.clone());
@@ -49,6 +49,9 @@ fn should_drop_request_header(name: &HeaderName) -> bool {
     if name_str == "x-real-ip" {
         return true;
     }
+    if name_str == crate::routing::ACCOUNT_OVERRIDE_HEADER {
+        return true;
+    }
 
     false
 }
@@ -76,7 +79,7 @@ mod tests {
         headers.insert("openai-beta", HeaderValue::from_static("realtime=v1"));
         headers.insert("session_id", HeaderValue::from_static("conv_123"));
 
-        let forwarded = forward_request_headers(&headers);
+        let forwarded = forward_request_headers(&headers, &[]);
 
         assert_eq!(forwarded.get(header::AUTHORIZATION), None);
         assert_eq!(forwarded.get(header::HOST), None);